@@ -2,7 +2,12 @@
 
 #![allow(dead_code)]
 
-use crate::{span::WithSpan, tokens::GeneralRegisterName};
+use crate::{
+    span::WithSpan,
+    tokens::{GeneralRegisterName, InvalidRegisterError},
+};
+use chip8_instructions::{Instruction, Operand};
+use thiserror::Error;
 
 /// Something that can be aliased.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -63,6 +68,8 @@ pub enum PseudoInstruction<'s> {
     Nop,
     Cls,
     Ret,
+    Low,
+    High,
     Jmp(Word<'s>),
     JmpPlus(Reg<'s>, Word<'s>),
     Call(Word<'s>),
@@ -79,8 +86,8 @@ pub enum PseudoInstruction<'s> {
     Xor(Reg<'s>, Reg<'s>),
     Sub(Reg<'s>, Reg<'s>),
     Subn(Reg<'s>, Reg<'s>),
-    Shr(Reg<'s>),
-    Shl(Reg<'s>),
+    Shr(Reg<'s>, Reg<'s>),
+    Shl(Reg<'s>, Reg<'s>),
     Rnd(Reg<'s>, Byte<'s>),
     Drw(Reg<'s>, Reg<'s>, Byte<'s>),
     Skp(Reg<'s>),
@@ -93,15 +100,230 @@ pub enum PseudoInstruction<'s> {
     Rstr(Reg<'s>),
 }
 
+/// An error returned when an [`Instruction`] can't be turned back into a [`PseudoInstruction`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Error)]
+pub enum FromInstructionError {
+    /// A register number in the instruction was too big.
+    #[error(transparent)]
+    InvalidRegister(#[from] InvalidRegisterError),
+
+    /// The assembler doesn't have a mnemonic for this instruction.
+    #[error("The assembler has no mnemonic for {0:?}")]
+    Unsupported(Instruction),
+}
+
+impl<'s> TryFrom<Instruction> for PseudoInstruction<'s> {
+    type Error = FromInstructionError;
+
+    /// Convert a real instruction back into a pseudo-instruction with no aliases. This fails if
+    /// any register number in the instruction is too big, or if it's a SUPER-CHIP instruction
+    /// that the assembler doesn't support.
+    fn try_from(instruction: Instruction) -> Result<Self, Self::Error> {
+        use Instruction as I;
+        use PseudoInstruction as PI;
+
+        let reg = |x: u8| GeneralRegisterName::try_from(x).map(OrAlias::Concrete);
+        let byte = |byte: u8| OrAlias::Concrete(RegOrByte::LiteralByte(byte));
+        let reg_or_byte = |op: Operand| match op {
+            Operand::Register(x) => reg(x).map(|r| r.map(RegOrByte::Register)),
+            Operand::Literal(b) => Ok(byte(b)),
+        };
+
+        Ok(match instruction {
+            I::Nop => PI::Nop,
+            I::ClearScreen => PI::Cls,
+            I::Return => PI::Ret,
+            I::LowRes => PI::Low,
+            I::HighRes => PI::High,
+            I::Jump(addr) => PI::Jmp(OrAlias::Concrete(addr)),
+            I::Call(addr) => PI::Call(OrAlias::Concrete(addr)),
+            I::SkipIfEqual(x, op) => PI::Se(reg(x)?, reg_or_byte(op)?),
+            I::SkipIfNotEqual(x, op) => PI::Sne(reg(x)?, reg_or_byte(op)?),
+            I::LoadRegister(x, op) => PI::Ld(reg(x)?, reg_or_byte(op)?),
+            I::AddNoCarry(x, b) => PI::Add(reg(x)?, byte(b)),
+            I::Or(x, y) => PI::Or(reg(x)?, reg(y)?),
+            I::And(x, y) => PI::And(reg(x)?, reg(y)?),
+            I::Xor(x, y) => PI::Xor(reg(x)?, reg(y)?),
+            I::AddWithCarry(x, y) => PI::Add(reg(x)?, reg_or_byte(Operand::Register(y))?),
+            I::Sub(x, y) => PI::Sub(reg(x)?, reg(y)?),
+            I::ShiftRight(x, y) => PI::Shr(reg(x)?, reg(y)?),
+            I::SubN(x, y) => PI::Subn(reg(x)?, reg(y)?),
+            I::ShiftLeft(x, y) => PI::Shl(reg(x)?, reg(y)?),
+            I::LoadMemoryRegister(addr) => PI::LdIndex(OrAlias::Concrete(addr)),
+            I::JumpPlusV0(addr) => PI::JmpPlus(
+                OrAlias::Concrete(GeneralRegisterName::V0),
+                OrAlias::Concrete(addr),
+            ),
+            I::LoadRandomWithMask(x, mask) => PI::Rnd(reg(x)?, OrAlias::Concrete(mask)),
+            I::Draw(x, y, n) => PI::Drw(reg(x)?, reg(y)?, OrAlias::Concrete(n)),
+            I::SkipIfKeyPressed(x) => PI::Skp(reg(x)?),
+            I::SkipIfKeyNotPressed(x) => PI::Sknp(reg(x)?),
+            I::LoadFromDelayTimer(x) => PI::LdFromDt(reg(x)?),
+            I::WaitForKeyPress(x) => PI::LdFromK(reg(x)?),
+            I::LoadIntoDelayTimer(x) => PI::Delay(reg(x)?),
+            I::LoadIntoSoundTimer(x) => PI::Sound(reg(x)?),
+            I::AddToMemoryRegister(x) => PI::AddIndex(reg(x)?),
+            I::LoadDigitAddress(x) => PI::Font(reg(x)?),
+            I::StoreBcdInMemory(x) => PI::Bcd(reg(x)?),
+            I::StoreRegistersInMemory(x) => PI::Stor(reg(x)?),
+            I::ReadRegistersFromMemory(x) => PI::Rstr(reg(x)?),
+            // Dxy0 is the same bytecode as drawing a sprite with 0 rows
+            I::DrawLargeSprite(x, y) => PI::Drw(reg(x)?, reg(y)?, OrAlias::Concrete(0)),
+            I::ScrollDown(_)
+            | I::ScrollRight
+            | I::ScrollLeft
+            | I::Exit
+            | I::LoadLargeDigitAddress(_)
+            | I::StoreRegistersInRpl(_)
+            | I::ReadRegistersFromRpl(_) => {
+                return Err(FromInstructionError::Unsupported(instruction))
+            }
+        })
+    }
+}
+
 /// A [`Stmt`] wrapped in [`WithSpan`].
 pub type SpanStmt<'s> = WithSpan<Stmt<'s>>;
 
 /// A list of all the possible statements.
 #[derive(Clone, Debug, PartialEq)]
 pub enum Stmt<'s> {
-    AliasDefinition(&'s str, AliasableThing),
+    AliasDefinition(&'s str, OrAlias<'s, AliasableThing>),
     RawDataDefinition(Vec<u8>),
     Label(&'s str),
     PseudoInstruction(PseudoInstruction<'s>),
     Include(&'s str),
+    IncludeBinary(&'s str),
+    SegmentDirective(SegmentKind),
+}
+
+/// The segments that code and data can be assembled into.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SegmentKind {
+    /// The code segment, which starts at the origin. This is the default.
+    #[default]
+    Code,
+
+    /// The data segment, which starts at a separate address.
+    Data,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        codegen::{codegen, Target, DEFAULT_MAX_ROM_SIZE},
+        span::Span,
+    };
+    use chip8_instructions::encode;
+    use std::path::Path;
+
+    #[test]
+    fn instruction_to_pseudo_instruction_test() {
+        use GeneralRegisterName as R;
+
+        assert_eq!(
+            PseudoInstruction::try_from(Instruction::Draw(3, 5, 4)),
+            Ok(PseudoInstruction::Drw(
+                OrAlias::Concrete(R::V3),
+                OrAlias::Concrete(R::V5),
+                OrAlias::Concrete(4)
+            ))
+        );
+        assert_eq!(
+            PseudoInstruction::try_from(Instruction::AddWithCarry(0xA, 0xF)),
+            Ok(PseudoInstruction::Add(
+                OrAlias::Concrete(R::Va),
+                OrAlias::Concrete(RegOrByte::Register(R::Vf))
+            ))
+        );
+        assert_eq!(
+            PseudoInstruction::try_from(Instruction::ShiftLeft(16, 0)),
+            Err(FromInstructionError::InvalidRegister(InvalidRegisterError(
+                16
+            )))
+        );
+
+        assert_eq!(
+            PseudoInstruction::try_from(Instruction::DrawLargeSprite(1, 2)),
+            Ok(PseudoInstruction::Drw(
+                OrAlias::Concrete(R::V1),
+                OrAlias::Concrete(R::V2),
+                OrAlias::Concrete(0)
+            ))
+        );
+        assert_eq!(
+            PseudoInstruction::try_from(Instruction::Exit),
+            Err(FromInstructionError::Unsupported(Instruction::Exit))
+        );
+    }
+
+    #[test]
+    fn pseudo_instruction_codegen_roundtrip_test() {
+        use Instruction as I;
+
+        let instructions = [
+            I::Nop,
+            I::ClearScreen,
+            I::Return,
+            I::LowRes,
+            I::HighRes,
+            I::Jump(0x234),
+            I::Call(0x456),
+            I::SkipIfEqual(1, Operand::Literal(0x23)),
+            I::SkipIfNotEqual(2, Operand::Register(3)),
+            I::LoadRegister(4, Operand::Literal(0xFF)),
+            I::LoadRegister(4, Operand::Register(5)),
+            I::AddNoCarry(6, 7),
+            I::Or(1, 2),
+            I::And(3, 4),
+            I::Xor(5, 6),
+            I::AddWithCarry(7, 8),
+            I::Sub(9, 0xA),
+            I::ShiftRight(0xB, 0xB),
+            I::SubN(0xC, 0xD),
+            I::ShiftLeft(0xE, 1),
+            I::LoadMemoryRegister(0xABC),
+            I::JumpPlusV0(0x300),
+            I::LoadRandomWithMask(0xF, 0x0F),
+            I::Draw(1, 2, 15),
+            I::SkipIfKeyPressed(3),
+            I::SkipIfKeyNotPressed(4),
+            I::LoadFromDelayTimer(5),
+            I::WaitForKeyPress(6),
+            I::LoadIntoDelayTimer(7),
+            I::LoadIntoSoundTimer(8),
+            I::AddToMemoryRegister(9),
+            I::LoadDigitAddress(0xA),
+            I::StoreBcdInMemory(0xB),
+            I::StoreRegistersInMemory(0xC),
+            I::ReadRegistersFromMemory(0xD),
+        ];
+
+        for instruction in instructions {
+            let statement = WithSpan {
+                span: Span {
+                    start: 0,
+                    end: 0,
+                    file: 0,
+                },
+                value: Stmt::PseudoInstruction(instruction.try_into().unwrap()),
+            };
+            assert_eq!(
+                codegen(
+                    vec![statement],
+                    Path::new(""),
+                    Target::Chip8,
+                    0x200,
+                    0x300,
+                    false,
+                    DEFAULT_MAX_ROM_SIZE,
+                )
+                .ok()
+                .map(|segments| segments.code),
+                encode(instruction).ok().map(Vec::from),
+                "{instruction:?}"
+            );
+        }
+    }
 }
@@ -2,13 +2,36 @@
 //! to resolve alias definitions.
 
 use crate::{
-    ast::{AliasableThing, OrAlias, PseudoInstruction as PI, RegOrByte, Stmt},
-    error::report_error,
-    span::WithSpan,
+    ast::{
+        AliasableThing, OrAlias, PseudoInstruction as PI, RegOrByte, SegmentKind, SpanStmt, Stmt,
+    },
+    error::{add_source_file, checking_errors, report_warning},
+    parser::Parser,
+    scanner::Scanner,
+    span::{Span, WithSpan},
+};
+use chip8_instructions::{
+    encode, encode_strict, EncodingError, Instruction as I, Operand, StrictEncodingError,
+    INSTRUCTION_SIZE,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
 };
-use chip8_instructions::{encode, EncodingError, Instruction as I, Operand};
-use std::collections::HashMap;
 use thiserror::Error;
+use typed_arena::Arena;
+
+/// The CHIP-8 variant that we're assembling for.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Target {
+    /// The original CHIP-8 for the COSMAC VIP.
+    #[default]
+    Chip8,
+
+    /// CHIP-48 for the HP-48 calculators, which SUPER-CHIP is based on.
+    Chip48,
+}
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Error)]
 pub enum CodegenError<'s> {
@@ -21,6 +44,12 @@ pub enum CodegenError<'s> {
     #[error("The alias {0:?} is not defined")]
     AliasNotDefined(&'s str),
 
+    #[error("The alias {0:?} is defined in terms of itself")]
+    AliasCycle(&'s str),
+
+    #[error("The alias {0:?} could not be resolved within {1} passes")]
+    AliasChainTooLong(&'s str, usize),
+
     #[error("The alias {0:?} should be a register but isn't")]
     AliasShouldBeRegister(&'s str),
 
@@ -32,261 +61,1315 @@ pub enum CodegenError<'s> {
 
     #[error("Alias {0:?} resolved to a number which was too large: {1} should be at most {2}")]
     AliasedLiteralTooBig(&'s str, u16, u16),
+
+    #[error("Failed to read binary file {0:?}: {1}")]
+    IncludeBinaryReadFailed(&'s str, io::ErrorKind),
+
+    #[error("Binary file {0:?} is {1} bytes, but there are only {2} bytes of ROM space left")]
+    IncludeBinaryTooBig(&'s str, usize, usize),
+
+    #[error("Failed to read included file {0:?}: {1}")]
+    IncludeReadFailed(&'s str, io::ErrorKind),
+
+    #[error("The included file {0:?} has syntax errors, which are reported in the file")]
+    IncludeInvalid(&'s str),
+
+    #[error("The file {0:?} includes itself")]
+    IncludeCycle(&'s str),
+
+    #[error("jmpp with register V{0:X} can only jump to addresses from 0x{0:X}00 to 0x{0:X}FF, not 0x{1:0>3X}")]
+    JumpPlusAddressMismatch(u8, u16),
+
+    #[error("The code segment from 0x{0:0>3X} to 0x{1:0>3X} overlaps the data segment from 0x{2:0>3X} to 0x{3:0>3X}")]
+    SegmentsOverlap(u16, u16, u16, u16),
+
+    #[error("The ROM is {0} bytes, but it can be at most {1} bytes")]
+    RomTooBig(u16, u16),
+
+    #[error("Anything after 0x{0:0>4X} would go past the end of the 16 bit address space")]
+    AddressOverflow(u16),
+}
+
+/// The assembled bytes of the code and data segments.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Segments {
+    /// The code segment, which starts at the origin.
+    pub code: Vec<u8>,
+
+    /// The data segment, which starts at the data origin. This is empty unless the source uses
+    /// `.segment data`.
+    pub data: Vec<u8>,
+
+    /// The address of every instruction in the code segment, with the instruction itself.
+    pub instructions: Vec<(u16, I)>,
+
+    /// The value of every alias and label, after resolving aliases of other aliases.
+    pub symbols: HashMap<String, AliasableThing>,
+}
+
+impl Segments {
+    /// Combine the segments into a single ROM that starts at `origin`, padding the gap between
+    /// them with zeroes. Returns `None` if the data segment starts before the end of the code.
+    pub fn combined(&self, origin: u16, data_origin: u16) -> Option<Vec<u8>> {
+        if self.data.is_empty() {
+            return Some(self.code.clone());
+        }
+
+        let code_end = origin as usize + self.code.len();
+        let gap = (data_origin as usize).checked_sub(code_end)?;
+
+        let mut rom = self.code.clone();
+        rom.extend(std::iter::repeat_n(0, gap));
+        rom.extend(&self.data);
+        Some(rom)
+    }
+}
+
+/// The default maximum size of a ROM, which is all the memory after the interpreter's reserved
+/// space at the start.
+pub const DEFAULT_MAX_ROM_SIZE: u16 = 0x1000 - 0x200;
+
+/// The default number of passes to make when resolving aliases that refer to other aliases.
+const MAX_ALIAS_PASSES: usize = 10;
+
+/// Resolve aliases that are defined as other aliases or labels, like `define handler loop`.
+///
+/// Each pass resolves every alias whose target is already known, so chains of aliases need one
+/// pass per link. We stop when a pass doesn't change anything, or after `max_passes` passes. Any
+/// aliases that are still unresolved are either undefined, part of a cycle, or at the end of a
+/// chain that was too long, and we return an error for each of them.
+fn resolve_alias_chains<'s>(
+    alias_map: &mut HashMap<&'s str, AliasableThing>,
+    mut pending: Vec<WithSpan<(&'s str, &'s str)>>,
+    max_passes: usize,
+) -> Result<(), Vec<WithSpan<CodegenError<'s>>>> {
+    let mut stable = false;
+
+    for _ in 0..max_passes {
+        let unresolved = pending.len();
+        pending.retain(|alias| {
+            let (name, target) = alias.value;
+            match alias_map.get(target).copied() {
+                Some(thing) => {
+                    alias_map.insert(name, thing);
+                    false
+                }
+                None => true,
+            }
+        });
+
+        if pending.len() == unresolved {
+            stable = true;
+            break;
+        }
+    }
+
+    if pending.is_empty() {
+        return Ok(());
+    }
+
+    let errors = pending
+        .iter()
+        .map(|alias| {
+            let (name, target) = alias.value;
+            let value = if !stable {
+                CodegenError::AliasChainTooLong(name, max_passes)
+            } else if pending.iter().any(|alias| alias.value.0 == target) {
+                CodegenError::AliasCycle(name)
+            } else {
+                CodegenError::AliasNotDefined(target)
+            };
+            WithSpan {
+                value,
+                span: alias.span,
+            }
+        })
+        .collect();
+    Err(errors)
+}
+
+/// Replace every [`Stmt::Include`] directive with the statements from the file that it includes,
+/// and any files that they include. Each file is read relative to the directory of the file that
+/// includes it, starting from the directory of `main_file`.
+///
+/// The source code of the included files is kept in `sources`, which the statements borrow from.
+/// Syntax errors in an included file are reported in that file when it's parsed, and its
+/// statements keep their spans in it, so that anything reported about them later points there
+/// too.
+pub fn expand_includes<'s>(
+    statements: Vec<SpanStmt<'s>>,
+    main_file: &Path,
+    sources: &'s Arena<String>,
+) -> Result<Vec<SpanStmt<'s>>, Vec<WithSpan<CodegenError<'s>>>> {
+    // Including the main file from anywhere would be a cycle
+    let mut in_progress = HashSet::from([canonical_path(main_file)]);
+    let mut errors = Vec::new();
+    let statements = expand_includes_from(
+        statements,
+        main_file.parent().unwrap_or(Path::new("")),
+        Path::new(""),
+        sources,
+        &mut in_progress,
+        &mut errors,
+    );
+
+    if errors.is_empty() {
+        Ok(statements)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Get the canonical form of the path if it exists, so that we can tell when two paths are the
+/// same file, or the path itself otherwise.
+fn canonical_path(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Expand the includes in statements from the file in `dir`, which is relative to `base_dir`.
+/// `in_progress` has the canonical paths of the files that are being included, so that we can
+/// detect cycles.
+fn expand_includes_from<'s>(
+    statements: Vec<SpanStmt<'s>>,
+    base_dir: &Path,
+    dir: &Path,
+    sources: &'s Arena<String>,
+    in_progress: &mut HashSet<PathBuf>,
+    errors: &mut Vec<WithSpan<CodegenError<'s>>>,
+) -> Vec<SpanStmt<'s>> {
+    let mut expanded = Vec::with_capacity(statements.len());
+
+    for WithSpan { span, value: stmt } in statements {
+        let mut error = |value| errors.push(WithSpan { value, span });
+
+        match stmt {
+            Stmt::Include(path) => {
+                let file = dir.join(path);
+                let full_path = base_dir.join(&file);
+                let canonical_path = canonical_path(&full_path);
+                if in_progress.contains(&canonical_path) {
+                    error(CodegenError::IncludeCycle(path));
+                    continue;
+                }
+
+                let source = match fs::read_to_string(&full_path) {
+                    Ok(source) => source,
+                    Err(io_error) => {
+                        error(CodegenError::IncludeReadFailed(path, io_error.kind()));
+                        continue;
+                    }
+                };
+
+                // The file is prepared the same way as the main file, since the scanner only
+                // knows lowercase mnemonics
+                let file_number = add_source_file(full_path.display().to_string(), &source);
+                let source = source.replace("\t", "    ");
+                let lowercase: &'s str = sources.alloc(source.to_ascii_lowercase());
+                let original: &'s str = sources.alloc(source);
+
+                // The syntax errors have already been reported in the included file
+                let (statements, had_error) = checking_errors(|| {
+                    Parser::parse(Scanner::scan_tokens_from(lowercase, original, file_number))
+                });
+                if had_error {
+                    error(CodegenError::IncludeInvalid(path));
+                    continue;
+                }
+
+                in_progress.insert(canonical_path.clone());
+                expanded.extend(expand_includes_from(
+                    statements,
+                    base_dir,
+                    file.parent().unwrap_or(Path::new("")),
+                    sources,
+                    in_progress,
+                    errors,
+                ));
+                in_progress.remove(&canonical_path);
+            }
+            // Binary files are read relative to base_dir, not the included file
+            Stmt::IncludeBinary(path) if dir != Path::new("") => {
+                let path: &'s str = sources.alloc(dir.join(path).to_string_lossy().into_owned());
+                expanded.push(WithSpan {
+                    value: Stmt::IncludeBinary(path),
+                    span,
+                });
+            }
+            stmt => expanded.push(WithSpan { value: stmt, span }),
+        }
+    }
+
+    expanded
+}
+
+/// Get the size of the ROM from the end offsets of the code and data segments. The data segment
+/// only counts if it has anything in it, and then the size includes the gap between the segments.
+fn rom_size(origin: u16, code_offset: u16, data_origin: u16, data_offset: u16) -> u16 {
+    if data_offset > data_origin {
+        code_offset.max(data_offset) - origin.min(data_origin)
+    } else {
+        code_offset - origin
+    }
 }
 
 /// Resolve all the defined aliases and labels to produce a list of instructions ready to encode.
 ///
-/// This method currently emits an error and moves on if it encounters a [`Stmt::Include`] directive.
+/// The statements should have already had their [`Stmt::Include`] directives expanded with
+/// [`expand_includes`], so included files share labels and aliases with the main file. Files in
+/// [`Stmt::IncludeBinary`] directives are read relative to `base_dir`, which should be the
+/// directory of the source file.
+///
+/// The `target` decides which variant-specific instructions are allowed, and `origin` is the
+/// address that the ROM will be loaded at, which is normally 0x200. Anything after a
+/// `.segment data` directive goes in a separate segment starting at `data_origin`, until the next
+/// `.segment code`. If `strict` is true, we warn about instructions that are valid but
+/// suspicious, like `xor v1, v1`.
+///
+/// The assembled ROM can be at most `max_rom_size` bytes, counting any gap between the segments.
+/// We warn if it's more than 90% of that, since some hardware has less memory than the standard
+/// 4K.
+///
+/// We carry on past errors where we can, so that we can return all of them at once. Instructions
+/// that can't be resolved or encoded are replaced with `0x0000`, so that the addresses of
+/// everything after them stay the same. If a binary file can't be included, then we don't know
+/// where anything after it goes, so we stop after the first pass. If the program goes past the end
+/// of the 16 bit address space, we stop straight away.
 pub fn codegen<'s>(
     statements: Vec<WithSpan<Stmt<'s>>>,
-) -> Result<Vec<u8>, WithSpan<CodegenError<'s>>> {
+    base_dir: &Path,
+    target: Target,
+    origin: u16,
+    data_origin: u16,
+    strict: bool,
+    max_rom_size: u16,
+) -> Result<Segments, Vec<WithSpan<CodegenError<'s>>>> {
+    let mut errors: Vec<WithSpan<CodegenError<'s>>> = Vec::new();
+
     // The first pass is just to get numbers for all the aliases.
-    let mut offset: u16 = 0x200;
+    let mut segment = SegmentKind::Code;
+    let mut code_offset: u16 = origin;
+    let mut data_offset: u16 = data_origin;
+    let mut first_data_span = None;
     let mut alias_map: HashMap<&'s str, AliasableThing> = HashMap::new();
+    let mut binaries: HashMap<&'s str, Vec<u8>> = HashMap::new();
+    let mut missing_binary = false;
+
+    // The spans of the first statements that made the ROM too big, or close to too big
+    let mut too_big_span = None;
+    let mut near_limit_span = None;
+    let near_limit = (max_rom_size as u32 * 9 / 10) as u16;
+
+    // Aliases of other aliases or labels, which we resolve after the first pass
+    let mut pending_aliases: Vec<WithSpan<(&'s str, &'s str)>> = Vec::new();
 
     for WithSpan { span, value: stmt } in statements.iter() {
-        match stmt {
+        let is_pending = |name| pending_aliases.iter().any(|alias| alias.value.0 == name);
+        let offset = match segment {
+            SegmentKind::Code => &mut code_offset,
+            SegmentKind::Data => &mut data_offset,
+        };
+
+        let size = match stmt {
             Stmt::AliasDefinition(name, thing) => {
-                if alias_map.insert(name, *thing).is_some() {
-                    return Err(WithSpan {
+                let already_defined = is_pending(*name)
+                    || match *thing {
+                        OrAlias::Concrete(thing) => alias_map.insert(name, thing).is_some(),
+                        OrAlias::Alias(target) => {
+                            pending_aliases.push(WithSpan {
+                                span: *span,
+                                value: (name, target),
+                            });
+                            alias_map.contains_key(name)
+                        }
+                    };
+                if already_defined {
+                    errors.push(WithSpan {
                         value: CodegenError::AliasAlreadyDefined(name),
                         span: *span,
                     });
                 }
+                0
             }
-            Stmt::RawDataDefinition(data) => offset += data.len() as u16,
+            Stmt::RawDataDefinition(data) => data.len(),
             Stmt::Label(name) => {
-                if alias_map
-                    .insert(name, AliasableThing::RawData(offset))
-                    .is_some()
+                if is_pending(*name)
+                    || alias_map
+                        .insert(name, AliasableThing::RawData(*offset))
+                        .is_some()
                 {
-                    return Err(WithSpan {
+                    errors.push(WithSpan {
                         value: CodegenError::LabelAlreadyDefined(name),
                         span: *span,
                     });
                 }
+                0
+            }
+            Stmt::PseudoInstruction(_) => INSTRUCTION_SIZE,
+            Stmt::Include(_) => unreachable!("Includes should have been expanded before codegen"),
+            Stmt::IncludeBinary(path) => {
+                let data = match fs::read(base_dir.join(path)) {
+                    Ok(data) => data,
+                    Err(error) => {
+                        errors.push(WithSpan {
+                            value: CodegenError::IncludeBinaryReadFailed(path, error.kind()),
+                            span: *span,
+                        });
+                        missing_binary = true;
+                        continue;
+                    }
+                };
+
+                let remaining = 0x1000_usize.saturating_sub(*offset as usize);
+                if data.len() > remaining {
+                    errors.push(WithSpan {
+                        value: CodegenError::IncludeBinaryTooBig(path, data.len(), remaining),
+                        span: *span,
+                    });
+                    missing_binary = true;
+                    continue;
+                }
+
+                let size = data.len();
+                binaries.insert(path, data);
+                size
+            }
+            Stmt::SegmentDirective(kind) => {
+                if *kind == SegmentKind::Data {
+                    first_data_span.get_or_insert(*span);
+                }
+                segment = *kind;
+                0
             }
-            Stmt::PseudoInstruction(_) => offset += 2,
-            Stmt::Include(_) => report_error(
-                *span,
-                "Including other files is currently not implemented, so this will be ignored",
-            ),
         };
+
+        // We don't know where anything after this goes, so we stop straight away
+        match u16::try_from(size)
+            .ok()
+            .and_then(|size| offset.checked_add(size))
+        {
+            Some(end) => *offset = end,
+            None => {
+                errors.push(WithSpan {
+                    value: CodegenError::AddressOverflow(*offset),
+                    span: *span,
+                });
+                return Err(errors);
+            }
+        }
+
+        let size = rom_size(origin, code_offset, data_origin, data_offset);
+        if size > max_rom_size {
+            too_big_span.get_or_insert(*span);
+        } else if size > near_limit {
+            near_limit_span.get_or_insert(*span);
+        }
+    }
+
+    if missing_binary {
+        return Err(errors);
+    }
+
+    if let Some(span) = first_data_span {
+        if data_origin < data_offset && origin < data_offset && data_origin < code_offset {
+            errors.push(WithSpan {
+                value: CodegenError::SegmentsOverlap(origin, code_offset, data_origin, data_offset),
+                span,
+            });
+        }
     }
 
-    let mut blob: Vec<u8> = Vec::with_capacity(offset as usize - 0x200);
+    let size = rom_size(origin, code_offset, data_origin, data_offset);
+    if let Some(span) = too_big_span {
+        errors.push(WithSpan {
+            value: CodegenError::RomTooBig(size, max_rom_size),
+            span,
+        });
+    } else if let Some(span) = near_limit_span {
+        report_warning(
+            span,
+            &format!(
+                "The ROM is {size} bytes, which is over 90% of the maximum of {max_rom_size} bytes"
+            ),
+        );
+    }
+
+    if let Err(alias_errors) =
+        resolve_alias_chains(&mut alias_map, pending_aliases, MAX_ALIAS_PASSES)
+    {
+        errors.extend(alias_errors);
+    }
+
+    let mut segments = Segments {
+        code: Vec::with_capacity((code_offset - origin) as usize),
+        data: Vec::with_capacity((data_offset - data_origin) as usize),
+        instructions: Vec::new(),
+        symbols: alias_map
+            .iter()
+            .map(|(name, thing)| (name.to_string(), *thing))
+            .collect(),
+    };
+    let mut segment = SegmentKind::Code;
 
     for WithSpan { span, value: stmt } in statements.into_iter() {
-        macro_rules! resolve_addr {
-            ($arg:ident) => {
-                match $arg {
-                    OrAlias::Alias(alias) => match *alias_map.get(alias).ok_or(WithSpan {
-                        value: CodegenError::AliasNotDefined(alias),
+        let blob = match segment {
+            SegmentKind::Code => &mut segments.code,
+            SegmentKind::Data => &mut segments.data,
+        };
+
+        match stmt {
+            Stmt::AliasDefinition(_, _) | Stmt::Label(_) => {}
+            Stmt::RawDataDefinition(data) => blob.extend(data),
+            Stmt::PseudoInstruction(instr) => {
+                let resolved = resolve_instruction(instr, span, &alias_map, target);
+                if let (SegmentKind::Code, Ok(instruction)) = (segment, &resolved) {
+                    let address = origin + blob.len() as u16;
+                    segments.instructions.push((address, *instruction));
+                }
+
+                let encoded = resolved.and_then(|instruction| {
+                    let encoded = if strict {
+                        match encode_strict(instruction) {
+                            Err(StrictEncodingError::Suspicious { cause, bytes }) => {
+                                report_warning(span, &format!("{cause}"));
+                                Ok(bytes)
+                            }
+                            Err(StrictEncodingError::Encoding(encoding_error)) => {
+                                Err(encoding_error)
+                            }
+                            Ok(bytes) => Ok(bytes),
+                        }
+                    } else {
+                        encode(instruction)
+                    };
+                    encoded.map_err(|encoding_error| WithSpan {
+                        value: CodegenError::EncodingError(encoding_error),
                         span,
-                    })? {
-                        AliasableThing::RawData(data) => data,
-                        AliasableThing::Register(_) => {
+                    })
+                });
+
+                match encoded {
+                    Ok(bytes) => blob.extend(bytes),
+                    Err(error) => {
+                        // Keep everything after this at the right address
+                        errors.push(error);
+                        blob.extend([0x00; INSTRUCTION_SIZE]);
+                    }
+                }
+            }
+            Stmt::Include(_) => unreachable!("Includes should have been expanded before codegen"),
+            Stmt::IncludeBinary(path) => blob.extend(&binaries[path]),
+            Stmt::SegmentDirective(kind) => segment = kind,
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(segments)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Resolve the aliases in a single pseudo-instruction to get an instruction ready to encode.
+fn resolve_instruction<'s>(
+    instr: PI<'s>,
+    span: Span,
+    alias_map: &HashMap<&'s str, AliasableThing>,
+    target: Target,
+) -> Result<I, WithSpan<CodegenError<'s>>> {
+    macro_rules! resolve_addr {
+        ($arg:ident) => {
+            match $arg {
+                OrAlias::Alias(alias) => match *alias_map.get(alias).ok_or(WithSpan {
+                    value: CodegenError::AliasNotDefined(alias),
+                    span,
+                })? {
+                    AliasableThing::RawData(data) => data,
+                    AliasableThing::Register(_) => {
+                        return Err(WithSpan {
+                            value: CodegenError::AliasShouldBeNumber(alias),
+                            span,
+                        });
+                    }
+                },
+                OrAlias::Concrete(addr) => addr,
+            }
+        };
+    }
+
+    macro_rules! resolve_reg {
+        ($arg:ident) => {
+            match $arg {
+                OrAlias::Alias(alias) => match *alias_map.get(alias).ok_or(WithSpan {
+                    value: CodegenError::AliasNotDefined(alias),
+                    span,
+                })? {
+                    AliasableThing::RawData(_) => {
+                        return Err(WithSpan {
+                            value: CodegenError::AliasShouldBeRegister(alias),
+                            span,
+                        });
+                    }
+                    AliasableThing::Register(register) => register as u8,
+                },
+                OrAlias::Concrete(reg) => reg as u8,
+            }
+        };
+    }
+
+    macro_rules! resolve_reg_or_byte {
+        ($arg:ident; $reg_name:ident => $reg_code:expr; $byte_name:ident => $byte_code:expr) => {
+            match $arg {
+                OrAlias::Alias(alias) => match *alias_map.get(alias).ok_or(WithSpan {
+                    value: CodegenError::AliasNotDefined(alias),
+                    span,
+                })? {
+                    AliasableThing::RawData(data) => {
+                        if data > 0xFF {
                             return Err(WithSpan {
-                                value: CodegenError::AliasShouldBeNumber(alias),
+                                value: CodegenError::AliasedLiteralTooBig(alias, data, 0xFF),
                                 span,
                             });
                         }
-                    },
-                    OrAlias::Concrete(addr) => addr,
-                }
-            };
+                        let $byte_name = data as u8;
+                        $byte_code
+                    }
+                    AliasableThing::Register($reg_name) => $reg_code,
+                },
+                OrAlias::Concrete(RegOrByte::Register($reg_name)) => $reg_code,
+                OrAlias::Concrete(RegOrByte::LiteralByte($byte_name)) => $byte_code,
+            }
+        };
+    }
+
+    let instruction = match instr {
+        PI::Nop => I::Nop,
+        PI::Cls => I::ClearScreen,
+        PI::Ret => I::Return,
+        PI::Low | PI::High => {
+            if target == Target::Chip8 {
+                report_warning(span, "low and high are only valid in SUPER-CHIP mode");
+            }
+
+            if instr == PI::Low {
+                I::LowRes
+            } else {
+                I::HighRes
+            }
         }
+        PI::Jmp(addr) => I::Jump(resolve_addr!(addr)),
+        PI::JmpPlus(reg, addr) => {
+            let reg = resolve_reg!(reg);
+            let addr = resolve_addr!(addr);
 
-        macro_rules! resolve_reg {
-            ($arg:ident) => {
-                match $arg {
-                    OrAlias::Alias(alias) => match *alias_map.get(alias).ok_or(WithSpan {
+            // CHIP-48 reads BXNN as a jump to XNN plus VX, so the register is
+            // always the top nibble of the address and the bytecode is the same.
+            // That means V0 only works with CHIP-48 when the address is below 0x100
+            if (reg != 0 || target == Target::Chip48) && addr >> 8 != reg as u16 {
+                return Err(WithSpan {
+                    value: CodegenError::JumpPlusAddressMismatch(reg, addr),
+                    span,
+                });
+            }
+            if reg != 0 && target == Target::Chip8 {
+                report_warning(
+                    span,
+                    "jmpp with non-V0 register is only valid in CHIP-48/SUPER-CHIP mode",
+                );
+            }
+
+            I::JumpPlusV0(addr)
+        }
+        PI::Call(addr) => I::Call(resolve_addr!(addr)),
+        PI::Se(reg, reg_or_byte) => {
+            let r1 = resolve_reg!(reg);
+            resolve_reg_or_byte!(
+                reg_or_byte;
+                r2 => I::SkipIfEqual(r1, Operand::Register(r2 as u8));
+                data => I::SkipIfEqual(r1, Operand::Literal(data))
+            )
+        }
+        PI::Sne(reg, reg_or_byte) => {
+            let r1 = resolve_reg!(reg);
+            resolve_reg_or_byte!(
+                reg_or_byte;
+                r2 => I::SkipIfNotEqual(r1, Operand::Register(r2 as u8));
+                data => I::SkipIfNotEqual(r1, Operand::Literal(data))
+            )
+        }
+        PI::Ld(reg, reg_or_byte) => {
+            let r1 = resolve_reg!(reg);
+            resolve_reg_or_byte!(
+                reg_or_byte;
+                r2 => I::LoadRegister(r1, Operand::Register(r2 as u8));
+                data => I::LoadRegister(r1, Operand::Literal(data))
+            )
+        }
+        PI::LdIndex(addr) => I::LoadMemoryRegister(resolve_addr!(addr)),
+        PI::LdFromK(reg) => I::WaitForKeyPress(resolve_reg!(reg)),
+        PI::LdFromDt(reg) => I::LoadFromDelayTimer(resolve_reg!(reg)),
+        PI::Add(r1, reg_or_byte) => {
+            let r1 = resolve_reg!(r1);
+            resolve_reg_or_byte!(
+                reg_or_byte;
+                r2 => I::AddWithCarry(r1, r2 as u8);
+                data => I::AddNoCarry(r1, data)
+            )
+        }
+        PI::AddIndex(reg) => I::AddToMemoryRegister(resolve_reg!(reg)),
+        PI::Or(r1, r2) => I::Or(resolve_reg!(r1), resolve_reg!(r2)),
+        PI::And(r1, r2) => I::And(resolve_reg!(r1), resolve_reg!(r2)),
+        PI::Xor(r1, r2) => I::Xor(resolve_reg!(r1), resolve_reg!(r2)),
+        PI::Sub(r1, r2) => I::Sub(resolve_reg!(r1), resolve_reg!(r2)),
+        PI::Subn(r1, r2) => I::SubN(resolve_reg!(r1), resolve_reg!(r2)),
+        PI::Shr(r1, r2) => I::ShiftRight(resolve_reg!(r1), resolve_reg!(r2)),
+        PI::Shl(r1, r2) => I::ShiftLeft(resolve_reg!(r1), resolve_reg!(r2)),
+        PI::Rnd(reg, mask) => {
+            let reg = resolve_reg!(reg);
+            let mask = match mask {
+                OrAlias::Alias(alias) => {
+                    match *alias_map.get(alias).ok_or(WithSpan {
                         value: CodegenError::AliasNotDefined(alias),
                         span,
                     })? {
-                        AliasableThing::RawData(_) => {
+                        AliasableThing::RawData(data) => {
+                            if data > 0xFF {
+                                return Err(WithSpan {
+                                    value: CodegenError::AliasedLiteralTooBig(alias, data, 0xFF),
+                                    span,
+                                });
+                            }
+                            data as u8
+                        }
+                        AliasableThing::Register(_) => {
                             return Err(WithSpan {
-                                value: CodegenError::AliasShouldBeRegister(alias),
+                                value: CodegenError::AliasShouldBeNumber(alias),
                                 span,
                             });
                         }
-                        AliasableThing::Register(register) => register as u8,
-                    },
-                    OrAlias::Concrete(reg) => reg as u8,
+                    }
                 }
+                OrAlias::Concrete(byte) => byte,
             };
+            I::LoadRandomWithMask(reg, mask)
         }
-
-        macro_rules! resolve_reg_or_byte {
-            ($arg:ident; $reg_name:ident => $reg_code:expr; $byte_name:ident => $byte_code:expr) => {
-                match $arg {
-                    OrAlias::Alias(alias) => match *alias_map.get(alias).ok_or(WithSpan {
+        PI::Drw(r1, r2, nibble) => {
+            let r1 = resolve_reg!(r1);
+            let r2 = resolve_reg!(r2);
+            let nibble = match nibble {
+                OrAlias::Alias(alias) => {
+                    match *alias_map.get(alias).ok_or(WithSpan {
                         value: CodegenError::AliasNotDefined(alias),
                         span,
                     })? {
                         AliasableThing::RawData(data) => {
-                            if data > 0xFF {
+                            if data > 0xF {
                                 return Err(WithSpan {
-                                    value: CodegenError::AliasedLiteralTooBig(alias, data, 0xFF),
+                                    value: CodegenError::AliasedLiteralTooBig(alias, data, 0xF),
                                     span,
                                 });
                             }
-                            let $byte_name = data as u8;
-                            $byte_code
+                            data as u8
+                        }
+                        AliasableThing::Register(_) => {
+                            return Err(WithSpan {
+                                value: CodegenError::AliasShouldBeNumber(alias),
+                                span,
+                            });
                         }
-                        AliasableThing::Register($reg_name) => $reg_code,
-                    },
-                    OrAlias::Concrete(RegOrByte::Register($reg_name)) => $reg_code,
-                    OrAlias::Concrete(RegOrByte::LiteralByte($byte_name)) => $byte_code,
+                    }
                 }
+                OrAlias::Concrete(byte) => byte,
             };
+            I::Draw(r1, r2, nibble)
         }
+        PI::Skp(reg) => I::SkipIfKeyPressed(resolve_reg!(reg)),
+        PI::Sknp(reg) => I::SkipIfKeyNotPressed(resolve_reg!(reg)),
+        PI::Delay(reg) => I::LoadIntoDelayTimer(resolve_reg!(reg)),
+        PI::Sound(reg) => I::LoadIntoSoundTimer(resolve_reg!(reg)),
+        PI::Font(reg) => I::LoadDigitAddress(resolve_reg!(reg)),
+        PI::Bcd(reg) => I::StoreBcdInMemory(resolve_reg!(reg)),
+        PI::Stor(reg) => I::StoreRegistersInMemory(resolve_reg!(reg)),
+        PI::Rstr(reg) => I::ReadRegistersFromMemory(resolve_reg!(reg)),
+    };
+    Ok(instruction)
+}
 
-        match stmt {
-            Stmt::AliasDefinition(_, _) | Stmt::Label(_) => {}
-            Stmt::RawDataDefinition(data) => blob.extend(data),
-            Stmt::PseudoInstruction(instr) => {
-                let instruction = match instr {
-                    PI::Nop => I::Nop,
-                    PI::Cls => I::ClearScreen,
-                    PI::Ret => I::Return,
-                    PI::Jmp(addr) => I::Jump(resolve_addr!(addr)),
-                    PI::JmpPlus(reg, addr) => {
-                        let reg = resolve_reg!(reg);
-                        if reg != 0 {
-                            report_error(
-                                span,
-                                "The jmpp instruction only supports jumping plus V0",
-                            );
-                            panic!("The jmpp instruction only supports jumping plus V0");
-                        }
-                        let addr = resolve_addr!(addr);
-                        I::JumpPlusV0(addr)
-                    }
-                    PI::Call(addr) => I::Call(resolve_addr!(addr)),
-                    PI::Se(reg, reg_or_byte) => {
-                        let r1 = resolve_reg!(reg);
-                        resolve_reg_or_byte!(
-                            reg_or_byte;
-                            r2 => I::SkipIfEqual(r1, Operand::Register(r2 as u8));
-                            data => I::SkipIfEqual(r1, Operand::Literal(data))
-                        )
-                    }
-                    PI::Sne(reg, reg_or_byte) => {
-                        let r1 = resolve_reg!(reg);
-                        resolve_reg_or_byte!(
-                            reg_or_byte;
-                            r2 => I::SkipIfNotEqual(r1, Operand::Register(r2 as u8));
-                            data => I::SkipIfNotEqual(r1, Operand::Literal(data))
-                        )
-                    }
-                    PI::Ld(reg, reg_or_byte) => {
-                        let r1 = resolve_reg!(reg);
-                        resolve_reg_or_byte!(
-                            reg_or_byte;
-                            r2 => I::LoadRegister(r1, Operand::Register(r2 as u8));
-                            data => I::LoadRegister(r1, Operand::Literal(data))
-                        )
-                    }
-                    PI::LdIndex(addr) => I::LoadMemoryRegister(resolve_addr!(addr)),
-                    PI::LdFromK(reg) => I::WaitForKeyPress(resolve_reg!(reg)),
-                    PI::LdFromDt(reg) => I::LoadFromDelayTimer(resolve_reg!(reg)),
-                    PI::Add(r1, reg_or_byte) => {
-                        let r1 = resolve_reg!(r1);
-                        resolve_reg_or_byte!(
-                            reg_or_byte;
-                            r2 => I::AddWithCarry(r1, r2 as u8);
-                            data => I::AddNoCarry(r1, data)
-                        )
-                    }
-                    PI::AddIndex(reg) => I::AddToMemoryRegister(resolve_reg!(reg)),
-                    PI::Or(r1, r2) => I::Or(resolve_reg!(r1), resolve_reg!(r2)),
-                    PI::And(r1, r2) => I::And(resolve_reg!(r1), resolve_reg!(r2)),
-                    PI::Xor(r1, r2) => I::Xor(resolve_reg!(r1), resolve_reg!(r2)),
-                    PI::Sub(r1, r2) => I::Sub(resolve_reg!(r1), resolve_reg!(r2)),
-                    PI::Subn(r1, r2) => I::SubN(resolve_reg!(r1), resolve_reg!(r2)),
-                    PI::Shr(reg) => I::ShiftRight(resolve_reg!(reg)),
-                    PI::Shl(reg) => I::ShiftLeft(resolve_reg!(reg)),
-                    PI::Rnd(reg, mask) => {
-                        let reg = resolve_reg!(reg);
-                        let mask = match mask {
-                            OrAlias::Alias(alias) => {
-                                match *alias_map.get(alias).ok_or(WithSpan {
-                                    value: CodegenError::AliasNotDefined(alias),
-                                    span,
-                                })? {
-                                    AliasableThing::RawData(data) => {
-                                        if data > 0xFF {
-                                            return Err(WithSpan {
-                                                value: CodegenError::AliasedLiteralTooBig(
-                                                    alias, data, 0xFF,
-                                                ),
-                                                span,
-                                            });
-                                        }
-                                        data as u8
-                                    }
-                                    AliasableThing::Register(_) => {
-                                        return Err(WithSpan {
-                                            value: CodegenError::AliasShouldBeNumber(alias),
-                                            span,
-                                        });
-                                    }
-                                }
-                            }
-                            OrAlias::Concrete(byte) => byte,
-                        };
-                        I::LoadRandomWithMask(reg, mask)
-                    }
-                    PI::Drw(r1, r2, nibble) => {
-                        let r1 = resolve_reg!(r1);
-                        let r2 = resolve_reg!(r2);
-                        let nibble = match nibble {
-                            OrAlias::Alias(alias) => {
-                                match *alias_map.get(alias).ok_or(WithSpan {
-                                    value: CodegenError::AliasNotDefined(alias),
-                                    span,
-                                })? {
-                                    AliasableThing::RawData(data) => {
-                                        if data > 0xF {
-                                            return Err(WithSpan {
-                                                value: CodegenError::AliasedLiteralTooBig(
-                                                    alias, data, 0xF,
-                                                ),
-                                                span,
-                                            });
-                                        }
-                                        data as u8
-                                    }
-                                    AliasableThing::Register(_) => {
-                                        return Err(WithSpan {
-                                            value: CodegenError::AliasShouldBeNumber(alias),
-                                            span,
-                                        });
-                                    }
-                                }
-                            }
-                            OrAlias::Concrete(byte) => byte,
-                        };
-                        I::Draw(r1, r2, nibble)
-                    }
-                    PI::Skp(reg) => I::SkipIfKeyPressed(resolve_reg!(reg)),
-                    PI::Sknp(reg) => I::SkipIfKeyNotPressed(resolve_reg!(reg)),
-                    PI::Delay(reg) => I::LoadIntoDelayTimer(resolve_reg!(reg)),
-                    PI::Sound(reg) => I::LoadIntoSoundTimer(resolve_reg!(reg)),
-                    PI::Font(reg) => I::LoadDigitAddress(resolve_reg!(reg)),
-                    PI::Bcd(reg) => I::StoreBcdInMemory(resolve_reg!(reg)),
-                    PI::Stor(reg) => I::StoreRegistersInMemory(resolve_reg!(reg)),
-                    PI::Rstr(reg) => I::ReadRegistersFromMemory(resolve_reg!(reg)),
-                };
-                blob.extend(encode(instruction).map_err(|encoding_error| WithSpan {
-                    value: CodegenError::EncodingError(encoding_error),
-                    span,
-                })?);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        error::{init_error_reporting, quietly},
+        parser::Parser,
+        scanner::Scanner,
+        span::Span,
+    };
+    use std::{env, path::PathBuf};
+
+    /// Assemble the given source code for CHIP-8, resolving any files relative to `base_dir`.
+    /// Any errors are returned without their spans.
+    ///
+    /// The maximum ROM size is all of memory, so that big ROMs don't print a warning about the
+    /// size, since that would need [`init_error_reporting`].
+    fn assemble<'s>(source: &'s str, base_dir: &Path) -> Result<Vec<u8>, Vec<CodegenError<'s>>> {
+        codegen(
+            Parser::parse(Scanner::scan_tokens(source)),
+            base_dir,
+            Target::Chip8,
+            0x200,
+            0x300,
+            false,
+            0x1000,
+        )
+        .map(|segments| segments.code)
+        .map_err(without_spans)
+    }
+
+    /// Assemble the given source code like [`assemble`], after expanding its includes as if it's
+    /// `main.asm` in `dir`. The source code of the included files is kept in `sources`.
+    fn assemble_with_includes<'s>(
+        source: &'s str,
+        dir: &Path,
+        sources: &'s Arena<String>,
+    ) -> Result<Vec<u8>, Vec<CodegenError<'s>>> {
+        let statements = Parser::parse(Scanner::scan_tokens(source));
+        let statements =
+            expand_includes(statements, &dir.join("main.asm"), sources).map_err(without_spans)?;
+        codegen(statements, dir, Target::Chip8, 0x200, 0x300, false, 0x1000)
+            .map(|segments| segments.code)
+            .map_err(without_spans)
+    }
+
+    /// Strip the spans from a list of errors.
+    fn without_spans<'s>(errors: Vec<WithSpan<CodegenError<'s>>>) -> Vec<CodegenError<'s>> {
+        errors.into_iter().map(|error| error.value).collect()
+    }
+
+    /// Create a fresh temporary directory for a test.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("ch8a-{}-{name}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn include_binary_test() {
+        let dir = temp_dir("incbin");
+        fs::write(dir.join("sprite.bin"), [0x3C, 0x42, 0x81]).unwrap();
+
+        let source = "ld i, sprite\ncls\nsprite:\nincbin \"sprite.bin\"\nend: jmp end\n";
+        assert_eq!(
+            assemble(source, &dir).unwrap(),
+            [0xA2, 0x04, 0x00, 0xE0, 0x3C, 0x42, 0x81, 0x12, 0x07]
+        );
+
+        // The path keeps its case, even though everything else is lowercased
+        fs::write(dir.join("Sprite.BIN"), [0x18, 0x24]).unwrap();
+        let source = "INCBIN \"Sprite.BIN\"\n";
+        let lowercase = source.to_ascii_lowercase();
+        let code = codegen(
+            Parser::parse(Scanner::scan_tokens_from(&lowercase, source, 0)),
+            &dir,
+            Target::Chip8,
+            0x200,
+            0x300,
+            false,
+            0x1000,
+        )
+        .unwrap()
+        .code;
+        assert_eq!(code, [0x18, 0x24]);
+
+        // Other strings are lowercased like before, so `text` data doesn't change
+        let source = "TEXT \"Hi\"\n";
+        let lowercase = source.to_ascii_lowercase();
+        let code = codegen(
+            Parser::parse(Scanner::scan_tokens_from(&lowercase, source, 0)),
+            &dir,
+            Target::Chip8,
+            0x200,
+            0x300,
+            false,
+            0x1000,
+        )
+        .unwrap()
+        .code;
+        assert_eq!(code, b"hi");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn include_binary_error_test() {
+        let dir = temp_dir("incbin-error");
+        fs::write(dir.join("big.bin"), [0xFF; 0xE00]).unwrap();
+
+        assert_eq!(assemble("incbin \"big.bin\"", &dir).unwrap(), [0xFF; 0xE00]);
+        assert_eq!(
+            assemble("cls\nincbin \"big.bin\"", &dir),
+            Err(vec![CodegenError::IncludeBinaryTooBig(
+                "big.bin", 0xE00, 0xDFE
+            )])
+        );
+        assert_eq!(
+            assemble("incbin \"missing.bin\"", &dir),
+            Err(vec![CodegenError::IncludeBinaryReadFailed(
+                "missing.bin",
+                io::ErrorKind::NotFound
+            )])
+        );
+
+        // We don't know where anything after a missing binary goes, so we stop after the first pass
+        assert_eq!(
+            assemble("incbin \"missing.bin\"\njmp nowhere\n", &dir),
+            Err(vec![CodegenError::IncludeBinaryReadFailed(
+                "missing.bin",
+                io::ErrorKind::NotFound
+            )])
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn include_test() {
+        let dir = temp_dir("include");
+        fs::create_dir_all(dir.join("Lib")).unwrap();
+        fs::write(
+            dir.join("Lib/Helper.asm"),
+            "define counter v3\nincrement:\n\tadd counter, 1\n\tRET\ninclude \"Sprite.asm\"\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.join("Lib/Sprite.asm"),
+            "sprite:\nincbin \"sprite.bin\"\n",
+        )
+        .unwrap();
+        fs::write(dir.join("Lib/sprite.bin"), [0x3C, 0x42]).unwrap();
+
+        // The included file is assembled in place, and its labels and aliases can be used before
+        // and after the include, with files and binaries relative to the file that includes them.
+        // Paths keep their case, even though everything else is lowercased
+        let sources = Arena::new();
+        let source = "call increment\nld counter, 0\nend: jmp end\ninclude \"Lib/Helper.asm\"\n";
+        let lowercase = source.to_ascii_lowercase();
+        let statements = Parser::parse(Scanner::scan_tokens_from(&lowercase, source, 0));
+        let statements = expand_includes(statements, &dir.join("main.asm"), &sources).unwrap();
+        assert_eq!(
+            codegen(statements, &dir, Target::Chip8, 0x200, 0x300, false, 0x1000)
+                .unwrap()
+                .code,
+            [0x22, 0x06, 0x63, 0x00, 0x12, 0x04, 0x73, 0x01, 0x00, 0xEE, 0x3C, 0x42]
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn include_error_test() {
+        let dir = temp_dir("include-error");
+        fs::write(dir.join("main.asm"), "include \"main.asm\"\n").unwrap();
+        fs::write(dir.join("a.asm"), "cls\ninclude \"b.asm\"\n").unwrap();
+        fs::write(dir.join("b.asm"), "include \"a.asm\"\n").unwrap();
+        fs::write(dir.join("invalid.asm"), "cls v0\n").unwrap();
+        fs::write(dir.join("undefined.asm"), "cls\njmp nowhere\n").unwrap();
+
+        let sources = Arena::new();
+        assert_eq!(
+            assemble_with_includes("include \"a.asm\"", &dir, &sources),
+            Err(vec![CodegenError::IncludeCycle("a.asm")])
+        );
+        assert_eq!(
+            assemble_with_includes("include \"main.asm\"", &dir, &sources),
+            Err(vec![CodegenError::IncludeCycle("main.asm")])
+        );
+        assert_eq!(
+            assemble_with_includes("include \"missing.asm\"", &dir, &sources),
+            Err(vec![CodegenError::IncludeReadFailed(
+                "missing.asm",
+                io::ErrorKind::NotFound
+            )])
+        );
+        assert_eq!(
+            assemble_with_includes("include \"invalid.asm\"", &dir, &sources),
+            Err(vec![CodegenError::IncludeInvalid("invalid.asm")])
+        );
+
+        // Errors in the included code are reported in the included file
+        let statements = Parser::parse(Scanner::scan_tokens("cls\ninclude \"undefined.asm\""));
+        let statements = expand_includes(statements, &dir.join("main.asm"), &sources).unwrap();
+        let errors = codegen(
+            statements,
+            &dir,
+            Target::Chip8,
+            0x200,
+            0x300,
+            false,
+            DEFAULT_MAX_ROM_SIZE,
+        )
+        .unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].value, CodegenError::AliasNotDefined("nowhere"));
+        assert_eq!((errors[0].span.start, errors[0].span.end), (4, 14));
+        assert_ne!(errors[0].span.file, 0);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn jump_plus_test() {
+        let dir = Path::new("");
+
+        assert_eq!(assemble("jmpp v0, #345", dir).unwrap(), [0xB3, 0x45]);
+
+        for target in [Target::Chip8, Target::Chip48] {
+            let assemble = |source: &'static str| {
+                // Needed to print the warning for Target::Chip8
+                init_error_reporting(source.to_string());
+                codegen(
+                    Parser::parse(Scanner::scan_tokens(source)),
+                    dir,
+                    target,
+                    0x200,
+                    0x300,
+                    false,
+                    DEFAULT_MAX_ROM_SIZE,
+                )
+                .map(|segments| segments.code)
+                .map_err(without_spans)
+            };
+
+            assert_eq!(assemble("jmpp v3, #345"), Ok(vec![0xB3, 0x45]));
+            assert_eq!(
+                assemble("jmpp v2, #345"),
+                Err(vec![CodegenError::JumpPlusAddressMismatch(2, 0x345)])
+            );
+            assert_eq!(assemble("jmpp v0, #045"), Ok(vec![0xB0, 0x45]));
+
+            // CHIP-48 would jump to 0x345 + V3, not 0x345 + V0
+            let expected = match target {
+                Target::Chip8 => Ok(vec![0xB3, 0x45]),
+                Target::Chip48 => Err(vec![CodegenError::JumpPlusAddressMismatch(0, 0x345)]),
+            };
+            assert_eq!(assemble("jmpp v0, #345"), expected);
+        }
+    }
+
+    #[test]
+    fn shift_test() {
+        // `shr vx` uses VX as both registers, so it shifts VX whatever the shift quirk is
+        let source = "define reg v3\nshr v1\nshl v1, v2\nshr v1 reg\nshl reg\nlabel:\nshr reg\n";
+        assert_eq!(
+            assemble(source, Path::new("")).unwrap(),
+            [0x81, 0x16, 0x81, 0x2E, 0x81, 0x36, 0x83, 0x3E, 0x83, 0x36]
+        );
+    }
+
+    #[test]
+    fn graphics_mode_test() {
+        let source = "high\ncls\nlow\n";
+        for target in [Target::Chip8, Target::Chip48] {
+            // Needed to print the warning for Target::Chip8
+            init_error_reporting(source.to_string());
+            let code = codegen(
+                Parser::parse(Scanner::scan_tokens(source)),
+                Path::new(""),
+                target,
+                0x200,
+                0x300,
+                false,
+                DEFAULT_MAX_ROM_SIZE,
+            )
+            .unwrap()
+            .code;
+            assert_eq!(code, [0x00, 0xFF, 0x00, 0xE0, 0x00, 0xFE]);
+        }
+    }
+
+    #[test]
+    fn origin_test() {
+        let source = "jmp start\nstart:\nld i, sprite\nsprite:\ndb #ff\n";
+        let assemble = |origin| {
+            codegen(
+                Parser::parse(Scanner::scan_tokens(source)),
+                Path::new(""),
+                Target::Chip8,
+                origin,
+                0x300,
+                false,
+                DEFAULT_MAX_ROM_SIZE,
+            )
+            .unwrap()
+            .code
+        };
+
+        assert_eq!(assemble(0x200), [0x12, 0x02, 0xA2, 0x04, 0xFF]);
+        assert_eq!(assemble(0x000), [0x10, 0x02, 0xA0, 0x04, 0xFF]);
+        assert_eq!(assemble(0x600), [0x16, 0x02, 0xA6, 0x04, 0xFF]);
+    }
+
+    #[test]
+    fn address_overflow_test() {
+        let assemble = |source: &'static str, origin| {
+            codegen(
+                Parser::parse(Scanner::scan_tokens(source)),
+                Path::new(""),
+                Target::Chip8,
+                origin,
+                0x300,
+                false,
+                u16::MAX,
+            )
+            .map(|segments| segments.code)
+            .map_err(without_spans)
+        };
+
+        assert_eq!(assemble("cls\n", 0xFFFD), Ok(vec![0x00, 0xE0]));
+        assert_eq!(
+            assemble("cls\ncls\njmp nowhere\n", 0xFFFB),
+            Err(vec![CodegenError::AddressOverflow(0xFFFF)])
+        );
+        assert_eq!(
+            assemble("db #01 #02 #03\n", 0xFFFE),
+            Err(vec![CodegenError::AddressOverflow(0xFFFE)])
+        );
+    }
+
+    #[test]
+    fn alias_of_label_test() {
+        let dir = Path::new("");
+
+        let source = "define handler loop_label\ncall handler\nloop_label: jmp loop_label\n";
+        assert_eq!(assemble(source, dir).unwrap(), [0x22, 0x02, 0x12, 0x02]);
+
+        let source = "define a b\ndefine b c\ndefine c v3\nld a, 1\n";
+        assert_eq!(assemble(source, dir).unwrap(), [0x63, 0x01]);
+
+        let errors = [
+            (
+                "define a b\ncls\n",
+                vec![CodegenError::AliasNotDefined("b")],
+            ),
+            (
+                "define a b\ndefine b a\ncls\n",
+                vec![CodegenError::AliasCycle("a"), CodegenError::AliasCycle("b")],
+            ),
+            ("define a a\ncls\n", vec![CodegenError::AliasCycle("a")]),
+            (
+                "define a 1\ndefine a b\nb: cls\n",
+                vec![CodegenError::AliasAlreadyDefined("a")],
+            ),
+            (
+                "define a b\na: cls\nb: cls\n",
+                vec![CodegenError::LabelAlreadyDefined("a")],
+            ),
+        ];
+        for (source, errors) in errors {
+            assert_eq!(assemble(source, dir), Err(errors), "{source:?}");
+        }
+    }
+
+    #[test]
+    fn alias_chain_too_long_test() {
+        let span = Span {
+            start: 0,
+            end: 0,
+            file: 0,
+        };
+        let pending = |links: &[(&'static str, &'static str)]| {
+            links
+                .iter()
+                .map(|&value| WithSpan { span, value })
+                .collect::<Vec<_>>()
+        };
+
+        let mut alias_map = HashMap::from([("d", AliasableThing::RawData(5))]);
+        let chain = pending(&[("a", "b"), ("b", "c"), ("c", "d")]);
+        assert_eq!(
+            resolve_alias_chains(&mut alias_map.clone(), chain.clone(), 2).map_err(without_spans),
+            Err(vec![CodegenError::AliasChainTooLong("a", 2)])
+        );
+
+        assert_eq!(resolve_alias_chains(&mut alias_map, chain, 3), Ok(()));
+        assert_eq!(alias_map["a"], AliasableThing::RawData(5));
+    }
+
+    #[test]
+    fn strict_test() {
+        let source = "drw v1, v2, 0\nrnd v3, 0\nor v4, v4\nxor v5, v5\n";
+        init_error_reporting(source.to_string());
+
+        // Strict mode only adds warnings, so the output is the same
+        for strict in [false, true] {
+            assert_eq!(
+                codegen(
+                    Parser::parse(Scanner::scan_tokens(source)),
+                    Path::new(""),
+                    Target::Chip8,
+                    0x200,
+                    0x300,
+                    strict,
+                    DEFAULT_MAX_ROM_SIZE,
+                )
+                .map(|segments| segments.code),
+                Ok(vec![0xD1, 0x20, 0xC3, 0x00, 0x84, 0x41, 0x85, 0x53])
+            );
+        }
+    }
+
+    #[test]
+    fn multiple_errors_test() {
+        let source = "jmp nowhere\nb: cls\nb: cls\nld missing, 1\nld v1, b\nend: jmp end\n";
+        assert_eq!(
+            assemble(source, Path::new("")),
+            Err(vec![
+                CodegenError::LabelAlreadyDefined("b"),
+                CodegenError::AliasNotDefined("nowhere"),
+                CodegenError::AliasNotDefined("missing"),
+                CodegenError::AliasedLiteralTooBig("b", 0x204, 0xFF),
+            ])
+        );
+    }
+
+    #[test]
+    fn segments_test() {
+        let source = "ld i, sprite\n.segment data\nsprite: db #ff #81\n.segment code\njmp sprite\n";
+        let assemble = |data_origin| {
+            codegen(
+                Parser::parse(Scanner::scan_tokens(source)),
+                Path::new(""),
+                Target::Chip8,
+                0x200,
+                data_origin,
+                false,
+                DEFAULT_MAX_ROM_SIZE,
+            )
+            .map_err(without_spans)
+        };
+
+        let segments = assemble(0x300).unwrap();
+        assert_eq!(
+            segments,
+            Segments {
+                code: vec![0xA3, 0x00, 0x13, 0x00],
+                data: vec![0xFF, 0x81],
+                instructions: vec![
+                    (0x200, I::LoadMemoryRegister(0x300)),
+                    (0x202, I::Jump(0x300)),
+                ],
+                symbols: HashMap::from([("sprite".to_string(), AliasableThing::RawData(0x300))]),
+            }
+        );
+
+        let mut combined = vec![0xA3, 0x00, 0x13, 0x00];
+        combined.extend([0; 0xFC]);
+        combined.extend([0xFF, 0x81]);
+        assert_eq!(segments.combined(0x200, 0x300), Some(combined));
+        assert_eq!(segments.combined(0x200, 0x100), None);
+
+        assert_eq!(assemble(0x100).unwrap().combined(0x200, 0x100), None);
+        assert_eq!(
+            assemble(0x202),
+            Err(vec![CodegenError::SegmentsOverlap(
+                0x200, 0x204, 0x202, 0x204
+            )])
+        );
+
+        // Without any data, the code is the whole ROM
+        let code_only = Segments {
+            code: vec![0x12, 0x00],
+            data: vec![],
+            instructions: vec![(0x200, I::Jump(0x200))],
+            symbols: HashMap::new(),
+        };
+        assert_eq!(code_only.combined(0x200, 0x100), Some(vec![0x12, 0x00]));
+    }
+
+    #[test]
+    fn display_roundtrip_test() {
+        use chip8_instructions::{decode, OpcodeCategory as C};
+
+        let categories = [
+            C::System,
+            C::Jump,
+            C::Call,
+            C::SkipIfEqualLiteral,
+            C::SkipIfNotEqualLiteral,
+            C::SkipIfEqualRegister,
+            C::LoadLiteral,
+            C::AddLiteral,
+            C::Arithmetic,
+            C::SkipIfNotEqualRegister,
+            C::LoadMemoryRegister,
+            C::JumpPlusV0,
+            C::Random,
+            C::Draw,
+            C::Keys,
+            C::Misc,
+        ];
+        // Most SUPER-CHIP instructions have no mnemonic in the assembler
+        let instructions: Vec<_> = categories
+            .into_iter()
+            .flat_map(C::instructions)
+            .filter(|&instruction| PI::try_from(instruction).is_ok())
+            .collect();
+
+        // Assemble the instructions in small chunks, since each ROM has to fit in memory and
+        // assembling gets slow for very long sources
+        for chunk in instructions.chunks(0x40) {
+            let source: String = chunk
+                .iter()
+                .map(|instruction| format!("{instruction}\n"))
+                .collect();
+            // High and low resolution modes give warnings when targeting CHIP-8
+            let code = quietly(|| assemble(&source, Path::new("")))
+                .unwrap_or_else(|errors| panic!("Failed to assemble: {errors:?}"));
+            assert_eq!(code.len(), chunk.len() * 2);
+
+            for (&instruction, bytes) in chunk.iter().zip(code.chunks(2)) {
+                assert_eq!(
+                    decode([bytes[0], bytes[1]]),
+                    Ok(instruction),
+                    "{instruction}"
+                );
             }
-            Stmt::Include(_) => {} // We already emitted an error on the first pass
         }
     }
 
-    Ok(blob)
+    #[test]
+    fn max_rom_size_test() {
+        let assemble = |source: &'static str, max_rom_size| {
+            // Needed to print the warning for ROMs that are close to the limit
+            init_error_reporting(source.to_string());
+            codegen(
+                Parser::parse(Scanner::scan_tokens(source)),
+                Path::new(""),
+                Target::Chip8,
+                0x200,
+                0x300,
+                false,
+                max_rom_size,
+            )
+            .map(|segments| segments.code.len())
+        };
+
+        assert_eq!(assemble("cls\ncls\ncls\ncls\ncls\n", 10), Ok(10));
+        assert_eq!(
+            assemble("cls\ncls\ncls\ncls\ncls\ncls\n", 10),
+            Err(vec![WithSpan {
+                value: CodegenError::RomTooBig(12, 10),
+                span: Span {
+                    start: 20,
+                    end: 22,
+                    file: 0
+                },
+            }])
+        );
+
+        // The gap between the segments counts towards the size
+        assert_eq!(
+            assemble("cls\n.segment data\ndb #ff\n", 0x100).map_err(without_spans),
+            Err(vec![CodegenError::RomTooBig(0x101, 0x100)])
+        );
+        assert_eq!(assemble("cls\n.segment data\ndb #ff\n", 0x101), Ok(2));
+    }
 }
@@ -6,7 +6,10 @@ use crossterm::{
     style::{Attribute, Color, Print, ResetColor, SetAttribute, SetForegroundColor},
 };
 use lazy_static::lazy_static;
+#[cfg(feature = "sarif")]
+use std::collections::HashMap;
 use std::{
+    cell::Cell,
     cmp,
     sync::{
         atomic::{AtomicBool, Ordering},
@@ -17,48 +20,270 @@ use std::{
 /// Have we encountered at least one error before runtime?
 pub static HAD_ERROR: AtomicBool = AtomicBool::new(false);
 
+/// A file of source code that spans can point into.
+struct SourceFile {
+    /// The path of the file, or an empty string for the main file.
+    name: String,
+
+    /// The source code in the file.
+    code: String,
+
+    /// The line offsets of the source code.
+    line_offsets: LineOffsets,
+}
+
+impl SourceFile {
+    /// Create a source file with the given name and code.
+    fn new(name: String, code: String) -> Self {
+        Self {
+            line_offsets: LineOffsets::new(&code),
+            name,
+            code,
+        }
+    }
+}
+
 lazy_static! {
-    /// The LineOffsets of the code being worked with.
-    static ref LINE_OFFSETS: RwLock<LineOffsets> = RwLock::new(LineOffsets::new(""));
+    /// The source code that we're working with, indexed by [`Span::file`]. The main file is
+    /// always first.
+    static ref SOURCE_FILES: RwLock<Vec<SourceFile>> =
+        RwLock::new(vec![SourceFile::new(String::new(), String::new())]);
+}
 
-    /// The source code that we're working with.
-    static ref SOURCE_CODE: RwLock<String> = RwLock::new(String::new());
+#[cfg(feature = "sarif")]
+lazy_static! {
+    /// Collects the errors and warnings as SARIF instead of printing them, if it's been started.
+    static ref SARIF_REPORTER: RwLock<Option<SarifReporter>> = RwLock::new(None);
+}
+
+thread_local! {
+    /// Are we ignoring errors and warnings on this thread?
+    static QUIET: Cell<bool> = const { Cell::new(false) };
+
+    /// Did anything try to report an error on this thread while we were checking for them?
+    static REPORTED_ERROR: Cell<bool> = const { Cell::new(false) };
 }
 
-/// Initialise the error reporting with the given source code.
+/// Run the given function without reporting any errors or warnings, like when we run part of the
+/// assembler again and don't want to report everything twice.
+pub fn quietly<T>(f: impl FnOnce() -> T) -> T {
+    let was_quiet = QUIET.replace(true);
+    let result = f();
+    QUIET.set(was_quiet);
+    result
+}
+
+/// Run the given function, and also return whether it tried to report any errors, even if they
+/// were ignored with [`quietly`].
+pub fn checking_errors<T>(f: impl FnOnce() -> T) -> (T, bool) {
+    let outer_reported_error = REPORTED_ERROR.replace(false);
+    let result = f();
+    let had_error = REPORTED_ERROR.get();
+    REPORTED_ERROR.set(outer_reported_error || had_error);
+    (result, had_error)
+}
+
+/// Initialise the error reporting with the given source code for the main file.
 pub fn init_error_reporting(code: String) {
-    *LINE_OFFSETS.write().unwrap() = LineOffsets::new(&code);
-    *SOURCE_CODE.write().unwrap() = code;
+    SOURCE_FILES.write().unwrap()[0] = SourceFile::new(String::new(), code);
+}
+
+/// Add the source code of an included file at the given path, so that errors can be reported in
+/// it. The code should be unmodified, since SARIF columns count the tabs that are expanded before
+/// scanning. Returns the number of the file to use in its spans.
+pub fn add_source_file(path: String, code: &str) -> usize {
+    let mut files = SOURCE_FILES.write().unwrap();
+    let file = files.len();
+
+    #[cfg(feature = "sarif")]
+    if let Some(reporter) = SARIF_REPORTER.write().unwrap().as_mut() {
+        reporter.add_file(file, &path, code);
+    }
+
+    files.push(SourceFile::new(path, code.replace("\t", "    ")));
+    file
+}
+
+/// The formats that errors and warnings can be reported in.
+#[cfg(feature = "sarif")]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum MessageFormat {
+    /// Coloured messages on stderr, with the source code highlighted.
+    #[default]
+    Human,
+
+    /// SARIF JSON on stdout, for IDEs.
+    Sarif,
+}
+
+/// Collect every error and warning from now on as SARIF for the file at `uri`, instead of
+/// printing them. The code should be unmodified, like in [`add_source_file`]. Get the SARIF with
+/// [`finish_sarif_reporting`].
+#[cfg(feature = "sarif")]
+pub fn start_sarif_reporting(uri: &str, code: &str) {
+    *SARIF_REPORTER.write().unwrap() = Some(SarifReporter::new(uri, code));
+}
+
+/// Stop collecting errors and warnings as SARIF, and get the SARIF JSON of everything that was
+/// reported, if [`start_sarif_reporting`] was called.
+#[cfg(feature = "sarif")]
+pub fn finish_sarif_reporting() -> Option<String> {
+    SARIF_REPORTER
+        .write()
+        .unwrap()
+        .take()
+        .map(|reporter| reporter.to_json())
 }
 
 /// Report an error.
 pub fn report_error(span: Span, message: &str) {
-    print_error_message(Some(span), message);
+    REPORTED_ERROR.set(true);
+    if QUIET.get() {
+        return;
+    }
+    report_message(span, message, Color::Red, "ERROR");
     HAD_ERROR.store(true, Ordering::Relaxed);
 }
 
-/// Print the given error message.
-fn print_error_message(span: Option<Span>, message: &str) {
-    let highlight_color = Color::Red;
-    let severity_name = "ERROR";
+/// Report a warning. Unlike errors, warnings don't stop the assembler from producing output.
+pub fn report_warning(span: Span, message: &str) {
+    if QUIET.get() {
+        return;
+    }
+    report_message(span, message, Color::Yellow, "WARNING");
+}
+
+/// Print the given message like [`print_message`], or add it to the SARIF if we're collecting
+/// that instead.
+fn report_message(span: Span, message: &str, highlight_color: Color, severity_name: &str) {
+    #[cfg(feature = "sarif")]
+    if let Some(reporter) = SARIF_REPORTER.write().unwrap().as_mut() {
+        reporter.report(span, message, &severity_name.to_ascii_lowercase());
+        return;
+    }
 
+    print_message(Some(span), message, highlight_color, severity_name);
+}
+
+/// Collects errors and warnings in the Static Analysis Results Interchange Format (SARIF), which
+/// IDEs can read. See <https://docs.oasis-open.org/sarif/sarif/v2.1.0/sarif-v2.1.0.html>.
+#[cfg(feature = "sarif")]
+pub struct SarifReporter {
+    /// The URI, line offsets, and unmodified source code of each file by [`Span::file`], for
+    /// turning spans into locations.
+    files: HashMap<usize, (String, LineOffsets, String)>,
+
+    /// The SARIF `result` objects for everything that's been reported.
+    results: Vec<serde_json::Value>,
+}
+
+#[cfg(feature = "sarif")]
+impl SarifReporter {
+    /// Create a reporter with no results for the given unmodified source code, which is in the
+    /// file at `uri`.
+    pub fn new(uri: &str, code: &str) -> Self {
+        let mut reporter = Self {
+            files: HashMap::new(),
+            results: Vec::new(),
+        };
+        reporter.add_file(0, uri, code);
+        reporter
+    }
+
+    /// Add the unmodified source code of an included file at `uri`, which spans refer to with the
+    /// given file number.
+    pub fn add_file(&mut self, file: usize, uri: &str, code: &str) {
+        // Spans point into the code after its tabs are expanded
+        let line_offsets = LineOffsets::new(&code.replace("\t", "    "));
+        self.files
+            .insert(file, (uri.to_string(), line_offsets, code.to_string()));
+    }
+
+    /// Add a result with the given level, which should be `error` or `warning`.
+    pub fn report(&mut self, span: Span, message: &str, level: &str) {
+        let (uri, line_offsets, code) = &self.files[&span.file];
+        let (start_line, start_nl) = line_offsets.line_and_newline_offset(span.start);
+        let (end_line, end_nl) = line_offsets.line_and_newline_offset(span.end);
+
+        // Expanding tabs doesn't change the lines, only the columns
+        let column = |line: usize, expanded_offset: usize| {
+            unexpanded_column(code.lines().nth(line - 1).unwrap_or(""), expanded_offset)
+        };
+
+        // Our spans include their last character, but SARIF regions don't
+        self.results.push(serde_json::json!({
+            "ruleId": format!("ch8a/{level}"),
+            "level": level,
+            "message": { "text": message },
+            "locations": [{
+                "physicalLocation": {
+                    "artifactLocation": { "uri": uri },
+                    "region": {
+                        "startLine": start_line,
+                        "startColumn": column(start_line, span.start - start_nl),
+                        "endLine": end_line,
+                        "endColumn": column(end_line, span.end - end_nl) + 1,
+                    },
+                },
+            }],
+        }));
+    }
+
+    /// Get the SARIF log of everything that's been reported, as JSON.
+    pub fn to_json(&self) -> String {
+        let log = serde_json::json!({
+            "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+            "version": "2.1.0",
+            "runs": [{
+                "tool": {
+                    "driver": {
+                        "name": env!("CARGO_PKG_NAME"),
+                        "version": env!("CARGO_PKG_VERSION"),
+                    },
+                },
+                "results": self.results,
+            }],
+        });
+        serde_json::to_string_pretty(&log).expect("SARIF should always serialize")
+    }
+}
+
+/// Find the column, counting from 1, of the character in the given line that's at the given offset
+/// after each tab in the line is expanded to 4 spaces.
+#[cfg(feature = "sarif")]
+fn unexpanded_column(line: &str, expanded_offset: usize) -> usize {
+    let mut width = 0;
+    for (column, c) in line.chars().enumerate() {
+        if width >= expanded_offset {
+            return column + 1;
+        }
+        width += if c == '\t' { 4 } else { 1 };
+    }
+    line.chars().count() + 1
+}
+
+/// Print the given message, highlighted in the given color and labelled with the given severity.
+fn print_message(span: Option<Span>, message: &str, highlight_color: Color, severity_name: &str) {
     let message = if let Some(span) = span {
-        let (start_line, start_nl) = LINE_OFFSETS
-            .read()
-            .unwrap()
-            .line_and_newline_offset(span.start);
-        let (end_line, end_nl) = LINE_OFFSETS
-            .read()
-            .unwrap()
-            .line_and_newline_offset(span.end);
+        let files = SOURCE_FILES.read().unwrap();
+        let file = &files[span.file];
+        let (start_line, start_nl) = file.line_offsets.line_and_newline_offset(span.start);
+        let (end_line, end_nl) = file.line_offsets.line_and_newline_offset(span.end);
         let start_col = span.start - start_nl + 1;
         let end_col = span.end - end_nl + 1;
         let line_number_width =
             cmp::max(start_line.to_string().len(), end_line.to_string().len()) + 1;
 
+        // Included files are named, but the main file isn't
+        let location = if file.name.is_empty() {
+            format!("{start_line}:{start_col}")
+        } else {
+            format!("{}:{start_line}:{start_col}", file.name)
+        };
+
         let mut message = format!(": {message}\n");
         message.push_str(&format!(
-            "{:width$}{}{}-->{}{} {start_line}:{start_col}\n",
+            "{:width$}{}{}-->{}{} {location}\n",
             "",
             SetForegroundColor(Color::Blue),
             Attribute::Bold,
@@ -86,9 +311,7 @@ fn print_error_message(span: Option<Span>, message: &str) {
                 width = line_number_width - start_line.to_string().len(),
             ));
             message.push_str(
-                SOURCE_CODE
-                    .read()
-                    .unwrap()
+                file.code
                     .lines()
                     .nth(start_line.saturating_sub(1))
                     .unwrap_or(""),
@@ -127,7 +350,7 @@ fn print_error_message(span: Option<Span>, message: &str) {
                 ));
             }
         } else {
-            let source_code_text = SOURCE_CODE.read().unwrap();
+            let source_code_text = &file.code;
 
             for line in start_line..=end_line {
                 let line_text = source_code_text
@@ -208,5 +431,118 @@ fn print_error_message(span: Option<Span>, message: &str) {
         SetAttribute(Attribute::Reset),
         Print(message)
     )
-    .expect("Should be able to print error messages with crossterm");
+    .expect("Should be able to print messages with crossterm");
+}
+
+#[cfg(all(test, feature = "sarif"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sarif_test() {
+        let mut reporter = SarifReporter::new("test.ch8a", "cls\nld v1, 300\nxor v2,\n  v2\n");
+        reporter.report(
+            Span {
+                start: 11,
+                end: 13,
+                file: 0,
+            },
+            "This number is too big",
+            "error",
+        );
+        reporter.report(
+            Span {
+                start: 15,
+                end: 26,
+                file: 0,
+            },
+            "This does nothing",
+            "warning",
+        );
+        reporter.add_file(3, "lib/helper.ch8a", "cls\nret v1\n");
+        reporter.report(
+            Span {
+                start: 4,
+                end: 9,
+                file: 3,
+            },
+            "Unexpected register",
+            "error",
+        );
+
+        let sarif: serde_json::Value = serde_json::from_str(&reporter.to_json()).unwrap();
+        assert_eq!(sarif["version"], "2.1.0");
+
+        let run = &sarif["runs"][0];
+        assert_eq!(run["tool"]["driver"]["name"], "ch8a");
+
+        let results = run["results"].as_array().unwrap();
+        assert_eq!(results.len(), 3);
+
+        assert_eq!(results[0]["ruleId"], "ch8a/error");
+        assert_eq!(results[0]["level"], "error");
+        assert_eq!(results[0]["message"]["text"], "This number is too big");
+        let location = &results[0]["locations"][0]["physicalLocation"];
+        assert_eq!(location["artifactLocation"]["uri"], "test.ch8a");
+        assert_eq!(
+            location["region"],
+            serde_json::json!({
+                "startLine": 2,
+                "startColumn": 8,
+                "endLine": 2,
+                "endColumn": 11,
+            })
+        );
+
+        assert_eq!(results[1]["level"], "warning");
+        assert_eq!(
+            results[1]["locations"][0]["physicalLocation"]["region"],
+            serde_json::json!({
+                "startLine": 3,
+                "startColumn": 1,
+                "endLine": 4,
+                "endColumn": 5,
+            })
+        );
+
+        // Results in included files point into them
+        let location = &results[2]["locations"][0]["physicalLocation"];
+        assert_eq!(location["artifactLocation"]["uri"], "lib/helper.ch8a");
+        assert_eq!(
+            location["region"],
+            serde_json::json!({
+                "startLine": 2,
+                "startColumn": 1,
+                "endLine": 2,
+                "endColumn": 7,
+            })
+        );
+    }
+
+    #[test]
+    fn sarif_tabs_test() {
+        // The spans point into the code with its tabs expanded to 4 spaces, which is
+        // "cls\n    ld v1,    300\n"
+        let mut reporter = SarifReporter::new("test.ch8a", "cls\n\tld v1,\t300\n");
+        reporter.report(
+            Span {
+                start: 18,
+                end: 20,
+                file: 0,
+            },
+            "This number is too big",
+            "error",
+        );
+
+        let sarif: serde_json::Value = serde_json::from_str(&reporter.to_json()).unwrap();
+        assert_eq!(
+            sarif["runs"][0]["results"][0]["locations"][0]["physicalLocation"]["region"],
+            serde_json::json!({
+                "startLine": 2,
+                "startColumn": 9,
+                "endLine": 2,
+                "endColumn": 12,
+            })
+        );
+    }
 }
@@ -0,0 +1,367 @@
+//! This module contains the lints that `--lint` runs, which look for code that assembles fine but
+//! is probably a mistake.
+
+use crate::{
+    ast::{
+        AliasableThing, OrAlias, PseudoInstruction as PI, RegOrByte, SegmentKind, SpanStmt, Stmt,
+    },
+    error::{report_error, report_warning},
+    span::Span,
+    tokens::GeneralRegisterName,
+};
+use std::collections::HashMap;
+
+/// How serious a lint finding is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SeverityLevel {
+    /// The finding is reported, but the code still passes.
+    Warning,
+
+    /// The finding is reported, and the code fails the lint.
+    Error,
+}
+
+/// Something that a lint found in the code.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LintFinding {
+    /// Where the problem is.
+    pub span: Span,
+
+    /// How serious the problem is.
+    pub severity: SeverityLevel,
+
+    /// What the problem is.
+    pub message: String,
+}
+
+/// A static analysis pass over the parsed code.
+pub trait Lint {
+    /// The name of this lint, which is used with `--allow` and `--deny`.
+    fn name(&self) -> &str;
+
+    /// Check the statements, with the symbols that codegen resolved, and return everything that
+    /// looks wrong.
+    fn check(
+        &self,
+        stmts: &[SpanStmt<'_>],
+        symbols: &HashMap<&str, AliasableThing>,
+    ) -> Vec<LintFinding>;
+}
+
+/// Get every lint that `--lint` runs.
+pub fn all_lints() -> Vec<Box<dyn Lint>> {
+    vec![Box::new(VfClobber), Box::new(UnreachableCode)]
+}
+
+/// Run the lints, skipping any that are in `allow`, and turning the findings of any in `deny`
+/// into errors. Each message ends with the name of the lint that found it.
+pub fn run_lints(
+    lints: &[Box<dyn Lint>],
+    stmts: &[SpanStmt<'_>],
+    symbols: &HashMap<&str, AliasableThing>,
+    allow: &[String],
+    deny: &[String],
+) -> Vec<LintFinding> {
+    let mut findings = Vec::new();
+
+    for lint in lints {
+        let name = lint.name();
+        if allow.iter().any(|allowed| allowed == name) {
+            continue;
+        }
+        let denied = deny.iter().any(|denied| denied == name);
+
+        findings.extend(
+            lint.check(stmts, symbols)
+                .into_iter()
+                .map(|finding| LintFinding {
+                    severity: if denied {
+                        SeverityLevel::Error
+                    } else {
+                        finding.severity
+                    },
+                    message: format!("{} [{name}]", finding.message),
+                    ..finding
+                }),
+        );
+    }
+
+    findings.sort_by_key(|finding| (finding.span.file, finding.span.start));
+    findings
+}
+
+/// Report the findings as errors and warnings, and return how many errors there were.
+pub fn report_findings(findings: &[LintFinding]) -> usize {
+    let mut errors = 0;
+    for finding in findings {
+        match finding.severity {
+            SeverityLevel::Warning => report_warning(finding.span, &finding.message),
+            SeverityLevel::Error => {
+                report_error(finding.span, &finding.message);
+                errors += 1;
+            }
+        }
+    }
+    errors
+}
+
+/// Find the register that a register argument refers to, if we can.
+fn resolve_register(
+    reg: OrAlias<'_, GeneralRegisterName>,
+    symbols: &HashMap<&str, AliasableThing>,
+) -> Option<GeneralRegisterName> {
+    match reg {
+        OrAlias::Concrete(reg) => Some(reg),
+        OrAlias::Alias(name) => match symbols.get(name) {
+            Some(AliasableThing::Register(reg)) => Some(*reg),
+            _ => None,
+        },
+    }
+}
+
+/// Warns about arithmetic that stores its result in VF, since the instruction then overwrites VF
+/// with its flag and the result is lost.
+struct VfClobber;
+
+impl Lint for VfClobber {
+    fn name(&self) -> &str {
+        "vf-clobber"
+    }
+
+    fn check(
+        &self,
+        stmts: &[SpanStmt<'_>],
+        symbols: &HashMap<&str, AliasableThing>,
+    ) -> Vec<LintFinding> {
+        let reg = |reg| resolve_register(reg, symbols);
+
+        stmts
+            .iter()
+            .filter(|stmt| {
+                let Stmt::PseudoInstruction(instr) = stmt.value else {
+                    return false;
+                };
+
+                let dest = match instr {
+                    // Adding a register sets the carry flag, but adding a literal doesn't
+                    PI::Add(x, OrAlias::Concrete(RegOrByte::Register(_))) => reg(x),
+                    PI::Add(x, OrAlias::Alias(name)) => match symbols.get(name) {
+                        Some(AliasableThing::Register(_)) => reg(x),
+                        _ => None,
+                    },
+                    PI::Sub(x, _) | PI::Subn(x, _) | PI::Shr(x, _) | PI::Shl(x, _) => reg(x),
+                    _ => None,
+                };
+                dest == Some(GeneralRegisterName::Vf)
+            })
+            .map(|stmt| LintFinding {
+                span: stmt.span,
+                severity: SeverityLevel::Warning,
+                message: "The result is stored in VF, but VF is then overwritten by the flag"
+                    .to_string(),
+            })
+            .collect()
+    }
+}
+
+/// Warns about instructions that can never run, because they come straight after an
+/// unconditional jump or return with no label in between.
+struct UnreachableCode;
+
+impl Lint for UnreachableCode {
+    fn name(&self) -> &str {
+        "unreachable-code"
+    }
+
+    fn check(
+        &self,
+        stmts: &[SpanStmt<'_>],
+        _symbols: &HashMap<&str, AliasableThing>,
+    ) -> Vec<LintFinding> {
+        let mut findings = Vec::new();
+        let mut segment = SegmentKind::Code;
+        let mut after_skip = false;
+        let mut unreachable = false;
+        let mut reported = false;
+
+        for stmt in stmts {
+            match stmt.value {
+                Stmt::SegmentDirective(kind) => segment = kind,
+                Stmt::Label(_) if segment == SegmentKind::Code => {
+                    unreachable = false;
+                    reported = false;
+                }
+                Stmt::PseudoInstruction(instr) if segment == SegmentKind::Code => {
+                    // Only report the first instruction in each unreachable run
+                    if unreachable && !reported {
+                        findings.push(LintFinding {
+                            span: stmt.span,
+                            severity: SeverityLevel::Warning,
+                            message: "This instruction can never run, because there's no label \
+                                      after the jump or return before it"
+                                .to_string(),
+                        });
+                        reported = true;
+                    }
+
+                    unreachable |=
+                        !after_skip && matches!(instr, PI::Jmp(_) | PI::JmpPlus(_, _) | PI::Ret);
+                    after_skip = matches!(
+                        instr,
+                        PI::Se(_, _) | PI::Sne(_, _) | PI::Skp(_) | PI::Sknp(_)
+                    );
+                }
+                _ => {}
+            }
+        }
+
+        findings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        codegen::{codegen, expand_includes, Target, DEFAULT_MAX_ROM_SIZE},
+        parser::Parser,
+        scanner::Scanner,
+    };
+    use std::{env, fs, path::Path};
+    use typed_arena::Arena;
+
+    /// Lint the source code, and return the line number and severity of each finding, along with
+    /// the name of the lint at the end of its message.
+    fn lint(
+        source: &'static str,
+        allow: &[&str],
+        deny: &[&str],
+    ) -> Vec<(usize, SeverityLevel, &'static str)> {
+        let statements = Parser::parse(Scanner::scan_tokens(source));
+        let segments = codegen(
+            statements.clone(),
+            Path::new(""),
+            Target::Chip8,
+            0x200,
+            0x300,
+            false,
+            DEFAULT_MAX_ROM_SIZE,
+        )
+        .unwrap();
+        let symbols = segments
+            .symbols
+            .iter()
+            .map(|(name, thing)| (name.as_str(), *thing))
+            .collect();
+
+        let to_strings =
+            |names: &[&str]| -> Vec<String> { names.iter().map(|name| name.to_string()).collect() };
+        run_lints(
+            &all_lints(),
+            &statements,
+            &symbols,
+            &to_strings(allow),
+            &to_strings(deny),
+        )
+        .into_iter()
+        .map(|finding| {
+            let line = source[..finding.span.start].matches('\n').count() + 1;
+            let name = ["vf-clobber", "unreachable-code"]
+                .into_iter()
+                .find(|name| finding.message.ends_with(&format!("[{name}]")))
+                .unwrap();
+            (line, finding.severity, name)
+        })
+        .collect()
+    }
+
+    #[test]
+    fn vf_clobber_test() {
+        use SeverityLevel::Warning;
+
+        let source = "define flag vf\ndefine five 5\n\
+            add vf, v1\nadd vf, 1\nadd flag, five\nsub flag, v2\nsubn v3, vf\nshr vf\nshl v3\n";
+        assert_eq!(
+            lint(source, &[], &[]),
+            [
+                (3, Warning, "vf-clobber"),
+                (6, Warning, "vf-clobber"),
+                (8, Warning, "vf-clobber"),
+            ]
+        );
+    }
+
+    #[test]
+    fn unreachable_code_test() {
+        use SeverityLevel::Warning;
+
+        let source =
+            "start:\nse v0, 1\njmp start\ncls\njmp start\ncls\ncls\nend:\nret\ndb #ff\nnop\n\
+            .segment data\ndb #ff\n.segment code\nnop\n";
+        assert_eq!(
+            lint(source, &[], &[]),
+            [
+                (6, Warning, "unreachable-code"),
+                (11, Warning, "unreachable-code")
+            ]
+        );
+    }
+
+    #[test]
+    fn allow_and_deny_test() {
+        use SeverityLevel::{Error, Warning};
+
+        let source = "shr vf\njmp #200\ncls\n";
+        assert_eq!(
+            lint(source, &[], &[]),
+            [(1, Warning, "vf-clobber"), (3, Warning, "unreachable-code")]
+        );
+        assert_eq!(
+            lint(source, &["vf-clobber"], &["unreachable-code"]),
+            [(3, Error, "unreachable-code")]
+        );
+    }
+
+    #[test]
+    fn include_test() {
+        use SeverityLevel::Warning;
+
+        let dir = env::temp_dir().join(format!("ch8a-{}-lint-include", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let helper = "shr vf\nret\ncls\n";
+        fs::write(dir.join("helper.asm"), helper).unwrap();
+
+        // The lints see the included code, and report it in the included file
+        let sources = Arena::new();
+        let source = "call helper\nend: jmp end\nhelper:\ninclude \"helper.asm\"\n";
+        let statements = Parser::parse(Scanner::scan_tokens(source));
+        let statements = expand_includes(statements, &dir.join("main.asm"), &sources).unwrap();
+        let segments = codegen(
+            statements.clone(),
+            &dir,
+            Target::Chip8,
+            0x200,
+            0x300,
+            false,
+            DEFAULT_MAX_ROM_SIZE,
+        )
+        .unwrap();
+        let symbols = segments
+            .symbols
+            .iter()
+            .map(|(name, thing)| (name.as_str(), *thing))
+            .collect();
+
+        let findings: Vec<_> = run_lints(&all_lints(), &statements, &symbols, &[], &[])
+            .into_iter()
+            .map(|finding| {
+                assert_ne!(finding.span.file, 0);
+                let line = helper[..finding.span.start].matches('\n').count() + 1;
+                (line, finding.severity)
+            })
+            .collect();
+        assert_eq!(findings, [(1, Warning), (3, Warning)]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
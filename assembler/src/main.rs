@@ -4,21 +4,29 @@
 mod ast;
 mod codegen;
 mod error;
+mod lint;
+mod optimizer;
+mod output;
 mod parser;
 mod scanner;
 mod span;
 mod tokens;
 
 use crate::{
-    codegen::codegen,
+    codegen::{codegen, expand_includes, CodegenError, Target, DEFAULT_MAX_ROM_SIZE},
     error::{init_error_reporting, HAD_ERROR},
+    output::OutputFormat,
     parser::Parser,
     scanner::Scanner,
 };
 use color_eyre::{Report, Result};
 use error::report_error;
 use span::WithSpan;
-use std::{fs, sync::atomic::Ordering};
+use std::{fs, path::Path, sync::atomic::Ordering};
+use typed_arena::Arena;
+
+/// The address that the interpreters load their font from, which is where `--font-file` puts it.
+const FONT_ADDRESS: u16 = 0x050;
 
 #[derive(clap::Parser)]
 #[command(author, version, about)]
@@ -27,18 +35,162 @@ struct Args {
     file: String,
 
     /// The name of the file to output the assembled ROM to.
-    #[arg(long, short)]
-    output: String,
+    #[arg(long, short, required_unless_present_any = ["split_output", "check", "lint"])]
+    output: Option<String>,
+
+    /// Output the code and data segments to separate files instead, each at its own address.
+    #[arg(
+        long,
+        num_args = 2,
+        value_names = ["CODE_FILE", "DATA_FILE"],
+        conflicts_with = "output"
+    )]
+    split_output: Option<Vec<String>>,
+
+    /// The file format of the assembled ROM.
+    #[arg(long, value_enum, default_value_t)]
+    output_format: OutputFormat,
+
+    /// The format to report errors and warnings in. SARIF is printed to stdout as JSON once
+    /// assembly has finished.
+    #[cfg(feature = "sarif")]
+    #[arg(long, value_enum, default_value_t)]
+    message_format: error::MessageFormat,
+
+    /// An 80 byte font file to embed at address 0x050, with 5 bytes for each hex digit. This
+    /// needs an output format that includes addresses.
+    #[arg(long)]
+    font_file: Option<String>,
+
+    /// The CHIP-8 variant to assemble for.
+    #[arg(long, value_enum, default_value_t)]
+    target: Target,
+
+    /// The address that the ROM will be loaded at, like `0x200`. Labels are resolved relative to
+    /// this address.
+    #[arg(long, value_parser = parse_address, default_value_t = 0x200)]
+    entry_point: u16,
+
+    /// The address that the data segment starts at, for anything after `.segment data`.
+    #[arg(long, value_parser = parse_address, default_value_t = 0x300)]
+    data_address: u16,
+
+    /// Warn about instructions that are valid but probably mistakes, like `xor v1, v1`.
+    #[arg(long)]
+    strict: bool,
+
+    /// The maximum size of the assembled ROM in bytes. The default is all the memory after 0x200
+    /// on a standard 4K machine, but some hardware has less, like 1536 bytes on a Telmac 1800.
+    #[arg(long, default_value_t = DEFAULT_MAX_ROM_SIZE)]
+    max_rom_size: u16,
+
+    /// Check the code for errors and warnings without writing any output.
+    #[arg(long)]
+    check: bool,
+
+    /// Run every lint, which look for code that assembles fine but is probably a mistake, like
+    /// storing the result of `sub` in VF. This implies `--check` and `--strict`.
+    #[arg(long)]
+    lint: bool,
+
+    /// Don't run the lint with this name. This can be given more than once.
+    #[arg(long, value_name = "LINT", requires = "lint")]
+    allow: Vec<String>,
+
+    /// Report the findings of the lint with this name as errors instead of warnings. This can be
+    /// given more than once.
+    #[arg(long, value_name = "LINT", requires = "lint")]
+    deny: Vec<String>,
+
+    /// Optimize the code with a peephole optimizer, which merges and removes redundant
+    /// instructions. Programs that use `jmpp` are left alone.
+    #[arg(long)]
+    optimize: bool,
+
+    /// Print the SHA-256 hash of the assembled program, which the interpreter can check with
+    /// `--check-hash`. The hash doesn't include the font.
+    #[cfg(feature = "sha2")]
+    #[arg(long)]
+    print_hash: bool,
+}
+
+/// Parse an address in hex with a `0x` prefix, or in decimal. Addresses are 12 bits, so they can
+/// be at most 0xFFF.
+fn parse_address(address: &str) -> Result<u16, String> {
+    let address = match address.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => address.parse(),
+    }
+    .map_err(|error| error.to_string())?;
+
+    if address > 0xFFF {
+        return Err(format!(
+            "0x{address:0>3X} is past the end of memory, which is at most 0xFFF"
+        ));
+    }
+    Ok(address)
 }
 
 fn main() -> Result<()> {
     let args = <Args as clap::Parser>::parse();
+    let result = assemble(&args);
 
-    let input = fs::read_to_string(args.file)?.replace("\t", "    ");
+    #[cfg(feature = "sarif")]
+    if let Some(sarif) = error::finish_sarif_reporting() {
+        println!("{sarif}");
+    }
+
+    result
+}
+
+/// Assemble the file, reporting any errors and warnings as we go.
+fn assemble(args: &Args) -> Result<()> {
+    let lints = lint::all_lints();
+    if let Some(name) = args
+        .allow
+        .iter()
+        .chain(&args.deny)
+        .find(|&name| !lints.iter().any(|lint| lint.name() == name))
+    {
+        return Err(Report::msg(format!("Unknown lint {name:?}")));
+    }
+
+    let font = match &args.font_file {
+        Some(_) if args.output_format == OutputFormat::Bin => {
+            return Err(Report::msg(
+                "A font can't be embedded at 0x050 in a raw binary ROM, which starts at 0x200",
+            ));
+        }
+        Some(font_file) => {
+            let font = fs::read(font_file)?;
+            if font.len() != 80 {
+                return Err(Report::msg(format!(
+                    "The font file should be 80 bytes, but it's {} bytes",
+                    font.len()
+                )));
+            }
+            if args.entry_point < FONT_ADDRESS + 80 {
+                return Err(Report::msg(format!(
+                    "The font at 0x050 would overlap with the ROM at 0x{:0>3X}",
+                    args.entry_point
+                )));
+            }
+            Some(font)
+        }
+        None => None,
+    };
+
+    let unmodified_input = fs::read_to_string(&args.file)?;
+    let input = unmodified_input.replace("\t", "    ");
     init_error_reporting(input.clone());
+    #[cfg(feature = "sarif")]
+    if args.message_format == error::MessageFormat::Sarif {
+        error::start_sarif_reporting(&args.file, &unmodified_input);
+    }
     let lowercase_input = input.to_ascii_lowercase();
 
-    let tokens = Scanner::scan_tokens(&lowercase_input);
+    // The paths in include and incbin are taken from the input, so they keep their case
+    let tokens = Scanner::scan_tokens_from(&lowercase_input, &input, 0);
 
     if HAD_ERROR.load(Ordering::Relaxed) {
         return Err(Report::msg("Failed to tokenise input"));
@@ -46,17 +198,126 @@ fn main() -> Result<()> {
 
     let statements = Parser::parse(tokens);
 
-    // TODO: Handle Include directives
+    let base_dir = Path::new(&args.file).parent().unwrap_or(Path::new(""));
+
+    let sources = Arena::new();
+    let statements = expand_includes(statements, Path::new(&args.file), &sources)
+        .map_err(|errors| report_codegen_errors(&errors))?;
+    let lint_statements = args.lint.then(|| statements.clone());
+
+    let statements = if args.optimize {
+        optimizer::optimize_statements(
+            statements,
+            base_dir,
+            args.target,
+            args.entry_point,
+            args.data_address,
+        )
+    } else {
+        statements
+    };
+
+    match codegen(
+        statements,
+        base_dir,
+        args.target,
+        args.entry_point,
+        args.data_address,
+        args.strict || args.lint,
+        args.max_rom_size,
+    ) {
+        Ok(segments) => {
+            if let Some(statements) = lint_statements {
+                let symbols = segments
+                    .symbols
+                    .iter()
+                    .map(|(name, thing)| (name.as_str(), *thing))
+                    .collect();
+                let findings =
+                    lint::run_lints(&lints, &statements, &symbols, &args.allow, &args.deny);
+                match lint::report_findings(&findings) {
+                    0 => {}
+                    1 => return Err(Report::msg("Failed due to 1 lint error")),
+                    n => return Err(Report::msg(format!("Failed due to {n} lint errors"))),
+                }
+            }
+            if args.check || args.lint {
+                return Ok(());
+            }
+
+            let combined = segments.combined(args.entry_point, args.data_address);
+
+            // The code is always the last segment, so that it's used as the entry point
+            let mut code_segments: Vec<(u16, &[u8])> = Vec::new();
+            if let Some(font) = &font {
+                code_segments.push((FONT_ADDRESS, font));
+            }
+
+            match (&args.split_output, &args.output) {
+                (Some(files), _) => {
+                    let [code_file, data_file] = &files[..] else {
+                        unreachable!("clap only allows exactly two split output files");
+                    };
+                    code_segments.push((args.entry_point, &segments.code));
+                    fs::write(
+                        code_file,
+                        args.output_format.format_segments(&code_segments),
+                    )?;
+                    fs::write(
+                        data_file,
+                        args.output_format.format(&segments.data, args.data_address),
+                    )?;
+                }
+                (None, Some(output_file)) => {
+                    let output = if args.output_format == OutputFormat::Bin {
+                        combined.clone().ok_or_else(|| {
+                            Report::msg(
+                                "A raw binary ROM can't hold a data segment that starts before the end of the code",
+                            )
+                        })?
+                    } else {
+                        if !segments.data.is_empty() {
+                            code_segments.push((args.data_address, &segments.data));
+                        }
+                        code_segments.push((args.entry_point, &segments.code));
+                        args.output_format.format_segments(&code_segments)
+                    };
+                    fs::write(output_file, output)?;
+                }
+                (None, None) => unreachable!("clap requires an output file"),
+            }
+
+            #[cfg(feature = "sha2")]
+            if args.print_hash {
+                use sha2::{Digest, Sha256};
+
+                match &combined {
+                    Some(rom) => {
+                        let hash: String = Sha256::digest(rom)
+                            .iter()
+                            .map(|byte| format!("{byte:0>2x}"))
+                            .collect();
+                        println!("{hash}");
+                    }
+                    None => eprintln!(
+                        "The data segment starts before the end of the code, so there's no single ROM to hash"
+                    ),
+                }
+            }
 
-    match codegen(statements) {
-        Ok(final_binary) => {
-            fs::write(args.output, final_binary)?;
             Ok(())
         }
-        Err(WithSpan { value: error, span }) => {
-            let message = format!("{error}");
-            report_error(span, &message);
-            Err(Report::msg(message))
-        }
+        Err(errors) => Err(report_codegen_errors(&errors)),
+    }
+}
+
+/// Report every error from codegen, and get a report that says how many there were.
+fn report_codegen_errors(errors: &[WithSpan<CodegenError<'_>>]) -> Report {
+    for WithSpan { value: error, span } in errors {
+        report_error(*span, &format!("{error}"));
     }
+    Report::msg(match errors.len() {
+        1 => "Failed to assemble due to 1 error".to_string(),
+        n => format!("Failed to assemble due to {n} errors"),
+    })
 }
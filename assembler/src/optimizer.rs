@@ -0,0 +1,497 @@
+//! This module contains a peephole optimizer, which replaces short runs of instructions with
+//! fewer instructions that do the same thing.
+
+use crate::{
+    ast::{OrAlias, PseudoInstruction, SegmentKind, SpanStmt, Stmt},
+    codegen::{codegen, Target},
+    error::quietly,
+};
+use chip8_instructions::{Instruction, Operand, INSTRUCTION_SIZE};
+use std::{collections::HashSet, path::Path};
+
+/// The values of the registers that we know for certain at some point in the program.
+type Constants = [Option<u8>; 16];
+
+/// Is this instruction a skip, which makes whether the next instruction runs depend on something?
+fn is_skip(instruction: Instruction) -> bool {
+    use Instruction as I;
+
+    matches!(
+        instruction,
+        I::SkipIfEqual(_, _)
+            | I::SkipIfNotEqual(_, _)
+            | I::SkipIfKeyPressed(_)
+            | I::SkipIfKeyNotPressed(_)
+    )
+}
+
+/// Does this instruction do nothing at all when assembling for `target`?
+fn is_no_op(instruction: Instruction, target: Target) -> bool {
+    use Instruction as I;
+
+    match instruction {
+        I::LoadRegister(x, Operand::Register(y)) => x == y,
+        // The original CHIP-8 resets VF after these, so they're only no-ops on CHIP-48
+        I::Or(x, y) | I::And(x, y) => x == y && target == Target::Chip48,
+        _ => false,
+    }
+}
+
+/// Work out whether a skip will be taken, if we know the value of its register.
+fn skip_taken(instruction: Instruction, constants: &Constants) -> Option<bool> {
+    match instruction {
+        Instruction::SkipIfEqual(x, Operand::Literal(byte)) => {
+            constants[x as usize].map(|value| value == byte)
+        }
+        Instruction::SkipIfNotEqual(x, Operand::Literal(byte)) => {
+            constants[x as usize].map(|value| value != byte)
+        }
+        _ => None,
+    }
+}
+
+/// Update the known register values after running the instruction.
+fn update_constants(constants: &mut Constants, instruction: Instruction) {
+    use Instruction as I;
+
+    match instruction {
+        I::LoadRegister(x, Operand::Literal(byte)) => constants[x as usize] = Some(byte),
+        I::LoadRegister(x, Operand::Register(y)) => constants[x as usize] = constants[y as usize],
+        I::AddNoCarry(x, byte) => {
+            constants[x as usize] = constants[x as usize].map(|value| value.wrapping_add(byte))
+        }
+        I::Nop
+        | I::ClearScreen
+        | I::LowRes
+        | I::HighRes
+        | I::LoadMemoryRegister(_)
+        | I::LoadIntoDelayTimer(_)
+        | I::LoadIntoSoundTimer(_)
+        | I::AddToMemoryRegister(_)
+        | I::LoadDigitAddress(_)
+        | I::StoreBcdInMemory(_)
+        | I::StoreRegistersInMemory(_) => (),
+        I::Draw(_, _, _) => constants[0xF] = None,
+        I::ScrollDown(_)
+        | I::ScrollRight
+        | I::ScrollLeft
+        | I::Exit
+        | I::LoadLargeDigitAddress(_)
+        | I::StoreRegistersInRpl(_) => (),
+        I::DrawLargeSprite(_, _) => constants[0xF] = None,
+        I::ReadRegistersFromRpl(x) => constants[..=x as usize].fill(None),
+        I::Or(x, _)
+        | I::And(x, _)
+        | I::Xor(x, _)
+        | I::AddWithCarry(x, _)
+        | I::Sub(x, _)
+        | I::SubN(x, _)
+        | I::ShiftRight(x, _)
+        | I::ShiftLeft(x, _) => {
+            constants[x as usize] = None;
+            constants[0xF] = None;
+        }
+        I::LoadRandomWithMask(x, _) | I::LoadFromDelayTimer(x) | I::WaitForKeyPress(x) => {
+            constants[x as usize] = None
+        }
+        I::ReadRegistersFromMemory(x) => constants[..=x as usize].fill(None),
+        // Control flow means that we don't know where we came from at the next instruction
+        I::Return
+        | I::Jump(_)
+        | I::Call(_)
+        | I::JumpPlusV0(_)
+        | I::SkipIfEqual(_, _)
+        | I::SkipIfNotEqual(_, _)
+        | I::SkipIfKeyPressed(_)
+        | I::SkipIfKeyNotPressed(_) => *constants = [None; 16],
+    }
+}
+
+/// Optimize a list of instructions with their addresses, in address order, and return the
+/// instructions that are left with their original addresses. The addresses will be wrong after
+/// anything that was removed, so the caller has to lay the code out again.
+///
+/// `barriers` are the addresses that could be reached from somewhere other than the instruction
+/// before, like labels. We never remove or merge an instruction at a barrier, unless it's the
+/// first instruction of a run, and we forget what we know about the registers there.
+///
+/// These are the optimizations:
+/// - `ld vx, a; add vx, b` becomes `ld vx, a + b`.
+/// - `ld vx, a` is removed if the next instruction loads something else into VX.
+/// - `ld vx, vx` is removed, and so are `or vx, vx` and `and vx, vx` for CHIP-48, since they
+///   reset VF on the original CHIP-8.
+/// - `se vx, k; jmp a; jmp b` becomes just the jump that's taken, if we know VX.
+/// - Other skips that we know the outcome of are removed, along with the instruction that they
+///   would always skip.
+///
+/// Programs with `jmpp` aren't optimized at all, since they often jump into tables of
+/// instructions, which we can't safely move.
+pub fn optimize(
+    instructions: Vec<(u16, Instruction)>,
+    barriers: &HashSet<u16>,
+    target: Target,
+) -> Vec<(u16, Instruction)> {
+    use Instruction as I;
+
+    if instructions
+        .iter()
+        .any(|(_, instruction)| matches!(instruction, I::JumpPlusV0(_)))
+    {
+        return instructions;
+    }
+
+    // Get the instruction `n` after the one at `idx`, if it comes straight after it with no
+    // barriers in between
+    let following = |idx: usize, n: usize| -> Option<Instruction> {
+        (1..=n).try_fold(None, |_, offset| {
+            let (prev_address, _) = instructions[idx + offset - 1];
+            let (address, instruction) = *instructions.get(idx + offset)?;
+            (address == prev_address + INSTRUCTION_SIZE as u16 && !barriers.contains(&address))
+                .then_some(Some(instruction))
+        })?
+    };
+
+    let mut optimized: Vec<(u16, Instruction)> = Vec::with_capacity(instructions.len());
+    let mut constants: Constants = [None; 16];
+    let mut idx = 0;
+
+    while idx < instructions.len() {
+        let (address, instruction) = instructions[idx];
+        let after_gap = idx == 0
+            || instructions[idx - 1].0 + INSTRUCTION_SIZE as u16 != address
+            || barriers.contains(&address);
+        if after_gap {
+            constants = [None; 16];
+        }
+
+        // Removing or merging an instruction after a skip would change what gets skipped
+        let after_skip = !after_gap
+            && optimized.last().is_some_and(|&(prev_address, prev)| {
+                prev_address + INSTRUCTION_SIZE as u16 == address && is_skip(prev)
+            });
+        if after_skip {
+            optimized.push((address, instruction));
+            constants = [None; 16];
+            idx += 1;
+            continue;
+        }
+
+        if is_no_op(instruction, target) {
+            idx += 1;
+            continue;
+        }
+
+        match (instruction, following(idx, 1)) {
+            (I::LoadRegister(x, Operand::Literal(a)), Some(I::AddNoCarry(y, b))) if x == y => {
+                let merged = I::LoadRegister(x, Operand::Literal(a.wrapping_add(b)));
+                optimized.push((address, merged));
+                update_constants(&mut constants, merged);
+                idx += 2;
+                continue;
+            }
+            (I::LoadRegister(x, _), Some(I::LoadRegister(y, operand)))
+                if x == y && operand != Operand::Register(x) =>
+            {
+                idx += 1;
+                continue;
+            }
+            _ => (),
+        }
+
+        if let Some(taken) = skip_taken(instruction, &constants) {
+            if let (Some(I::Jump(_)), Some(I::Jump(_))) = (following(idx, 1), following(idx, 2)) {
+                // Keep the jump that's taken as it is, since it might jump to a label that moves
+                let jump_idx = if taken { idx + 2 } else { idx + 1 };
+                optimized.push(instructions[jump_idx]);
+                constants = [None; 16];
+                idx += 3;
+                continue;
+            }
+
+            match (taken, following(idx, 1)) {
+                (false, _) => {
+                    idx += 1;
+                    continue;
+                }
+                (true, Some(_)) => {
+                    idx += 2;
+                    continue;
+                }
+                (true, None) => (),
+            }
+        }
+
+        optimized.push((address, instruction));
+        update_constants(&mut constants, instruction);
+        idx += 1;
+    }
+
+    optimized
+}
+
+/// Check if any instruction uses an address that isn't a label, like `jmp #204`.
+///
+/// Removing instructions moves everything after them, and labels get resolved again when the
+/// statements are assembled properly, but literal addresses and aliases for them don't.
+fn uses_literal_address(statements: &[SpanStmt<'_>]) -> bool {
+    use PseudoInstruction as PI;
+
+    let labels: HashSet<&str> = statements
+        .iter()
+        .filter_map(|statement| match statement.value {
+            Stmt::Label(name) => Some(name),
+            _ => None,
+        })
+        .collect();
+
+    statements.iter().any(|statement| match &statement.value {
+        Stmt::PseudoInstruction(
+            PI::Jmp(address) | PI::JmpPlus(_, address) | PI::Call(address) | PI::LdIndex(address),
+        ) => !matches!(address, OrAlias::Alias(name) if labels.contains(name)),
+        _ => false,
+    })
+}
+
+/// Optimize the instructions in the code segment with [`optimize`], and return the statements
+/// with the optimized instructions, ready to be assembled properly.
+///
+/// We quietly assemble the statements once to resolve the aliases and find the address of every
+/// instruction. If that fails, we return the statements unchanged, so that the errors get
+/// reported when they're assembled properly. Includes should already be expanded with
+/// [`expand_includes`](crate::codegen::expand_includes), so that the instructions in included
+/// files get optimized too.
+///
+/// Programs that jump to or load a literal address aren't optimized at all, since we can't tell
+/// what it points at once instructions start moving.
+pub fn optimize_statements<'s>(
+    statements: Vec<SpanStmt<'s>>,
+    base_dir: &Path,
+    target: Target,
+    origin: u16,
+    data_origin: u16,
+) -> Vec<SpanStmt<'s>> {
+    if uses_literal_address(&statements) {
+        return statements;
+    }
+
+    // Everything gets reported when the statements are assembled properly, so we don't want to
+    // report it twice
+    let Ok(segments) = quietly(|| {
+        codegen(
+            statements.clone(),
+            base_dir,
+            target,
+            origin,
+            data_origin,
+            false,
+            u16::MAX,
+        )
+    }) else {
+        return statements;
+    };
+
+    // Find the statement for each instruction in the code segment, and which instructions have
+    // something other than an alias definition between them and the previous instruction
+    let mut barriers = HashSet::new();
+    let mut instruction_indices = Vec::with_capacity(segments.instructions.len());
+    let mut segment = SegmentKind::Code;
+    let mut interrupted = true;
+    for (idx, statement) in statements.iter().enumerate() {
+        match &statement.value {
+            Stmt::PseudoInstruction(_) if segment == SegmentKind::Code => {
+                let (address, _) = segments.instructions[instruction_indices.len()];
+                if interrupted {
+                    barriers.insert(address);
+                }
+                instruction_indices.push(idx);
+                interrupted = false;
+            }
+            Stmt::AliasDefinition(_, _) => (),
+            Stmt::SegmentDirective(kind) => {
+                segment = *kind;
+                interrupted = true;
+            }
+            _ => interrupted = true,
+        }
+    }
+
+    let mut optimized = optimize(segments.instructions.clone(), &barriers, target).into_iter();
+    let mut next = optimized.next();
+    let mut statements: Vec<Option<SpanStmt<'s>>> = statements.into_iter().map(Some).collect();
+
+    for (&idx, &(address, instruction)) in instruction_indices.iter().zip(&segments.instructions) {
+        match next {
+            Some((new_address, new_instruction)) if new_address == address => {
+                if new_instruction != instruction {
+                    let statement = statements[idx].as_mut().unwrap();
+                    statement.value = Stmt::PseudoInstruction(
+                        new_instruction
+                            .try_into()
+                            .expect("Optimized instructions should only use valid registers"),
+                    );
+                }
+                next = optimized.next();
+            }
+            _ => statements[idx] = None,
+        }
+    }
+
+    statements.into_iter().flatten().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{error::init_error_reporting, parser::Parser, scanner::Scanner};
+
+    /// Assemble the source code for the given target, with or without optimizing it.
+    fn assemble(source: &'static str, target: Target, optimize: bool) -> Vec<u8> {
+        init_error_reporting(source.to_string());
+        let mut statements = Parser::parse(Scanner::scan_tokens(source));
+        if optimize {
+            statements = optimize_statements(statements, Path::new(""), target, 0x200, 0x300);
+        }
+        codegen(
+            statements,
+            Path::new(""),
+            target,
+            0x200,
+            0x300,
+            false,
+            0xE00,
+        )
+        .unwrap()
+        .code
+    }
+
+    /// Assemble the source code for CHIP-8 with and without optimizing, and check that the
+    /// optimized version is `saved` bytes smaller and matches `expected`.
+    fn check(source: &'static str, saved: usize, expected: &[u8]) {
+        let unoptimized = assemble(source, Target::Chip8, false);
+        let optimized = assemble(source, Target::Chip8, true);
+        assert_eq!(unoptimized.len() - optimized.len(), saved, "{source:?}");
+        assert_eq!(optimized, expected, "{source:?}");
+    }
+
+    #[test]
+    fn merge_loads_test() {
+        check("ld v1, 0\nadd v1, 5\n", 2, &[0x61, 0x05]);
+        check("ld v1, #fe\nadd v1, 3\n", 2, &[0x61, 0x01]);
+        check("ld v1, 2\nld v1, v2\n", 2, &[0x81, 0x20]);
+        check("ld v1, 2\nld v1, 3\nld v1, 4\n", 4, &[0x61, 0x04]);
+
+        // Loading VX into itself keeps its value, and adding to a different register can't merge
+        check("ld v1, 2\nld v1, v1\n", 2, &[0x61, 0x02]);
+        check("ld v1, 0\nadd v2, 5\n", 0, &[0x61, 0x00, 0x72, 0x05]);
+
+        // The label means that the add can be reached without the load
+        check(
+            "ld v1, 0\nloop:\nadd v1, 5\njmp loop\n",
+            0,
+            &[0x61, 0x00, 0x71, 0x05, 0x12, 0x02],
+        );
+    }
+
+    #[test]
+    fn no_op_test() {
+        check("or v3, v3\ncls\n", 0, &[0x83, 0x31, 0x00, 0xE0]);
+        assert_eq!(
+            assemble("or v3, v3\nand v4, v4\ncls\n", Target::Chip48, true),
+            [0x00, 0xE0]
+        );
+    }
+
+    #[test]
+    fn branch_folding_test() {
+        // The labels move, so the jumps have to be resolved again
+        check(
+            "ld v0, 3\nse v0, 3\njmp a\njmp b\na: cls\nb: ret\n",
+            4,
+            &[0x60, 0x03, 0x12, 0x06, 0x00, 0xE0, 0x00, 0xEE],
+        );
+        check(
+            "ld v0, 2\nsne v0, 3\njmp a\njmp b\na: cls\nb: ret\n",
+            4,
+            &[0x60, 0x02, 0x12, 0x06, 0x00, 0xE0, 0x00, 0xEE],
+        );
+        check(
+            "ld v0, 2\nse v0, 3\njmp a\njmp b\na: cls\nb: ret\n",
+            4,
+            &[0x60, 0x02, 0x12, 0x04, 0x00, 0xE0, 0x00, 0xEE],
+        );
+
+        // A skip that's never taken goes, and one that's always taken takes the next instruction
+        // with it
+        check(
+            "ld v0, 2\nse v0, 3\ncls\nret\n",
+            2,
+            &[0x60, 0x02, 0x00, 0xE0, 0x00, 0xEE],
+        );
+        check(
+            "ld v0, 3\nse v0, 3\ncls\nret\n",
+            4,
+            &[0x60, 0x03, 0x00, 0xEE],
+        );
+
+        // We don't know V0 after a key press
+        check(
+            "ld v0, k\nse v0, 3\njmp a\njmp b\na: cls\nb: ret\n",
+            0,
+            &[
+                0xF0, 0x0A, 0x30, 0x03, 0x12, 0x08, 0x12, 0x0A, 0x00, 0xE0, 0x00, 0xEE,
+            ],
+        );
+    }
+
+    #[test]
+    fn after_skip_test() {
+        // Merging the load into the add would change what gets skipped
+        check(
+            "se v0, 1\nld v1, 0\nadd v1, 5\n",
+            0,
+            &[0x30, 0x01, 0x61, 0x00, 0x71, 0x05],
+        );
+
+        // After the skip, we don't know whether V1 was loaded
+        check(
+            "se v0, 1\nld v1, 3\nse v1, 3\ncls\n",
+            0,
+            &[0x30, 0x01, 0x61, 0x03, 0x31, 0x03, 0x00, 0xE0],
+        );
+    }
+
+    #[test]
+    fn jump_plus_test() {
+        check(
+            "ld v1, 0\nadd v1, 5\njmpp v0, #300\n",
+            0,
+            &[0x61, 0x00, 0x71, 0x05, 0xB3, 0x00],
+        );
+    }
+
+    #[test]
+    fn literal_address_test() {
+        check(
+            "ld v1, 0\nadd v1, 5\ncls\njmp #204\n",
+            0,
+            &[0x61, 0x00, 0x71, 0x05, 0x00, 0xE0, 0x12, 0x04],
+        );
+        check(
+            "define target #204\nld v1, 0\nadd v1, 5\ncls\ncall target\n",
+            0,
+            &[0x61, 0x00, 0x71, 0x05, 0x00, 0xE0, 0x22, 0x04],
+        );
+        check(
+            "ld v1, 0\nadd v1, 5\nld i, #300\n",
+            0,
+            &[0x61, 0x00, 0x71, 0x05, 0xA3, 0x00],
+        );
+
+        // Labels get resolved again, so they're fine
+        check(
+            "ld v1, 0\nadd v1, 5\nend:\njmp end\n",
+            2,
+            &[0x61, 0x05, 0x12, 0x02],
+        );
+    }
+}
@@ -0,0 +1,216 @@
+//! This module handles writing the assembled ROM in different file formats.
+
+use std::fmt::Write;
+
+/// The number of data bytes in each record of a text output format.
+const RECORD_LEN: usize = 16;
+
+/// The file format to write the assembled ROM in.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Raw binary.
+    #[default]
+    Bin,
+
+    /// Intel HEX.
+    Ihex,
+
+    /// Motorola S-record.
+    Srec,
+}
+
+impl OutputFormat {
+    /// Format the ROM, which should be loaded at `start_addr`.
+    pub fn format(self, data: &[u8], start_addr: u16) -> Vec<u8> {
+        self.format_segments(&[(start_addr, data)])
+    }
+
+    /// Format several segments of data, each of which should be loaded at the given address. The
+    /// last segment should be the program, and its address is used as the entry point.
+    ///
+    /// Raw binary has no addresses, so the segments are just concatenated in that case.
+    pub fn format_segments(self, segments: &[(u16, &[u8])]) -> Vec<u8> {
+        match self {
+            Self::Bin => segments
+                .iter()
+                .flat_map(|(_, data)| *data)
+                .copied()
+                .collect(),
+            Self::Ihex => to_ihex(segments).into_bytes(),
+            Self::Srec => to_srec(segments).into_bytes(),
+        }
+    }
+}
+
+/// Write a record as hex, followed by a checksum computed from all the bytes of the record.
+fn push_record(out: &mut String, bytes: &[u8], checksum: impl Fn(u8) -> u8) {
+    let sum = bytes.iter().fold(0u8, |acc, &byte| acc.wrapping_add(byte));
+    for byte in bytes.iter().chain([&checksum(sum)]) {
+        write!(out, "{byte:0>2X}").unwrap();
+    }
+    out.push('\n');
+}
+
+/// Convert segments of data to Intel HEX, with each one starting at the given address.
+fn to_ihex(segments: &[(u16, &[u8])]) -> String {
+    let mut out = String::new();
+
+    for &(start_addr, data) in segments {
+        for (idx, chunk) in data.chunks(RECORD_LEN).enumerate() {
+            let [addr_hi, addr_lo] = (start_addr + (idx * RECORD_LEN) as u16).to_be_bytes();
+
+            let mut record = vec![chunk.len() as u8, addr_hi, addr_lo, 0x00];
+            record.extend(chunk);
+
+            out.push(':');
+            push_record(&mut out, &record, u8::wrapping_neg);
+        }
+    }
+
+    // End of file
+    out.push_str(":00000001FF\n");
+    out
+}
+
+/// Convert segments of data to Motorola S-records, with each one starting at the given address.
+/// The address of the last segment is used as the entry point in the termination record.
+fn to_srec(segments: &[(u16, &[u8])]) -> String {
+    let mut out = String::new();
+
+    // Header with no name
+    out.push_str("S0030000FC\n");
+
+    for &(start_addr, data) in segments {
+        for (idx, chunk) in data.chunks(RECORD_LEN).enumerate() {
+            let [addr_hi, addr_lo] = (start_addr + (idx * RECORD_LEN) as u16).to_be_bytes();
+
+            // The count includes the address and checksum
+            let mut record = vec![chunk.len() as u8 + 3, addr_hi, addr_lo];
+            record.extend(chunk);
+
+            out.push_str("S1");
+            push_record(&mut out, &record, |sum| !sum);
+        }
+    }
+
+    let entry_point = segments.last().map_or(0, |&(start_addr, _)| start_addr);
+    let [addr_hi, addr_lo] = entry_point.to_be_bytes();
+    out.push_str("S9");
+    push_record(&mut out, &[3, addr_hi, addr_lo], |sum| !sum);
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parse a line of hex digits into bytes.
+    fn parse_bytes(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|idx| u8::from_str_radix(&hex[idx..idx + 2], 16).unwrap())
+            .collect()
+    }
+
+    /// Parse Intel HEX back into the start address and the data, checking every checksum. The
+    /// data must be contiguous.
+    fn parse_ihex(text: &str) -> (u16, Vec<u8>) {
+        let mut start = None;
+        let mut data = Vec::new();
+
+        for line in text.lines() {
+            let bytes = parse_bytes(line.strip_prefix(':').unwrap());
+            assert_eq!(bytes.iter().fold(0u8, |acc, &b| acc.wrapping_add(b)), 0);
+            assert_eq!(bytes.len(), bytes[0] as usize + 5);
+
+            match bytes[3] {
+                0x00 => {
+                    let addr = u16::from_be_bytes([bytes[1], bytes[2]]);
+                    let start = *start.get_or_insert(addr);
+                    assert_eq!(addr as usize, start as usize + data.len());
+                    data.extend(&bytes[4..bytes.len() - 1]);
+                }
+                0x01 => break,
+                record_type => panic!("Unexpected record type {record_type}"),
+            }
+        }
+
+        (start.unwrap(), data)
+    }
+
+    /// Parse S-records back into the start address, the data, and the entry point, checking every
+    /// checksum.
+    fn parse_srec(text: &str) -> (u16, Vec<u8>, u16) {
+        let mut start = None;
+        let mut data = Vec::new();
+
+        for line in text.lines() {
+            let (record_type, rest) = line.split_at(2);
+            let bytes = parse_bytes(rest);
+            assert_eq!(bytes.iter().fold(0u8, |acc, &b| acc.wrapping_add(b)), 0xFF);
+            assert_eq!(bytes.len(), bytes[0] as usize + 1);
+            let addr = u16::from_be_bytes([bytes[1], bytes[2]]);
+
+            match record_type {
+                "S0" => {}
+                "S1" => {
+                    let start = *start.get_or_insert(addr);
+                    assert_eq!(addr as usize, start as usize + data.len());
+                    data.extend(&bytes[3..bytes.len() - 1]);
+                }
+                "S9" => return (start.unwrap(), data, addr),
+                _ => panic!("Unexpected record type {record_type}"),
+            }
+        }
+
+        panic!("No termination record");
+    }
+
+    #[test]
+    fn ihex_test() {
+        assert_eq!(
+            to_ihex(&[(0x200, &[0x60, 0x01, 0x70, 0x01])]),
+            ":040200006001700128\n:00000001FF\n"
+        );
+
+        let data: Vec<u8> = (0..=100).collect();
+        assert_eq!(parse_ihex(&to_ihex(&[(0x200, &data)])), (0x200, data));
+    }
+
+    #[test]
+    fn srec_test() {
+        assert_eq!(
+            to_srec(&[(0x200, &[0x60, 0x01, 0x70, 0x01])]),
+            "S0030000FC\nS10702006001700124\nS9030200FA\n"
+        );
+
+        let data: Vec<u8> = (0..=100).rev().collect();
+        assert_eq!(
+            parse_srec(&to_srec(&[(0x200, &data)])),
+            (0x200, data, 0x200)
+        );
+    }
+
+    #[test]
+    fn segments_test() {
+        let font = [0xF0; 20];
+        let rom = [0x12, 0x00];
+        let segments: [(u16, &[u8]); 2] = [(0x000, &font), (0x200, &rom)];
+
+        assert_eq!(
+            OutputFormat::Bin.format_segments(&segments),
+            [&font[..], &rom[..]].concat()
+        );
+
+        let ihex = to_ihex(&segments);
+        let (font_records, rom_records) = ihex.split_at(ihex.find(":02020000").unwrap());
+        assert_eq!(parse_ihex(font_records), (0x000, font.to_vec()));
+        assert_eq!(parse_ihex(rom_records), (0x200, rom.to_vec()));
+
+        let srec = to_srec(&segments);
+        assert!(srec.contains("S1130000F0F0"));
+        assert!(srec.contains("S10502001200E6"));
+        assert!(srec.ends_with("S9030200FA\n"));
+    }
+}
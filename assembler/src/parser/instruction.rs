@@ -33,6 +33,20 @@ impl<'s> Parser<'s> {
             }};
         }
 
+        // `shr vx` is short for `shr vx, vx`
+        macro_rules! one_or_two_reg {
+            ($pseudo:ident) => {{
+                let (r1, r1_span) = self.parse_arg_general_register(instr_span)?;
+                if self.next_is_argument() {
+                    let (r2, r2_span) =
+                        self.parse_arg_general_register(instr_span.union(&r1_span))?;
+                    (PI::$pseudo(r1, r2), Some(r1_span.union(&r2_span)))
+                } else {
+                    (PI::$pseudo(r1, r1), Some(r1_span))
+                }
+            }};
+        }
+
         macro_rules! reg_or_byte {
             ($r1_span:expr) => {
                 match self.parse_arg_general_register(instr_span.union(&($r1_span))) {
@@ -50,6 +64,8 @@ impl<'s> Parser<'s> {
             IN::Nop => (PI::Nop, None),
             IN::Cls => (PI::Cls, None),
             IN::Ret => (PI::Ret, None),
+            IN::Low => (PI::Low, None),
+            IN::High => (PI::High, None),
             IN::Jmp => {
                 let (addr, span) = self.parse_arg_addr(instr_span)?;
                 (PI::Jmp(addr), Some(span))
@@ -110,8 +126,8 @@ impl<'s> Parser<'s> {
                     Some(instr_span.union(&nibble_span)),
                 )
             }
-            IN::Shr => one_reg!(Shr),
-            IN::Shl => one_reg!(Shl),
+            IN::Shr => one_or_two_reg!(Shr),
+            IN::Shl => one_or_two_reg!(Shl),
             IN::Skp => one_reg!(Skp),
             IN::Sknp => one_reg!(Sknp),
             IN::Delay => one_reg!(Delay),
@@ -194,6 +210,19 @@ impl<'s> Parser<'s> {
         }
     }
 
+    /// Is the next token an argument for the current instruction? This is a register, or an
+    /// identifier that isn't the name of a label, since that starts the next statement.
+    fn next_is_argument(&self) -> bool {
+        match self.peek().map(|token| token.value) {
+            Some(T::GeneralRegisterName(_)) => true,
+            Some(T::Identifier(_)) => !matches!(
+                self.tokens.get(self.current + 1).map(|token| token.value),
+                Some(T::Colon)
+            ),
+            _ => false,
+        }
+    }
+
     fn parse_arg_general_register(
         &mut self,
         previous_span: Span,
@@ -3,7 +3,7 @@
 mod instruction;
 
 use crate::{
-    ast::{AliasableThing, SpanStmt, Stmt},
+    ast::{AliasableThing, OrAlias, SegmentKind, SpanStmt, Stmt},
     error::report_error,
     span::{Span, WithSpan},
     tokens::{self, Token as T, TokenSpan},
@@ -111,18 +111,21 @@ impl<'s> Parser<'s> {
         self.advance();
 
         while !self.is_at_end() {
-            match self.peek() {
-                Some(WithSpan { span: _, value }) => match value {
+            if let Some(WithSpan {
+                span: _,
+                value:
                     T::Identifier(_)
                     | T::InstructionName(_)
                     | T::Define
                     | T::DefineBytes
                     | T::DefineWords
                     | T::Text
-                    | T::Include => return,
-                    _ => {}
-                },
-                _ => {}
+                    | T::Include
+                    | T::IncBin
+                    | T::Segment,
+            }) = self.peek()
+            {
+                return;
             }
 
             self.advance();
@@ -138,14 +141,15 @@ impl<'s> Parser<'s> {
         }
     }
 
-    /// statement → aliasDefinition | RawDataDefinition | label | instruction | include;
+    /// statement → aliasDefinition | RawDataDefinition | label | instruction | include | segment;
     fn parse_statement(&mut self) -> Option<SpanStmt<'s>> {
         let result = match self.peek()?.value {
             T::Define => self.parse_alias_definition(),
             T::DefineBytes | T::DefineWords | T::Text => self.parse_raw_data_definition(),
             T::Identifier(_) => self.parse_label(),
             T::InstructionName(_) => self.parse_instruction(),
-            T::Include => self.parse_include(),
+            T::Include | T::IncBin => self.parse_include(),
+            T::Segment => self.parse_segment_directive(),
             _ => Err(ParseError {
                 token: *self.peek()?,
                 previous_span: None,
@@ -194,16 +198,27 @@ impl<'s> Parser<'s> {
         match next_token.value {
             T::NumericLiteral(number) => Ok(WithSpan {
                 span: prev_span.union(&next_token.span),
-                value: Stmt::AliasDefinition(identifier, AliasableThing::RawData(number)),
+                value: Stmt::AliasDefinition(
+                    identifier,
+                    OrAlias::Concrete(AliasableThing::RawData(number)),
+                ),
             }),
             T::GeneralRegisterName(reg) => Ok(WithSpan {
                 span: prev_span.union(&next_token.span),
-                value: Stmt::AliasDefinition(identifier, AliasableThing::Register(reg)),
+                value: Stmt::AliasDefinition(
+                    identifier,
+                    OrAlias::Concrete(AliasableThing::Register(reg)),
+                ),
+            }),
+            T::Identifier(other) => Ok(WithSpan {
+                span: prev_span.union(&next_token.span),
+                value: Stmt::AliasDefinition(identifier, OrAlias::Alias(other)),
             }),
             _ => Err(ParseError {
                 token: next_token,
                 previous_span: Some(prev_span),
-                message: "Can only create aliases for raw data or general registers".to_string(),
+                message: "Can only create aliases for raw data, general registers, labels, or other aliases"
+                    .to_string(),
             }),
         }
     }
@@ -251,14 +266,16 @@ impl<'s> Parser<'s> {
         })
     }
 
-    /// include → "include" STRING_LITERAL;
+    /// include → ("include" | "incbin") STRING_LITERAL;
     fn parse_include(&mut self) -> ParseResult<'s, SpanStmt<'s>> {
         let WithSpan {
             span: include_span,
-            value: T::Include,
+            value: include_token @ (T::Include | T::IncBin),
         } = self.advance()
         else {
-            panic!("We should only call parse_include() when the previous token is Include");
+            panic!(
+                "We should only call parse_include() when the previous token is Include or IncBin"
+            );
         };
 
         let next_token = self.advance();
@@ -270,13 +287,49 @@ impl<'s> Parser<'s> {
             return Err(ParseError {
                 token: next_token,
                 previous_span: Some(include_span),
-                message: "`include` must be followed with a string literal".to_string(),
+                message: "`include` and `incbin` must be followed with a string literal"
+                    .to_string(),
             });
         };
 
         Ok(WithSpan {
             span: include_span.union(&string_span),
-            value: Stmt::Include(filename),
+            value: if include_token == T::Include {
+                Stmt::Include(filename)
+            } else {
+                Stmt::IncludeBinary(filename)
+            },
+        })
+    }
+
+    /// segment → ".segment" ("code" | "data");
+    fn parse_segment_directive(&mut self) -> ParseResult<'s, SpanStmt<'s>> {
+        let WithSpan {
+            span: segment_span,
+            value: T::Segment,
+        } = self.advance()
+        else {
+            panic!(
+                "We should only call parse_segment_directive() when the previous token is Segment"
+            );
+        };
+
+        let next_token = self.advance();
+        let kind = match next_token.value {
+            T::Identifier("code") => SegmentKind::Code,
+            T::Identifier("data") => SegmentKind::Data,
+            _ => {
+                return Err(ParseError {
+                    token: next_token,
+                    previous_span: Some(segment_span),
+                    message: "`.segment` must be followed by `code` or `data`".to_string(),
+                })
+            }
+        };
+
+        Ok(WithSpan {
+            span: segment_span.union(&next_token.span),
+            value: Stmt::SegmentDirective(kind),
         })
     }
 
@@ -309,3 +362,69 @@ impl<'s> Parser<'s> {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        error::{init_error_reporting, HAD_ERROR},
+        scanner::Scanner,
+    };
+    use std::sync::atomic::Ordering;
+
+    /// Parse a single raw data definition from the given source code.
+    fn parse_data(source: &str) -> ParseResult<'_, SpanStmt<'_>> {
+        let mut parser = Parser {
+            tokens: Scanner::scan_tokens(source),
+            current: 0,
+            statements: vec![],
+        };
+        parser.parse_raw_data_definition()
+    }
+
+    #[test]
+    fn raw_data_range_test() {
+        assert_eq!(
+            parse_data("db 0 255").map(|stmt| stmt.value),
+            Ok(Stmt::RawDataDefinition(vec![0x00, 0xFF]))
+        );
+        assert_eq!(
+            parse_data("dw 65535 #1234").map(|stmt| stmt.value),
+            Ok(Stmt::RawDataDefinition(vec![0xFF, 0xFF, 0x12, 0x34]))
+        );
+
+        let error = parse_data("db 1 256 2").unwrap_err();
+        assert_eq!(
+            error.token,
+            WithSpan {
+                span: Span {
+                    start: 5,
+                    end: 7,
+                    file: 0
+                },
+                value: T::NumericLiteral(256)
+            }
+        );
+        assert_eq!(
+            error.message,
+            "Number in byte definition must only be 8 bit"
+        );
+    }
+
+    #[test]
+    fn raw_data_scanner_errors_test() {
+        let source = "db -1\ndw 65536";
+        init_error_reporting(source.to_string());
+
+        // Neither negative numbers nor numbers above 16 bits get past the scanner
+        let tokens: Vec<_> = Scanner::scan_tokens(source)
+            .into_iter()
+            .map(|token| token.value)
+            .collect();
+        assert_eq!(
+            tokens,
+            [T::DefineBytes, T::NumericLiteral(1), T::DefineWords]
+        );
+        assert!(HAD_ERROR.load(Ordering::Relaxed));
+    }
+}
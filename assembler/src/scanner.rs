@@ -10,6 +10,12 @@ pub struct Scanner<'s> {
     /// The source code.
     source: &'s str,
 
+    /// The source code before it was lowercased, which file paths are taken from.
+    original: &'s str,
+
+    /// The file that the source code is from, which goes in every [`Span`].
+    file: usize,
+
     /// The tokens that we've already scanned out.
     tokens: Vec<WithSpan<Token<'s>>>,
 
@@ -21,10 +27,26 @@ pub struct Scanner<'s> {
 }
 
 impl<'s> Scanner<'s> {
-    /// Scan all the tokens from the given source code.
-    pub fn scan_tokens(source: &'s str) -> Vec<WithSpan<Token>> {
+    /// Scan all the tokens from the given source code, which is the main file.
+    #[cfg(test)]
+    pub fn scan_tokens(source: &'s str) -> Vec<WithSpan<Token<'s>>> {
+        Self::scan_tokens_from(source, source, 0)
+    }
+
+    /// Scan all the tokens from the given file. Mnemonics and names are case insensitive, so
+    /// `source` should be lowercased with [`str::to_ascii_lowercase`], but the file paths after
+    /// `include` and `incbin` are taken from the `original` source so that they keep their case.
+    pub fn scan_tokens_from(
+        source: &'s str,
+        original: &'s str,
+        file: usize,
+    ) -> Vec<WithSpan<Token<'s>>> {
+        debug_assert_eq!(source.len(), original.len());
+
         let mut scanner = Self {
             source,
+            original,
+            file,
             tokens: Vec::new(),
             start: 0,
             current: 0,
@@ -50,6 +72,7 @@ impl<'s> Scanner<'s> {
         Span {
             start: self.start,
             end: self.current - 1,
+            file: self.file,
         }
     }
 
@@ -100,6 +123,7 @@ impl<'s> Scanner<'s> {
             '0'..='9' => self.scan_decimal_number(),
             '%' => self.scan_binary_number(),
             '#' => self.scan_hex_number(),
+            '.' => self.scan_directive(),
             c if c.is_whitespace() => {}
             c if c.is_ascii_alphabetic() || c == '_' => self.scan_identifier_or_keyword(),
             _ => self.report_error(&format!("Unrecognised character: {c:?}")),
@@ -120,9 +144,19 @@ impl<'s> Scanner<'s> {
         // The closing "
         self.advance();
 
+        // File paths keep their case, but other strings like `text` data are
+        // lowercased like the rest of the source
+        let source = match self.tokens.last() {
+            Some(WithSpan {
+                value: Token::Include | Token::IncBin,
+                ..
+            }) => self.original,
+            _ => self.source,
+        };
+
         // Trim the surrounding quotes
         self.add_token(Token::StringLiteral(
-            &self.source[(self.start + 1)..(self.current - 1)],
+            &source[(self.start + 1)..(self.current - 1)],
         ));
     }
 
@@ -168,10 +202,7 @@ impl<'s> Scanner<'s> {
         // Ignore the leading #
         self.advance();
 
-        while self
-            .current_char()
-            .is_some_and(|c| ('0'..='9').contains(&c) || ('a'..='f').contains(&c))
-        {
+        while self.current_char().is_some_and(|c| c.is_ascii_hexdigit()) {
             self.advance();
         }
 
@@ -186,6 +217,21 @@ impl<'s> Scanner<'s> {
         self.add_token(Token::NumericLiteral(num));
     }
 
+    /// Scan a directive that starts with a `.`, like `.segment`.
+    fn scan_directive(&mut self) {
+        while self
+            .current_char()
+            .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            self.advance();
+        }
+
+        match &self.source[self.start..self.current] {
+            ".segment" => self.add_token(Token::Segment),
+            directive => self.report_error(&format!("Unknown directive: {directive:?}")),
+        }
+    }
+
     /// Scan a single identifier or keyword.
     fn scan_identifier_or_keyword(&mut self) {
         use GeneralRegisterName as G;
@@ -208,6 +254,8 @@ impl<'s> Scanner<'s> {
             "nop" => Token::InstructionName(I::Nop),
             "cls" => Token::InstructionName(I::Cls),
             "ret" => Token::InstructionName(I::Ret),
+            "low" => Token::InstructionName(I::Low),
+            "high" => Token::InstructionName(I::High),
             "jmp" | "jp" => Token::InstructionName(I::Jmp),
             "jmpp" | "jpp" => Token::InstructionName(I::Jmpp),
             "call" => Token::InstructionName(I::Call),
@@ -262,8 +310,9 @@ impl<'s> Scanner<'s> {
             "dw" => Token::DefineWords,
             "text" => Token::Text,
 
-            // Include
+            // Includes
             "include" => Token::Include,
+            "incbin" => Token::IncBin,
 
             // Identifier
             _ => Token::Identifier(word_slice),
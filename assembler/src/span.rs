@@ -10,6 +10,10 @@ pub struct Span {
 
     /// The index of the end of the span (inclusive).
     pub end: usize,
+
+    /// The file that the span is in, where 0 is the main file and included files are numbered by
+    /// [`add_source_file`](crate::error::add_source_file).
+    pub file: usize,
 }
 
 impl fmt::Debug for Span {
@@ -18,6 +22,7 @@ impl fmt::Debug for Span {
             f.debug_struct("Span")
                 .field("start", &self.start)
                 .field("end", &self.end)
+                .field("file", &self.file)
                 .finish()
         } else {
             write!(f, "")
@@ -26,11 +31,12 @@ impl fmt::Debug for Span {
 }
 
 impl Span {
-    /// Join two spans.
+    /// Join two spans, which should be in the same file.
     pub fn union(&self, other: &Self) -> Self {
         Self {
             start: cmp::min(self.start, other.start),
             end: cmp::max(self.end, other.end),
+            file: self.file,
         }
     }
 
@@ -1,6 +1,7 @@
 //! This module contains token definitions.
 
 use crate::span::WithSpan;
+use thiserror::Error;
 
 pub type TokenSpan<'s> = WithSpan<Token<'s>>;
 
@@ -17,8 +18,10 @@ pub enum Token<'s> {
     DefineWords,
     NumericLiteral(u16),
     Include,
+    IncBin,
     StringLiteral(&'s str),
     Text,
+    Segment,
 }
 
 /// All the instruction mnemonics.
@@ -27,6 +30,8 @@ pub enum InstructionName {
     Nop,
     Cls,
     Ret,
+    Low,
+    High,
     Jmp,
     Jmpp,
     Call,
@@ -75,6 +80,39 @@ pub enum GeneralRegisterName {
     Vf = 15,
 }
 
+impl TryFrom<u8> for GeneralRegisterName {
+    type Error = InvalidRegisterError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        use GeneralRegisterName as R;
+
+        Ok(match value {
+            0 => R::V0,
+            1 => R::V1,
+            2 => R::V2,
+            3 => R::V3,
+            4 => R::V4,
+            5 => R::V5,
+            6 => R::V6,
+            7 => R::V7,
+            8 => R::V8,
+            9 => R::V9,
+            10 => R::Va,
+            11 => R::Vb,
+            12 => R::Vc,
+            13 => R::Vd,
+            14 => R::Ve,
+            15 => R::Vf,
+            _ => return Err(InvalidRegisterError(value)),
+        })
+    }
+}
+
+/// An error returned when a register number is too big to name a general register.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Error)]
+#[error("There are only 16 general registers, so {0} is not a valid register number")]
+pub struct InvalidRegisterError(pub u8);
+
 /// The special registers used in mnemonics.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum SpecialRegisterName {
@@ -27,6 +27,27 @@ pub fn decode(bytes: [u8; 2]) -> Result<Instruction, DecodingError> {
     Ok(match (n1, n2, n3, n4) {
         (0, 0, 0xE, 0) => I::ClearScreen,
         (0, 0, 0xE, 0xE) => I::Return,
+        #[cfg(feature = "schip")]
+        (0, 0, 0xF, 0xE) => I::LowRes,
+        #[cfg(feature = "schip")]
+        (0, 0, 0xF, 0xF) => I::HighRes,
+        #[cfg(feature = "schip")]
+        (0, 0, 0xC, n) => I::ScrollDown(n),
+        #[cfg(feature = "schip")]
+        (0, 0, 0xF, 0xB) => I::ScrollRight,
+        #[cfg(feature = "schip")]
+        (0, 0, 0xF, 0xC) => I::ScrollLeft,
+        #[cfg(feature = "schip")]
+        (0, 0, 0xF, 0xD) => I::Exit,
+        // Without SUPER-CHIP, reject scrolling instead of mistaking it for a machine code routine.
+        // Other 0nnn opcodes are skipped as a Nop, but a SUPER-CHIP program that scrolls would
+        // silently draw the wrong thing, so interpreters should report it instead.
+        #[cfg(not(feature = "schip"))]
+        (0, 0, 0xC, _) => {
+            return Err(DecodingError::UnrecognisedBytecode(u16::from_be_bytes([
+                b1, b2,
+            ])))
+        }
         (0, _, _, _) => I::Nop,
         (1, n2, n3, n4) => {
             let address = ((n2 as u16) << 8) + ((n3 as u16) << 4) + n4 as u16;
@@ -47,6 +68,12 @@ pub fn decode(bytes: [u8; 2]) -> Result<Instruction, DecodingError> {
         (3, x, _, _) => I::SkipIfEqual(x, Lit(b2)),
         (4, x, _, _) => I::SkipIfNotEqual(x, Lit(b2)),
         (5, x, y, 0) => I::SkipIfEqual(x, Reg(y)),
+        // There are no other 5xyn instructions, so the last nibble has to be zero
+        (5, _, _, _) => {
+            return Err(DecodingError::UnrecognisedBytecode(u16::from_be_bytes([
+                b1, b2,
+            ])))
+        }
         (6, x, _, _) => I::LoadRegister(x, Lit(b2)),
         (7, x, _, _) => I::AddNoCarry(x, b2),
         (8, x, y, 0) => I::LoadRegister(x, Reg(y)),
@@ -55,9 +82,9 @@ pub fn decode(bytes: [u8; 2]) -> Result<Instruction, DecodingError> {
         (8, x, y, 3) => I::Xor(x, y),
         (8, x, y, 4) => I::AddWithCarry(x, y),
         (8, x, y, 5) => I::Sub(x, y),
-        (8, x, _, 6) => I::ShiftRight(x),
+        (8, x, y, 6) => I::ShiftRight(x, y),
         (8, x, y, 7) => I::SubN(x, y),
-        (8, x, _, 0xE) => I::ShiftLeft(x),
+        (8, x, y, 0xE) => I::ShiftLeft(x, y),
         (9, x, y, 0) => I::SkipIfNotEqual(x, Reg(y)),
         (0xA, n2, n3, n4) => {
             let address = ((n2 as u16) << 8) + ((n3 as u16) << 4) + n4 as u16;
@@ -76,7 +103,12 @@ pub fn decode(bytes: [u8; 2]) -> Result<Instruction, DecodingError> {
             I::JumpPlusV0(address)
         }
         (0xC, x, _, _) => I::LoadRandomWithMask(x, b2),
-        (0xD, x, y, n) => I::Draw(x, y, n),
+        #[cfg(feature = "schip")]
+        (0xD, x, y, 0) => I::DrawLargeSprite(x, y),
+        (0xD, x, y, n) => {
+            debug_assert!(n <= 0xF, "Nibbles should only ever be 4 bits");
+            I::Draw(x, y, n)
+        }
         (0xE, x, 9, 0xE) => I::SkipIfKeyPressed(x),
         (0xE, x, 0xA, 1) => I::SkipIfKeyNotPressed(x),
         (0xF, x, 0, 7) => I::LoadFromDelayTimer(x),
@@ -85,9 +117,15 @@ pub fn decode(bytes: [u8; 2]) -> Result<Instruction, DecodingError> {
         (0xF, x, 1, 8) => I::LoadIntoSoundTimer(x),
         (0xF, x, 1, 0xE) => I::AddToMemoryRegister(x),
         (0xF, x, 2, 9) => I::LoadDigitAddress(x),
+        #[cfg(feature = "schip")]
+        (0xF, x, 3, 0) => I::LoadLargeDigitAddress(x),
         (0xF, x, 3, 3) => I::StoreBcdInMemory(x),
         (0xF, x, 5, 5) => I::StoreRegistersInMemory(x),
         (0xF, x, 6, 5) => I::ReadRegistersFromMemory(x),
+        #[cfg(feature = "schip")]
+        (0xF, x, 7, 5) => I::StoreRegistersInRpl(x),
+        #[cfg(feature = "schip")]
+        (0xF, x, 8, 5) => I::ReadRegistersFromRpl(x),
         _ => {
             return Err(DecodingError::UnrecognisedBytecode(u16::from_be_bytes([
                 b1, b2,
@@ -96,6 +134,38 @@ pub fn decode(bytes: [u8; 2]) -> Result<Instruction, DecodingError> {
     })
 }
 
+/// Decode a whole program, two bytes at a time from the start of `bytes`. Each instruction comes
+/// with its address, which is its offset from the start of the program. If there's an odd number
+/// of bytes, the last one is ignored.
+///
+/// Addresses are 16 bits, so only the first 64 KiB is decoded, and anything after that is
+/// ignored.
+pub fn decode_program(bytes: &[u8]) -> Vec<(u16, Result<Instruction, DecodingError>)> {
+    bytes
+        .chunks_exact(2)
+        .enumerate()
+        .map_while(|(idx, pair)| Some((u16::try_from(idx * 2).ok()?, decode([pair[0], pair[1]]))))
+        .collect()
+}
+
+impl TryFrom<[u8; 2]> for Instruction {
+    type Error = DecodingError;
+
+    /// Decode a pair of bytes with [`decode`].
+    fn try_from(bytes: [u8; 2]) -> Result<Self, Self::Error> {
+        decode(bytes)
+    }
+}
+
+impl TryFrom<u16> for Instruction {
+    type Error = DecodingError;
+
+    /// Decode an opcode like `0xD015` with [`decode`].
+    fn try_from(opcode: u16) -> Result<Self, Self::Error> {
+        decode(opcode.to_be_bytes())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -104,11 +174,67 @@ mod tests {
         decode(instr.to_be_bytes())
     }
 
+    #[test]
+    fn try_from_test() {
+        assert_eq!(
+            Instruction::try_from(0xD015),
+            Ok(Instruction::Draw(0, 1, 5))
+        );
+        assert_eq!(Instruction::try_from([0xD0, 0x15]), dec(0xD015));
+        assert_eq!(
+            Instruction::try_from(0xFFFF),
+            Err(DecodingError::UnrecognisedBytecode(0xFFFF))
+        );
+
+        let instruction: Result<Instruction, _> = [0x00, 0xE0].try_into();
+        assert_eq!(instruction, Ok(Instruction::ClearScreen));
+    }
+
+    #[test]
+    fn decode_program_test() {
+        use Instruction as I;
+        use Operand::Literal as Lit;
+
+        assert_eq!(decode_program(&[]), []);
+        assert_eq!(decode_program(&[0x00]), []);
+        assert_eq!(
+            decode_program(&[0x00, 0xE0, 0x60, 0x05, 0xFF, 0xFF, 0x12, 0x00, 0xAB]),
+            [
+                (0, Ok(I::ClearScreen)),
+                (2, Ok(I::LoadRegister(0, Lit(5)))),
+                (4, Err(DecodingError::UnrecognisedBytecode(0xFFFF))),
+                (6, Ok(I::Jump(0x200))),
+            ]
+        );
+
+        // Offsets past 0xFFFF don't fit in an address, so decoding stops there
+        let decoded = decode_program(&[0x00; 0x10004]);
+        assert_eq!(decoded.len(), 0x8000);
+        assert_eq!(decoded.last(), Some(&(0xFFFE, Ok(I::Nop))));
+
+        #[cfg(feature = "encode")]
+        {
+            let instructions = [
+                I::ClearScreen,
+                I::Draw(1, 2, 3),
+                I::ShiftLeft(4, 5),
+                I::Return,
+            ];
+            let bytes = crate::encode_all(&instructions).unwrap();
+            let decoded: Vec<_> = decode_program(&bytes)
+                .into_iter()
+                .map(|(_, instruction)| instruction.unwrap())
+                .collect();
+            assert_eq!(decoded, instructions);
+        }
+    }
+
     #[test]
     fn decode_success_test() {
         use Instruction as I;
         use Operand::{Literal as Lit, Register as Reg};
 
+        // 0000 is caught by the 0nnn arm, so it decodes to Nop like encode expects
         assert_eq!(dec(0x0000), Ok(I::Nop));
         assert_eq!(dec(0x0123), Ok(I::Nop));
         assert_eq!(dec(0x0FFF), Ok(I::Nop));
@@ -200,12 +326,12 @@ mod tests {
         assert_eq!(dec(0x8F25), Ok(I::Sub(15, 2)));
         assert_eq!(dec(0x83C5), Ok(I::Sub(3, 12)));
 
-        assert_eq!(dec(0x8016), Ok(I::ShiftRight(0)));
-        assert_eq!(dec(0x8496), Ok(I::ShiftRight(4)));
-        assert_eq!(dec(0x8806), Ok(I::ShiftRight(8)));
-        assert_eq!(dec(0x8CA6), Ok(I::ShiftRight(12)));
-        assert_eq!(dec(0x8F26), Ok(I::ShiftRight(15)));
-        assert_eq!(dec(0x83C6), Ok(I::ShiftRight(3)));
+        assert_eq!(dec(0x8016), Ok(I::ShiftRight(0, 1)));
+        assert_eq!(dec(0x8496), Ok(I::ShiftRight(4, 9)));
+        assert_eq!(dec(0x8806), Ok(I::ShiftRight(8, 0)));
+        assert_eq!(dec(0x8CA6), Ok(I::ShiftRight(12, 10)));
+        assert_eq!(dec(0x8F26), Ok(I::ShiftRight(15, 2)));
+        assert_eq!(dec(0x83C6), Ok(I::ShiftRight(3, 12)));
 
         assert_eq!(dec(0x8017), Ok(I::SubN(0, 1)));
         assert_eq!(dec(0x8497), Ok(I::SubN(4, 9)));
@@ -214,12 +340,12 @@ mod tests {
         assert_eq!(dec(0x8F27), Ok(I::SubN(15, 2)));
         assert_eq!(dec(0x83C7), Ok(I::SubN(3, 12)));
 
-        assert_eq!(dec(0x801E), Ok(I::ShiftLeft(0)));
-        assert_eq!(dec(0x849E), Ok(I::ShiftLeft(4)));
-        assert_eq!(dec(0x880E), Ok(I::ShiftLeft(8)));
-        assert_eq!(dec(0x8CAE), Ok(I::ShiftLeft(12)));
-        assert_eq!(dec(0x8F2E), Ok(I::ShiftLeft(15)));
-        assert_eq!(dec(0x83CE), Ok(I::ShiftLeft(3)));
+        assert_eq!(dec(0x801E), Ok(I::ShiftLeft(0, 1)));
+        assert_eq!(dec(0x849E), Ok(I::ShiftLeft(4, 9)));
+        assert_eq!(dec(0x880E), Ok(I::ShiftLeft(8, 0)));
+        assert_eq!(dec(0x8CAE), Ok(I::ShiftLeft(12, 10)));
+        assert_eq!(dec(0x8F2E), Ok(I::ShiftLeft(15, 2)));
+        assert_eq!(dec(0x83CE), Ok(I::ShiftLeft(3, 12)));
 
         assert_eq!(dec(0xA375), Ok(I::LoadMemoryRegister(0x375)));
         assert_eq!(dec(0xA200), Ok(I::LoadMemoryRegister(0x200)));
@@ -317,6 +443,46 @@ mod tests {
         assert_eq!(dec(0xFE65), Ok(I::ReadRegistersFromMemory(14)));
     }
 
+    #[cfg(feature = "schip")]
+    #[test]
+    fn decode_schip_test() {
+        use Instruction as I;
+
+        assert_eq!(dec(0x00C0), Ok(I::ScrollDown(0)));
+        assert_eq!(dec(0x00C7), Ok(I::ScrollDown(7)));
+        assert_eq!(dec(0x00FB), Ok(I::ScrollRight));
+        assert_eq!(dec(0x00FC), Ok(I::ScrollLeft));
+        assert_eq!(dec(0x00FD), Ok(I::Exit));
+        assert_eq!(dec(0x00FE), Ok(I::LowRes));
+        assert_eq!(dec(0x00FF), Ok(I::HighRes));
+        assert_eq!(dec(0xD120), Ok(I::DrawLargeSprite(1, 2)));
+        assert_eq!(dec(0xD121), Ok(I::Draw(1, 2, 1)));
+        assert_eq!(dec(0xF430), Ok(I::LoadLargeDigitAddress(4)));
+        assert_eq!(dec(0xF775), Ok(I::StoreRegistersInRpl(7)));
+        assert_eq!(dec(0xF385), Ok(I::ReadRegistersFromRpl(3)));
+    }
+
+    #[cfg(not(feature = "schip"))]
+    #[test]
+    fn decode_scroll_without_schip_test() {
+        for n in 0..=0xF {
+            let opcode = 0x00C0 | n;
+            assert_eq!(
+                dec(opcode),
+                Err(DecodingError::UnrecognisedBytecode(opcode))
+            );
+        }
+
+        // Only 00Cn is rejected, so the machine code routines either side are still a Nop
+        assert_eq!(dec(0x00BF), Ok(Instruction::Nop));
+        assert_eq!(dec(0x00D0), Ok(Instruction::Nop));
+        assert_eq!(dec(0x0C00), Ok(Instruction::Nop));
+
+        // Switching graphics modes is SUPER-CHIP too, but old ROMs ran it as a harmless Nop
+        assert_eq!(dec(0x00FE), Ok(Instruction::Nop));
+        assert_eq!(dec(0x00FF), Ok(Instruction::Nop));
+    }
+
     #[test]
     fn decode_error_test() {
         assert_eq!(
@@ -339,6 +505,7 @@ mod tests {
             dec(0x8FFF),
             Err(DecodingError::UnrecognisedBytecode(0x8FFF))
         );
+        #[cfg(not(feature = "schip"))]
         assert_eq!(
             dec(0x00CD),
             Err(DecodingError::UnrecognisedBytecode(0x00CD))
@@ -352,4 +519,21 @@ mod tests {
             Err(DecodingError::UnrecognisedBytecode(0xE17C))
         );
     }
+
+    #[test]
+    fn decode_skip_register_error_test() {
+        for x in 0..=0xF {
+            for y in 0..=0xF {
+                for n in 1..=0xF {
+                    for opcode in [0x5000, 0x9000] {
+                        let opcode = opcode | x << 8 | y << 4 | n;
+                        assert_eq!(
+                            dec(opcode),
+                            Err(DecodingError::UnrecognisedBytecode(opcode))
+                        );
+                    }
+                }
+            }
+        }
+    }
 }
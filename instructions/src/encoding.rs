@@ -1,6 +1,7 @@
 //! This module handles encoding instructions to bytecode.
 
 use crate::{Instruction, Operand};
+use std::fmt;
 use thiserror::Error;
 
 /// A potential error when encoding.
@@ -17,11 +18,69 @@ pub enum EncodingError {
 
     /// A nibble is only four bits, but the smallest integer type in Rust is 8 bits. This error
     /// means a `u8` that was expected to be a nibble was too big. This error is only produced when
-    /// trying to encode [`Instruction::Draw`].
+    /// trying to encode [`Instruction::Draw`] or [`Instruction::ScrollDown`].
     #[error("This number should be one nibble: 0x{0:0>2X}")]
     NibbleTooBig(u8),
 }
 
+impl EncodingError {
+    /// Try to recover from this error by encoding the instruction that caused it with
+    /// [`encode_clamped`]. The error doesn't keep the instruction, so it has to be passed in
+    /// again. Returns `None` if encoding `instruction` doesn't give this error.
+    pub fn recover(&self, instruction: Instruction) -> Option<[u8; 2]> {
+        (encode(instruction) == Err(*self)).then(|| encode_clamped(instruction))
+    }
+}
+
+/// An error when encoding a list of instructions with [`encode_all`]. [`EncodingError`] is
+/// [`Copy`], so this wraps it to say which instruction failed instead of boxing it in a new variant.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Error)]
+#[error("Failed to encode the instruction at index {index}: {error}")]
+pub struct EncodeAllError {
+    /// The index of the instruction that couldn't be encoded.
+    pub index: usize,
+
+    /// Why the instruction couldn't be encoded.
+    #[source]
+    pub error: EncodingError,
+}
+
+/// An instruction that can be encoded, but probably doesn't do what the programmer wanted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Error)]
+pub enum StrictWarning {
+    /// Drawing a sprite with 0 rows does nothing on the original CHIP-8. SUPER-CHIP uses this to
+    /// draw a 16x16 sprite instead.
+    #[error("Drawing a sprite with 0 rows does nothing outside of SUPER-CHIP")]
+    DrawZeroRows,
+
+    /// A random number masked with 0 is always 0.
+    #[error("A random number with a mask of 0 is always 0")]
+    RandomMaskZero,
+
+    /// ORing a register with itself doesn't change it.
+    #[error("ORing V{0:X} with itself doesn't change it")]
+    OrSelf(u8),
+
+    /// XORing a register with itself always sets it to 0.
+    #[error("XORing V{0:X} with itself always sets it to 0")]
+    XorSelf(u8),
+}
+
+/// A potential error when encoding in strict mode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Error)]
+pub enum StrictEncodingError {
+    /// The instruction couldn't be encoded at all.
+    #[error(transparent)]
+    Encoding(#[from] EncodingError),
+
+    /// The instruction was encoded into `bytes`, but it looks suspicious.
+    #[error("{cause}")]
+    Suspicious {
+        cause: StrictWarning,
+        bytes: [u8; 2],
+    },
+}
+
 /// Return an error if the address is too big.
 #[inline]
 fn assert_addr(addr: u16) -> Result<(), EncodingError> {
@@ -51,6 +110,18 @@ pub fn encode(instruction: Instruction) -> Result<[u8; 2], EncodingError> {
         I::Nop => 0x0000,
         I::ClearScreen => 0x00E0,
         I::Return => 0x00EE,
+        I::LowRes => 0x00FE,
+        I::HighRes => 0x00FF,
+        I::ScrollDown(n) => {
+            // 00Cn
+            if n > 15 {
+                return Err(EncodingError::NibbleTooBig(n));
+            }
+            0x00C0 | n as u16
+        }
+        I::ScrollRight => 0x00FB,
+        I::ScrollLeft => 0x00FC,
+        I::Exit => 0x00FD,
         I::Jump(address) => {
             // 1nnn
             assert_addr(address)?;
@@ -129,10 +200,11 @@ pub fn encode(instruction: Instruction) -> Result<[u8; 2], EncodingError> {
             assert_reg(r2)?;
             0x8005 | (r1 as u16) << 8 | (r2 as u16) << 4
         }
-        I::ShiftRight(reg) => {
-            // 8x_6
-            assert_reg(reg)?;
-            0x8006 | (reg as u16) << 8
+        I::ShiftRight(r1, r2) => {
+            // 8xy6
+            assert_reg(r1)?;
+            assert_reg(r2)?;
+            0x8006 | (r1 as u16) << 8 | (r2 as u16) << 4
         }
         I::SubN(r1, r2) => {
             // 8xy7
@@ -140,10 +212,11 @@ pub fn encode(instruction: Instruction) -> Result<[u8; 2], EncodingError> {
             assert_reg(r2)?;
             0x8007 | (r1 as u16) << 8 | (r2 as u16) << 4
         }
-        I::ShiftLeft(reg) => {
-            // 8x_E
-            assert_reg(reg)?;
-            0x800E | (reg as u16) << 8
+        I::ShiftLeft(r1, r2) => {
+            // 8xyE
+            assert_reg(r1)?;
+            assert_reg(r2)?;
+            0x800E | (r1 as u16) << 8 | (r2 as u16) << 4
         }
         I::LoadMemoryRegister(address) => {
             // Annn
@@ -169,6 +242,12 @@ pub fn encode(instruction: Instruction) -> Result<[u8; 2], EncodingError> {
             }
             0xD000 | (x as u16) << 8 | (y as u16) << 4 | n as u16
         }
+        I::DrawLargeSprite(x, y) => {
+            // Dxy0
+            assert_reg(x)?;
+            assert_reg(y)?;
+            0xD000 | (x as u16) << 8 | (y as u16) << 4
+        }
         I::SkipIfKeyPressed(reg) => {
             // Ex9E
             assert_reg(reg)?;
@@ -209,6 +288,11 @@ pub fn encode(instruction: Instruction) -> Result<[u8; 2], EncodingError> {
             assert_reg(reg)?;
             0xF029 | (reg as u16) << 8
         }
+        I::LoadLargeDigitAddress(reg) => {
+            // Fx30
+            assert_reg(reg)?;
+            0xF030 | (reg as u16) << 8
+        }
         I::StoreBcdInMemory(reg) => {
             // Fx33
             assert_reg(reg)?;
@@ -224,13 +308,231 @@ pub fn encode(instruction: Instruction) -> Result<[u8; 2], EncodingError> {
             assert_reg(reg)?;
             0xF065 | (reg as u16) << 8
         }
+        I::StoreRegistersInRpl(reg) => {
+            // Fx75
+            assert_reg(reg)?;
+            0xF075 | (reg as u16) << 8
+        }
+        I::ReadRegistersFromRpl(reg) => {
+            // Fx85
+            assert_reg(reg)?;
+            0xF085 | (reg as u16) << 8
+        }
     }))
 }
 
+/// Mask every operand of the instruction to the number of bits that it gets in the encoding, so
+/// that addresses are 12 bits, and registers and nibbles are 4 bits.
+fn mask_operands(instruction: Instruction) -> Instruction {
+    use Instruction as I;
+
+    let op = |operand| match operand {
+        Operand::Register(reg) => Operand::Register(reg & 0xF),
+        literal @ Operand::Literal(_) => literal,
+    };
+
+    match instruction {
+        I::Nop | I::ClearScreen | I::Return | I::LowRes | I::HighRes => instruction,
+        I::ScrollRight | I::ScrollLeft | I::Exit => instruction,
+        I::ScrollDown(n) => I::ScrollDown(n & 0xF),
+        I::Jump(address) => I::Jump(address & 0xFFF),
+        I::Call(address) => I::Call(address & 0xFFF),
+        I::SkipIfEqual(x, operand) => I::SkipIfEqual(x & 0xF, op(operand)),
+        I::SkipIfNotEqual(x, operand) => I::SkipIfNotEqual(x & 0xF, op(operand)),
+        I::LoadRegister(x, operand) => I::LoadRegister(x & 0xF, op(operand)),
+        I::AddNoCarry(x, byte) => I::AddNoCarry(x & 0xF, byte),
+        I::Or(x, y) => I::Or(x & 0xF, y & 0xF),
+        I::And(x, y) => I::And(x & 0xF, y & 0xF),
+        I::Xor(x, y) => I::Xor(x & 0xF, y & 0xF),
+        I::AddWithCarry(x, y) => I::AddWithCarry(x & 0xF, y & 0xF),
+        I::Sub(x, y) => I::Sub(x & 0xF, y & 0xF),
+        I::ShiftRight(x, y) => I::ShiftRight(x & 0xF, y & 0xF),
+        I::SubN(x, y) => I::SubN(x & 0xF, y & 0xF),
+        I::ShiftLeft(x, y) => I::ShiftLeft(x & 0xF, y & 0xF),
+        I::LoadMemoryRegister(address) => I::LoadMemoryRegister(address & 0xFFF),
+        I::JumpPlusV0(address) => I::JumpPlusV0(address & 0xFFF),
+        I::LoadRandomWithMask(x, mask) => I::LoadRandomWithMask(x & 0xF, mask),
+        I::Draw(x, y, n) => I::Draw(x & 0xF, y & 0xF, n & 0xF),
+        I::DrawLargeSprite(x, y) => I::DrawLargeSprite(x & 0xF, y & 0xF),
+        I::SkipIfKeyPressed(x) => I::SkipIfKeyPressed(x & 0xF),
+        I::SkipIfKeyNotPressed(x) => I::SkipIfKeyNotPressed(x & 0xF),
+        I::LoadFromDelayTimer(x) => I::LoadFromDelayTimer(x & 0xF),
+        I::WaitForKeyPress(x) => I::WaitForKeyPress(x & 0xF),
+        I::LoadIntoDelayTimer(x) => I::LoadIntoDelayTimer(x & 0xF),
+        I::LoadIntoSoundTimer(x) => I::LoadIntoSoundTimer(x & 0xF),
+        I::AddToMemoryRegister(x) => I::AddToMemoryRegister(x & 0xF),
+        I::LoadDigitAddress(x) => I::LoadDigitAddress(x & 0xF),
+        I::LoadLargeDigitAddress(x) => I::LoadLargeDigitAddress(x & 0xF),
+        I::StoreBcdInMemory(x) => I::StoreBcdInMemory(x & 0xF),
+        I::StoreRegistersInMemory(x) => I::StoreRegistersInMemory(x & 0xF),
+        I::ReadRegistersFromMemory(x) => I::ReadRegistersFromMemory(x & 0xF),
+        I::StoreRegistersInRpl(x) => I::StoreRegistersInRpl(x & 0xF),
+        I::ReadRegistersFromRpl(x) => I::ReadRegistersFromRpl(x & 0xF),
+    }
+}
+
+/// Encode every instruction with [`encode`] and concatenate the bytes, stopping at the first
+/// instruction that can't be encoded.
+pub fn encode_all(instructions: &[Instruction]) -> Result<Vec<u8>, EncodeAllError> {
+    let mut bytes = Vec::with_capacity(instructions.len() * 2);
+    for (index, &instruction) in instructions.iter().enumerate() {
+        let encoded = encode(instruction).map_err(|error| EncodeAllError { index, error })?;
+        bytes.extend(encoded);
+    }
+    Ok(bytes)
+}
+
+/// Encode every instruction like [`encode_all`], panicking if any of them can't be encoded.
+///
+/// This is meant for instructions that are known to be valid, like ones that were just decoded.
+pub fn encode_all_unchecked(instructions: &[Instruction]) -> Vec<u8> {
+    match encode_all(instructions) {
+        Ok(bytes) => bytes,
+        Err(e) => panic!("{e}"),
+    }
+}
+
+/// Encode an instruction into a pair of bytes, like [`encode`], but mask any operands that are
+/// too big instead of returning an error. For example, register 17 is masked to 1, and address
+/// 0x1234 is masked to 0x234. Valid instructions are encoded exactly like [`encode`].
+///
+/// This is meant for fuzzing and testing. Don't use it in real code, since it silently turns an
+/// invalid instruction into a completely different valid one.
+pub fn encode_clamped(instruction: Instruction) -> [u8; 2] {
+    encode(mask_operands(instruction)).expect("Masked operands should always be valid")
+}
+
+/// Encode an instruction into a pair of bytes, like [`encode`], but also return an error if the
+/// instruction is valid but suspicious. The error contains the encoded bytes, so the caller can
+/// treat it as a warning and use them anyway.
+pub fn encode_strict(instruction: Instruction) -> Result<[u8; 2], StrictEncodingError> {
+    use Instruction as I;
+
+    let bytes = encode(instruction)?;
+    let cause = match instruction {
+        I::Draw(_, _, 0) => StrictWarning::DrawZeroRows,
+        I::LoadRandomWithMask(_, 0) => StrictWarning::RandomMaskZero,
+        I::Or(x, y) if x == y => StrictWarning::OrSelf(x),
+        I::Xor(x, y) if x == y => StrictWarning::XorSelf(x),
+        _ => return Ok(bytes),
+    };
+
+    Err(StrictEncodingError::Suspicious { cause, bytes })
+}
+
+/// Format the opcode of the instruction with the given formatting function for `u16`, or just
+/// `?` if it can't be encoded. The formatting options like width and `#` are passed through.
+fn fmt_opcode(
+    instruction: Instruction,
+    f: &mut fmt::Formatter<'_>,
+    fmt_u16: fn(&u16, &mut fmt::Formatter<'_>) -> fmt::Result,
+) -> fmt::Result {
+    match encode(instruction) {
+        Ok(bytes) => fmt_u16(&u16::from_be_bytes(bytes), f),
+        Err(_) => f.pad("?"),
+    }
+}
+
+/// Format the opcode in lowercase hex, so `format!("{:04x}", Instruction::Draw(3, 5, 4))` gives
+/// `"d354"`. Instructions that can't be encoded are formatted as `?`.
+impl fmt::LowerHex for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt_opcode(*self, f, fmt::LowerHex::fmt)
+    }
+}
+
+/// Format the opcode in uppercase hex, so `format!("{:04X}", Instruction::Draw(3, 5, 4))` gives
+/// `"D354"`. Instructions that can't be encoded are formatted as `?`.
+impl fmt::UpperHex for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt_opcode(*self, f, fmt::UpperHex::fmt)
+    }
+}
+
+/// Format the opcode in binary, so `format!("{:016b}", Instruction::Draw(3, 5, 4))` gives
+/// `"1101001101010100"`. Instructions that can't be encoded are formatted as `?`.
+impl fmt::Binary for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt_opcode(*self, f, fmt::Binary::fmt)
+    }
+}
+
+impl TryFrom<Instruction> for [u8; 2] {
+    type Error = EncodingError;
+
+    /// Encode the instruction with [`encode`].
+    fn try_from(instruction: Instruction) -> Result<Self, Self::Error> {
+        encode(instruction)
+    }
+}
+
+impl TryFrom<Instruction> for u16 {
+    type Error = EncodingError;
+
+    /// Encode the instruction with [`encode`] into an opcode like `0xD015`.
+    fn try_from(instruction: Instruction) -> Result<Self, Self::Error> {
+        encode(instruction).map(u16::from_be_bytes)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn encode_all_test() {
+        use Instruction as I;
+
+        assert_eq!(encode_all(&[]), Ok(vec![]));
+        assert_eq!(
+            encode_all(&[I::ClearScreen, I::Draw(1, 2, 3), I::Jump(0x200)]),
+            Ok(vec![0x00, 0xE0, 0xD1, 0x23, 0x12, 0x00])
+        );
+        assert_eq!(
+            encode_all_unchecked(&[I::Return, I::AddWithCarry(3, 4)]),
+            [0x00, 0xEE, 0x83, 0x44]
+        );
+
+        // Only the first error is returned
+        let error = encode_all(&[I::Nop, I::Jump(0x1234), I::Draw(16, 0, 1)]).unwrap_err();
+        assert_eq!(
+            error,
+            EncodeAllError {
+                index: 1,
+                error: EncodingError::AddressTooBig(0x1234)
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "Failed to encode the instruction at index 1: This address is more than 12 bits: 0x1234"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "index 2")]
+    fn encode_all_unchecked_panic_test() {
+        encode_all_unchecked(&[
+            Instruction::Nop,
+            Instruction::Nop,
+            Instruction::Draw(0, 0, 16),
+        ]);
+    }
+
+    #[test]
+    fn try_from_test() {
+        use Instruction as I;
+
+        assert_eq!(u16::try_from(I::Draw(0, 1, 5)), Ok(0xD015));
+        assert_eq!(<[u8; 2]>::try_from(I::Draw(0, 1, 5)), Ok([0xD0, 0x15]));
+        assert_eq!(
+            u16::try_from(I::Jump(0x1234)),
+            Err(EncodingError::AddressTooBig(0x1234))
+        );
+
+        let opcode: Result<u16, _> = I::Return.try_into();
+        assert_eq!(opcode, Ok(0x00EE));
+    }
+
     #[test]
     fn encode_success_test() {
         use Instruction as I;
@@ -245,6 +547,8 @@ mod tests {
         assert_eq!(enc(I::ClearScreen), Ok(0x00E0));
 
         assert_eq!(enc(I::Return), Ok(0x00EE));
+        assert_eq!(enc(I::LowRes), Ok(0x00FE));
+        assert_eq!(enc(I::HighRes), Ok(0x00FF));
 
         assert_eq!(enc(I::Jump(0x37C)), Ok(0x137C));
         assert_eq!(enc(I::Jump(0x590)), Ok(0x1590));
@@ -328,12 +632,13 @@ mod tests {
         assert_eq!(enc(I::Sub(15, 2)), Ok(0x8F25));
         assert_eq!(enc(I::Sub(3, 12)), Ok(0x83C5));
 
-        assert_eq!(enc(I::ShiftRight(0)), Ok(0x8006));
-        assert_eq!(enc(I::ShiftRight(4)), Ok(0x8406));
-        assert_eq!(enc(I::ShiftRight(8)), Ok(0x8806));
-        assert_eq!(enc(I::ShiftRight(12)), Ok(0x8C06));
-        assert_eq!(enc(I::ShiftRight(15)), Ok(0x8F06));
-        assert_eq!(enc(I::ShiftRight(3)), Ok(0x8306));
+        assert_eq!(enc(I::ShiftRight(0, 0)), Ok(0x8006));
+        assert_eq!(enc(I::ShiftRight(4, 4)), Ok(0x8446));
+        assert_eq!(enc(I::ShiftRight(8, 8)), Ok(0x8886));
+        assert_eq!(enc(I::ShiftRight(12, 12)), Ok(0x8CC6));
+        assert_eq!(enc(I::ShiftRight(15, 15)), Ok(0x8FF6));
+        assert_eq!(enc(I::ShiftRight(3, 3)), Ok(0x8336));
+        assert_eq!(enc(I::ShiftRight(1, 2)), Ok(0x8126));
 
         assert_eq!(enc(I::SubN(0, 1)), Ok(0x8017));
         assert_eq!(enc(I::SubN(4, 9)), Ok(0x8497));
@@ -342,12 +647,13 @@ mod tests {
         assert_eq!(enc(I::SubN(15, 2)), Ok(0x8F27));
         assert_eq!(enc(I::SubN(3, 12)), Ok(0x83C7));
 
-        assert_eq!(enc(I::ShiftLeft(0)), Ok(0x800E));
-        assert_eq!(enc(I::ShiftLeft(4)), Ok(0x840E));
-        assert_eq!(enc(I::ShiftLeft(8)), Ok(0x880E));
-        assert_eq!(enc(I::ShiftLeft(12)), Ok(0x8C0E));
-        assert_eq!(enc(I::ShiftLeft(15)), Ok(0x8F0E));
-        assert_eq!(enc(I::ShiftLeft(3)), Ok(0x830E));
+        assert_eq!(enc(I::ShiftLeft(0, 0)), Ok(0x800E));
+        assert_eq!(enc(I::ShiftLeft(4, 4)), Ok(0x844E));
+        assert_eq!(enc(I::ShiftLeft(8, 8)), Ok(0x888E));
+        assert_eq!(enc(I::ShiftLeft(12, 12)), Ok(0x8CCE));
+        assert_eq!(enc(I::ShiftLeft(15, 15)), Ok(0x8FFE));
+        assert_eq!(enc(I::ShiftLeft(3, 3)), Ok(0x833E));
+        assert_eq!(enc(I::ShiftLeft(1, 2)), Ok(0x812E));
 
         assert_eq!(enc(I::LoadMemoryRegister(0x375)), Ok(0xA375));
         assert_eq!(enc(I::LoadMemoryRegister(0x200)), Ok(0xA200));
@@ -488,7 +794,188 @@ mod tests {
         assert_eq!(encode(I::Draw(9, 3, 87)), Err(E::NibbleTooBig(87)));
         assert_eq!(encode(I::Draw(13, 0, 200)), Err(E::NibbleTooBig(200)));
         assert_eq!(encode(I::Draw(10, 4, 186)), Err(E::NibbleTooBig(186)));
+        assert_eq!(encode(I::ShiftRight(1, 16)), Err(E::RegisterTooBig(16)));
+        assert_eq!(encode(I::ShiftLeft(16, 1)), Err(E::RegisterTooBig(16)));
+
         assert_eq!(encode(I::Draw(100, 4, 186)), Err(E::RegisterTooBig(100)));
         assert_eq!(encode(I::Draw(10, 40, 186)), Err(E::RegisterTooBig(40)));
+
+        // Nop encodes to 0000, which must decode back to Nop
+        #[cfg(feature = "decode")]
+        assert_eq!(crate::decode(encode(I::Nop).unwrap()), Ok(I::Nop));
+    }
+
+    #[test]
+    fn encode_schip_test() {
+        use EncodingError as E;
+        use Instruction as I;
+
+        let enc = |instr| encode(instr).map(u16::from_be_bytes);
+
+        assert_eq!(enc(I::ScrollDown(0)), Ok(0x00C0));
+        assert_eq!(enc(I::ScrollDown(0xF)), Ok(0x00CF));
+        assert_eq!(enc(I::ScrollRight), Ok(0x00FB));
+        assert_eq!(enc(I::ScrollLeft), Ok(0x00FC));
+        assert_eq!(enc(I::Exit), Ok(0x00FD));
+        assert_eq!(enc(I::DrawLargeSprite(0xA, 3)), Ok(0xDA30));
+        assert_eq!(enc(I::LoadLargeDigitAddress(9)), Ok(0xF930));
+        assert_eq!(enc(I::StoreRegistersInRpl(5)), Ok(0xF575));
+        assert_eq!(enc(I::ReadRegistersFromRpl(0)), Ok(0xF085));
+
+        assert_eq!(encode(I::ScrollDown(16)), Err(E::NibbleTooBig(16)));
+        assert_eq!(
+            encode(I::DrawLargeSprite(1, 16)),
+            Err(E::RegisterTooBig(16))
+        );
+        assert_eq!(
+            encode(I::LoadLargeDigitAddress(20)),
+            Err(E::RegisterTooBig(20))
+        );
+        assert_eq!(
+            encode(I::StoreRegistersInRpl(16)),
+            Err(E::RegisterTooBig(16))
+        );
+        assert_eq!(
+            encode(I::ReadRegistersFromRpl(99)),
+            Err(E::RegisterTooBig(99))
+        );
+
+        assert_eq!(encode_clamped(I::ScrollDown(0x13)), [0x00, 0xC3]);
+    }
+
+    #[test]
+    fn encode_strict_test() {
+        use Instruction as I;
+        use StrictWarning as W;
+
+        let suspicious = |cause, bytes: u16| {
+            Err(StrictEncodingError::Suspicious {
+                cause,
+                bytes: bytes.to_be_bytes(),
+            })
+        };
+
+        assert_eq!(
+            encode_strict(I::Draw(1, 2, 0)),
+            suspicious(W::DrawZeroRows, 0xD120)
+        );
+        assert_eq!(
+            encode_strict(I::LoadRandomWithMask(3, 0)),
+            suspicious(W::RandomMaskZero, 0xC300)
+        );
+        assert_eq!(encode_strict(I::Or(4, 4)), suspicious(W::OrSelf(4), 0x8441));
+        assert_eq!(
+            encode_strict(I::Xor(5, 5)),
+            suspicious(W::XorSelf(5), 0x8553)
+        );
+
+        assert_eq!(encode_strict(I::Draw(1, 2, 3)), Ok([0xD1, 0x23]));
+        assert_eq!(encode_strict(I::LoadRandomWithMask(3, 1)), Ok([0xC3, 0x01]));
+        assert_eq!(encode_strict(I::Or(4, 5)), Ok([0x84, 0x51]));
+        assert_eq!(encode_strict(I::Xor(5, 6)), Ok([0x85, 0x63]));
+        assert_eq!(encode_strict(I::And(7, 7)), Ok([0x87, 0x72]));
+
+        assert_eq!(
+            encode_strict(I::Or(16, 16)),
+            Err(StrictEncodingError::Encoding(
+                EncodingError::RegisterTooBig(16)
+            ))
+        );
+    }
+
+    #[test]
+    fn encode_clamped_test() {
+        use EncodingError as E;
+        use Instruction as I;
+        use Operand::{Literal as Lit, Register as Reg};
+
+        let valid = [
+            I::Nop,
+            I::ClearScreen,
+            I::Return,
+            I::Jump(0xFFF),
+            I::Call(0x234),
+            I::SkipIfEqual(0xF, Reg(0xE)),
+            I::SkipIfNotEqual(1, Lit(0xFF)),
+            I::LoadRegister(2, Lit(0x12)),
+            I::AddNoCarry(3, 0x80),
+            I::Xor(4, 5),
+            I::SubN(6, 7),
+            I::ShiftLeft(8, 9),
+            I::LoadMemoryRegister(0x300),
+            I::JumpPlusV0(0x456),
+            I::LoadRandomWithMask(9, 0x0F),
+            I::Draw(0xA, 0xB, 0xF),
+            I::SkipIfKeyNotPressed(0xC),
+            I::WaitForKeyPress(0xD),
+            I::ReadRegistersFromMemory(0xF),
+        ];
+        for instruction in valid {
+            assert_eq!(
+                Ok(encode_clamped(instruction)),
+                encode(instruction),
+                "{instruction:?}"
+            );
+        }
+
+        assert_eq!(encode_clamped(I::Jump(0x1234)), [0x12, 0x34]);
+        assert_eq!(encode_clamped(I::LoadMemoryRegister(0xFFFF)), [0xAF, 0xFF]);
+        assert_eq!(encode_clamped(I::AddNoCarry(17, 0x56)), [0x71, 0x56]);
+        assert_eq!(encode_clamped(I::SkipIfEqual(2, Reg(31))), [0x52, 0xF0]);
+        assert_eq!(encode_clamped(I::Draw(16, 40, 186)), [0xD0, 0x8A]);
+
+        assert_eq!(
+            E::RegisterTooBig(17).recover(I::AddNoCarry(17, 0x56)),
+            Some([0x71, 0x56])
+        );
+        assert_eq!(
+            E::NibbleTooBig(16).recover(I::Draw(1, 2, 16)),
+            Some([0xD1, 0x20])
+        );
+        assert_eq!(E::RegisterTooBig(17).recover(I::AddNoCarry(16, 0x56)), None);
+        assert_eq!(E::AddressTooBig(0x1000).recover(I::Jump(0xFFF)), None);
+    }
+
+    #[test]
+    fn format_opcode_test() {
+        use Instruction as I;
+        use Operand::{Literal as Lit, Register as Reg};
+
+        // (instruction, lower hex, upper hex, binary)
+        let cases = [
+            (I::Nop, "0000", "0000", "0000000000000000"),
+            (I::ClearScreen, "00e0", "00E0", "0000000011100000"),
+            (I::Jump(0xABC), "1abc", "1ABC", "0001101010111100"),
+            (
+                I::SkipIfEqual(0xA, Lit(0xBC)),
+                "3abc",
+                "3ABC",
+                "0011101010111100",
+            ),
+            (
+                I::LoadRegister(0xE, Reg(0xF)),
+                "8ef0",
+                "8EF0",
+                "1000111011110000",
+            ),
+            (I::Draw(3, 5, 4), "d354", "D354", "1101001101010100"),
+            (
+                I::ReadRegistersFromMemory(0xC),
+                "fc65",
+                "FC65",
+                "1111110001100101",
+            ),
+        ];
+
+        for (instruction, lower, upper, binary) in cases {
+            assert_eq!(format!("{instruction:04x}"), lower);
+            assert_eq!(format!("{instruction:04X}"), upper);
+            assert_eq!(format!("{instruction:016b}"), binary);
+        }
+
+        assert_eq!(format!("{:x}", I::ClearScreen), "e0");
+        assert_eq!(format!("{:#06X}", I::ClearScreen), "0x00E0");
+        assert_eq!(format!("{:04x}", I::Jump(0x1000)), "?   ");
+        assert_eq!(format!("{:b}", I::Draw(16, 0, 5)), "?");
     }
 }
@@ -4,15 +4,30 @@
 mod decoding;
 
 #[cfg(feature = "decode")]
-pub use self::decoding::{decode, DecodingError};
+pub use self::decoding::{decode, decode_program, DecodingError};
 
 #[cfg(feature = "encode")]
 mod encoding;
 
 #[cfg(feature = "encode")]
-pub use self::encoding::{encode, EncodingError};
+pub use self::encoding::{
+    encode, encode_all, encode_all_unchecked, encode_clamped, encode_strict, EncodeAllError,
+    EncodingError, StrictEncodingError, StrictWarning,
+};
+
+mod parsing;
+
+pub use self::parsing::ParseInstructionError;
+
+use std::fmt;
+
+/// The size of every instruction in bytes.
+pub const INSTRUCTION_SIZE: usize = 2;
 
 /// The set of instructions that are supported by the interpreter.
+///
+/// The SUPER-CHIP instructions always exist, so that matching on them doesn't depend on which
+/// features are enabled, but they're only decoded with the `schip` feature.
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Instruction {
     /// Do nothing.
@@ -24,6 +39,24 @@ pub enum Instruction {
     /// Return from the current subroutine.
     Return,
 
+    /// Switch to the standard 64x32 display. This is a SUPER-CHIP instruction.
+    LowRes,
+
+    /// Switch to the 128x64 high resolution display. This is a SUPER-CHIP instruction.
+    HighRes,
+
+    /// Scroll the display down by the given number of rows. This is a SUPER-CHIP instruction.
+    ScrollDown(u8),
+
+    /// Scroll the display 4 pixels to the right. This is a SUPER-CHIP instruction.
+    ScrollRight,
+
+    /// Scroll the display 4 pixels to the left. This is a SUPER-CHIP instruction.
+    ScrollLeft,
+
+    /// Stop the interpreter. This is a SUPER-CHIP instruction.
+    Exit,
+
     /// Jump to the given address.
     Jump(u16),
 
@@ -60,14 +93,18 @@ pub enum Instruction {
     /// Set Vx = Vx - Vy, and set VF to 1 if Vx > Vy, otherwise 0.
     Sub(u8, u8),
 
-    /// Shift this register to the right by 1 place, overflowing into VF.
-    ShiftRight(u8),
+    /// Shift a register to the right by 1 place and store it in the left register, storing the
+    /// bit that was shifted out in VF. The original CHIP-8 shifts the right register, but CHIP-48
+    /// and SUPER-CHIP shift the left one in place and ignore the right one.
+    ShiftRight(u8, u8),
 
     /// Set Vx = Vy - Vx, and set VF to 1 if Vy > Vx, otherwise 0.
     SubN(u8, u8),
 
-    /// Shift this register to the left by 1 place, overflowing into VF.
-    ShiftLeft(u8),
+    /// Shift a register to the left by 1 place and store it in the left register, storing the
+    /// bit that was shifted out in VF. Which register gets shifted works like
+    /// [`ShiftRight`](Self::ShiftRight).
+    ShiftLeft(u8, u8),
 
     /// Load the given address into the memory register.
     LoadMemoryRegister(u16),
@@ -89,6 +126,11 @@ pub enum Instruction {
     /// the coordinates of the display, it wraps around to the opposite side of the screen.
     Draw(u8, u8, u8),
 
+    /// Draw a 16x16 sprite at (Vx, Vy), like [`Draw`](Self::Draw). The sprite is 32 bytes in
+    /// memory, with two bytes for each row. This is a SUPER-CHIP instruction, which uses the
+    /// bytecode of drawing a sprite with 0 rows.
+    DrawLargeSprite(u8, u8),
+
     /// Skip the next instruction if the key with the number in Vx is currently being pressed.
     SkipIfKeyPressed(u8),
 
@@ -113,6 +155,10 @@ pub enum Instruction {
     /// Load the memory register with the address of the sprite representing the bottom nibble in Vx.
     LoadDigitAddress(u8),
 
+    /// Load the memory register with the address of the large 8x10 sprite representing the
+    /// digit in Vx. This is a SUPER-CHIP instruction.
+    LoadLargeDigitAddress(u8),
+
     /// Store BCD representation of Vx in memory locations I, I+1, and I+2.
     ///
     /// The interpreter takes the decimal value of Vx, and places the hundreds digit in memory at
@@ -125,6 +171,348 @@ pub enum Instruction {
 
     /// Read registers V0 through Vx from memory starting at the location in the memory register.
     ReadRegistersFromMemory(u8),
+
+    /// Store registers V0 through Vx in the RPL user flags, which are kept outside of the main
+    /// memory. This is a SUPER-CHIP instruction. SUPER-CHIP only has 8 flags, so x should be at
+    /// most 7, but any register can be encoded.
+    StoreRegistersInRpl(u8),
+
+    /// Read registers V0 through Vx from the RPL user flags. This is a SUPER-CHIP instruction, and
+    /// x should be at most 7 like [`StoreRegistersInRpl`](Self::StoreRegistersInRpl).
+    ReadRegistersFromRpl(u8),
+}
+
+impl Instruction {
+    /// Get the size of this instruction in bytes, which is always [`INSTRUCTION_SIZE`].
+    pub const fn size_of_in_bytes(&self) -> usize {
+        INSTRUCTION_SIZE
+    }
+
+    /// Get the alignment that this instruction needs in memory, in bytes. Every instruction has
+    /// to start at an even address.
+    pub const fn alignment_requirement(&self) -> usize {
+        INSTRUCTION_SIZE
+    }
+
+    /// Does this instruction always have the same effect when it's run from the same state?
+    /// Only [`LoadRandomWithMask`](Self::LoadRandomWithMask) doesn't.
+    pub const fn is_deterministic(&self) -> bool {
+        !matches!(self, Self::LoadRandomWithMask(_, _))
+    }
+
+    /// Does this instruction only affect the registers, timers, stack, and memory, based only on
+    /// their current values? Instructions that use the display, the buzzer, the keys, or random
+    /// numbers aren't pure. Neither are the SUPER-CHIP instructions that stop the interpreter or
+    /// use the RPL user flags.
+    pub const fn is_pure(&self) -> bool {
+        !matches!(
+            self,
+            Self::ClearScreen
+                | Self::LowRes
+                | Self::HighRes
+                | Self::ScrollDown(_)
+                | Self::ScrollRight
+                | Self::ScrollLeft
+                | Self::Exit
+                | Self::Draw(_, _, _)
+                | Self::DrawLargeSprite(_, _)
+                | Self::StoreRegistersInRpl(_)
+                | Self::ReadRegistersFromRpl(_)
+                | Self::LoadIntoSoundTimer(_)
+                | Self::SkipIfKeyPressed(_)
+                | Self::SkipIfKeyNotPressed(_)
+                | Self::WaitForKeyPress(_)
+                | Self::LoadRandomWithMask(_, _)
+        )
+    }
+
+    /// Get the bytecode of this instruction as a single number, or `None` if it can't be
+    /// encoded because of invalid operands.
+    #[cfg(feature = "encode")]
+    pub fn opcode(&self) -> Option<u16> {
+        encode(*self).map(u16::from_be_bytes).ok()
+    }
+
+    /// Get the first nibble of the bytecode of this instruction. This only depends on which
+    /// instruction this is, so it's available even if the operands are invalid.
+    pub const fn opcode_nibble(&self) -> u8 {
+        self.opcode_category() as u8
+    }
+
+    /// Get the group of instructions that share the first nibble of their bytecode with this
+    /// one.
+    pub const fn opcode_category(&self) -> OpcodeCategory {
+        use Instruction as I;
+        use OpcodeCategory as C;
+        use Operand::{Literal as Lit, Register as Reg};
+
+        match self {
+            I::Nop | I::ClearScreen | I::Return | I::LowRes | I::HighRes => C::System,
+            I::ScrollDown(_) | I::ScrollRight | I::ScrollLeft | I::Exit => C::System,
+            I::Jump(_) => C::Jump,
+            I::Call(_) => C::Call,
+            I::SkipIfEqual(_, Lit(_)) => C::SkipIfEqualLiteral,
+            I::SkipIfNotEqual(_, Lit(_)) => C::SkipIfNotEqualLiteral,
+            I::SkipIfEqual(_, Reg(_)) => C::SkipIfEqualRegister,
+            I::LoadRegister(_, Lit(_)) => C::LoadLiteral,
+            I::AddNoCarry(_, _) => C::AddLiteral,
+            I::LoadRegister(_, Reg(_))
+            | I::Or(_, _)
+            | I::And(_, _)
+            | I::Xor(_, _)
+            | I::AddWithCarry(_, _)
+            | I::Sub(_, _)
+            | I::ShiftRight(_, _)
+            | I::SubN(_, _)
+            | I::ShiftLeft(_, _) => C::Arithmetic,
+            I::SkipIfNotEqual(_, Reg(_)) => C::SkipIfNotEqualRegister,
+            I::LoadMemoryRegister(_) => C::LoadMemoryRegister,
+            I::JumpPlusV0(_) => C::JumpPlusV0,
+            I::LoadRandomWithMask(_, _) => C::Random,
+            I::Draw(_, _, _) => C::Draw,
+            I::DrawLargeSprite(_, _) => C::Draw,
+            I::SkipIfKeyPressed(_) | I::SkipIfKeyNotPressed(_) => C::Keys,
+            I::LoadFromDelayTimer(_)
+            | I::WaitForKeyPress(_)
+            | I::LoadIntoDelayTimer(_)
+            | I::LoadIntoSoundTimer(_)
+            | I::AddToMemoryRegister(_)
+            | I::LoadDigitAddress(_)
+            | I::StoreBcdInMemory(_)
+            | I::StoreRegistersInMemory(_)
+            | I::ReadRegistersFromMemory(_) => C::Misc,
+            I::LoadLargeDigitAddress(_)
+            | I::StoreRegistersInRpl(_)
+            | I::ReadRegistersFromRpl(_) => C::Misc,
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    /// Write this instruction as a line of assembly that the assembler understands, like
+    /// `drw v3, v5, 4`. Registers are written in lowercase like `va`, and bytes and addresses in
+    /// uppercase hex like `#0F` and `#2A0`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Instruction as I;
+
+        match *self {
+            I::Nop => write!(f, "nop"),
+            I::ClearScreen => write!(f, "cls"),
+            I::Return => write!(f, "ret"),
+            I::LowRes => write!(f, "low"),
+            I::HighRes => write!(f, "high"),
+            I::ScrollDown(n) => write!(f, "scd {n}"),
+            I::ScrollRight => write!(f, "scr"),
+            I::ScrollLeft => write!(f, "scl"),
+            I::Exit => write!(f, "exit"),
+            I::Jump(addr) => write!(f, "jmp #{addr:03X}"),
+            I::Call(addr) => write!(f, "call #{addr:03X}"),
+            I::SkipIfEqual(x, op) => write!(f, "se v{x:x}, {op}"),
+            I::SkipIfNotEqual(x, op) => write!(f, "sne v{x:x}, {op}"),
+            I::LoadRegister(x, op) => write!(f, "ld v{x:x}, {op}"),
+            I::AddNoCarry(x, byte) => write!(f, "add v{x:x}, #{byte:02X}"),
+            I::Or(x, y) => write!(f, "or v{x:x}, v{y:x}"),
+            I::And(x, y) => write!(f, "and v{x:x}, v{y:x}"),
+            I::Xor(x, y) => write!(f, "xor v{x:x}, v{y:x}"),
+            I::AddWithCarry(x, y) => write!(f, "add v{x:x}, v{y:x}"),
+            I::Sub(x, y) => write!(f, "sub v{x:x}, v{y:x}"),
+            // The short form shifts a register in place, whichever register the quirks shift
+            I::ShiftRight(x, y) if x == y => write!(f, "shr v{x:x}"),
+            I::ShiftRight(x, y) => write!(f, "shr v{x:x}, v{y:x}"),
+            I::SubN(x, y) => write!(f, "subn v{x:x}, v{y:x}"),
+            I::ShiftLeft(x, y) if x == y => write!(f, "shl v{x:x}"),
+            I::ShiftLeft(x, y) => write!(f, "shl v{x:x}, v{y:x}"),
+            I::LoadMemoryRegister(addr) => write!(f, "ld i, #{addr:03X}"),
+            I::JumpPlusV0(addr) => write!(f, "jmpp v0, #{addr:03X}"),
+            I::LoadRandomWithMask(x, mask) => write!(f, "rnd v{x:x}, #{mask:02X}"),
+            I::Draw(x, y, n) => write!(f, "drw v{x:x}, v{y:x}, {n}"),
+            I::DrawLargeSprite(x, y) => write!(f, "drw v{x:x}, v{y:x}, 0"),
+            I::SkipIfKeyPressed(x) => write!(f, "skp v{x:x}"),
+            I::SkipIfKeyNotPressed(x) => write!(f, "sknp v{x:x}"),
+            I::LoadFromDelayTimer(x) => write!(f, "ld v{x:x}, dt"),
+            I::WaitForKeyPress(x) => write!(f, "ld v{x:x}, k"),
+            I::LoadIntoDelayTimer(x) => write!(f, "delay v{x:x}"),
+            I::LoadIntoSoundTimer(x) => write!(f, "sound v{x:x}"),
+            I::AddToMemoryRegister(x) => write!(f, "add i, v{x:x}"),
+            I::LoadDigitAddress(x) => write!(f, "font v{x:x}"),
+            I::LoadLargeDigitAddress(x) => write!(f, "hfont v{x:x}"),
+            I::StoreBcdInMemory(x) => write!(f, "bcd v{x:x}"),
+            I::StoreRegistersInMemory(x) => write!(f, "stor v{x:x}"),
+            I::ReadRegistersFromMemory(x) => write!(f, "rstr v{x:x}"),
+            I::StoreRegistersInRpl(x) => write!(f, "srpl v{x:x}"),
+            I::ReadRegistersFromRpl(x) => write!(f, "rrpl v{x:x}"),
+        }
+    }
+}
+
+/// A group of instructions whose bytecode starts with the same nibble, which is the value of
+/// each variant.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[repr(u8)]
+pub enum OpcodeCategory {
+    /// `0nnn`: [`Nop`](Instruction::Nop), [`ClearScreen`](Instruction::ClearScreen), and
+    /// [`Return`](Instruction::Return), along with the SUPER-CHIP [`LowRes`](Instruction::LowRes),
+    /// [`HighRes`](Instruction::HighRes), scrolling, and [`Exit`](Instruction::Exit) instructions.
+    System = 0x0,
+
+    /// `1nnn`: [`Jump`](Instruction::Jump).
+    Jump = 0x1,
+
+    /// `2nnn`: [`Call`](Instruction::Call).
+    Call = 0x2,
+
+    /// `3xkk`: [`SkipIfEqual`](Instruction::SkipIfEqual) with a literal.
+    SkipIfEqualLiteral = 0x3,
+
+    /// `4xkk`: [`SkipIfNotEqual`](Instruction::SkipIfNotEqual) with a literal.
+    SkipIfNotEqualLiteral = 0x4,
+
+    /// `5xy0`: [`SkipIfEqual`](Instruction::SkipIfEqual) with a register.
+    SkipIfEqualRegister = 0x5,
+
+    /// `6xkk`: [`LoadRegister`](Instruction::LoadRegister) with a literal.
+    LoadLiteral = 0x6,
+
+    /// `7xkk`: [`AddNoCarry`](Instruction::AddNoCarry).
+    AddLiteral = 0x7,
+
+    /// `8xyn`: operations between two registers, like [`Or`](Instruction::Or) and
+    /// [`Sub`](Instruction::Sub).
+    Arithmetic = 0x8,
+
+    /// `9xy0`: [`SkipIfNotEqual`](Instruction::SkipIfNotEqual) with a register.
+    SkipIfNotEqualRegister = 0x9,
+
+    /// `Annn`: [`LoadMemoryRegister`](Instruction::LoadMemoryRegister).
+    LoadMemoryRegister = 0xA,
+
+    /// `Bnnn`: [`JumpPlusV0`](Instruction::JumpPlusV0).
+    JumpPlusV0 = 0xB,
+
+    /// `Cxkk`: [`LoadRandomWithMask`](Instruction::LoadRandomWithMask).
+    Random = 0xC,
+
+    /// `Dxyn`: [`Draw`](Instruction::Draw) and [`DrawLargeSprite`](Instruction::DrawLargeSprite).
+    Draw = 0xD,
+
+    /// `Exnn`: [`SkipIfKeyPressed`](Instruction::SkipIfKeyPressed) and
+    /// [`SkipIfKeyNotPressed`](Instruction::SkipIfKeyNotPressed).
+    Keys = 0xE,
+
+    /// `Fxnn`: the timers, waiting for keys, and working with memory.
+    Misc = 0xF,
+}
+
+impl OpcodeCategory {
+    /// Get every valid instruction in this category, with every possible combination of
+    /// operands. This is useful for generating exhaustive tests for one group of instructions.
+    ///
+    /// Every instruction has a different bytecode, so the SUPER-CHIP instructions are only
+    /// included when they can be decoded with the `schip` feature, and then sprites with 0
+    /// rows are left out in favour of `DrawLargeSprite`.
+    pub fn instructions(self) -> impl Iterator<Item = Instruction> {
+        use Instruction as I;
+        use OpcodeCategory as C;
+        use Operand::{Literal as Lit, Register as Reg};
+
+        let addresses = || 0..=0xFFF;
+        let registers = || 0..=0xF;
+        let register_bytes =
+            move || registers().flat_map(|x| (0..=0xFF).map(move |byte| (x, byte)));
+        let register_pairs =
+            move || registers().flat_map(move |x| registers().map(move |y| (x, y)));
+
+        let instructions: Box<dyn Iterator<Item = Instruction>> = match self {
+            C::System => {
+                let system = [I::Nop, I::ClearScreen, I::Return].into_iter();
+
+                #[cfg(feature = "schip")]
+                let system = system.chain((0..=0xF).map(I::ScrollDown)).chain([
+                    I::ScrollRight,
+                    I::ScrollLeft,
+                    I::Exit,
+                    I::LowRes,
+                    I::HighRes,
+                ]);
+
+                Box::new(system)
+            }
+            C::Jump => Box::new(addresses().map(I::Jump)),
+            C::Call => Box::new(addresses().map(I::Call)),
+            C::SkipIfEqualLiteral => {
+                Box::new(register_bytes().map(|(x, byte)| I::SkipIfEqual(x, Lit(byte))))
+            }
+            C::SkipIfNotEqualLiteral => {
+                Box::new(register_bytes().map(|(x, byte)| I::SkipIfNotEqual(x, Lit(byte))))
+            }
+            C::SkipIfEqualRegister => {
+                Box::new(register_pairs().map(|(x, y)| I::SkipIfEqual(x, Reg(y))))
+            }
+            C::LoadLiteral => {
+                Box::new(register_bytes().map(|(x, byte)| I::LoadRegister(x, Lit(byte))))
+            }
+            C::AddLiteral => Box::new(register_bytes().map(|(x, byte)| I::AddNoCarry(x, byte))),
+            C::Arithmetic => Box::new(register_pairs().flat_map(|(x, y)| {
+                [
+                    I::LoadRegister(x, Reg(y)),
+                    I::Or(x, y),
+                    I::And(x, y),
+                    I::Xor(x, y),
+                    I::AddWithCarry(x, y),
+                    I::Sub(x, y),
+                    I::ShiftRight(x, y),
+                    I::SubN(x, y),
+                    I::ShiftLeft(x, y),
+                ]
+            })),
+            C::SkipIfNotEqualRegister => {
+                Box::new(register_pairs().map(|(x, y)| I::SkipIfNotEqual(x, Reg(y))))
+            }
+            C::LoadMemoryRegister => Box::new(addresses().map(I::LoadMemoryRegister)),
+            C::JumpPlusV0 => Box::new(addresses().map(I::JumpPlusV0)),
+            C::Random => Box::new(register_bytes().map(|(x, byte)| I::LoadRandomWithMask(x, byte))),
+            #[cfg(not(feature = "schip"))]
+            C::Draw => Box::new(
+                register_pairs().flat_map(|(x, y)| (0..=0xF).map(move |n| I::Draw(x, y, n))),
+            ),
+            #[cfg(feature = "schip")]
+            C::Draw => Box::new(register_pairs().flat_map(|(x, y)| {
+                std::iter::once(I::DrawLargeSprite(x, y))
+                    .chain((1..=0xF).map(move |n| I::Draw(x, y, n)))
+            })),
+            C::Keys => Box::new(
+                registers().flat_map(|x| [I::SkipIfKeyPressed(x), I::SkipIfKeyNotPressed(x)]),
+            ),
+            C::Misc => {
+                let misc = registers().flat_map(|x| {
+                    [
+                        I::LoadFromDelayTimer(x),
+                        I::WaitForKeyPress(x),
+                        I::LoadIntoDelayTimer(x),
+                        I::LoadIntoSoundTimer(x),
+                        I::AddToMemoryRegister(x),
+                        I::LoadDigitAddress(x),
+                        I::StoreBcdInMemory(x),
+                        I::StoreRegistersInMemory(x),
+                        I::ReadRegistersFromMemory(x),
+                    ]
+                });
+
+                #[cfg(feature = "schip")]
+                let misc = misc.chain(registers().flat_map(|x| {
+                    [
+                        I::LoadLargeDigitAddress(x),
+                        I::StoreRegistersInRpl(x),
+                        I::ReadRegistersFromRpl(x),
+                    ]
+                }));
+
+                Box::new(misc)
+            }
+        };
+
+        instructions
+    }
 }
 
 /// An operand that can be used in an instruction.
@@ -136,3 +524,349 @@ pub enum Operand {
     /// A literal byte value.
     Literal(u8),
 }
+
+impl fmt::Display for Operand {
+    /// Write a register in lowercase like `va`, or a literal as an uppercase hex byte like `#0F`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Register(x) => write!(f, "v{x:x}"),
+            Self::Literal(byte) => write!(f, "#{byte:02X}"),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "decode", feature = "encode"))]
+mod tests {
+    use super::*;
+
+    /// Get every valid instruction with every possible combination of operands.
+    fn all_valid_instructions() -> Vec<Instruction> {
+        use Instruction as I;
+        use Operand::{Literal as Lit, Register as Reg};
+
+        let mut instructions = vec![I::Nop, I::ClearScreen, I::Return];
+
+        #[cfg(feature = "schip")]
+        {
+            instructions.extend([
+                I::ScrollRight,
+                I::ScrollLeft,
+                I::Exit,
+                I::LowRes,
+                I::HighRes,
+            ]);
+            instructions.extend((0..=0xF).map(I::ScrollDown));
+        }
+
+        for addr in 0..=0xFFF {
+            instructions.extend([
+                I::Jump(addr),
+                I::Call(addr),
+                I::LoadMemoryRegister(addr),
+                I::JumpPlusV0(addr),
+            ]);
+        }
+
+        for x in 0..=0xF {
+            instructions.extend([
+                I::SkipIfKeyPressed(x),
+                I::SkipIfKeyNotPressed(x),
+                I::LoadFromDelayTimer(x),
+                I::WaitForKeyPress(x),
+                I::LoadIntoDelayTimer(x),
+                I::LoadIntoSoundTimer(x),
+                I::AddToMemoryRegister(x),
+                I::LoadDigitAddress(x),
+                I::StoreBcdInMemory(x),
+                I::StoreRegistersInMemory(x),
+                I::ReadRegistersFromMemory(x),
+            ]);
+
+            #[cfg(feature = "schip")]
+            instructions.extend([
+                I::LoadLargeDigitAddress(x),
+                I::StoreRegistersInRpl(x),
+                I::ReadRegistersFromRpl(x),
+            ]);
+
+            for byte in 0..=0xFF {
+                instructions.extend([
+                    I::SkipIfEqual(x, Lit(byte)),
+                    I::SkipIfNotEqual(x, Lit(byte)),
+                    I::LoadRegister(x, Lit(byte)),
+                    I::AddNoCarry(x, byte),
+                    I::LoadRandomWithMask(x, byte),
+                ]);
+            }
+
+            for y in 0..=0xF {
+                instructions.extend([
+                    I::SkipIfEqual(x, Reg(y)),
+                    I::SkipIfNotEqual(x, Reg(y)),
+                    I::LoadRegister(x, Reg(y)),
+                    I::Or(x, y),
+                    I::And(x, y),
+                    I::Xor(x, y),
+                    I::AddWithCarry(x, y),
+                    I::Sub(x, y),
+                    I::ShiftRight(x, y),
+                    I::SubN(x, y),
+                    I::ShiftLeft(x, y),
+                ]);
+
+                // Dxy0 draws a large sprite in SUPER-CHIP
+                #[cfg(not(feature = "schip"))]
+                instructions.push(I::Draw(x, y, 0));
+                #[cfg(feature = "schip")]
+                instructions.push(I::DrawLargeSprite(x, y));
+
+                for n in 1..=0xF {
+                    instructions.push(I::Draw(x, y, n));
+                }
+            }
+        }
+
+        instructions
+    }
+
+    #[test]
+    fn exhaustive_roundtrip() {
+        for instruction in all_valid_instructions() {
+            let bytes = encode(instruction)
+                .unwrap_or_else(|e| panic!("Failed to encode {instruction:?}: {e}"));
+            assert_eq!(decode(bytes), Ok(instruction), "Bytes: {bytes:02X?}");
+        }
+    }
+
+    #[test]
+    fn display_roundtrip() {
+        for instruction in all_valid_instructions() {
+            let text = instruction.to_string();
+            assert_eq!(
+                Instruction::try_from(text.as_str()),
+                Ok(instruction),
+                "{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn display_test() {
+        use Instruction as I;
+        use Operand::{Literal as Lit, Register as Reg};
+
+        assert_eq!(Reg(0xA).to_string(), "va");
+        assert_eq!(Lit(0x0F).to_string(), "#0F");
+
+        for (instruction, text) in [
+            (I::ClearScreen, "cls"),
+            (I::Jump(0x2A0), "jmp #2A0"),
+            (I::SkipIfEqual(3, Lit(0x05)), "se v3, #05"),
+            (I::SkipIfNotEqual(3, Reg(0xE)), "sne v3, ve"),
+            (I::LoadRegister(1, Lit(0xFC)), "ld v1, #FC"),
+            (I::Or(0xA, 0xB), "or va, vb"),
+            (I::LoadMemoryRegister(0x050), "ld i, #050"),
+            (I::JumpPlusV0(0x3FF), "jmpp v0, #3FF"),
+            (I::Draw(4, 0, 9), "drw v4, v0, 9"),
+            (I::SkipIfKeyPressed(3), "skp v3"),
+            (I::LoadFromDelayTimer(0xF), "ld vf, dt"),
+            (I::WaitForKeyPress(2), "ld v2, k"),
+            (I::AddToMemoryRegister(7), "add i, v7"),
+            (I::ReadRegistersFromMemory(0xF), "rstr vf"),
+        ] {
+            assert_eq!(instruction.to_string(), text);
+        }
+    }
+
+    #[test]
+    fn system_instructions_roundtrip() {
+        use Instruction as I;
+
+        for instruction in [I::Nop, I::ClearScreen, I::Return] {
+            assert_eq!(decode(encode(instruction).unwrap()), Ok(instruction));
+        }
+
+        // Machine code routines aren't supported, so they're decoded as Nop and don't roundtrip
+        assert_eq!(decode([0x02, 0x34]), Ok(I::Nop));
+        assert_eq!(encode(I::Nop), Ok([0x00, 0x00]));
+    }
+
+    #[cfg(feature = "schip")]
+    #[test]
+    fn schip_roundtrip_test() {
+        use Instruction as I;
+
+        for (instruction, opcode, text) in [
+            (I::ScrollDown(3), 0x00C3, "scd 3"),
+            (I::ScrollRight, 0x00FB, "scr"),
+            (I::ScrollLeft, 0x00FC, "scl"),
+            (I::Exit, 0x00FD, "exit"),
+            (I::LowRes, 0x00FE, "low"),
+            (I::HighRes, 0x00FF, "high"),
+            (I::DrawLargeSprite(4, 5), 0xD450, "drw v4, v5, 0"),
+            (I::LoadLargeDigitAddress(0xB), 0xFB30, "hfont vb"),
+            (I::StoreRegistersInRpl(7), 0xF775, "srpl v7"),
+            (I::ReadRegistersFromRpl(2), 0xF285, "rrpl v2"),
+        ] {
+            assert_eq!(instruction.opcode(), Some(opcode));
+            assert_eq!(decode(opcode.to_be_bytes()), Ok(instruction));
+            assert_eq!(instruction.to_string(), text);
+            assert_eq!(Instruction::try_from(text), Ok(instruction));
+        }
+
+        // Sprites with 0 rows can still be encoded, but they decode as large sprites
+        assert_eq!(
+            decode(encode(I::Draw(4, 5, 0)).unwrap()),
+            Ok(I::DrawLargeSprite(4, 5))
+        );
+
+        assert!(!I::ScrollLeft.is_pure());
+        assert!(!I::StoreRegistersInRpl(0).is_pure());
+        assert!(I::LoadLargeDigitAddress(0).is_pure());
+        assert_eq!(I::Exit.opcode_category(), OpcodeCategory::System);
+        assert_eq!(
+            I::DrawLargeSprite(0, 0).opcode_category(),
+            OpcodeCategory::Draw
+        );
+    }
+
+    #[test]
+    fn is_deterministic_and_pure_test() {
+        use Instruction as I;
+        use Operand::{Literal as Lit, Register as Reg};
+
+        // (instruction, is_deterministic, is_pure)
+        let cases = [
+            (I::Nop, true, true),
+            (I::ClearScreen, true, false),
+            (I::Return, true, true),
+            (I::LowRes, true, false),
+            (I::HighRes, true, false),
+            (I::Jump(0x200), true, true),
+            (I::Call(0x200), true, true),
+            (I::SkipIfEqual(0, Lit(1)), true, true),
+            (I::SkipIfNotEqual(0, Reg(1)), true, true),
+            (I::LoadRegister(0, Lit(1)), true, true),
+            (I::AddNoCarry(0, 1), true, true),
+            (I::Or(0, 1), true, true),
+            (I::And(0, 1), true, true),
+            (I::Xor(0, 1), true, true),
+            (I::AddWithCarry(0, 1), true, true),
+            (I::Sub(0, 1), true, true),
+            (I::ShiftRight(0, 1), true, true),
+            (I::SubN(0, 1), true, true),
+            (I::ShiftLeft(0, 1), true, true),
+            (I::LoadMemoryRegister(0x300), true, true),
+            (I::JumpPlusV0(0x300), true, true),
+            (I::LoadRandomWithMask(0, 0xFF), false, false),
+            (I::Draw(0, 1, 5), true, false),
+            (I::SkipIfKeyPressed(0), true, false),
+            (I::SkipIfKeyNotPressed(0), true, false),
+            (I::LoadFromDelayTimer(0), true, true),
+            (I::WaitForKeyPress(0), true, false),
+            (I::LoadIntoDelayTimer(0), true, true),
+            (I::LoadIntoSoundTimer(0), true, false),
+            (I::AddToMemoryRegister(0), true, true),
+            (I::LoadDigitAddress(0), true, true),
+            (I::StoreBcdInMemory(0), true, true),
+            (I::StoreRegistersInMemory(0), true, true),
+            (I::ReadRegistersFromMemory(0), true, true),
+        ];
+
+        for (instruction, deterministic, pure) in cases {
+            assert_eq!(
+                instruction.is_deterministic(),
+                deterministic,
+                "{instruction:?}"
+            );
+            assert_eq!(instruction.is_pure(), pure, "{instruction:?}");
+        }
+
+        for instruction in all_valid_instructions() {
+            assert!(instruction.is_deterministic() || !instruction.is_pure());
+        }
+    }
+
+    #[test]
+    fn opcode_test() {
+        for instruction in all_valid_instructions() {
+            let opcode = u16::from_be_bytes(encode(instruction).unwrap());
+            assert_eq!(instruction.opcode(), Some(opcode));
+            assert_eq!(
+                instruction.opcode_nibble() as u16,
+                opcode >> 12,
+                "{instruction:?}"
+            );
+            assert_eq!(
+                instruction.opcode_category() as u8,
+                instruction.opcode_nibble()
+            );
+        }
+
+        assert_eq!(Instruction::Nop.opcode(), Some(0x0000));
+        assert_eq!(Instruction::ClearScreen.opcode(), Some(0x00E0));
+        assert_eq!(Instruction::Jump(0x1000).opcode(), None);
+        assert_eq!(Instruction::Draw(16, 0, 5).opcode(), None);
+        assert_eq!(Instruction::Draw(16, 0, 5).opcode_nibble(), 0xD);
+        assert_eq!(
+            Instruction::ShiftLeft(0x20, 0).opcode_category(),
+            OpcodeCategory::Arithmetic
+        );
+    }
+
+    #[test]
+    fn category_instructions_test() {
+        use std::collections::HashSet;
+        use OpcodeCategory as C;
+
+        let categories = [
+            C::System,
+            C::Jump,
+            C::Call,
+            C::SkipIfEqualLiteral,
+            C::SkipIfNotEqualLiteral,
+            C::SkipIfEqualRegister,
+            C::LoadLiteral,
+            C::AddLiteral,
+            C::Arithmetic,
+            C::SkipIfNotEqualRegister,
+            C::LoadMemoryRegister,
+            C::JumpPlusV0,
+            C::Random,
+            C::Draw,
+            C::Keys,
+            C::Misc,
+        ];
+
+        let mut opcodes = HashSet::new();
+        for category in categories {
+            for instruction in category.instructions() {
+                assert_eq!(instruction.opcode_category(), category, "{instruction:?}");
+                let bytes = encode(instruction).unwrap();
+                assert_eq!(decode(bytes), Ok(instruction));
+                assert!(
+                    opcodes.insert(bytes),
+                    "Duplicate instruction: {instruction:?}"
+                );
+            }
+        }
+        assert_eq!(opcodes.len(), all_valid_instructions().len());
+
+        assert_eq!(C::Draw.instructions().count(), 16 * 16 * 16);
+        assert_eq!(C::Arithmetic.instructions().count(), 16 * 16 * 9);
+        #[cfg(not(feature = "schip"))]
+        assert_eq!(C::System.instructions().count(), 3);
+        #[cfg(feature = "schip")]
+        assert_eq!(C::System.instructions().count(), 3 + 16 + 5);
+        assert_eq!(C::Jump.instructions().count(), 0x1000);
+    }
+
+    #[test]
+    fn instruction_size_test() {
+        for instruction in all_valid_instructions() {
+            let bytes = encode(instruction).unwrap();
+            assert_eq!(bytes.len(), instruction.size_of_in_bytes());
+            assert_eq!(instruction.alignment_requirement(), INSTRUCTION_SIZE);
+        }
+    }
+}
@@ -0,0 +1,406 @@
+//! This module handles parsing single instructions from assembly mnemonics like `drw v3, v5, 4`.
+//!
+//! This is a minimal parser for debuggers and REPLs. It understands the same mnemonics as the
+//! assembler, including the alternative names `jp`, `jpp`, `draw`, and `hex` for `jmp`, `jmpp`,
+//! `drw`, and `font`. It doesn't support aliases made with `define`, labels, or anything else that
+//! needs more context than a single line.
+
+use crate::{Instruction, Operand};
+use thiserror::Error;
+
+/// A potential error when parsing an instruction from text.
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum ParseInstructionError {
+    /// The text didn't contain anything.
+    #[error("Expected an instruction but found nothing")]
+    Empty,
+
+    /// The first word wasn't a mnemonic that we know.
+    #[error("Unknown mnemonic {0:?}")]
+    UnknownMnemonic(String),
+
+    /// The mnemonic was given the wrong number of arguments.
+    #[error("{mnemonic:?} takes {expected} argument(s) but {found} were given")]
+    WrongArgumentCount {
+        mnemonic: String,
+        expected: usize,
+        found: usize,
+    },
+
+    /// An argument wasn't a register, a special register, or a number.
+    #[error("Invalid argument {0:?}")]
+    InvalidArgument(String),
+
+    /// The arguments were all valid on their own, but not in this combination for this mnemonic.
+    #[error("Invalid arguments for {mnemonic:?}: {args}")]
+    InvalidArguments { mnemonic: String, args: String },
+
+    /// A literal was too big for where it was used.
+    #[error("Literal {value} is too big, and must be at most {max}")]
+    LiteralOutOfRange { value: u32, max: u16 },
+}
+
+/// A single parsed argument.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Arg {
+    /// A general purpose register, V0 to VF.
+    Register(u8),
+
+    /// A numeric literal, which hasn't been range-checked yet.
+    Number(u32),
+
+    /// The memory register.
+    I,
+
+    /// The delay timer.
+    Dt,
+
+    /// A key press.
+    K,
+}
+
+/// Parse a single argument, which should already be trimmed and lowercase.
+fn parse_arg(arg: &str) -> Result<Arg, ParseInstructionError> {
+    let invalid = || ParseInstructionError::InvalidArgument(arg.to_string());
+
+    let number = |digits: &str, radix| {
+        if digits.is_empty() {
+            Err(invalid())
+        } else {
+            u32::from_str_radix(digits, radix).map_err(|_| invalid())
+        }
+    };
+
+    match arg {
+        "i" => Ok(Arg::I),
+        "dt" => Ok(Arg::Dt),
+        "k" => Ok(Arg::K),
+        _ => {
+            if let Some(digits) = arg.strip_prefix('#') {
+                number(digits, 16).map(Arg::Number)
+            } else if let Some(digits) = arg.strip_prefix('%') {
+                number(digits, 2).map(Arg::Number)
+            } else if let Some(digit) = arg.strip_prefix('v') {
+                match digit.chars().collect::<Vec<_>>()[..] {
+                    [c] => c
+                        .to_digit(16)
+                        .map(|x| Arg::Register(x as u8))
+                        .ok_or_else(invalid),
+                    _ => Err(invalid()),
+                }
+            } else if arg.starts_with(|c: char| c.is_ascii_digit()) {
+                number(arg, 10).map(Arg::Number)
+            } else {
+                Err(invalid())
+            }
+        }
+    }
+}
+
+/// Check that a number is at most `max`.
+fn in_range(value: u32, max: u16) -> Result<u16, ParseInstructionError> {
+    if value <= max as u32 {
+        Ok(value as u16)
+    } else {
+        Err(ParseInstructionError::LiteralOutOfRange { value, max })
+    }
+}
+
+/// How many arguments does this mnemonic take? Returns `None` if we don't know the mnemonic.
+fn arity(mnemonic: &str) -> Option<usize> {
+    Some(match mnemonic {
+        "nop" | "cls" | "ret" | "low" | "high" => 0,
+        "scr" | "scl" | "exit" => 0,
+        "jmp" | "jp" | "call" | "skp" | "sknp" | "delay" | "sound" | "font" | "hex" | "bcd"
+        | "stor" | "rstr" => 1,
+        "scd" | "hfont" | "srpl" | "rrpl" => 1,
+        "jmpp" | "jpp" | "se" | "sne" | "ld" | "add" | "or" | "and" | "xor" | "sub" | "subn"
+        | "shr" | "shl" | "rnd" => 2,
+        "drw" | "draw" => 3,
+        _ => return None,
+    })
+}
+
+impl TryFrom<&str> for Instruction {
+    type Error = ParseInstructionError;
+
+    /// Parse a single instruction from its mnemonic, like `drw v3, v5, 4`. This is
+    /// case-insensitive, and accepts the same literals as the assembler: decimal, `#` for hex, and
+    /// `%` for binary.
+    fn try_from(text: &str) -> Result<Self, Self::Error> {
+        use Arg::{Number as Num, Register as Reg};
+        use Instruction as I;
+
+        let text = text.trim().to_ascii_lowercase();
+        let (mnemonic, rest) = text.split_once(char::is_whitespace).unwrap_or((&text, ""));
+
+        if mnemonic.is_empty() {
+            return Err(ParseInstructionError::Empty);
+        }
+
+        let expected = arity(mnemonic)
+            .ok_or_else(|| ParseInstructionError::UnknownMnemonic(mnemonic.to_string()))?;
+
+        let rest = rest.trim();
+        let mut args = if rest.is_empty() {
+            Vec::new()
+        } else {
+            rest.split(',')
+                .map(|arg| parse_arg(arg.trim()))
+                .collect::<Result<Vec<_>, _>>()?
+        };
+
+        // `shr vx` is short for `shr vx, vx`
+        if matches!(mnemonic, "shr" | "shl") && args.len() == 1 {
+            args.push(args[0]);
+        }
+
+        if args.len() != expected {
+            return Err(ParseInstructionError::WrongArgumentCount {
+                mnemonic: mnemonic.to_string(),
+                expected,
+                found: args.len(),
+            });
+        }
+
+        let addr = |value| in_range(value, 0xFFF);
+        let byte = |value| in_range(value, 0xFF).map(|byte| byte as u8);
+        let nibble = |value| in_range(value, 0xF).map(|nibble| nibble as u8);
+        let operand = |arg| match arg {
+            Reg(y) => Ok(Some(Operand::Register(y))),
+            Num(value) => byte(value).map(|byte| Some(Operand::Literal(byte))),
+            _ => Ok(None),
+        };
+
+        let instruction = match (mnemonic, &args[..]) {
+            ("nop", []) => Some(I::Nop),
+            ("cls", []) => Some(I::ClearScreen),
+            ("ret", []) => Some(I::Return),
+            ("low", []) => Some(I::LowRes),
+            ("high", []) => Some(I::HighRes),
+            ("scd", &[Num(n)]) => Some(I::ScrollDown(nibble(n)?)),
+            ("scr", []) => Some(I::ScrollRight),
+            ("scl", []) => Some(I::ScrollLeft),
+            ("exit", []) => Some(I::Exit),
+            ("jmp" | "jp", &[Num(a)]) => Some(I::Jump(addr(a)?)),
+            // CHIP-48 reads BXNN as a jump to XNN plus VX, so like in the assembler, other
+            // registers are allowed when they match the top nibble of the address
+            ("jmpp" | "jpp", &[Reg(x), Num(a)]) if x == 0 || a >> 8 == x as u32 => {
+                Some(I::JumpPlusV0(addr(a)?))
+            }
+            ("call", &[Num(a)]) => Some(I::Call(addr(a)?)),
+            ("se", &[Reg(x), arg]) => operand(arg)?.map(|op| I::SkipIfEqual(x, op)),
+            ("sne", &[Reg(x), arg]) => operand(arg)?.map(|op| I::SkipIfNotEqual(x, op)),
+            ("ld", &[Reg(x), Arg::K]) => Some(I::WaitForKeyPress(x)),
+            ("ld", &[Reg(x), Arg::Dt]) => Some(I::LoadFromDelayTimer(x)),
+            ("ld", &[Arg::I, Num(a)]) => Some(I::LoadMemoryRegister(addr(a)?)),
+            ("ld", &[Reg(x), arg]) => operand(arg)?.map(|op| I::LoadRegister(x, op)),
+            ("add", &[Arg::I, Reg(x)]) => Some(I::AddToMemoryRegister(x)),
+            ("add", &[Reg(x), Reg(y)]) => Some(I::AddWithCarry(x, y)),
+            ("add", &[Reg(x), Num(b)]) => Some(I::AddNoCarry(x, byte(b)?)),
+            ("or", &[Reg(x), Reg(y)]) => Some(I::Or(x, y)),
+            ("and", &[Reg(x), Reg(y)]) => Some(I::And(x, y)),
+            ("xor", &[Reg(x), Reg(y)]) => Some(I::Xor(x, y)),
+            ("sub", &[Reg(x), Reg(y)]) => Some(I::Sub(x, y)),
+            ("subn", &[Reg(x), Reg(y)]) => Some(I::SubN(x, y)),
+            ("shr", &[Reg(x), Reg(y)]) => Some(I::ShiftRight(x, y)),
+            ("shl", &[Reg(x), Reg(y)]) => Some(I::ShiftLeft(x, y)),
+            ("rnd", &[Reg(x), Num(b)]) => Some(I::LoadRandomWithMask(x, byte(b)?)),
+            // SUPER-CHIP draws a large sprite instead of one with 0 rows, just like `decode`
+            #[cfg(feature = "schip")]
+            ("drw" | "draw", &[Reg(x), Reg(y), Num(0)]) => Some(I::DrawLargeSprite(x, y)),
+            ("drw" | "draw", &[Reg(x), Reg(y), Num(n)]) => Some(I::Draw(x, y, nibble(n)?)),
+            ("skp", &[Reg(x)]) => Some(I::SkipIfKeyPressed(x)),
+            ("sknp", &[Reg(x)]) => Some(I::SkipIfKeyNotPressed(x)),
+            ("delay", &[Reg(x)]) => Some(I::LoadIntoDelayTimer(x)),
+            ("sound", &[Reg(x)]) => Some(I::LoadIntoSoundTimer(x)),
+            ("font" | "hex", &[Reg(x)]) => Some(I::LoadDigitAddress(x)),
+            ("hfont", &[Reg(x)]) => Some(I::LoadLargeDigitAddress(x)),
+            ("bcd", &[Reg(x)]) => Some(I::StoreBcdInMemory(x)),
+            ("stor", &[Reg(x)]) => Some(I::StoreRegistersInMemory(x)),
+            ("rstr", &[Reg(x)]) => Some(I::ReadRegistersFromMemory(x)),
+            ("srpl", &[Reg(x)]) => Some(I::StoreRegistersInRpl(x)),
+            ("rrpl", &[Reg(x)]) => Some(I::ReadRegistersFromRpl(x)),
+            _ => None,
+        };
+
+        instruction.ok_or_else(|| ParseInstructionError::InvalidArguments {
+            mnemonic: mnemonic.to_string(),
+            args: rest.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_success_test() {
+        use Instruction as I;
+        use Operand::{Literal as Lit, Register as Reg};
+
+        let cases = [
+            ("nop", I::Nop),
+            ("cls", I::ClearScreen),
+            ("ret", I::Return),
+            ("low", I::LowRes),
+            ("high", I::HighRes),
+            ("jmp #234", I::Jump(0x234)),
+            ("jp 564", I::Jump(564)),
+            ("jmpp v0, #300", I::JumpPlusV0(0x300)),
+            ("jmpp v3, #345", I::JumpPlusV0(0x345)),
+            ("call #456", I::Call(0x456)),
+            ("se v1, #23", I::SkipIfEqual(1, Lit(0x23))),
+            ("se v1, v2", I::SkipIfEqual(1, Reg(2))),
+            ("sne v2, %1010", I::SkipIfNotEqual(2, Lit(0b1010))),
+            ("sne v2, v3", I::SkipIfNotEqual(2, Reg(3))),
+            ("ld v4, 255", I::LoadRegister(4, Lit(255))),
+            ("ld v4, v5", I::LoadRegister(4, Reg(5))),
+            ("ld i, #abc", I::LoadMemoryRegister(0xABC)),
+            ("ld v6, k", I::WaitForKeyPress(6)),
+            ("ld v5, dt", I::LoadFromDelayTimer(5)),
+            ("add v6, 7", I::AddNoCarry(6, 7)),
+            ("add v7, v8", I::AddWithCarry(7, 8)),
+            ("add i, v9", I::AddToMemoryRegister(9)),
+            ("or v1, v2", I::Or(1, 2)),
+            ("and v3, v4", I::And(3, 4)),
+            ("xor v5, v6", I::Xor(5, 6)),
+            ("sub v9, va", I::Sub(9, 0xA)),
+            ("subn vc, vd", I::SubN(0xC, 0xD)),
+            ("shr vb", I::ShiftRight(0xB, 0xB)),
+            ("shl ve", I::ShiftLeft(0xE, 0xE)),
+            ("shr v1, v2", I::ShiftRight(1, 2)),
+            ("shl v3, v4", I::ShiftLeft(3, 4)),
+            ("rnd vf, #0f", I::LoadRandomWithMask(0xF, 0x0F)),
+            ("drw v3, v5, 4", I::Draw(3, 5, 4)),
+            ("draw v1, v2, 15", I::Draw(1, 2, 15)),
+            ("skp v3", I::SkipIfKeyPressed(3)),
+            ("sknp v4", I::SkipIfKeyNotPressed(4)),
+            ("delay v7", I::LoadIntoDelayTimer(7)),
+            ("sound v8", I::LoadIntoSoundTimer(8)),
+            ("font va", I::LoadDigitAddress(0xA)),
+            ("hex va", I::LoadDigitAddress(0xA)),
+            ("bcd vb", I::StoreBcdInMemory(0xB)),
+            ("stor vc", I::StoreRegistersInMemory(0xC)),
+            ("rstr vd", I::ReadRegistersFromMemory(0xD)),
+            ("  DRW V3,V5 ,  #4  ", I::Draw(3, 5, 4)),
+        ];
+
+        for (text, instruction) in cases {
+            assert_eq!(Instruction::try_from(text), Ok(instruction), "{text:?}");
+        }
+    }
+
+    #[test]
+    fn parse_schip_test() {
+        use Instruction as I;
+
+        let cases = [
+            ("scd 4", I::ScrollDown(4)),
+            ("scr", I::ScrollRight),
+            ("scl", I::ScrollLeft),
+            ("exit", I::Exit),
+            ("drw v1, v2, 1", I::Draw(1, 2, 1)),
+            ("hfont v3", I::LoadLargeDigitAddress(3)),
+            ("srpl v7", I::StoreRegistersInRpl(7)),
+            ("RRPL V2", I::ReadRegistersFromRpl(2)),
+        ];
+
+        for (text, instruction) in cases {
+            assert_eq!(Instruction::try_from(text), Ok(instruction), "{text}");
+        }
+
+        #[cfg(not(feature = "schip"))]
+        assert_eq!(Instruction::try_from("drw v1, v2, 0"), Ok(I::Draw(1, 2, 0)));
+        #[cfg(feature = "schip")]
+        assert_eq!(
+            Instruction::try_from("drw v1, v2, 0"),
+            Ok(I::DrawLargeSprite(1, 2))
+        );
+
+        assert_eq!(
+            Instruction::try_from("scd 16"),
+            Err(ParseInstructionError::LiteralOutOfRange { value: 16, max: 15 })
+        );
+    }
+
+    #[test]
+    fn parse_error_test() {
+        use ParseInstructionError as E;
+
+        let cases = [
+            ("", E::Empty),
+            ("   ", E::Empty),
+            ("mov v1, v2", E::UnknownMnemonic("mov".to_string())),
+            (
+                "cls v0",
+                E::WrongArgumentCount {
+                    mnemonic: "cls".to_string(),
+                    expected: 0,
+                    found: 1,
+                },
+            ),
+            (
+                "drw v3, v5",
+                E::WrongArgumentCount {
+                    mnemonic: "drw".to_string(),
+                    expected: 3,
+                    found: 2,
+                },
+            ),
+            ("ld v1, v", E::InvalidArgument("v".to_string())),
+            ("ld v1, vg", E::InvalidArgument("vg".to_string())),
+            ("ld v1, v10", E::InvalidArgument("v10".to_string())),
+            ("jmp label", E::InvalidArgument("label".to_string())),
+            ("jmp #", E::InvalidArgument("#".to_string())),
+            ("ld v1,", E::InvalidArgument("".to_string())),
+            (
+                "jmp #1000",
+                E::LiteralOutOfRange {
+                    value: 0x1000,
+                    max: 0xFFF,
+                },
+            ),
+            (
+                "ld v1, 256",
+                E::LiteralOutOfRange {
+                    value: 256,
+                    max: 0xFF,
+                },
+            ),
+            (
+                "drw v1, v2, 16",
+                E::LiteralOutOfRange {
+                    value: 16,
+                    max: 0xF,
+                },
+            ),
+            (
+                "jmpp v1, #200",
+                E::InvalidArguments {
+                    mnemonic: "jmpp".to_string(),
+                    args: "v1, #200".to_string(),
+                },
+            ),
+            (
+                "or v1, 5",
+                E::InvalidArguments {
+                    mnemonic: "or".to_string(),
+                    args: "v1, 5".to_string(),
+                },
+            ),
+            (
+                "ld dt, v1",
+                E::InvalidArguments {
+                    mnemonic: "ld".to_string(),
+                    args: "dt, v1".to_string(),
+                },
+            ),
+        ];
+
+        for (text, error) in cases {
+            assert_eq!(Instruction::try_from(text), Err(error), "{text:?}");
+        }
+    }
+}
@@ -0,0 +1,75 @@
+//! This module contains the configuration for the interpreter.
+
+/// Configurable behaviours that differ between CHIP-8 and its descendants like CHIP-48 and
+/// SUPER-CHIP.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    /// Should the `Or`, `And`, and `Xor` instructions reset VF to 0? The original COSMAC VIP
+    /// interpreter did this as a side effect, but CHIP-48 and SUPER-CHIP don't.
+    pub vf_reset: bool,
+
+    /// Should `8XY6` and `8XYE` shift VY into VX? The COSMAC VIP did this, but CHIP-48 and
+    /// SUPER-CHIP shift VX in place and ignore VY. Old ROMs that used `8X06` to shift VX in place
+    /// will shift V0 into VX instead when this is on.
+    pub shift_uses_vy: bool,
+
+    /// Should storing and reading registers in memory leave the memory register pointing just
+    /// after the last register? The COSMAC VIP did this, but CHIP-48 and SUPER-CHIP don't.
+    pub memory_increments_i: bool,
+
+    /// Should `BNNN` jump to `NNN` plus VX instead of V0, where X is the top nibble of `NNN`?
+    /// This is a mistake in CHIP-48 that SUPER-CHIP inherited.
+    pub jump_uses_vx: bool,
+}
+
+impl Quirks {
+    /// The behaviour of the original COSMAC VIP interpreter.
+    pub const fn chip8() -> Self {
+        Self {
+            vf_reset: true,
+            shift_uses_vy: true,
+            memory_increments_i: true,
+            jump_uses_vx: false,
+        }
+    }
+
+    /// The behaviour of CHIP-48 and SUPER-CHIP.
+    pub const fn chip48() -> Self {
+        Self {
+            vf_reset: false,
+            shift_uses_vy: false,
+            memory_increments_i: false,
+            jump_uses_vx: true,
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self::chip8()
+    }
+}
+
+/// Everything needed to create a new interpreter.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Chip8Config {
+    /// The ROM to load at address 0x200.
+    pub rom: Vec<u8>,
+
+    /// The clock frequency of the interpreter in Hz.
+    pub clock_frequency: f32,
+
+    /// The quirks that the interpreter should emulate.
+    pub quirks: Quirks,
+}
+
+impl Chip8Config {
+    /// Create a new config for the given ROM, running at 700 Hz with the original CHIP-8 quirks.
+    pub fn new(rom: Vec<u8>) -> Self {
+        Self {
+            rom,
+            clock_frequency: 700.,
+            quirks: Quirks::chip8(),
+        }
+    }
+}
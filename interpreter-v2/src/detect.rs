@@ -0,0 +1,211 @@
+//! This module contains [`detect_quirks_from_rom`], which guesses which quirks a ROM was written
+//! for.
+
+use crate::{config::Quirks, memory::PROGRAM_START};
+use chip8_instructions::INSTRUCTION_SIZE;
+
+/// The biggest ROM that we count as small when guessing whether it relies on `Fx55` and `Fx65`
+/// incrementing I. Early CHIP-8 programs had to fit in the COSMAC VIP's tiny memory.
+const SMALL_ROM_SIZE: usize = 1024;
+
+/// How many clues' worth of doubt we start with. Any one clue could be a coincidence, so a single
+/// clue only makes us a third sure, and it takes two clues to be half sure.
+const DOUBT: usize = 2;
+
+/// A guess at the quirks that a ROM needs, from [`detect_quirks_from_rom`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct QuirksSuggestion {
+    /// The quirks that the ROM most likely needs.
+    pub quirks: Quirks,
+
+    /// How sure we are about the quirks, from 0.0 for a complete guess to 1.0 for certain.
+    pub confidence: f32,
+
+    /// Why we chose these quirks, in plain English.
+    pub reasons: Vec<String>,
+}
+
+/// Find every instruction in the ROM that can be reached from the start by following jumps,
+/// calls, and skips, so that we don't mistake sprites for code. We can't follow `BNNN`, since it
+/// depends on a register. Returns the address and opcode of each instruction, in address order.
+fn reachable_instructions(rom: &[u8]) -> Vec<(u16, u16)> {
+    let start = PROGRAM_START as u16;
+    let mut visited = vec![false; rom.len()];
+    let mut pending = vec![start];
+
+    while let Some(address) = pending.pop() {
+        let Some(idx) = (address as usize).checked_sub(PROGRAM_START) else {
+            continue;
+        };
+        if idx + INSTRUCTION_SIZE > rom.len() || visited[idx] {
+            continue;
+        }
+        visited[idx] = true;
+
+        let opcode = u16::from_be_bytes([rom[idx], rom[idx + 1]]);
+        let next = address + INSTRUCTION_SIZE as u16;
+        match opcode >> 12 {
+            0x0 if opcode == 0x00EE => (),
+            0x1 => pending.push(opcode & 0xFFF),
+            0x2 => pending.extend([opcode & 0xFFF, next]),
+            0x3 | 0x4 | 0x5 | 0x9 => pending.extend([next, next + INSTRUCTION_SIZE as u16]),
+            0xB => (),
+            0xE => pending.extend([next, next + INSTRUCTION_SIZE as u16]),
+            _ => pending.push(next),
+        }
+    }
+
+    visited
+        .iter()
+        .enumerate()
+        .filter(|&(_, &visited)| visited)
+        .map(|(idx, _)| {
+            (
+                (idx + PROGRAM_START) as u16,
+                u16::from_be_bytes([rom[idx], rom[idx + 1]]),
+            )
+        })
+        .collect()
+}
+
+/// Guess which quirks the ROM was written for by looking at its opcodes.
+///
+/// We only look at the instructions that [can be reached](reachable_instructions) from the
+/// start, since sprite data can look like anything. Each kind of clue only counts once, and the
+/// quirks with the most clues win, with the original CHIP-8 quirks winning ties. A single clue
+/// isn't much to go on, so the confidence stays low until there are a few of them. `00FE` and
+/// `00FF` only exist in SUPER-CHIP, so they're a certain sign of it.
+pub fn detect_quirks_from_rom(rom: &[u8]) -> QuirksSuggestion {
+    let mut reasons = Vec::new();
+    let mut chip8_clues = 0;
+    let mut chip48_clues = 0;
+
+    let mut shift_with_vy = None;
+    let mut jump_with_vx = None;
+    let mut store_or_read = None;
+
+    for (address, opcode) in reachable_instructions(rom) {
+        let x = (opcode >> 8) & 0xF;
+        let y = (opcode >> 4) & 0xF;
+
+        if opcode == 0x00FE || opcode == 0x00FF {
+            return QuirksSuggestion {
+                quirks: Quirks::chip48(),
+                confidence: 1.,
+                reasons: vec![format!(
+                    "Uses SUPER-CHIP instruction 0x{opcode:0>4X} at address 0x{address:0>3X}"
+                )],
+            };
+        }
+
+        if matches!(opcode & 0xF00F, 0x8006 | 0x800E) && y != 0 {
+            shift_with_vy.get_or_insert((opcode, address));
+        } else if opcode & 0xF000 == 0xB000 && x != 0 {
+            jump_with_vx.get_or_insert((opcode, address));
+        } else if matches!(opcode & 0xF0FF, 0xF055 | 0xF065) {
+            store_or_read.get_or_insert((opcode, address));
+        }
+    }
+
+    if let Some((opcode, address)) = shift_with_vy {
+        chip8_clues += 1;
+        reasons.push(format!(
+            "Shifts with VY at address 0x{address:0>3X} (0x{opcode:0>4X}), which only matters on \
+             the original CHIP-8, where VY is shifted into VX"
+        ));
+    }
+
+    if let Some((opcode, address)) = jump_with_vx {
+        chip48_clues += 1;
+        reasons.push(format!(
+            "Jumps with a non-zero X at address 0x{address:0>3X} (0x{opcode:0>4X}), which \
+             CHIP-48 reads as a jump plus VX"
+        ));
+    }
+
+    if let Some((opcode, address)) = store_or_read {
+        if rom.len() <= SMALL_ROM_SIZE {
+            chip8_clues += 1;
+            reasons.push(format!(
+                "Stores or reads registers at address 0x{address:0>3X} (0x{opcode:0>4X}) in a \
+                 small ROM, which probably relies on I being incremented like on the original \
+                 CHIP-8"
+            ));
+        }
+    }
+
+    let (quirks, clues) = if chip48_clues > chip8_clues {
+        (Quirks::chip48(), chip48_clues)
+    } else {
+        (Quirks::chip8(), chip8_clues)
+    };
+
+    if reasons.is_empty() {
+        reasons.push("Found no clues, so we picked the original CHIP-8 quirks".to_string());
+    }
+
+    QuirksSuggestion {
+        quirks,
+        confidence: clues as f32 / (chip8_clues + chip48_clues + DOUBT) as f32,
+        reasons,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detect_quirks_test() {
+        // ld v0, 1; shr v0, v1; jmp #204
+        let suggestion = detect_quirks_from_rom(&[0x60, 0x01, 0x80, 0x16, 0x12, 0x04]);
+        assert_eq!(suggestion.quirks, Quirks::chip8());
+        assert_eq!(suggestion.confidence, 1. / 3.);
+        assert_eq!(suggestion.reasons.len(), 1);
+
+        // ld v0, 1; jmpp v2, #204
+        let suggestion = detect_quirks_from_rom(&[0x60, 0x01, 0xB2, 0x04]);
+        assert_eq!(suggestion.quirks, Quirks::chip48());
+        assert_eq!(suggestion.confidence, 1. / 3.);
+
+        // shr v0, v1; stor v1; jmp #204
+        // Two clues for the same side are more convincing than one
+        let suggestion = detect_quirks_from_rom(&[0x80, 0x16, 0xF1, 0x55, 0x12, 0x04]);
+        assert_eq!(suggestion.quirks, Quirks::chip8());
+        assert_eq!(suggestion.confidence, 0.5);
+        assert_eq!(suggestion.reasons.len(), 2);
+
+        // high; jmpp v2, #204; shr v0, v1
+        let suggestion = detect_quirks_from_rom(&[0x00, 0xFF, 0xB2, 0x04, 0x80, 0x16]);
+        assert_eq!(suggestion.quirks, Quirks::chip48());
+        assert_eq!(suggestion.confidence, 1.);
+
+        // shr v0, v1; jmpp v2, #204
+        // One clue for each side is a tie, which goes to the original CHIP-8
+        let suggestion = detect_quirks_from_rom(&[0x80, 0x16, 0xB2, 0x04]);
+        assert_eq!(suggestion.quirks, Quirks::chip8());
+        assert!(suggestion.confidence < 0.5);
+        assert_eq!(suggestion.reasons.len(), 2);
+
+        // jmp #200; jmpp v2, #204; shr v0, v1
+        // Only the jump is reachable, so there are no clues
+        let suggestion = detect_quirks_from_rom(&[0x12, 0x00, 0xB2, 0x04, 0x80, 0x16]);
+        assert_eq!(suggestion.quirks, Quirks::chip8());
+        assert_eq!(suggestion.confidence, 0.);
+
+        let suggestion = detect_quirks_from_rom(&[]);
+        assert_eq!(suggestion.quirks, Quirks::chip8());
+        assert_eq!(suggestion.confidence, 0.);
+    }
+
+    #[test]
+    fn detect_quirks_known_roms_test() {
+        let suggestion = detect_quirks_from_rom(include_bytes!("../../roms/IBM Logo.ch8"));
+        assert_eq!(suggestion.quirks, Quirks::chip8());
+        assert_eq!(suggestion.confidence, 0.);
+
+        let suggestion = detect_quirks_from_rom(include_bytes!("../../roms/Pong.ch8"));
+        assert_eq!(suggestion.quirks, Quirks::chip8());
+        assert!(suggestion.confidence > 0.);
+    }
+}
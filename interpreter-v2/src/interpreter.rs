@@ -0,0 +1,590 @@
+//! This module contains the [`Chip8InterpreterV2`] type.
+
+use crate::{
+    config::{Chip8Config, Quirks},
+    memory::{init_memory, FONT_ADDRESS_START, MEMORY_SIZE, PROGRAM_START},
+};
+use chip8_base::{Display, Interpreter, Keys, Pixel};
+use chip8_instructions::{decode, DecodingError, Instruction, Operand, INSTRUCTION_SIZE};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+/// The width of the display in pixels.
+const DISPLAY_WIDTH: usize = 64;
+
+/// The height of the display in pixels.
+const DISPLAY_HEIGHT: usize = 32;
+
+/// The number of return addresses that the stack can hold.
+const STACK_DEPTH: usize = 16;
+
+/// The time between each decrement of the delay and sound timers.
+const TIMER_PERIOD: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+/// An error that stops the interpreter from running a program.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Error)]
+pub enum Chip8Error {
+    /// The ROM doesn't fit in memory.
+    #[error("The ROM is {size} bytes, but at most {max} bytes can fit in memory")]
+    RomTooBig { size: usize, max: usize },
+
+    /// We found an opcode that we don't know how to execute.
+    #[error("Unrecognised instruction with bytecode 0x{opcode:0>4X} at address 0x{address:0>3X}")]
+    UnrecognisedInstruction { opcode: u16, address: u16 },
+
+    /// A subroutine was called when the stack was already full.
+    #[error("Stack overflow when calling a subroutine at address 0x{address:0>3X}")]
+    StackOverflow { address: u16 },
+
+    /// The program tried to return when the stack was empty.
+    #[error("Stack underflow when returning at address 0x{address:0>3X}")]
+    StackUnderflow { address: u16 },
+}
+
+/// A CHIP-8 interpreter that fixes the known bugs in `chip8::Chip8Interpreter` and emulates the
+/// original quirks by default.
+///
+/// See the CHIP-8 spec here: <http://devernay.free.fr/hacks/chip8/C8TECH10.HTM>.
+#[derive(Clone, Debug)]
+pub struct Chip8InterpreterV2 {
+    /// All the memory of the interpreter.
+    memory: [u8; MEMORY_SIZE],
+
+    /// The stack, used to keep track of return addresses.
+    stack: Vec<u16>,
+
+    /// General purpose registers V0, V1, ... VF.
+    v_registers: [u8; 16],
+
+    /// The `I` register, used to store memory addresses.
+    memory_register: u16,
+
+    /// The delay timer (DT) register.
+    delay_timer: u8,
+
+    /// The sound timer (ST) register.
+    sound_timer: u8,
+
+    /// The time that has passed since the timers last counted down.
+    timer_accumulator: Duration,
+
+    /// The time when we last ticked the timers.
+    last_timer_tick: Instant,
+
+    /// The program counter. Points to the next instruction to execute.
+    program_counter: u16,
+
+    /// The current display.
+    display: Display,
+
+    /// The speed of the interpreter.
+    speed: Duration,
+
+    /// Are we currently waiting for a key to be pressed? If so, which register should it go into?
+    waiting_for_key_press: Option<u8>,
+
+    /// The quirks that this interpreter should emulate.
+    quirks: Quirks,
+}
+
+impl Chip8InterpreterV2 {
+    /// Create a new instance of the interpreter, or return an error if the ROM is too big.
+    pub fn new(config: Chip8Config) -> Result<Self, Chip8Error> {
+        let memory = init_memory(&config.rom).ok_or(Chip8Error::RomTooBig {
+            size: config.rom.len(),
+            max: MEMORY_SIZE - PROGRAM_START,
+        })?;
+
+        Ok(Self {
+            memory,
+            stack: Vec::with_capacity(STACK_DEPTH),
+            v_registers: [0; 16],
+            memory_register: 0,
+            delay_timer: 0,
+            sound_timer: 0,
+            timer_accumulator: Duration::ZERO,
+            last_timer_tick: Instant::now(),
+            program_counter: PROGRAM_START as u16,
+            display: [[Pixel::Black; DISPLAY_WIDTH]; DISPLAY_HEIGHT],
+            speed: Duration::from_secs_f32(config.clock_frequency.recip()),
+            waiting_for_key_press: None,
+            quirks: config.quirks,
+        })
+    }
+
+    /// Execute a single cycle of the interpreter, or return an error if the program can't
+    /// continue.
+    pub fn try_step(&mut self, keys: &Keys) -> Result<Option<Display>, Chip8Error> {
+        if let Some(x) = self.waiting_for_key_press {
+            if let Some(key_num) = keys.iter().position(|&pressed| pressed) {
+                self.v_registers[x as usize] = key_num as u8;
+                self.waiting_for_key_press = None;
+            }
+        } else {
+            let address = self.program_counter;
+            let bytes = self.fetch();
+            let instruction =
+                decode(bytes).map_err(|DecodingError::UnrecognisedBytecode(opcode)| {
+                    Chip8Error::UnrecognisedInstruction { opcode, address }
+                })?;
+            self.execute(instruction, keys, address)?;
+        }
+
+        self.decrement_timers();
+
+        Ok(Some(self.display))
+    }
+
+    /// Fetch the next instruction from memory.
+    fn fetch(&mut self) -> [u8; 2] {
+        let instruction = [
+            self.read(self.program_counter),
+            self.read(self.program_counter + 1),
+        ];
+        self.program_counter = (self.program_counter + INSTRUCTION_SIZE as u16) & 0xFFF;
+        instruction
+    }
+
+    /// Get the error for an instruction at the given address that this interpreter doesn't
+    /// support, with the opcode that's in memory there.
+    fn unrecognised_instruction(&self, address: u16) -> Chip8Error {
+        Chip8Error::UnrecognisedInstruction {
+            opcode: u16::from_be_bytes([self.read(address), self.read(address + 1)]),
+            address,
+        }
+    }
+
+    /// Read the byte at the given address, wrapping around the end of memory.
+    fn read(&self, address: u16) -> u8 {
+        self.memory[address as usize % MEMORY_SIZE]
+    }
+
+    /// Write the byte to the given address, wrapping around the end of memory.
+    fn write(&mut self, address: u16, value: u8) {
+        self.memory[address as usize % MEMORY_SIZE] = value;
+    }
+
+    /// Set Vx to the given value, and then set VF to the given flag. The flag is set last so that
+    /// it takes precedence when x is F.
+    fn set_with_flag(&mut self, x: u8, value: u8, flag: bool) {
+        self.v_registers[x as usize] = value;
+        self.v_registers[0xF] = flag as u8;
+    }
+
+    /// Execute the given instruction, which was fetched from the given address.
+    fn execute(
+        &mut self,
+        instruction: Instruction,
+        keys: &Keys,
+        address: u16,
+    ) -> Result<(), Chip8Error> {
+        use Instruction as I;
+
+        let v = self.v_registers;
+
+        match instruction {
+            I::Nop => (),
+            I::ClearScreen => self.display = [[Pixel::Black; DISPLAY_WIDTH]; DISPLAY_HEIGHT],
+            // This interpreter only supports the original CHIP-8 instructions
+            I::LowRes
+            | I::HighRes
+            | I::ScrollDown(_)
+            | I::ScrollRight
+            | I::ScrollLeft
+            | I::Exit
+            | I::DrawLargeSprite(_, _)
+            | I::LoadLargeDigitAddress(_)
+            | I::StoreRegistersInRpl(_)
+            | I::ReadRegistersFromRpl(_) => return Err(self.unrecognised_instruction(address)),
+            I::Return => {
+                self.program_counter = self
+                    .stack
+                    .pop()
+                    .ok_or(Chip8Error::StackUnderflow { address })?;
+            }
+            I::Jump(target) => self.program_counter = target,
+            I::Call(target) => {
+                if self.stack.len() >= STACK_DEPTH {
+                    return Err(Chip8Error::StackOverflow { address });
+                }
+                self.stack.push(self.program_counter);
+                self.program_counter = target;
+            }
+            I::SkipIfEqual(x, op) => {
+                if v[x as usize] == self.get_operand(op) {
+                    self.skip();
+                }
+            }
+            I::SkipIfNotEqual(x, op) => {
+                if v[x as usize] != self.get_operand(op) {
+                    self.skip();
+                }
+            }
+            I::LoadRegister(x, op) => self.v_registers[x as usize] = self.get_operand(op),
+            I::AddNoCarry(x, byte) => {
+                self.v_registers[x as usize] = v[x as usize].wrapping_add(byte);
+            }
+            I::Or(x, y) => self.bitwise(x, v[x as usize] | v[y as usize]),
+            I::And(x, y) => self.bitwise(x, v[x as usize] & v[y as usize]),
+            I::Xor(x, y) => self.bitwise(x, v[x as usize] ^ v[y as usize]),
+            I::AddWithCarry(x, y) => {
+                let (value, carry) = v[x as usize].overflowing_add(v[y as usize]);
+                self.set_with_flag(x, value, carry);
+            }
+            I::Sub(x, y) => {
+                let (value, borrow) = v[x as usize].overflowing_sub(v[y as usize]);
+                self.set_with_flag(x, value, !borrow);
+            }
+            I::ShiftRight(x, y) => {
+                let value = v[self.shift_source(x, y) as usize];
+                self.set_with_flag(x, value >> 1, value & 1 == 1)
+            }
+            I::SubN(x, y) => {
+                let (value, borrow) = v[y as usize].overflowing_sub(v[x as usize]);
+                self.set_with_flag(x, value, !borrow);
+            }
+            I::ShiftLeft(x, y) => {
+                let value = v[self.shift_source(x, y) as usize];
+                self.set_with_flag(x, value << 1, value >> 7 == 1)
+            }
+            I::LoadMemoryRegister(target) => self.memory_register = target,
+            I::JumpPlusV0(target) => {
+                let register = if self.quirks.jump_uses_vx {
+                    (target >> 8) as usize
+                } else {
+                    0
+                };
+                self.program_counter = (target + v[register] as u16) & 0xFFF;
+            }
+            I::LoadRandomWithMask(x, mask) => {
+                self.v_registers[x as usize] = rand::random::<u8>() & mask;
+            }
+            I::Draw(x, y, n) => self.draw(v[x as usize], v[y as usize], n),
+            I::SkipIfKeyPressed(x) => {
+                if keys[v[x as usize] as usize & 0xF] {
+                    self.skip();
+                }
+            }
+            I::SkipIfKeyNotPressed(x) => {
+                if !keys[v[x as usize] as usize & 0xF] {
+                    self.skip();
+                }
+            }
+            I::LoadFromDelayTimer(x) => self.v_registers[x as usize] = self.delay_timer,
+            I::WaitForKeyPress(x) => self.waiting_for_key_press = Some(x),
+            I::LoadIntoDelayTimer(x) => self.delay_timer = v[x as usize],
+            I::LoadIntoSoundTimer(x) => self.sound_timer = v[x as usize],
+            I::AddToMemoryRegister(x) => {
+                self.memory_register = (self.memory_register + v[x as usize] as u16) & 0xFFF;
+            }
+            I::LoadDigitAddress(x) => {
+                self.memory_register = FONT_ADDRESS_START as u16 + 5 * (v[x as usize] & 0xF) as u16;
+            }
+            I::StoreBcdInMemory(x) => {
+                let num = v[x as usize];
+                self.write(self.memory_register, num / 100);
+                self.write(self.memory_register + 1, num / 10 % 10);
+                self.write(self.memory_register + 2, num % 10);
+            }
+            I::StoreRegistersInMemory(x) => {
+                for reg in 0..=x {
+                    self.write(self.memory_register + reg as u16, v[reg as usize]);
+                }
+                self.increment_i_if_quirk(x);
+            }
+            I::ReadRegistersFromMemory(x) => {
+                for reg in 0..=x {
+                    self.v_registers[reg as usize] = self.read(self.memory_register + reg as u16);
+                }
+                self.increment_i_if_quirk(x);
+            }
+        };
+
+        Ok(())
+    }
+
+    /// Get the value of the operand.
+    fn get_operand(&self, op: Operand) -> u8 {
+        match op {
+            Operand::Register(x) => self.v_registers[x as usize],
+            Operand::Literal(byte) => byte,
+        }
+    }
+
+    /// Skip the next instruction.
+    fn skip(&mut self) {
+        self.program_counter = (self.program_counter + INSTRUCTION_SIZE as u16) & 0xFFF;
+    }
+
+    /// Store the result of a bitwise operation in Vx, and reset VF if that quirk is enabled.
+    fn bitwise(&mut self, x: u8, value: u8) {
+        self.v_registers[x as usize] = value;
+        if self.quirks.vf_reset {
+            self.v_registers[0xF] = 0;
+        }
+    }
+
+    /// Get the register that a shift should read from, which is Vy if that quirk is enabled, or
+    /// Vx itself otherwise.
+    fn shift_source(&self, x: u8, y: u8) -> u8 {
+        if self.quirks.shift_uses_vy {
+            y
+        } else {
+            x
+        }
+    }
+
+    /// Move the memory register past V0 through Vx, if that quirk is enabled.
+    fn increment_i_if_quirk(&mut self, x: u8) {
+        if self.quirks.memory_increments_i {
+            self.memory_register = (self.memory_register + x as u16 + 1) & 0xFFF;
+        }
+    }
+
+    /// Draw the n-byte sprite at the memory register to the given coordinates. The starting
+    /// coordinates wrap around the screen, but the sprite itself is clipped at the edges.
+    fn draw(&mut self, x: u8, y: u8, n: u8) {
+        let first_x = x as usize % DISPLAY_WIDTH;
+        let first_y = y as usize % DISPLAY_HEIGHT;
+        self.v_registers[0xF] = 0;
+
+        for (offset, y) in (0..n as u16).zip(first_y..DISPLAY_HEIGHT) {
+            let row = self.read(self.memory_register + offset);
+
+            for (bit, x) in (0..8).rev().zip(first_x..DISPLAY_WIDTH) {
+                if row & (1 << bit) == 0 {
+                    continue;
+                }
+
+                let pixel = &mut self.display[y][x];
+                if *pixel == Pixel::White {
+                    self.v_registers[0xF] = 1;
+                }
+                *pixel ^= Pixel::White;
+            }
+        }
+    }
+
+    /// Count the timers down at 60 Hz, based on how much time has passed since they were last
+    /// ticked.
+    fn decrement_timers(&mut self) {
+        let now = Instant::now();
+        self.timer_accumulator += now - self.last_timer_tick;
+        self.last_timer_tick = now;
+
+        let decrements = (self.timer_accumulator.as_nanos() / TIMER_PERIOD.as_nanos()) as u32;
+        self.timer_accumulator -= TIMER_PERIOD * decrements;
+
+        let decrements = decrements.try_into().unwrap_or(u8::MAX);
+        self.delay_timer = self.delay_timer.saturating_sub(decrements);
+        self.sound_timer = self.sound_timer.saturating_sub(decrements);
+    }
+}
+
+impl Interpreter for Chip8InterpreterV2 {
+    fn step(&mut self, keys: &Keys) -> Option<Display> {
+        self.try_step(keys)
+            .unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    fn speed(&self) -> Duration {
+        self.speed
+    }
+
+    fn buzzer_active(&self) -> bool {
+        self.sound_timer > 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEYS: Keys = [false; 16];
+
+    /// Create an interpreter running the given ROM with the given quirks.
+    fn new_interpreter(rom: &[u8], quirks: Quirks) -> Chip8InterpreterV2 {
+        Chip8InterpreterV2::new(Chip8Config {
+            quirks,
+            ..Chip8Config::new(rom.to_vec())
+        })
+        .unwrap()
+    }
+
+    /// Run a single instruction with the given registers, and return the resulting registers.
+    fn run_with_registers(instruction: Instruction, registers: &[(u8, u8)]) -> [u8; 16] {
+        let mut interpreter = new_interpreter(&[], Quirks::chip8());
+        for &(x, value) in registers {
+            interpreter.v_registers[x as usize] = value;
+        }
+        interpreter.execute(instruction, &KEYS, 0x200).unwrap();
+        interpreter.v_registers
+    }
+
+    #[test]
+    fn arithmetic_flags_test() {
+        use Instruction as I;
+
+        let v = run_with_registers(I::Sub(0, 1), &[(0, 5), (1, 3)]);
+        assert_eq!((v[0], v[0xF]), (2, 1));
+        let v = run_with_registers(I::Sub(0, 1), &[(0, 3), (1, 5)]);
+        assert_eq!((v[0], v[0xF]), (254, 0));
+
+        let v = run_with_registers(I::SubN(0, 1), &[(0, 3), (1, 5)]);
+        assert_eq!((v[0], v[0xF]), (2, 1));
+        let v = run_with_registers(I::SubN(0, 1), &[(0, 5), (1, 3)]);
+        assert_eq!((v[0], v[0xF]), (254, 0));
+
+        let v = run_with_registers(I::AddWithCarry(0, 1), &[(0, 200), (1, 100)]);
+        assert_eq!((v[0], v[0xF]), (44, 1));
+
+        let v = run_with_registers(I::ShiftLeft(0, 0), &[(0, 0b1000_0001)]);
+        assert_eq!((v[0], v[0xF]), (0b10, 1));
+        let v = run_with_registers(I::ShiftRight(0, 0), &[(0, 0b1000_0001)]);
+        assert_eq!((v[0], v[0xF]), (0b0100_0000, 1));
+
+        // The flag takes precedence over the result when it's stored in VF
+        let v = run_with_registers(I::ShiftLeft(0xF, 0xF), &[(0xF, 0b0100_0000)]);
+        assert_eq!(v[0xF], 0);
+        let v = run_with_registers(I::Sub(0xF, 1), &[(0xF, 5), (1, 3)]);
+        assert_eq!(v[0xF], 1);
+    }
+
+    #[test]
+    fn quirks_test() {
+        // ld v0, #23; ld i, #300; stor v1; ld v1, #F; or v1, v0; jmpp #123
+        let rom = [
+            0x60, 0x23, 0xA3, 0x00, 0xF1, 0x55, 0x61, 0x0F, 0x81, 0x01, 0xB1, 0x23,
+        ];
+
+        let mut chip8 = new_interpreter(&rom, Quirks::chip8());
+        chip8.v_registers[0xF] = 1;
+        for _ in 0..6 {
+            chip8.try_step(&KEYS).unwrap();
+        }
+        assert_eq!(chip8.memory_register, 0x302);
+        assert_eq!(chip8.v_registers[0xF], 0);
+        assert_eq!(chip8.program_counter, 0x123 + 0x23);
+
+        let mut chip48 = new_interpreter(&rom, Quirks::chip48());
+        chip48.v_registers[0xF] = 1;
+        for _ in 0..6 {
+            chip48.try_step(&KEYS).unwrap();
+        }
+        assert_eq!(chip48.memory_register, 0x300);
+        assert_eq!(chip48.v_registers[0xF], 1);
+        assert_eq!(chip48.program_counter, 0x123 + 0x2F);
+
+        // ld v0, #81; ld v1, #02; shr v1, v0; shl v2, v0
+        let rom = [0x60, 0x81, 0x61, 0x02, 0x81, 0x06, 0x82, 0x0E];
+
+        let mut chip8 = new_interpreter(&rom, Quirks::chip8());
+        for _ in 0..4 {
+            chip8.try_step(&KEYS).unwrap();
+        }
+        assert_eq!(chip8.v_registers[1], 0x40);
+        assert_eq!(chip8.v_registers[2], 0x02);
+        assert_eq!(chip8.v_registers[0xF], 1);
+
+        let mut chip48 = new_interpreter(&rom, Quirks::chip48());
+        for _ in 0..4 {
+            chip48.try_step(&KEYS).unwrap();
+        }
+        assert_eq!(chip48.v_registers[1], 0x01);
+        assert_eq!(chip48.v_registers[2], 0x00);
+        assert_eq!(chip48.v_registers[0xF], 0);
+    }
+
+    #[test]
+    fn draw_test() {
+        // ld i, #050; drw v0, v1, 5
+        let rom = [0xA0, 0x50, 0xD0, 0x15];
+
+        let mut interpreter = new_interpreter(&rom, Quirks::chip8());
+        interpreter.v_registers[0] = 62;
+        interpreter.v_registers[1] = 30;
+        interpreter.try_step(&KEYS).unwrap();
+        let display = interpreter.try_step(&KEYS).unwrap().unwrap();
+
+        // The top of the 0 sprite is clipped to 2 pixels wide and 2 pixels tall
+        assert_eq!(display[30][62..], [Pixel::White, Pixel::White]);
+        assert_eq!(display[31][62..], [Pixel::White, Pixel::Black]);
+        assert_eq!(
+            display
+                .iter()
+                .flatten()
+                .filter(|&&pixel| pixel == Pixel::White)
+                .count(),
+            3
+        );
+        assert_eq!(interpreter.v_registers[0xF], 0);
+
+        interpreter.program_counter = 0x202;
+        let display = interpreter.try_step(&KEYS).unwrap().unwrap();
+        assert!(display.iter().flatten().all(|&pixel| pixel == Pixel::Black));
+        assert_eq!(interpreter.v_registers[0xF], 1);
+    }
+
+    #[test]
+    fn error_test() {
+        assert_eq!(
+            Chip8InterpreterV2::new(Chip8Config::new(vec![0; 0xE01])).map(|_| ()),
+            Err(Chip8Error::RomTooBig {
+                size: 0xE01,
+                max: 0xE00
+            })
+        );
+
+        let mut interpreter = new_interpreter(&[0x00, 0xEE], Quirks::chip8());
+        assert_eq!(
+            interpreter.try_step(&KEYS),
+            Err(Chip8Error::StackUnderflow { address: 0x200 })
+        );
+
+        let mut interpreter = new_interpreter(&[0x22, 0x00], Quirks::chip8());
+        for _ in 0..STACK_DEPTH {
+            interpreter.try_step(&KEYS).unwrap();
+        }
+        assert_eq!(
+            interpreter.try_step(&KEYS),
+            Err(Chip8Error::StackOverflow { address: 0x200 })
+        );
+
+        let mut interpreter = new_interpreter(&[0xFF, 0xFF], Quirks::chip8());
+        assert_eq!(
+            interpreter.try_step(&KEYS),
+            Err(Chip8Error::UnrecognisedInstruction {
+                opcode: 0xFFFF,
+                address: 0x200
+            })
+        );
+
+        // SUPER-CHIP's 00FF is only decoded with the schip feature of chip8-instructions, which
+        // can be enabled by another crate even when ours isn't, and this interpreter doesn't
+        // support it. Without the feature, it's a machine code routine that's skipped.
+        let mut interpreter = new_interpreter(&[0x00, 0xFF], Quirks::chip8());
+        if decode([0x00, 0xFF]) == Ok(Instruction::Nop) {
+            assert!(interpreter.try_step(&KEYS).is_ok());
+        } else {
+            assert_eq!(
+                interpreter.try_step(&KEYS),
+                Err(Chip8Error::UnrecognisedInstruction {
+                    opcode: 0x00FF,
+                    address: 0x200
+                })
+            );
+        }
+
+        #[cfg(feature = "schip")]
+        {
+            let mut interpreter = new_interpreter(&[0xF3, 0x75], Quirks::chip8());
+            assert_eq!(
+                interpreter.try_step(&KEYS),
+                Err(Chip8Error::UnrecognisedInstruction {
+                    opcode: 0xF375,
+                    address: 0x200
+                })
+            );
+        }
+    }
+}
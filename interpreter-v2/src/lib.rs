@@ -0,0 +1,16 @@
+//! This is the second version of the CHIP-8 interpreter from the `chip8` crate. It fixes all the
+//! known bugs of the original, and it emulates the quirks of the original COSMAC VIP
+//! interpreter by default.
+//!
+//! This will eventually replace the original interpreter.
+
+mod config;
+mod detect;
+mod interpreter;
+mod memory;
+
+pub use self::{
+    config::{Chip8Config, Quirks},
+    detect::{detect_quirks_from_rom, QuirksSuggestion},
+    interpreter::{Chip8Error, Chip8InterpreterV2},
+};
@@ -0,0 +1,121 @@
+//! This module handles memory.
+
+/// The total amount of RAM in bytes.
+pub const MEMORY_SIZE: usize = 4096;
+
+/// The address where ROMs get loaded, and where execution starts.
+pub const PROGRAM_START: usize = 0x200;
+
+/// The start of the font addresses. Each character sprite is 5 bytes long.
+pub const FONT_ADDRESS_START: usize = 0x50;
+
+#[rustfmt::skip]
+const FONT: [u8; 80] = [
+    0b11110000,
+    0b10010000,
+    0b10010000,
+    0b10010000,
+    0b11110000,
+
+    0b00100000,
+    0b01100000,
+    0b00100000,
+    0b00100000,
+    0b01110000,
+
+    0b11110000,
+    0b00010000,
+    0b11110000,
+    0b10000000,
+    0b11110000,
+
+    0b11110000,
+    0b00010000,
+    0b11110000,
+    0b00010000,
+    0b11110000,
+
+    0b10010000,
+    0b10010000,
+    0b11110000,
+    0b00010000,
+    0b00010000,
+
+    0b11110000,
+    0b10000000,
+    0b11110000,
+    0b00010000,
+    0b11110000,
+
+    0b11110000,
+    0b10000000,
+    0b11110000,
+    0b10010000,
+    0b11110000,
+
+    0b11110000,
+    0b00010000,
+    0b00100000,
+    0b01000000,
+    0b01000000,
+
+    0b11110000,
+    0b10010000,
+    0b11110000,
+    0b10010000,
+    0b11110000,
+
+    0b11110000,
+    0b10010000,
+    0b11110000,
+    0b00010000,
+    0b11110000,
+
+    0b11110000,
+    0b10010000,
+    0b11110000,
+    0b10010000,
+    0b10010000,
+
+    0b11100000,
+    0b10010000,
+    0b11100000,
+    0b10010000,
+    0b11100000,
+
+    0b11110000,
+    0b10000000,
+    0b10000000,
+    0b10000000,
+    0b11110000,
+
+    0b11100000,
+    0b10010000,
+    0b10010000,
+    0b10010000,
+    0b11100000,
+
+    0b11110000,
+    0b10000000,
+    0b11110000,
+    0b10000000,
+    0b11110000,
+
+    0b11110000,
+    0b10000000,
+    0b11110000,
+    0b10000000,
+    0b10000000,
+];
+
+/// Initialize a new 4k block of memory with the font and the given ROM, or return `None` if the
+/// ROM is too big to fit.
+pub fn init_memory(rom: &[u8]) -> Option<[u8; MEMORY_SIZE]> {
+    let mut mem = [0; MEMORY_SIZE];
+
+    mem[FONT_ADDRESS_START..(FONT_ADDRESS_START + FONT.len())].copy_from_slice(&FONT);
+    mem.get_mut(PROGRAM_START..(PROGRAM_START + rom.len()))?
+        .copy_from_slice(rom);
+
+    Some(mem)
+}
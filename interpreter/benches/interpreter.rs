@@ -0,0 +1,65 @@
+//! Benchmarks for instruction decoding and encoding, and for the throughput of the interpreter.
+//! The [`NullInterpreter`] benchmark gives a baseline for the overhead of driving an interpreter.
+
+use chip8::{null_interpreter::NullInterpreter, Chip8Interpreter, Quirks};
+use chip8_base::Interpreter;
+use chip8_instructions::{decode, encode, Instruction};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+fn bench_decode(c: &mut Criterion) {
+    c.bench_function("decode", |b| b.iter(|| decode(black_box([0x8A, 0xB4]))));
+}
+
+fn bench_encode(c: &mut Criterion) {
+    let instruction = Instruction::AddNoCarry(0xA, 0x12);
+    c.bench_function("encode", |b| b.iter(|| encode(black_box(instruction))));
+}
+
+fn bench_step_n(c: &mut Criterion) {
+    // ld v0, 0; add v0, 1; jmp #202
+    let rom = [0x60, 0x00, 0x70, 0x01, 0x12, 0x02];
+    let keys = [false; 16];
+    let mut interpreter = Chip8Interpreter::new(&rom, 700., Quirks::default());
+
+    c.bench_function("step_n(10000)", |b| {
+        b.iter(|| interpreter.step_n(black_box(10_000), black_box(&keys)))
+    });
+}
+
+fn bench_draw(c: &mut Criterion) {
+    // ld i, #206; draw v0, v1, 15; jmp #202; followed by a solid 15 byte sprite
+    let mut rom = vec![0xA2, 0x06, 0xD0, 0x1F, 0x12, 0x02];
+    rom.extend([0xFF; 15]);
+    let keys = [false; 16];
+    let mut interpreter = Chip8Interpreter::new(&rom, 700., Quirks::default());
+    interpreter.step(&keys);
+
+    // Every draw is followed by a jump, and every other draw collides with the whole sprite
+    c.bench_function("draw 15 byte sprite", |b| {
+        b.iter(|| interpreter.step_n(black_box(2), black_box(&keys)))
+    });
+}
+
+fn bench_null_interpreter(c: &mut Criterion) {
+    let mut interpreter = NullInterpreter::new();
+
+    // A headless run loop, which asks for the keys and checks the display on every cycle
+    c.bench_function("NullInterpreter 100000 cycles", |b| {
+        b.iter(|| {
+            for _ in 0..100_000 {
+                let keys = black_box([false; 16]);
+                black_box(interpreter.step(&keys));
+            }
+        })
+    });
+}
+
+criterion_group!(
+    benches,
+    bench_decode,
+    bench_encode,
+    bench_step_n,
+    bench_draw,
+    bench_null_interpreter
+);
+criterion_main!(benches);
@@ -0,0 +1,64 @@
+//! This module contains the [`SquareWaveBuzzer`] type, which generates PCM samples for the buzzer.
+
+/// The frequency of the standard CHIP-8 buzzer, in Hz.
+pub const BUZZER_FREQUENCY: f32 = 440.;
+
+/// The sample rate that we assume if the caller doesn't set one, in Hz.
+pub const DEFAULT_SAMPLE_RATE: f32 = 44_100.;
+
+/// A square wave generator, which produces one sample at a time.
+///
+/// The buzzer keeps track of where it is in the wave with a phase accumulator, so the wave stays
+/// continuous even if the frequency or sample rate change.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SquareWaveBuzzer {
+    /// The frequency of the wave, in Hz.
+    pub freq_hz: f32,
+
+    /// How far through the current period we are, in the range `0.0..1.0`.
+    pub phase: f32,
+
+    /// The number of samples per second that the caller will ask for.
+    pub sample_rate: f32,
+}
+
+impl SquareWaveBuzzer {
+    /// Create a new buzzer at the start of its period.
+    pub fn new(freq_hz: f32, sample_rate: f32) -> Self {
+        Self {
+            freq_hz,
+            phase: 0.,
+            sample_rate,
+        }
+    }
+
+    /// Get the next sample, which is 1.0 for the first half of each period and -1.0 for the
+    /// second half.
+    pub fn next_sample(&mut self) -> f32 {
+        let sample = if self.phase < 0.5 { 1. } else { -1. };
+        self.phase = (self.phase + self.freq_hz / self.sample_rate).fract();
+        sample
+    }
+}
+
+impl Default for SquareWaveBuzzer {
+    fn default() -> Self {
+        Self::new(BUZZER_FREQUENCY, DEFAULT_SAMPLE_RATE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn square_wave_test() {
+        let mut buzzer = SquareWaveBuzzer::new(1., 4.);
+        let samples: Vec<f32> = (0..8).map(|_| buzzer.next_sample()).collect();
+        assert_eq!(samples, [1., 1., -1., -1., 1., 1., -1., -1.]);
+
+        let mut buzzer = SquareWaveBuzzer::default();
+        let high_samples = (0..44_100).filter(|_| buzzer.next_sample() > 0.).count();
+        assert!((22_000..=22_100).contains(&high_samples));
+    }
+}
@@ -0,0 +1,95 @@
+//! This module contains the [`Capabilities`] trait, which lets tools find out what an interpreter
+//! can run before they run anything on it.
+
+use crate::{
+    interpreter::memory::{MEMORY_SIZE, STACK_DEPTH},
+    null_interpreter::NullInterpreter,
+    platform::Clock,
+    Chip8Interpreter,
+};
+use chip8_base::Interpreter;
+use chip8_instructions::{decode, Instruction};
+
+/// Describe what an interpreter supports, like which opcodes it can execute. This is a separate
+/// trait because [`Interpreter`] is a foreign trait and can't have new methods.
+pub trait Capabilities: Interpreter {
+    /// Can this interpreter execute the given opcode?
+    fn supports_opcode(&self, opcode: u16) -> bool;
+
+    /// Get every opcode that this interpreter can execute, in ascending order.
+    fn supported_instructions(&self) -> Vec<u16> {
+        (0..=u16::MAX)
+            .filter(|&opcode| self.supports_opcode(opcode))
+            .collect()
+    }
+
+    /// Get the number of return addresses that the stack can hold.
+    fn max_stack_depth(&self) -> u8 {
+        STACK_DEPTH as u8
+    }
+
+    /// Get the highest address in memory.
+    fn max_address(&self) -> u16 {
+        MEMORY_SIZE as u16 - 1
+    }
+}
+
+impl<C: Clock> Capabilities for Chip8Interpreter<C> {
+    /// Every standard CHIP-8 opcode is supported, and so are the SUPER-CHIP ones that switch
+    /// between the low and high resolution displays. Without the `schip` feature, those run as a
+    /// Nop. The rest of SUPER-CHIP can be decoded with the `schip` feature, but not executed.
+    fn supports_opcode(&self, opcode: u16) -> bool {
+        match decode(opcode.to_be_bytes()) {
+            Ok(
+                Instruction::ScrollDown(_)
+                | Instruction::ScrollRight
+                | Instruction::ScrollLeft
+                | Instruction::Exit
+                | Instruction::DrawLargeSprite(_, _)
+                | Instruction::LoadLargeDigitAddress(_)
+                | Instruction::StoreRegistersInRpl(_)
+                | Instruction::ReadRegistersFromRpl(_),
+            ) => false,
+            Ok(_) => true,
+            Err(_) => false,
+        }
+    }
+}
+
+impl Capabilities for NullInterpreter {
+    /// Nothing is supported, since this interpreter doesn't run any code.
+    fn supports_opcode(&self, _opcode: u16) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Quirks;
+
+    #[test]
+    fn capabilities_test() {
+        let interpreter = Chip8Interpreter::new(&[], 700., Quirks::default());
+        for opcode in [
+            0x00E0, 0x00EE, 0x00FE, 0x00FF, 0x1234, 0x8AB6, 0xD125, 0xF165,
+        ] {
+            assert!(interpreter.supports_opcode(opcode), "{opcode:04X}");
+        }
+        for opcode in [0x00C1, 0x5AB1, 0x800F, 0xE000, 0xF0FF] {
+            assert!(!interpreter.supports_opcode(opcode), "{opcode:04X}");
+        }
+
+        let supported = interpreter.supported_instructions();
+        assert!(supported.contains(&0x00E0));
+        assert!(!supported.contains(&0x00C1));
+        assert!(supported.windows(2).all(|pair| pair[0] < pair[1]));
+
+        assert_eq!(interpreter.max_stack_depth(), 16);
+        assert_eq!(interpreter.max_address(), 0xFFF);
+
+        let interpreter = NullInterpreter::new();
+        assert!(interpreter.supported_instructions().is_empty());
+        assert_eq!(interpreter.max_address(), 0xFFF);
+    }
+}
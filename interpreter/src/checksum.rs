@@ -0,0 +1,61 @@
+//! This module contains simple checksums for detecting corrupted memory and display state.
+
+/// The modulus used by Adler-32, which is the largest prime below 2^16.
+const ADLER_MODULUS: u32 = 65521;
+
+/// Compute the Adler-32 checksum of the data.
+pub fn adler32(data: &[u8]) -> u32 {
+    let (a, b) = data.iter().fold((1, 0), |(a, b), &byte| {
+        let a = (a + byte as u32) % ADLER_MODULUS;
+        (a, (b + a) % ADLER_MODULUS)
+    });
+    (b << 16) | a
+}
+
+/// Compute the Fletcher-16 checksum of the data.
+pub fn fletcher16(data: &[u8]) -> u16 {
+    let (sum1, sum2) = data.iter().fold((0u16, 0u16), |(sum1, sum2), &byte| {
+        let sum1 = (sum1 + byte as u16) % 255;
+        (sum1, (sum2 + sum1) % 255)
+    });
+    (sum2 << 8) | sum1
+}
+
+/// Compute the 64-bit FNV-1a hash of the data. This is fast, but not cryptographically secure.
+pub const fn fnv1a(data: &[u8]) -> u64 {
+    let mut hash = 0xCBF2_9CE4_8422_2325;
+    let mut idx = 0;
+    while idx < data.len() {
+        hash ^= data[idx] as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01B3);
+        idx += 1;
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adler32_test() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+        assert_eq!(adler32(&[0xFF; 4096]), 0x8161_F0E2);
+    }
+
+    #[test]
+    fn fletcher16_test() {
+        assert_eq!(fletcher16(b""), 0);
+        assert_eq!(fletcher16(b"abcde"), 0xC8F0);
+        assert_eq!(fletcher16(b"abcdef"), 0x2057);
+        assert_eq!(fletcher16(b"abcdefgh"), 0x0627);
+    }
+
+    #[test]
+    fn fnv1a_test() {
+        assert_eq!(fnv1a(b""), 0xCBF2_9CE4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xAF63_DC4C_8601_EC8C);
+        assert_eq!(fnv1a(b"foobar"), 0x8594_4171_F739_67E8);
+    }
+}
@@ -0,0 +1,429 @@
+//! This module provides conversions between a [`Display`] and the flat formats expected by
+//! external renderers.
+
+use crate::checksum::fnv1a;
+use chip8_base::{Display, Pixel};
+use thiserror::Error;
+
+/// The width of the display in pixels.
+pub const DISPLAY_WIDTH: usize = 64;
+
+/// The height of the display in pixels.
+pub const DISPLAY_HEIGHT: usize = 32;
+
+/// The width of the SUPER-CHIP high resolution display in pixels.
+pub const HIRES_DISPLAY_WIDTH: usize = 128;
+
+/// The height of the SUPER-CHIP high resolution display in pixels.
+pub const HIRES_DISPLAY_HEIGHT: usize = 64;
+
+/// The SUPER-CHIP high resolution display, which is twice as wide and twice as tall as a
+/// [`Display`].
+pub type HiResDisplay = [[Pixel; HIRES_DISPLAY_WIDTH]; HIRES_DISPLAY_HEIGHT];
+
+/// The number of bytes in a packed display, with 8 pixels per byte.
+const PACKED_LEN: usize = DISPLAY_WIDTH * DISPLAY_HEIGHT / 8;
+
+/// The [`DisplayExt::screen_hash`] of a completely black display.
+pub const BLANK_SCREEN_HASH: u64 = fnv1a(&[0; PACKED_LEN]);
+
+/// Which display the interpreter is drawing to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum GraphicsMode {
+    /// The standard 64x32 CHIP-8 display.
+    #[default]
+    LowRes,
+
+    /// The 128x64 SUPER-CHIP display.
+    HighRes,
+}
+
+impl GraphicsMode {
+    /// Get the width of the display in this mode, in pixels.
+    pub const fn width(self) -> usize {
+        match self {
+            Self::LowRes => DISPLAY_WIDTH,
+            Self::HighRes => HIRES_DISPLAY_WIDTH,
+        }
+    }
+
+    /// Get the height of the display in this mode, in pixels.
+    pub const fn height(self) -> usize {
+        match self {
+            Self::LowRes => DISPLAY_HEIGHT,
+            Self::HighRes => HIRES_DISPLAY_HEIGHT,
+        }
+    }
+}
+
+/// Shrink a high resolution display down to a normal [`Display`], where each pixel is white if
+/// any of the 2x2 block of pixels that it covers is white.
+pub fn downsample(hires: &HiResDisplay) -> Display {
+    let mut display = [[Pixel::Black; DISPLAY_WIDTH]; DISPLAY_HEIGHT];
+    for (y, row) in display.iter_mut().enumerate() {
+        for (x, pixel) in row.iter_mut().enumerate() {
+            let block = [(0, 0), (1, 0), (0, 1), (1, 1)];
+            *pixel = pixel_from_bool(
+                block
+                    .into_iter()
+                    .any(|(dx, dy)| hires[2 * y + dy][2 * x + dx] == Pixel::White),
+            );
+        }
+    }
+    display
+}
+
+/// An error returned when trying to build a [`Display`] from a slice of the wrong length.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Error)]
+#[error("Expected {expected} pixels to build a display, but got {actual}")]
+pub struct WrongSizeError {
+    /// The number of pixels that a display needs.
+    pub expected: usize,
+
+    /// The number of pixels that we actually got.
+    pub actual: usize,
+}
+
+/// The colours that the display should be drawn with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ColorScheme {
+    /// The RGB colour of white pixels.
+    pub foreground: [u8; 3],
+
+    /// The RGB colour of black pixels.
+    pub background: [u8; 3],
+}
+
+impl ColorScheme {
+    /// The classic scheme of white pixels on a black background.
+    pub const fn classic() -> Self {
+        Self {
+            foreground: [0xFF, 0xFF, 0xFF],
+            background: [0x00, 0x00, 0x00],
+        }
+    }
+
+    /// Get the RGB colour of the given pixel.
+    pub fn color_of(&self, pixel: Pixel) -> [u8; 3] {
+        match pixel {
+            Pixel::White => self.foreground,
+            Pixel::Black => self.background,
+        }
+    }
+}
+
+impl Default for ColorScheme {
+    fn default() -> Self {
+        Self::classic()
+    }
+}
+
+/// Everything needed to draw the screen: the display and the colours to draw it with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ScreenOutput {
+    /// Which pixels are on.
+    pub display: Display,
+
+    /// The colours to draw the pixels with.
+    pub color_scheme: ColorScheme,
+}
+
+impl ScreenOutput {
+    /// Convert the screen into a row-major RGBA frame with 4 bytes per pixel, which is the format
+    /// that most pixel buffers expect.
+    pub fn to_rgba_frame(&self) -> Vec<u8> {
+        self.display
+            .iter()
+            .flatten()
+            .flat_map(|&pixel| {
+                let [r, g, b] = self.color_scheme.color_of(pixel);
+                [r, g, b, 0xFF]
+            })
+            .collect()
+    }
+}
+
+/// Convert a bool into a [`Pixel`], where `true` means white.
+///
+/// [`Pixel`] is a foreign type, so we can't implement `From<bool>` for it. The reverse conversion
+/// already exists as `bool::from(pixel)`.
+pub fn pixel_from_bool(bit: bool) -> Pixel {
+    if bit {
+        Pixel::White
+    } else {
+        Pixel::Black
+    }
+}
+
+/// Convert a byte into a [`Pixel`], where any non-zero value means white.
+///
+/// Unlike `Pixel::try_from`, this never fails. The reverse conversion already exists as
+/// `u8::from(pixel)`, which gives 0 for black and 1 for white.
+pub fn pixel_from_u8(value: u8) -> Pixel {
+    pixel_from_bool(value != 0)
+}
+
+/// Extra conversions and comparisons for [`Display`], which is a foreign type alias and can't
+/// have inherent methods.
+pub trait DisplayExt: Sized {
+    /// Flatten the display into a row-major list of 2048 pixels, where `true` means white.
+    fn to_flat_bits(&self) -> Vec<bool>;
+
+    /// Build a display from a row-major list of 2048 pixels, where `true` means white.
+    fn from_flat_bits(bits: &[bool]) -> Result<Self, WrongSizeError>;
+
+    /// Pack the display into 256 bytes, with 8 pixels per byte. This is the same format that
+    /// CHIP-8 sprites use, so the most significant bit of each byte is the leftmost pixel, and
+    /// each row is 8 bytes long.
+    fn to_packed_bits(&self) -> [u8; PACKED_LEN];
+
+    /// Unpack a display from the format described in [`DisplayExt::to_packed_bits`].
+    fn from_packed_bits(bytes: &[u8; PACKED_LEN]) -> Self;
+
+    /// Get a fast FNV-1a hash of the [packed](DisplayExt::to_packed_bits) display, to quickly
+    /// check whether two displays are probably the same.
+    fn screen_hash(&self) -> u64 {
+        fnv1a(&self.to_packed_bits())
+    }
+
+    /// Count the number of pixels that differ between the two displays.
+    fn pixel_diff_count(&self, other: &Self) -> usize;
+
+    /// Check if the two displays differ in at most `max_diff` pixels. This is useful for
+    /// comparing screenshots where the timing of a draw might be slightly off.
+    fn compare_fuzzy(&self, other: &Self, max_diff: usize) -> bool {
+        self.pixel_diff_count(other) <= max_diff
+    }
+
+    /// Get the `(x, y)` coordinates of the first pixel that differs between the two displays,
+    /// scanning in row-major order.
+    fn first_diff(&self, other: &Self) -> Option<(u8, u8)>;
+}
+
+impl DisplayExt for Display {
+    fn to_flat_bits(&self) -> Vec<bool> {
+        self.iter().flatten().map(|&pixel| pixel.into()).collect()
+    }
+
+    fn from_flat_bits(bits: &[bool]) -> Result<Self, WrongSizeError> {
+        if bits.len() != DISPLAY_WIDTH * DISPLAY_HEIGHT {
+            return Err(WrongSizeError {
+                expected: DISPLAY_WIDTH * DISPLAY_HEIGHT,
+                actual: bits.len(),
+            });
+        }
+
+        let mut display = [[Pixel::Black; DISPLAY_WIDTH]; DISPLAY_HEIGHT];
+        for (pixel, &bit) in display.iter_mut().flatten().zip(bits) {
+            *pixel = pixel_from_bool(bit);
+        }
+
+        Ok(display)
+    }
+
+    fn to_packed_bits(&self) -> [u8; PACKED_LEN] {
+        let mut bytes = [0; PACKED_LEN];
+        for (y, row) in self.iter().enumerate() {
+            for (x, &pixel) in row.iter().enumerate() {
+                bytes[(y * DISPLAY_WIDTH + x) / 8] |= u8::from(pixel) << (7 - x % 8);
+            }
+        }
+        bytes
+    }
+
+    fn from_packed_bits(bytes: &[u8; PACKED_LEN]) -> Self {
+        let mut display = [[Pixel::Black; DISPLAY_WIDTH]; DISPLAY_HEIGHT];
+        for (y, row) in display.iter_mut().enumerate() {
+            for (x, pixel) in row.iter_mut().enumerate() {
+                *pixel = pixel_from_u8(bytes[(y * DISPLAY_WIDTH + x) / 8] & (1 << (7 - x % 8)));
+            }
+        }
+        display
+    }
+
+    fn pixel_diff_count(&self, other: &Self) -> usize {
+        self.iter()
+            .flatten()
+            .zip(other.iter().flatten())
+            .filter(|(a, b)| a != b)
+            .count()
+    }
+
+    fn first_diff(&self, other: &Self) -> Option<(u8, u8)> {
+        self.iter()
+            .flatten()
+            .zip(other.iter().flatten())
+            .position(|(a, b)| a != b)
+            .map(|idx| ((idx % DISPLAY_WIDTH) as u8, (idx / DISPLAY_WIDTH) as u8))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A display with an irregular pattern so that any mixup of rows or bit order shows up.
+    fn test_display() -> Display {
+        let mut display = [[Pixel::Black; DISPLAY_WIDTH]; DISPLAY_HEIGHT];
+        for (y, row) in display.iter_mut().enumerate() {
+            for (x, pixel) in row.iter_mut().enumerate() {
+                if (x * 7 + y * 3) % 5 == 0 || x == y {
+                    *pixel = Pixel::White;
+                }
+            }
+        }
+        display
+    }
+
+    #[test]
+    fn pixel_conversion_test() {
+        assert_eq!(pixel_from_bool(true), Pixel::White);
+        assert_eq!(pixel_from_bool(false), Pixel::Black);
+        assert_eq!(pixel_from_u8(0), Pixel::Black);
+        assert_eq!(pixel_from_u8(1), Pixel::White);
+        assert_eq!(pixel_from_u8(0x80), Pixel::White);
+
+        for pixel in [Pixel::Black, Pixel::White] {
+            assert_eq!(pixel_from_bool(pixel.into()), pixel);
+            assert_eq!(pixel_from_u8(pixel.into()), pixel);
+        }
+    }
+
+    /// [`Pixel`]'s bitwise operators come from `chip8_base`, but the `Draw` instruction relies on
+    /// their semantics for collision detection, so we check them here.
+    #[test]
+    fn pixel_bitwise_ops_test() {
+        use Pixel::{Black as B, White as W};
+
+        for (a, b, and, or, xor) in [
+            (B, B, B, B, B),
+            (B, W, B, W, W),
+            (W, B, B, W, W),
+            (W, W, W, W, B),
+        ] {
+            assert_eq!(a & b, and, "{a:?} & {b:?}");
+            assert_eq!(a | b, or, "{a:?} | {b:?}");
+            assert_eq!(a ^ b, xor, "{a:?} ^ {b:?}");
+
+            let mut pixel = a;
+            pixel &= b;
+            assert_eq!(pixel, and);
+
+            let mut pixel = a;
+            pixel |= b;
+            assert_eq!(pixel, or);
+
+            let mut pixel = a;
+            pixel ^= b;
+            assert_eq!(pixel, xor);
+        }
+    }
+
+    #[test]
+    fn flat_bits_roundtrip_test() {
+        let display = test_display();
+        let bits = display.to_flat_bits();
+
+        assert_eq!(bits.len(), 2048);
+        assert!(bits[0]);
+        assert!(!bits[1]);
+        assert!(bits[DISPLAY_WIDTH + 1]);
+        assert_eq!(Display::from_flat_bits(&bits), Ok(display));
+
+        assert_eq!(
+            Display::from_flat_bits(&bits[..100]),
+            Err(WrongSizeError {
+                expected: 2048,
+                actual: 100
+            })
+        );
+    }
+
+    #[test]
+    fn packed_bits_roundtrip_test() {
+        let display = test_display();
+        let bytes = display.to_packed_bits();
+
+        assert_eq!(bytes[0], 0b1000_0100);
+        assert_eq!(Display::from_packed_bits(&bytes), display);
+
+        let mut bytes = [0; PACKED_LEN];
+        bytes[8] = 0b0100_0000;
+        let display = Display::from_packed_bits(&bytes);
+        assert_eq!(display[1][1], Pixel::White);
+        assert_eq!(display.to_flat_bits().iter().filter(|&&bit| bit).count(), 1);
+    }
+
+    #[test]
+    fn diff_test() {
+        let display = test_display();
+        assert_eq!(display.pixel_diff_count(&display), 0);
+        assert_eq!(display.first_diff(&display), None);
+        assert!(display.compare_fuzzy(&display, 0));
+
+        let mut other = display;
+        for (x, y) in [(40, 3), (2, 10), (63, 31)] {
+            other[y][x] = !other[y][x];
+        }
+        assert_eq!(display.pixel_diff_count(&other), 3);
+        assert_eq!(other.pixel_diff_count(&display), 3);
+        assert_eq!(display.first_diff(&other), Some((40, 3)));
+        assert!(display.compare_fuzzy(&other, 3));
+        assert!(!display.compare_fuzzy(&other, 2));
+    }
+
+    #[test]
+    fn screen_output_test() {
+        let mut display = [[Pixel::Black; DISPLAY_WIDTH]; DISPLAY_HEIGHT];
+        display[0][1] = Pixel::White;
+
+        let classic = ScreenOutput {
+            display,
+            color_scheme: ColorScheme::default(),
+        };
+        let frame = classic.to_rgba_frame();
+        assert_eq!(frame.len(), DISPLAY_WIDTH * DISPLAY_HEIGHT * 4);
+        assert_eq!(frame[..8], [0, 0, 0, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+
+        let amber = ScreenOutput {
+            display,
+            color_scheme: ColorScheme {
+                foreground: [0xFF, 0xB0, 0x00],
+                background: [0x20, 0x10, 0x00],
+            },
+        };
+        assert_eq!(
+            amber.to_rgba_frame()[..8],
+            [0x20, 0x10, 0x00, 0xFF, 0xFF, 0xB0, 0x00, 0xFF]
+        );
+    }
+
+    #[test]
+    fn downsample_test() {
+        let mut hires = [[Pixel::Black; HIRES_DISPLAY_WIDTH]; HIRES_DISPLAY_HEIGHT];
+        assert_eq!(
+            downsample(&hires),
+            [[Pixel::Black; DISPLAY_WIDTH]; DISPLAY_HEIGHT]
+        );
+
+        hires[0][0] = Pixel::White;
+        hires[5][3] = Pixel::White;
+        hires[63][127] = Pixel::White;
+        let display = downsample(&hires);
+        assert_eq!(display[0][0], Pixel::White);
+        assert_eq!(display[2][1], Pixel::White);
+        assert_eq!(display[31][63], Pixel::White);
+        assert_eq!(
+            display
+                .iter()
+                .flatten()
+                .filter(|&&p| p == Pixel::White)
+                .count(),
+            3
+        );
+
+        assert_eq!(GraphicsMode::default(), GraphicsMode::LowRes);
+        assert_eq!(GraphicsMode::HighRes.width(), 128);
+        assert_eq!(GraphicsMode::HighRes.height(), 64);
+    }
+}
@@ -0,0 +1,57 @@
+//! This module contains the configuration for the interpreter.
+
+use super::{
+    memory::{MEMORY_SIZE, PROGRAM_START},
+    Quirks,
+};
+use crate::{display::ColorScheme, keys::KeyMap};
+
+/// Everything needed to create a new interpreter.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Chip8Config {
+    /// The ROM to load at the entry point.
+    pub rom: Vec<u8>,
+
+    /// The clock frequency of the interpreter in Hz.
+    pub clock_frequency: f32,
+
+    /// The quirks that the interpreter should emulate.
+    pub quirks: Quirks,
+
+    /// The address to load the ROM at, which is also where execution starts. Standard CHIP-8
+    /// ROMs start at 0x200.
+    pub entry_point: u16,
+
+    /// The largest ROM that can be loaded, in bytes. Standard CHIP-8 machines have 4K of memory,
+    /// so this defaults to the 3584 bytes after 0x200, but some older hardware has less.
+    pub max_rom_size: u16,
+
+    /// The colours that the display should be drawn with.
+    pub color_scheme: ColorScheme,
+
+    /// The keyboard layout used to translate key presses from `chip8_base`'s runner.
+    pub key_map: KeyMap,
+
+    /// How slowly pixels fade out after being turned off, like on a CRT display. 0.0 means they
+    /// turn off straight away, and 1.0 means they never turn off.
+    #[cfg(feature = "display-effects")]
+    pub display_persistence: f32,
+}
+
+impl Chip8Config {
+    /// Create a new config for the given ROM, running at 700 Hz with the default quirks and the
+    /// classic colours and QWERTY layout, and starting at 0x200.
+    pub fn new(rom: Vec<u8>) -> Self {
+        Self {
+            rom,
+            clock_frequency: 700.,
+            quirks: Quirks::default(),
+            entry_point: PROGRAM_START as u16,
+            max_rom_size: (MEMORY_SIZE - PROGRAM_START) as u16,
+            color_scheme: ColorScheme::classic(),
+            key_map: KeyMap::default(),
+            #[cfg(feature = "display-effects")]
+            display_persistence: 0.,
+        }
+    }
+}
@@ -0,0 +1,99 @@
+//! This module contains the errors that can stop the interpreter.
+
+use chip8_instructions::EncodingError;
+use thiserror::Error;
+
+/// An error that stops the interpreter from executing a program.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Error)]
+pub enum InterpreterError {
+    /// We found an opcode that we don't know how to execute, and the
+    /// [`on_unimplemented`](super::Chip8Interpreter::on_unimplemented) handler told us to error.
+    #[error("Unrecognised instruction with bytecode 0x{opcode:0>4X} at address 0x{address:0>4X}")]
+    UnrecognisedInstruction { opcode: u16, address: u16 },
+
+    /// The program counter points at the last byte of memory, so there's no whole instruction to
+    /// fetch. The program counter wraps to 0x000 when it moves past 0xFFE, so this only happens
+    /// when jumping to an odd address like 0xFFF.
+    #[error("Program counter 0x{pc:0>4X} is outside of memory")]
+    ProgramCounterOutOfBounds { pc: u16 },
+
+    /// The ROM is bigger than [`Chip8Config::max_rom_size`](super::Chip8Config::max_rom_size), or
+    /// doesn't fit in memory after the entry point.
+    #[error("The ROM is {size} bytes, but it can be at most {max} bytes")]
+    RomTooBig { size: usize, max: usize },
+
+    /// The entry point in [`Chip8Config::entry_point`](super::Chip8Config::entry_point) is past
+    /// the end of memory at 0xFFF.
+    #[error("The entry point 0x{entry_point:0>4X} is outside of memory")]
+    EntryPointOutOfBounds { entry_point: u16 },
+
+    /// The clock frequency in [`Chip8Config::clock_frequency`](super::Chip8Config::clock_frequency)
+    /// isn't a positive, finite number of Hz.
+    #[error("The clock frequency must be positive and finite")]
+    InvalidFrequency,
+
+    /// A subroutine was called when the stack was already full.
+    #[error("Stack overflow when calling a subroutine at address 0x{address:0>4X}")]
+    StackOverflow { address: u16 },
+
+    /// The program tried to return when the stack was empty.
+    #[error("Stack underflow when returning at address 0x{address:0>4X}")]
+    StackUnderflow { address: u16 },
+
+    /// An instruction tried to read or write `len` bytes starting at `I`, but they go past the
+    /// end of memory at 0xFFF.
+    #[error("Accessing {len} bytes at I = 0x{i:0>4X} goes outside of memory, at address 0x{address:0>4X}")]
+    MemoryOutOfBounds { i: u16, len: usize, address: u16 },
+
+    /// A key instruction checked a register that isn't a valid key number from 0 to 15.
+    #[error("The only valid keys are 0-15, not {key}, at address 0x{address:0>4X}")]
+    InvalidKey { key: u8, address: u16 },
+}
+
+/// An error from patching the interpreter's memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Error)]
+pub enum PatchError {
+    /// The patch would write past the end of memory at 0xFFF.
+    #[error("A patch of {len} bytes at address 0x{addr:0>3X} would write past the end of memory")]
+    OutOfBounds { addr: u16, len: usize },
+
+    /// The instruction in the patch couldn't be encoded.
+    #[error("Failed to encode the patched instruction: {0}")]
+    Encoding(#[from] EncodingError),
+}
+
+/// An error from restoring a snapshot of the interpreter's state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Error)]
+pub enum RestoreStateError {
+    /// The stack pointer is past the end of the stack.
+    #[error("Stack pointer {sp} is past the end of the stack, which holds {max} addresses")]
+    StackPointerTooBig { sp: u8, max: usize },
+
+    /// The program counter is past the end of memory at 0xFFF.
+    #[error("Program counter 0x{pc:0>4X} is outside of memory")]
+    ProgramCounterOutOfBounds { pc: u16 },
+}
+
+/// What the interpreter should do when it finds an opcode that it doesn't recognise.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OnUnimplementedAction {
+    /// Skip over the opcode and carry on.
+    Skip,
+
+    /// Halt the interpreter, as if the program had jumped to itself.
+    Halt,
+
+    /// Return an [`InterpreterError::UnrecognisedInstruction`].
+    #[default]
+    Error,
+}
+
+/// The result of [`Chip8Interpreter::self_test`](super::Chip8Interpreter::self_test).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SelfTestResult {
+    /// Everything looks consistent.
+    Pass,
+
+    /// Something is wrong, with a description of each problem that we found.
+    Fail(Vec<String>),
+}
@@ -0,0 +1,113 @@
+//! This module contains [`InterpreterEvent`] and the queue that holds them until they're polled.
+
+use super::error::InterpreterError;
+
+/// The number of events that the interpreter holds before it starts forgetting the oldest ones.
+pub const EVENT_QUEUE_CAPACITY: usize = 16;
+
+/// Something that happened while the interpreter was running, which a frontend can react to
+/// with [`poll_event`](super::Chip8Interpreter::poll_event).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InterpreterEvent {
+    /// The sound timer started counting down, so the buzzer should start.
+    BuzzerStarted,
+
+    /// The sound timer reached 0, so the buzzer should stop.
+    BuzzerStopped,
+
+    /// The screen was cleared.
+    ScreenCleared,
+
+    /// A sprite was drawn. The new screen is returned from the step that drew it.
+    ScreenUpdated,
+
+    /// The program is waiting for a key press, which will go into the given register.
+    WaitingForKey(u8),
+
+    /// The key that the program was waiting for was pressed. This has the register and then the
+    /// number of the key.
+    KeyReceived(u8, u8),
+
+    /// The program has halted, usually by jumping to itself.
+    HaltDetected,
+
+    /// The program couldn't continue because of this error.
+    Error(InterpreterError),
+}
+
+/// A fixed size queue of events, so that the interpreter can still be [`Copy`].
+///
+/// If nothing polls the events, the queue would grow forever, so instead we forget the oldest
+/// event when it's full.
+#[derive(Clone, Copy, Debug)]
+pub(super) struct EventQueue {
+    /// A ring buffer of the events, where only `len` of them starting at `start` are valid.
+    events: [Option<InterpreterEvent>; EVENT_QUEUE_CAPACITY],
+
+    /// The index of the oldest event.
+    start: usize,
+
+    /// The number of events in the queue.
+    len: usize,
+}
+
+impl EventQueue {
+    /// Create an empty queue.
+    pub fn new() -> Self {
+        Self {
+            events: [None; EVENT_QUEUE_CAPACITY],
+            start: 0,
+            len: 0,
+        }
+    }
+
+    /// Add an event to the back of the queue, forgetting the oldest one if it's full.
+    pub fn push(&mut self, event: InterpreterEvent) {
+        let end = (self.start + self.len) % EVENT_QUEUE_CAPACITY;
+        self.events[end] = Some(event);
+
+        if self.len == EVENT_QUEUE_CAPACITY {
+            self.start = (self.start + 1) % EVENT_QUEUE_CAPACITY;
+        } else {
+            self.len += 1;
+        }
+    }
+
+    /// Remove the oldest event from the queue.
+    pub fn pop(&mut self) -> Option<InterpreterEvent> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let event = self.events[self.start].take();
+        self.start = (self.start + 1) % EVENT_QUEUE_CAPACITY;
+        self.len -= 1;
+        event
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_queue_test() {
+        let mut queue = EventQueue::new();
+        assert_eq!(queue.pop(), None);
+
+        queue.push(InterpreterEvent::WaitingForKey(1));
+        queue.push(InterpreterEvent::KeyReceived(1, 2));
+        assert_eq!(queue.pop(), Some(InterpreterEvent::WaitingForKey(1)));
+        assert_eq!(queue.pop(), Some(InterpreterEvent::KeyReceived(1, 2)));
+        assert_eq!(queue.pop(), None);
+
+        // The first 4 events are forgotten
+        for x in 0..EVENT_QUEUE_CAPACITY as u8 + 4 {
+            queue.push(InterpreterEvent::WaitingForKey(x));
+        }
+        for x in 4..EVENT_QUEUE_CAPACITY as u8 + 4 {
+            assert_eq!(queue.pop(), Some(InterpreterEvent::WaitingForKey(x)));
+        }
+        assert_eq!(queue.pop(), None);
+    }
+}
@@ -3,6 +3,207 @@
 /// The start of the font addresses. Each character sprite is 5 bytes long.
 pub const FONT_ADDRESS_START: usize = 0x50;
 
+/// The start of the SUPER-CHIP large font addresses, straight after the normal font. Each
+/// character sprite is 10 bytes long.
+pub const LARGE_FONT_ADDRESS_START: usize = FONT_ADDRESS_START + FONT.len();
+
+/// The address where ROMs get loaded, and where execution starts.
+pub const PROGRAM_START: usize = 0x200;
+
+/// The total amount of RAM in bytes.
+pub const MEMORY_SIZE: usize = 4096;
+
+/// The number of return addresses that the stack can hold.
+pub const STACK_DEPTH: usize = 16;
+
+/// A description of where things live in the memory of an interpreter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryLayout {
+    /// The address of the first font sprite.
+    pub font_start: u16,
+
+    /// The total size of the font sprites in bytes.
+    pub font_size: u16,
+
+    /// The address where ROMs get loaded, and where execution starts.
+    pub program_start: u16,
+
+    /// The address of the stack in RAM, or `None` if the stack is stored separately.
+    pub stack_start: Option<u16>,
+
+    /// The number of return addresses that the stack can hold.
+    pub stack_depth: u8,
+
+    /// The total amount of RAM in bytes.
+    pub total_ram: u16,
+}
+
+/// The kind of thing that a part of an interpreter's memory is used for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RegionKind {
+    /// The font sprites for the 16 hex digits.
+    Font,
+
+    /// Memory before the program that the interpreter doesn't use for anything else. On the
+    /// original COSMAC VIP, this held the interpreter itself.
+    Reserved,
+
+    /// The bytes of the ROM that was loaded.
+    Program,
+
+    /// The stack of return addresses, if it's stored in memory.
+    Stack,
+
+    /// The memory after the program, which the program is free to use.
+    Free,
+}
+
+impl RegionKind {
+    /// Get the name of this kind of region, for labelling it in memory views.
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Font => "Font",
+            Self::Reserved => "Reserved",
+            Self::Program => "Program",
+            Self::Stack => "Stack",
+            Self::Free => "Free",
+        }
+    }
+}
+
+/// A continuous part of memory that's used for one thing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryRegion {
+    /// The name of the region, from [`RegionKind::name`].
+    pub name: &'static str,
+
+    /// The address of the first byte in the region.
+    pub start: u16,
+
+    /// The address just after the last byte in the region.
+    pub end: u16,
+
+    /// What the region is used for.
+    pub kind: RegionKind,
+}
+
+impl MemoryRegion {
+    /// Is the address inside this region?
+    pub fn contains(&self, addr: u16) -> bool {
+        (self.start..self.end).contains(&addr)
+    }
+}
+
+/// Every region of an interpreter's memory, in address order. The regions never overlap, and
+/// together they cover all of memory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MemoryMap {
+    /// The regions, in address order.
+    pub regions: Vec<MemoryRegion>,
+}
+
+impl MemoryMap {
+    /// Make a map of memory with the given layout and a program of `program_size` bytes. Where
+    /// regions overlap, the program wins over the stack, which wins over the font, since
+    /// that's the order that they get written in.
+    pub fn new(layout: &MemoryLayout, program_size: u16) -> Self {
+        let total_ram = layout.total_ram as usize;
+        let mut kinds = vec![RegionKind::Reserved; total_ram];
+        let mut paint = |start: u16, len: usize, kind| {
+            let start = (start as usize).min(total_ram);
+            let end = (start + len).min(total_ram);
+            kinds[start..end].fill(kind);
+        };
+
+        let program_end = layout.program_start + program_size;
+        paint(program_end, total_ram, RegionKind::Free);
+        paint(
+            layout.font_start,
+            layout.font_size as usize,
+            RegionKind::Font,
+        );
+        if let Some(stack_start) = layout.stack_start {
+            paint(
+                stack_start,
+                layout.stack_depth as usize * 2,
+                RegionKind::Stack,
+            );
+        }
+        paint(
+            layout.program_start,
+            program_size as usize,
+            RegionKind::Program,
+        );
+
+        let mut regions: Vec<MemoryRegion> = Vec::new();
+        for (addr, kind) in kinds.into_iter().enumerate() {
+            match regions.last_mut() {
+                Some(region) if region.kind == kind => region.end += 1,
+                _ => regions.push(MemoryRegion {
+                    name: kind.name(),
+                    start: addr as u16,
+                    end: addr as u16 + 1,
+                    kind,
+                }),
+            }
+        }
+
+        Self { regions }
+    }
+
+    /// Get the region that contains the address.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the address is outside of memory.
+    pub fn region_at(&self, addr: u16) -> &MemoryRegion {
+        self.regions
+            .iter()
+            .find(|region| region.contains(addr))
+            .unwrap_or_else(|| panic!("Address 0x{addr:0>3X} is outside of memory"))
+    }
+}
+
+/// How an instruction uses a part of memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AccessMode {
+    /// The memory is only read.
+    Read,
+
+    /// The memory is only written.
+    Write,
+
+    /// The memory is read and then written.
+    ReadWrite,
+}
+
+/// A continuous part of memory that an instruction accesses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MemoryAccess {
+    /// The address of the first byte that's accessed.
+    pub addr: u16,
+
+    /// The number of bytes that are accessed.
+    pub size: usize,
+
+    /// How the memory is used.
+    pub mode: AccessMode,
+}
+
+impl Default for MemoryLayout {
+    /// The standard CHIP-8 layout, with the font at the very start of memory.
+    fn default() -> Self {
+        Self {
+            font_start: 0x000,
+            font_size: FONT.len() as u16,
+            program_start: PROGRAM_START as u16,
+            stack_start: None,
+            stack_depth: STACK_DEPTH as u8,
+            total_ram: MEMORY_SIZE as u16,
+        }
+    }
+}
+
 #[rustfmt::skip]
 const FONT: [u8; 80] = [
     0b11110000,
@@ -102,12 +303,346 @@ const FONT: [u8; 80] = [
     0b10000000,
 ];
 
-/// Initialize a new 4k block of memory with the given rom loaded in at address 0x200.
-pub fn init_memory(rom: &[u8]) -> [u8; 4096] {
+/// The SUPER-CHIP large font, with 10 bytes for each of the 16 hex digits. The original
+/// SUPER-CHIP only had large digits for 0 to 9, so A to F follow the style of later interpreters.
+#[rustfmt::skip]
+const LARGE_FONT: [u8; 160] = [
+    0b00111100,
+    0b01111110,
+    0b11100111,
+    0b11000011,
+    0b11000011,
+    0b11000011,
+    0b11000011,
+    0b11100111,
+    0b01111110,
+    0b00111100,
+
+    0b00011000,
+    0b00111000,
+    0b01011000,
+    0b00011000,
+    0b00011000,
+    0b00011000,
+    0b00011000,
+    0b00011000,
+    0b00011000,
+    0b00111100,
+
+    0b00111110,
+    0b01111111,
+    0b11000011,
+    0b00000110,
+    0b00001100,
+    0b00011000,
+    0b00110000,
+    0b01100000,
+    0b11111111,
+    0b11111111,
+
+    0b00111100,
+    0b01111110,
+    0b11000011,
+    0b00000011,
+    0b00001110,
+    0b00001110,
+    0b00000011,
+    0b11000011,
+    0b01111110,
+    0b00111100,
+
+    0b00000110,
+    0b00001110,
+    0b00011110,
+    0b00110110,
+    0b01100110,
+    0b11000110,
+    0b11111111,
+    0b11111111,
+    0b00000110,
+    0b00000110,
+
+    0b11111111,
+    0b11111111,
+    0b11000000,
+    0b11000000,
+    0b11111100,
+    0b11111110,
+    0b00000011,
+    0b11000011,
+    0b01111110,
+    0b00111100,
+
+    0b00111110,
+    0b01111100,
+    0b11000000,
+    0b11000000,
+    0b11111100,
+    0b11111110,
+    0b11000011,
+    0b11000011,
+    0b01111110,
+    0b00111100,
+
+    0b11111111,
+    0b11111111,
+    0b00000011,
+    0b00000011,
+    0b00000110,
+    0b00001100,
+    0b00011000,
+    0b00011000,
+    0b00011000,
+    0b00011000,
+
+    0b00111100,
+    0b01111110,
+    0b11000011,
+    0b11000011,
+    0b01111110,
+    0b01111110,
+    0b11000011,
+    0b11000011,
+    0b01111110,
+    0b00111100,
+
+    0b00111100,
+    0b01111110,
+    0b11000011,
+    0b11000011,
+    0b01111111,
+    0b00111111,
+    0b00000011,
+    0b00000011,
+    0b00111110,
+    0b01111100,
+
+    0b00011000,
+    0b00111100,
+    0b01100110,
+    0b11000011,
+    0b11000011,
+    0b11111111,
+    0b11111111,
+    0b11000011,
+    0b11000011,
+    0b11000011,
+
+    0b11111100,
+    0b11111110,
+    0b11000011,
+    0b11000011,
+    0b11111110,
+    0b11111110,
+    0b11000011,
+    0b11000011,
+    0b11111110,
+    0b11111100,
+
+    0b00111100,
+    0b01111110,
+    0b11000011,
+    0b11000000,
+    0b11000000,
+    0b11000000,
+    0b11000000,
+    0b11000011,
+    0b01111110,
+    0b00111100,
+
+    0b11111100,
+    0b11111110,
+    0b11000011,
+    0b11000011,
+    0b11000011,
+    0b11000011,
+    0b11000011,
+    0b11000011,
+    0b11111110,
+    0b11111100,
+
+    0b11111111,
+    0b11111111,
+    0b11000000,
+    0b11000000,
+    0b11111100,
+    0b11111100,
+    0b11000000,
+    0b11000000,
+    0b11111111,
+    0b11111111,
+
+    0b11111111,
+    0b11111111,
+    0b11000000,
+    0b11000000,
+    0b11111100,
+    0b11111100,
+    0b11000000,
+    0b11000000,
+    0b11000000,
+    0b11000000,
+];
+
+/// Get the address of the font sprite for the given hex digit, which should be in `0..=15`.
+pub const fn font_address(digit: u8) -> u16 {
+    FONT_ADDRESS_START as u16 + digit as u16 * 5
+}
+
+/// Get the address of the SUPER-CHIP large font sprite for the given hex digit, which should be
+/// in `0..=15`.
+pub const fn large_font_address(digit: u8) -> u16 {
+    LARGE_FONT_ADDRESS_START as u16 + digit as u16 * 10
+}
+
+/// The built-in font, with 5 bytes for each of the 16 hex digits.
+pub fn default_font() -> &'static [u8; 80] {
+    &FONT
+}
+
+/// Copy the given font into memory, over the top of the existing font.
+pub fn load_font(memory: &mut [u8; MEMORY_SIZE], font: &[u8; 80]) {
+    memory[FONT_ADDRESS_START..(FONT_ADDRESS_START + font.len())].copy_from_slice(font);
+}
+
+/// The memory layout that [`init_memory`] produces. The font region covers both the normal and
+/// the large font.
+pub fn chip8_memory_layout() -> MemoryLayout {
+    MemoryLayout {
+        font_start: FONT_ADDRESS_START as u16,
+        font_size: (FONT.len() + LARGE_FONT.len()) as u16,
+        ..MemoryLayout::default()
+    }
+}
+
+/// Initialize a new 4k block of memory with the given rom loaded in at `start_addr`, which is
+/// normally [`PROGRAM_START`]. The ROM is loaded after the fonts, so it can overwrite them.
+pub fn init_memory(rom: &[u8], start_addr: u16) -> [u8; MEMORY_SIZE] {
     let mut mem = [0; _];
+    let start_addr = start_addr as usize;
 
-    mem[FONT_ADDRESS_START..(FONT_ADDRESS_START + FONT.len())].copy_from_slice(&FONT);
-    mem[0x200..(0x200 + rom.len())].copy_from_slice(&rom);
+    load_font(&mut mem, &FONT);
+    mem[LARGE_FONT_ADDRESS_START..(LARGE_FONT_ADDRESS_START + LARGE_FONT.len())]
+        .copy_from_slice(&LARGE_FONT);
+    mem[start_addr..(start_addr + rom.len())].copy_from_slice(rom);
 
     mem
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn font_address_test() {
+        for digit in 0..=0xF {
+            assert_eq!(
+                font_address(digit),
+                FONT_ADDRESS_START as u16 + 5 * digit as u16
+            );
+            assert_eq!(
+                large_font_address(digit),
+                LARGE_FONT_ADDRESS_START as u16 + 10 * digit as u16
+            );
+        }
+
+        assert_eq!(font_address(0x0), 0x50);
+        assert_eq!(font_address(0xF), 0x9B);
+        assert_eq!(large_font_address(0x0), 0xA0);
+        assert_eq!(large_font_address(0xF), 0x136);
+        assert!((large_font_address(0xF) as usize + 10) <= PROGRAM_START);
+    }
+
+    #[test]
+    fn init_memory_test() {
+        let memory = init_memory(&[0x12, 0x00], PROGRAM_START as u16);
+
+        let small = font_address(0x0) as usize;
+        assert_eq!(memory[small..small + 5], [0xF0, 0x90, 0x90, 0x90, 0xF0]);
+
+        let large = large_font_address(0x0) as usize;
+        assert_eq!(
+            memory[large..large + 10],
+            [0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C]
+        );
+        let large = large_font_address(0xF) as usize;
+        assert_eq!(
+            memory[large..large + 10],
+            [0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xC0, 0xC0]
+        );
+        assert_eq!(memory[large + 10..PROGRAM_START], [0; 0xC0]);
+
+        assert_eq!(memory[PROGRAM_START..PROGRAM_START + 2], [0x12, 0x00]);
+    }
+
+    /// Check that the regions are in order, don't overlap, and cover all of memory.
+    fn assert_covers_memory(map: &MemoryMap, total_ram: u16) {
+        assert_eq!(map.regions.first().unwrap().start, 0);
+        assert_eq!(map.regions.last().unwrap().end, total_ram);
+        for pair in map.regions.windows(2) {
+            assert_eq!(pair[0].end, pair[1].start, "{pair:?}");
+            assert_ne!(pair[0].kind, pair[1].kind, "{pair:?}");
+        }
+        for region in &map.regions {
+            assert!(region.start < region.end, "{region:?}");
+            assert_eq!(region.name, region.kind.name());
+        }
+    }
+
+    #[test]
+    fn memory_map_test() {
+        let map = MemoryMap::new(&chip8_memory_layout(), 0x10);
+        assert_covers_memory(&map, 0x1000);
+        assert_eq!(
+            map.regions
+                .iter()
+                .map(|region| (region.kind, region.start, region.end))
+                .collect::<Vec<_>>(),
+            [
+                (RegionKind::Reserved, 0x000, 0x050),
+                (RegionKind::Font, 0x050, 0x140),
+                (RegionKind::Reserved, 0x140, 0x200),
+                (RegionKind::Program, 0x200, 0x210),
+                (RegionKind::Free, 0x210, 0x1000),
+            ]
+        );
+        assert_eq!(map.region_at(0x000).kind, RegionKind::Reserved);
+        assert_eq!(map.region_at(0x13F).kind, RegionKind::Font);
+        assert_eq!(map.region_at(0x20F).kind, RegionKind::Program);
+        assert_eq!(map.region_at(0x210).kind, RegionKind::Free);
+        assert_eq!(map.region_at(0xFFF).kind, RegionKind::Free);
+
+        // An empty program has no region at all, and a stack in memory gets its own region
+        let layout = MemoryLayout {
+            stack_start: Some(0xEA0),
+            ..MemoryLayout::default()
+        };
+        let map = MemoryMap::new(&layout, 0);
+        assert_covers_memory(&map, 0x1000);
+        assert_eq!(map.region_at(0x000).kind, RegionKind::Font);
+        assert_eq!(map.region_at(0x200).kind, RegionKind::Free);
+        assert_eq!(map.region_at(0xEBF).kind, RegionKind::Stack);
+        assert_eq!(map.region_at(0xEC0).kind, RegionKind::Free);
+        assert!(map
+            .regions
+            .iter()
+            .all(|region| region.kind != RegionKind::Program));
+
+        // The program overwrites the font if it's loaded on top of it
+        let layout = MemoryLayout {
+            program_start: 0x040,
+            ..chip8_memory_layout()
+        };
+        let map = MemoryMap::new(&layout, 0x20);
+        assert_covers_memory(&map, 0x1000);
+        assert_eq!(map.region_at(0x05F).kind, RegionKind::Program);
+        assert_eq!(map.region_at(0x060).kind, RegionKind::Font);
+        assert_eq!(map.region_at(0x140).kind, RegionKind::Free);
+    }
+
+    #[test]
+    #[should_panic(expected = "outside of memory")]
+    fn memory_map_out_of_bounds_test() {
+        MemoryMap::new(&chip8_memory_layout(), 0).region_at(0x1000);
+    }
+}
@@ -1,23 +1,119 @@
 //! This module contains the [`Interpreter`] type.
 
-mod memory;
+mod config;
+mod error;
+mod event;
+pub mod memory;
+mod state;
 
-use self::memory::init_memory;
-use crate::interpreter::memory::FONT_ADDRESS_START;
+pub use self::{
+    config::Chip8Config,
+    error::{
+        InterpreterError, OnUnimplementedAction, PatchError, RestoreStateError, SelfTestResult,
+    },
+    event::{InterpreterEvent, EVENT_QUEUE_CAPACITY},
+    state::InterpreterState,
+};
+
+use self::event::EventQueue;
+use self::memory::{
+    chip8_memory_layout, font_address, init_memory, load_font, AccessMode, MemoryAccess,
+    MemoryLayout, MemoryMap, RegionKind, FONT_ADDRESS_START, MEMORY_SIZE, STACK_DEPTH,
+};
+#[cfg(feature = "schip")]
+use crate::display::{downsample, HiResDisplay};
+#[cfg(feature = "display-effects")]
+use crate::display::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use crate::{
+    audio::SquareWaveBuzzer,
+    checksum,
+    display::{
+        pixel_from_bool, pixel_from_u8, ColorScheme, DisplayExt, GraphicsMode, ScreenOutput,
+        BLANK_SCREEN_HASH,
+    },
+    keys::KeyMap,
+    platform::{Clock, StdClock, Timestamp},
+    stats::{StepTimings, TimingStats},
+    timer::{FrameCounter, SoftwareTimer},
+};
 use chip8_base::{Display, Interpreter, Keys, Pixel};
-use chip8_instructions::{decode, DecodingError, Instruction, Operand};
-use std::time::{Duration, Instant};
+use chip8_instructions::{decode, encode, DecodingError, Instruction, Operand, INSTRUCTION_SIZE};
+use std::{marker::PhantomData, time::Duration};
+
+/// Configurable behaviours that differ between CHIP-8 and its descendants like CHIP-48 and
+/// SUPER-CHIP.
+///
+/// The default is the behaviour of the original COSMAC VIP interpreter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    /// Should the `Or`, `And`, and `Xor` instructions reset VF to 0? The original COSMAC VIP
+    /// interpreter did this as a side effect, but CHIP-48 and SUPER-CHIP don't.
+    pub or_and_xor_reset_vf: bool,
+
+    /// Should `8XY6` and `8XYE` shift VY into VX? The COSMAC VIP did this, but CHIP-48 and
+    /// SUPER-CHIP shift VX in place and ignore VY. This is on by default, so old ROMs that used
+    /// `8X06` to shift VX in place will shift V0 into VX instead unless it's turned off.
+    pub shift_uses_vy: bool,
+
+    /// Should `FX55` and `FX65` leave the memory register pointing just after the last register,
+    /// by adding X + 1 to it? The COSMAC VIP did this, but CHIP-48 and SUPER-CHIP don't.
+    pub memory_register_incremented: bool,
+
+    /// Should `BNNN` jump to `NNN` plus VX instead of V0, where X is the top nibble of `NNN`?
+    /// This is a mistake in CHIP-48 that SUPER-CHIP inherited.
+    pub jump_plus_uses_vx: bool,
+
+    /// Should sprites that go off the edge of the display wrap around to the other side? Most
+    /// interpreters clip them instead, but some games rely on wrapping.
+    pub sprite_wrapping: bool,
+}
+
+impl Quirks {
+    /// The behaviour of the original COSMAC VIP interpreter.
+    pub const fn chip8() -> Self {
+        Self {
+            or_and_xor_reset_vf: true,
+            shift_uses_vy: true,
+            memory_register_incremented: true,
+            jump_plus_uses_vx: false,
+            sprite_wrapping: false,
+        }
+    }
+
+    /// The behaviour of CHIP-48 and SUPER-CHIP.
+    pub const fn chip48() -> Self {
+        Self {
+            or_and_xor_reset_vf: false,
+            shift_uses_vy: false,
+            memory_register_incremented: false,
+            jump_plus_uses_vx: true,
+            sprite_wrapping: false,
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self::chip8()
+    }
+}
 
 /// A simple CHIP-8 interpreter.
 ///
 /// See the CHIP-8 spec here: <http://devernay.free.fr/hacks/chip8/C8TECH10.HTM>.
+///
+/// The interpreter reads the time from the [`Clock`] `C`, which uses [`std::time::Instant`] by
+/// default.
 #[derive(Clone, Copy, Debug)]
-pub struct Chip8Interpreter {
+pub struct Chip8Interpreter<C: Clock = StdClock> {
     /// All the memory of the interpreter.
-    memory: [u8; 4096],
+    memory: [u8; MEMORY_SIZE],
+
+    /// Has a custom font been loaded over the built-in one?
+    custom_font: bool,
 
     /// The stack, used to keep track of return addresses.
-    stack: [u16; 16],
+    stack: [u16; STACK_DEPTH],
 
     /// General purpose registers V0, V1, ... VF.
     v_registers: [u8; 16],
@@ -26,270 +122,3547 @@ pub struct Chip8Interpreter {
     memory_register: u16,
 
     /// The delay timer (DT) register.
-    delay_timer: u8,
+    delay_timer: SoftwareTimer,
 
     /// The sound timer (ST) register.
-    sound_timer: u8,
+    sound_timer: SoftwareTimer,
 
     /// The program counter. Points to the next instruction to execute.
     program_counter: u16,
 
+    /// The address that the ROM was loaded at, and where execution started.
+    entry_point: u16,
+
+    /// The length of the ROM in bytes.
+    rom_len: u16,
+
     /// The stack pointer. Points to the top of the stack.
     stack_pointer: u8,
 
-    /// The current display.
+    /// The current display. In [`GraphicsMode::HighRes`], this is the high resolution display
+    /// shrunk down to fit.
     display: Display,
 
+    /// Which display we're drawing to.
+    #[cfg(feature = "schip")]
+    graphics_mode: GraphicsMode,
+
+    /// The 128x64 display, which is only drawn to in [`GraphicsMode::HighRes`]. This is 8 KB, so
+    /// it only exists with the `schip` feature.
+    #[cfg(feature = "schip")]
+    hires_display: HiResDisplay,
+
+    /// The hash of the current display, which is updated whenever the display changes.
+    screen_hash: u64,
+
+    /// The colours that the display should be drawn with.
+    color_scheme: ColorScheme,
+
+    /// How much of a pixel's brightness is left after each 60 Hz frame once it's turned off.
+    #[cfg(feature = "display-effects")]
+    display_persistence: f32,
+
+    /// The brightness of each pixel from 0.0 to 1.0, which fades out over time after the pixel
+    /// is turned off.
+    #[cfg(feature = "display-effects")]
+    ghost_display: [[f32; DISPLAY_WIDTH]; DISPLAY_HEIGHT],
+
+    /// The keyboard layout used to translate keys in [`Interpreter::step`].
+    key_map: KeyMap,
+
+    /// The CHIP-8 keys that are held down according to
+    /// [`inject_key_event`](Self::inject_key_event).
+    current_keys: Keys,
+
+    /// The SHA-256 hash of the ROM, computed when the interpreter was created.
+    #[cfg(feature = "sha2")]
+    rom_hash: [u8; 32],
+
     /// The speed of the interpreter.
     speed: Duration,
 
-    /// The time when we last decremented the timers.
-    last_timer_decrement: Instant,
+    /// The time when we last ticked the timers.
+    last_timer_tick: Timestamp,
 
     /// Are we currently waiting for a key to be pressed? If so, which register should it go into?
     waiting_for_key_press: Option<u8>,
+
+    /// When did we start waiting for a key to be pressed?
+    key_wait_started: Option<Timestamp>,
+
+    /// The quirks that this interpreter should emulate.
+    quirks: Quirks,
+
+    /// The number of cycles that have been executed so far.
+    cycles: u64,
+
+    /// The value of `cycles` when the interpreter was last [`reset`](Self::reset).
+    cycles_at_reset: u64,
+
+    /// The number of times that the 60 Hz timer clock has ticked.
+    frames: FrameCounter,
+
+    /// The cycle in which the screen was last cleared, if it's been cleared at all.
+    last_clear_at_cycle: Option<u64>,
+
+    /// Has the program halted by jumping to itself?
+    halted: bool,
+
+    /// Decides what to do when we find an opcode that we don't recognise.
+    on_unimplemented_handler: fn(u16) -> OnUnimplementedAction,
+
+    /// Generates the samples for [`audio_sample`](Self::audio_sample).
+    buzzer: SquareWaveBuzzer,
+
+    /// Can the buzzer make a sound? The sound timer still counts down when this is false.
+    audio_enabled: bool,
+
+    /// When recent steps happened, for [`timing_stats`](Self::timing_stats).
+    timings: StepTimings,
+
+    /// The last instruction that was executed.
+    last_instruction: Option<Instruction>,
+
+    /// The address of the last instruction that was executed.
+    last_executed_pc: Option<u16>,
+
+    /// A bitset of the addresses that have breakpoints, with one bit for each byte of memory.
+    breakpoints: [u64; MEMORY_SIZE / 64],
+
+    /// The address of the breakpoint that was reached in the last step, if any.
+    last_breakpoint: Option<u16>,
+
+    /// Called by [`Interpreter::step`] when a breakpoint is reached.
+    on_breakpoint_handler: fn(&Self, u16),
+
+    /// Called by [`Interpreter::step`] when the program can't continue.
+    on_error_handler: fn(&Self, InterpreterError),
+
+    /// Has a `Draw` instruction run since the flag was last cleared?
+    display_dirty: bool,
+
+    /// Called after every `Draw` instruction.
+    on_display_update_handler: fn(&Self),
+
+    /// Called after an instruction writes a byte to memory.
+    on_memory_write_handler: fn(&Self, u16, u8),
+
+    /// Called after an instruction reads a byte from memory.
+    on_memory_read_handler: fn(&Self, u16, u8),
+
+    /// The events that haven't been polled yet.
+    events: EventQueue,
+
+    /// The clock that we read the time from.
+    clock: PhantomData<C>,
 }
 
 impl Chip8Interpreter {
-    /// Create a new instance of the interpreter.
+    /// Create a new instance of the interpreter, with the ROM loaded at 0x200.
     ///
     /// The clock frequency is measure in Hz.
-    pub fn new(rom: &[u8], clock_frequency: f32) -> Self {
-        Self {
-            memory: init_memory(rom),
+    ///
+    /// # Panics
+    ///
+    /// Panics if the ROM doesn't fit in memory, or the clock frequency isn't positive.
+    pub fn new(rom: &[u8], clock_frequency: f32, quirks: Quirks) -> Self {
+        Self::from_config(Chip8Config {
+            clock_frequency,
+            quirks,
+            ..Chip8Config::new(rom.to_vec())
+        })
+    }
+
+    /// Create a new instance of the interpreter from the given config.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the ROM is too big or the clock frequency is invalid, like
+    /// [`try_from_config`](Self::try_from_config) would return an error for.
+    pub fn from_config(config: Chip8Config) -> Self {
+        Self::with_clock(config)
+    }
+
+    /// Create a new instance of the interpreter from the given config, or return an error if the
+    /// ROM is bigger than [`Chip8Config::max_rom_size`] or doesn't fit in memory after the entry
+    /// point, the entry point is outside of memory, or the clock frequency isn't positive.
+    pub fn try_from_config(config: Chip8Config) -> Result<Self, InterpreterError> {
+        Self::try_with_clock(config)
+    }
+}
+
+impl<C: Clock> Chip8Interpreter<C> {
+    /// Create a new instance of the interpreter from the given config, reading the time from the
+    /// clock `C`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the ROM is too big, the entry point is outside of memory, or the clock frequency
+    /// is invalid, like [`try_with_clock`](Self::try_with_clock) would return an error for.
+    pub fn with_clock(config: Chip8Config) -> Self {
+        Self::try_with_clock(config).unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    /// Create a new instance of the interpreter from the given config, reading the time from the
+    /// clock `C`, or return an error if the ROM is bigger than [`Chip8Config::max_rom_size`] or
+    /// doesn't fit in memory after the entry point, the entry point is outside of memory, or the
+    /// clock frequency isn't positive.
+    pub fn try_with_clock(config: Chip8Config) -> Result<Self, InterpreterError> {
+        let Chip8Config {
+            rom,
+            clock_frequency,
+            quirks,
+            entry_point,
+            max_rom_size,
+            color_scheme,
+            key_map,
+            #[cfg(feature = "display-effects")]
+            display_persistence,
+        } = config;
+
+        if entry_point as usize >= MEMORY_SIZE {
+            return Err(InterpreterError::EntryPointOutOfBounds { entry_point });
+        }
+
+        // Like in set_frequency and set_speed, but a frequency so low that the time between steps
+        // doesn't fit in a Duration is an error too
+        let speed = match Duration::try_from_secs_f32(clock_frequency.recip()) {
+            Ok(speed) if clock_frequency > 0. && !speed.is_zero() => speed,
+            _ => return Err(InterpreterError::InvalidFrequency),
+        };
+
+        let max = (max_rom_size as usize).min(MEMORY_SIZE - entry_point as usize);
+        if rom.len() > max {
+            return Err(InterpreterError::RomTooBig {
+                size: rom.len(),
+                max,
+            });
+        }
+
+        Ok(Self {
+            #[cfg(feature = "sha2")]
+            rom_hash: crate::rom_hash::hash_rom(&rom),
+            memory: init_memory(&rom, entry_point),
+            custom_font: false,
+            rom_len: rom.len() as u16,
             stack: [0; _],
             v_registers: [0; _],
             memory_register: 0,
-            delay_timer: 0,
-            sound_timer: 0,
-            program_counter: 0x200,
+            delay_timer: SoftwareTimer::default(),
+            sound_timer: SoftwareTimer::default(),
+            program_counter: entry_point,
+            entry_point,
             stack_pointer: 0,
             display: [[Pixel::Black; _]; _],
-            speed: Duration::from_secs_f32(clock_frequency.recip()),
-            last_timer_decrement: Instant::now(),
+            #[cfg(feature = "schip")]
+            graphics_mode: GraphicsMode::LowRes,
+            #[cfg(feature = "schip")]
+            hires_display: [[Pixel::Black; _]; _],
+            screen_hash: BLANK_SCREEN_HASH,
+            color_scheme,
+            #[cfg(feature = "display-effects")]
+            display_persistence: check_display_persistence(display_persistence),
+            #[cfg(feature = "display-effects")]
+            ghost_display: [[0.; _]; _],
+            key_map,
+            current_keys: [false; 16],
+            speed,
+            last_timer_tick: C::now(),
             waiting_for_key_press: None,
-        }
+            key_wait_started: None,
+            quirks,
+            cycles: 0,
+            cycles_at_reset: 0,
+            frames: FrameCounter::default(),
+            last_clear_at_cycle: None,
+            halted: false,
+            on_unimplemented_handler: |_| OnUnimplementedAction::default(),
+            buzzer: SquareWaveBuzzer::default(),
+            audio_enabled: true,
+            timings: StepTimings::new(),
+            last_instruction: None,
+            last_executed_pc: None,
+            breakpoints: [0; _],
+            last_breakpoint: None,
+            on_breakpoint_handler: |_, _| (),
+            on_error_handler: panic_on_error,
+            display_dirty: false,
+            on_display_update_handler: |_| (),
+            on_memory_write_handler: |_, _, _| (),
+            on_memory_read_handler: |_, _, _| (),
+            events: EventQueue::new(),
+            clock: PhantomData,
+        })
     }
 
-    /// Load the value from the given register.
-    #[inline]
-    fn reg(&self, x: u8) -> u8 {
-        debug_assert!(x & 0xF0 == 0, "Register numbers should only be 4 bits");
-        self.v_registers[x as usize]
+    /// Set the time that each step should take, to speed up or slow down the interpreter while
+    /// it's running.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `speed` is zero.
+    pub fn set_speed(&mut self, speed: Duration) {
+        assert!(
+            !speed.is_zero(),
+            "The speed of the interpreter can't be zero"
+        );
+        self.speed = speed;
     }
 
-    #[inline]
-    fn mut_reg(&mut self, x: u8) -> &mut u8 {
-        debug_assert!(x & 0xF0 == 0, "Register numbers should only be 4 bits");
-        &mut self.v_registers[x as usize]
+    /// Set the clock frequency of the interpreter in Hz, like [`set_speed`](Self::set_speed).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `hz` isn't a positive finite number, or if it's so large that each step would
+    /// take no time at all.
+    pub fn set_frequency(&mut self, hz: f32) {
+        assert!(
+            hz.is_finite() && hz > 0.,
+            "The clock frequency must be positive, not {hz}"
+        );
+        self.set_speed(Duration::from_secs_f32(hz.recip()));
     }
 
-    /// Get the value of the operand.
-    #[inline]
-    fn get_operand(&self, op: Operand) -> u8 {
-        match op {
-            Operand::Register(x) => self.reg(x),
-            Operand::Literal(byte) => byte,
-        }
+    /// Set the sample rate that [`audio_sample`](Self::audio_sample) will be called at, in Hz.
+    /// This defaults to 44100 Hz.
+    pub fn set_audio_sample_rate(&mut self, sample_rate: f32) {
+        self.buzzer.sample_rate = sample_rate;
     }
 
-    /// Fetch the next instruction from memory.
-    fn fetch(&mut self) -> [u8; 2] {
-        let instruction = [
-            self.memory[self.program_counter as usize],
-            self.memory[self.program_counter as usize + 1],
-        ];
-        self.program_counter += 2;
-        self.program_counter = self.program_counter % self.memory.len() as u16;
-        instruction
+    /// Get the next sample of a 440 Hz square wave, in the range `-1.0..=1.0`, or `None` if the
+    /// buzzer is off. This should be called at the sample rate of the audio device.
+    pub fn audio_sample(&mut self) -> Option<f32> {
+        self.buzzer_active().then(|| self.buzzer.next_sample())
     }
 
-    /// Execute the given instruction.
-    fn execute(&mut self, instruction: Instruction, keys: &Keys) {
-        use Instruction as I;
+    /// Is the audio enabled? This is true by default.
+    pub fn audio_enabled(&self) -> bool {
+        self.audio_enabled
+    }
 
-        match instruction {
-            I::Nop => (),
-            I::ClearScreen => self.display = [[Pixel::Black; _]; _],
-            I::Return => {
-                self.stack_pointer = self
-                    .stack_pointer
-                    .checked_sub(1)
-                    .expect("The stack pointer should never go negative");
-                self.program_counter = self.stack[self.stack_pointer as usize];
-            }
-            I::Jump(address) => self.program_counter = address,
-            I::Call(address) => {
-                self.stack[self.stack_pointer as usize] = self.program_counter;
-                self.stack_pointer += 1;
-                assert!(
-                    self.stack_pointer <= 15,
-                    "The stack can only contain 16 addresses"
-                );
-                self.program_counter = address;
-            }
-            I::SkipIfEqual(x, op) => {
-                if self.reg(x) == self.get_operand(op) {
-                    self.program_counter += 2;
-                }
-            }
-            I::SkipIfNotEqual(x, op) => {
-                if self.reg(x) != self.get_operand(op) {
-                    self.program_counter += 2;
-                }
-            }
-            I::LoadRegister(x, op) => *self.mut_reg(x) = self.get_operand(op),
-            I::AddNoCarry(x, byte) => *self.mut_reg(x) = self.reg(x).wrapping_add(byte),
-            I::Or(x, y) => *self.mut_reg(x) |= self.reg(y),
-            I::And(x, y) => *self.mut_reg(x) &= self.reg(y),
-            I::Xor(x, y) => *self.mut_reg(x) ^= self.reg(y),
-            I::AddWithCarry(x, y) => {
-                let (value, carry) = self.reg(x).overflowing_add(self.reg(y));
-                *self.mut_reg(x) = value;
-                *self.mut_reg(0xF) = carry as u8;
-            }
-            I::Sub(x, y) => {
-                let (value, carry) = self.reg(x).overflowing_sub(self.reg(y));
-                *self.mut_reg(x) = value;
-                *self.mut_reg(0xF) = carry as u8;
-            }
-            I::ShiftRight(x) => {
-                *self.mut_reg(0xF) = self.reg(x) & 1;
-                *self.mut_reg(x) = self.reg(x) >> 1;
-            }
-            I::SubN(x, y) => {
-                let (value, carry) = self.reg(y).overflowing_sub(self.reg(x));
-                *self.mut_reg(x) = value;
-                *self.mut_reg(0xF) = carry as u8;
-            }
-            I::ShiftLeft(x) => {
-                *self.mut_reg(0xF) = self.reg(x) & 0b1000_0000;
-                *self.mut_reg(x) = self.reg(x) << 1;
-            }
-            I::LoadMemoryRegister(address) => self.memory_register = address,
-            I::JumpPlusV0(address) => {
-                let address = (address + self.reg(0) as u16) & 0xFFF;
-                self.program_counter = address;
-            }
-            I::LoadRandomWithMask(x, mask) => *self.mut_reg(x) = rand::random::<u8>() & mask,
-            I::Draw(x, y, n) => {
-                let first_x = (self.reg(x) % 64) as usize;
-                let mut x = first_x;
-                let mut y = (self.reg(y) % 32) as usize;
-                self.v_registers[0xF] = 0;
-
-                for offset in 0..n {
-                    let row = self.memory[self.memory_register as usize + offset as usize];
-                    if y >= 32 {
-                        return;
-                    }
+    /// Enable or disable the audio. When it's disabled, the buzzer is never active, but the sound
+    /// timer still counts down as normal.
+    pub fn set_audio_enabled(&mut self, enabled: bool) {
+        self.audio_enabled = enabled;
+    }
 
-                    for pixel in (0..=7).rev().map(|pos| {
-                        if row & (1 << pos) > 0 {
-                            Pixel::White
-                        } else {
-                            Pixel::Black
-                        }
-                    }) {
-                        if x >= 64 {
-                            break;
-                        }
-
-                        let old_pixel = self.display[y][x];
-                        self.display[y][x] = old_pixel ^ pixel;
-
-                        // Set VF if the pixel was erased
-                        //if old_pixel ^ pixel != old_pixel {
-                        if (old_pixel & pixel).into() {
-                            self.v_registers[0xF] = 1;
-                        }
-                        x += 1;
-                    }
-                    x = first_x;
-                    y += 1;
-                }
-            }
-            I::SkipIfKeyPressed(x) => match keys.get(self.reg(x) as usize) {
-                Some(true) => self.program_counter += 2,
-                None => panic!("The only valid keys are 0-15, not {}", self.reg(x)),
-                Some(false) => (),
-            },
-            I::SkipIfKeyNotPressed(x) => match keys.get(self.reg(x) as usize) {
-                Some(false) => self.program_counter += 2,
-                None => panic!("The only valid keys are 0-15, not {}", self.reg(x)),
-                Some(true) => (),
-            },
-            I::LoadFromDelayTimer(x) => *self.mut_reg(x) = self.delay_timer,
-            I::WaitForKeyPress(x) => self.waiting_for_key_press = Some(x),
-            I::LoadIntoDelayTimer(x) => self.delay_timer = self.reg(x),
-            I::LoadIntoSoundTimer(x) => self.sound_timer = self.reg(x),
-            I::AddToMemoryRegister(x) => {
-                self.memory_register = (self.memory_register + self.reg(x) as u16) & 0xFFF
-            }
-            I::LoadDigitAddress(x) => {
-                self.memory_register = FONT_ADDRESS_START as u16 + 5 * (self.reg(x) & 0xF) as u16
-            }
-            I::StoreBcdInMemory(x) => {
-                let num = self.reg(x);
-                let hundreds = (num - (num % 100)) / 100;
-                let tens = (num - (num % 10) - hundreds * 100) / 10;
-                let units = num - hundreds * 100 - tens * 10;
-                self.memory[self.memory_register as usize] = hundreds;
-                self.memory[self.memory_register as usize + 1] = tens;
-                self.memory[self.memory_register as usize + 2] = units;
-            }
-            I::StoreRegistersInMemory(reg_num) => {
-                for x in 0..=reg_num {
-                    self.memory[self.memory_register as usize + x as usize] = self.reg(x);
-                }
+    /// Mute the audio if it's enabled, or unmute it if it's disabled.
+    pub fn toggle_audio(&mut self) {
+        self.audio_enabled = !self.audio_enabled;
+    }
+
+    /// Set the function that decides what to do when we find an opcode that we don't recognise.
+    /// By default, we return an error.
+    pub fn set_on_unimplemented(&mut self, handler: fn(u16) -> OnUnimplementedAction) {
+        self.on_unimplemented_handler = handler;
+    }
+
+    /// Decide what to do with the given opcode, which we don't recognise.
+    pub fn on_unimplemented(&mut self, opcode: u16) -> OnUnimplementedAction {
+        (self.on_unimplemented_handler)(opcode)
+    }
+
+    /// Skip, halt, or return an error for the given opcode at the given address, which we don't
+    /// know how to execute, depending on the [`on_unimplemented`](Self::on_unimplemented)
+    /// handler.
+    fn handle_unimplemented(&mut self, opcode: u16, address: u16) -> Result<(), InterpreterError> {
+        match self.on_unimplemented(opcode) {
+            // The program counter has already moved past this opcode
+            OnUnimplementedAction::Skip => Ok(()),
+            OnUnimplementedAction::Halt => {
+                self.halted = true;
+                self.events.push(InterpreterEvent::HaltDetected);
+                Ok(())
             }
-            I::ReadRegistersFromMemory(reg_num) => {
-                for x in 0..=reg_num {
-                    *self.mut_reg(x) = self.memory[self.memory_register as usize + x as usize];
-                }
+            OnUnimplementedAction::Error => {
+                Err(InterpreterError::UnrecognisedInstruction { opcode, address })
             }
-        };
+        }
     }
 
-    /// Decrement the timers if it's been sufficiently long since they were last decremented. The
-    /// timers should be decremented at a frequency of 60 Hz.
-    fn decrement_timers(&mut self) {
-        if self.last_timer_decrement.elapsed() >= Duration::from_secs_f32(1. / 60.) {
-            self.last_timer_decrement = Instant::now();
-            self.delay_timer = self.delay_timer.saturating_sub(1);
-            self.sound_timer = self.sound_timer.saturating_sub(1);
+    /// Add a breakpoint at the given address. Addresses outside of memory are ignored.
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        if (addr as usize) < MEMORY_SIZE {
+            self.breakpoints[addr as usize / 64] |= 1 << (addr % 64);
         }
     }
-}
 
-impl Interpreter for Chip8Interpreter {
-    fn step(&mut self, keys: &Keys) -> Option<Display> {
+    /// Remove the breakpoint at the given address, returning whether there was one.
+    pub fn remove_breakpoint(&mut self, addr: u16) -> bool {
+        let had_breakpoint = self.has_breakpoint(addr);
+        if had_breakpoint {
+            self.breakpoints[addr as usize / 64] &= !(1 << (addr % 64));
+        }
+        had_breakpoint
+    }
+
+    /// Is there a breakpoint at the given address?
+    pub fn has_breakpoint(&self, addr: u16) -> bool {
+        (addr as usize) < MEMORY_SIZE
+            && self.breakpoints[addr as usize / 64] & (1 << (addr % 64)) != 0
+    }
+
+    /// Get the address of the breakpoint that was reached in the last step, if any. A breakpoint
+    /// is reached when the instruction at its address is executed.
+    pub fn breakpoint_reached(&self) -> Option<u16> {
+        self.last_breakpoint
+    }
+
+    /// Set the function that [`Interpreter::step`] calls after a step that reaches a breakpoint,
+    /// with the address of the breakpoint. By default, nothing happens.
+    pub fn set_on_breakpoint(&mut self, handler: fn(&Self, u16)) {
+        self.on_breakpoint_handler = handler;
+    }
+
+    /// Set the function that [`Interpreter::step`] calls when the program can't continue, with
+    /// the error that [`try_step`](Self::try_step) would have returned. If the handler returns,
+    /// the interpreter halts. By default, we panic with the error.
+    pub fn set_on_error(&mut self, handler: fn(&Self, InterpreterError)) {
+        self.on_error_handler = handler;
+    }
+
+    /// Set the function that's called straight after every `Draw` instruction, for frontends
+    /// that want to know exactly when the display changes. By default, nothing happens.
+    pub fn set_on_display_update(&mut self, handler: fn(&Self)) {
+        self.on_display_update_handler = handler;
+    }
+
+    /// Set the function that's called after an instruction writes a byte to memory, with the
+    /// address and the new value, for things like watchpoints. Only `Fx33` and `Fx55` write to
+    /// memory. By default, nothing happens.
+    pub fn set_on_memory_write(&mut self, handler: fn(&Self, u16, u8)) {
+        self.on_memory_write_handler = handler;
+    }
+
+    /// Set the function that's called after an instruction reads a byte from memory, with the
+    /// address and the value. Only `Dxyn` and `Fx65` read from memory, and fetching the
+    /// instructions themselves doesn't count. By default, nothing happens.
+    pub fn set_on_memory_read(&mut self, handler: fn(&Self, u16, u8)) {
+        self.on_memory_read_handler = handler;
+    }
+
+    /// Has a `Draw` instruction run since the flag was last cleared by
+    /// [`clear_display_dirty`](Self::clear_display_dirty) or a `ClearScreen` instruction?
+    ///
+    /// A renderer can check this once per frame and only redraw the screen when it's set.
+    pub fn display_dirty(&self) -> bool {
+        self.display_dirty
+    }
+
+    /// Clear the flag returned by [`display_dirty`](Self::display_dirty).
+    pub fn clear_display_dirty(&mut self) {
+        self.display_dirty = false;
+    }
+
+    /// Take the oldest event that hasn't been polled yet, if there are any. Only the most recent
+    /// [`EVENT_QUEUE_CAPACITY`] events are kept, so this should be called after every step.
+    pub fn poll_event(&mut self) -> Option<InterpreterEvent> {
+        self.events.pop()
+    }
+
+    /// Execute a single instruction directly, without fetching it from memory. This is mostly
+    /// useful for testing individual instructions.
+    ///
+    /// The program counter isn't moved past the instruction first, so skips move it forward by
+    /// a single instruction from where it is now, and jumps and calls work as normal. The timers
+    /// aren't ticked, and the cycle doesn't count towards the [`timing_stats`](Self::timing_stats).
+    pub fn execute_instruction(
+        &mut self,
+        instruction: Instruction,
+        keys: &Keys,
+    ) -> Result<(), InterpreterError> {
+        self.execute(instruction, keys, self.program_counter)
+    }
+
+    /// Execute a single cycle of the interpreter, or return an error if the program can't
+    /// continue. [`Interpreter::step`] passes these errors to the handler from
+    /// [`set_on_error`](Self::set_on_error) instead.
+    pub fn try_step(&mut self, keys: &Keys) -> Result<Option<Display>, InterpreterError> {
+        let was_buzzing = self.sound_timer.get() > 0;
+
+        if self.halted {
+            self.decrement_timers();
+            self.push_buzzer_event(was_buzzing);
+            return Ok(None);
+        }
+
+        self.timings.record_step(C::now());
+        self.last_breakpoint = None;
+
         if let Some(x) = self.waiting_for_key_press {
             if let Some((key_num, _)) = keys.iter().enumerate().find(|(_idx, pressed)| **pressed) {
                 *self.mut_reg(x) = key_num as u8;
                 self.waiting_for_key_press = None;
+                self.key_wait_started = None;
+                self.events
+                    .push(InterpreterEvent::KeyReceived(x, key_num as u8));
             }
         } else {
-            let instruction = match decode(self.fetch()) {
-                Ok(instruction) => instruction,
-                Err(DecodingError::UnrecognisedBytecode(bytecode)) => panic!(
-                    "Unrecognised instruction with bytecode 0x{bytecode:0>4X} at address 0x{:0>4X}",
-                    self.program_counter - 2
-                ),
+            // The instruction takes up two bytes, so the last valid address is 0xFFE
+            if self.program_counter as usize + INSTRUCTION_SIZE > MEMORY_SIZE {
+                return Err(InterpreterError::ProgramCounterOutOfBounds {
+                    pc: self.program_counter,
+                });
+            }
+
+            let address = self.program_counter;
+            if self.has_breakpoint(address) {
+                self.last_breakpoint = Some(address);
+            }
+
+            match decode(self.fetch()) {
+                Ok(instruction) => {
+                    self.execute(instruction, keys, address)?;
+                    self.last_instruction = Some(instruction);
+                    self.last_executed_pc = Some(address);
+                }
+                Err(DecodingError::UnrecognisedBytecode(opcode)) => {
+                    self.handle_unimplemented(opcode, address)?;
+                }
             };
-            self.execute(instruction, keys);
         }
 
         self.decrement_timers();
+        self.push_buzzer_event(was_buzzing);
+        self.cycles += 1;
 
-        Some(self.display)
+        #[cfg(feature = "display-effects")]
+        return Ok(Some(self.ghosted_display()));
+
+        #[cfg(not(feature = "display-effects"))]
+        Ok(Some(self.display))
     }
 
-    fn speed(&self) -> Duration {
-        self.speed
+    /// Set how slowly pixels fade out after being turned off, from 0.0 for straight away to 1.0
+    /// for never.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `value` isn't in `0.0..=1.0`.
+    #[cfg(feature = "display-effects")]
+    pub fn set_display_persistence(&mut self, value: f32) {
+        self.display_persistence = check_display_persistence(value);
     }
 
-    fn buzzer_active(&self) -> bool {
-        self.sound_timer > 0
+    /// Get the brightness of each pixel from 0.0 to 1.0, including pixels that are fading out
+    /// after being turned off. This is for frontends that can draw shades of grey.
+    #[cfg(feature = "display-effects")]
+    pub fn ghost_display(&self) -> &[[f32; DISPLAY_WIDTH]; DISPLAY_HEIGHT] {
+        &self.ghost_display
+    }
+
+    /// Get the display with fading pixels blended in. A pixel is white if it's at least half
+    /// brightness.
+    #[cfg(feature = "display-effects")]
+    fn ghosted_display(&self) -> Display {
+        self.ghost_display
+            .map(|row| row.map(|brightness| pixel_from_bool(brightness >= 0.5)))
+    }
+
+    /// Fade out the pixels that are turned off, for the given amount of time, and make the
+    /// pixels that are turned on fully bright.
+    #[cfg(feature = "display-effects")]
+    fn fade_ghost_display(&mut self, elapsed: Duration) {
+        // Without persistence, pixels turn off straight away, even if no time has passed
+        let fade = if self.display_persistence == 0. {
+            0.
+        } else {
+            self.display_persistence.powf(elapsed.as_secs_f32() * 60.)
+        };
+        for (ghost_row, row) in self.ghost_display.iter_mut().zip(&self.display) {
+            for (brightness, &pixel) in ghost_row.iter_mut().zip(row) {
+                *brightness = match pixel {
+                    Pixel::White => 1.,
+                    Pixel::Black => *brightness * fade,
+                };
+            }
+        }
+    }
+
+    /// Get the width of the display in pixels, which depends on the graphics mode.
+    pub fn display_width(&self) -> u8 {
+        self.graphics_mode().width() as u8
+    }
+
+    /// Get the height of the display in pixels, which depends on the graphics mode.
+    pub fn display_height(&self) -> u8 {
+        self.graphics_mode().height() as u8
+    }
+
+    /// Get the graphics mode, which SUPER-CHIP programs can change with `00FE` and `00FF`.
+    #[cfg(feature = "schip")]
+    pub fn graphics_mode(&self) -> GraphicsMode {
+        self.graphics_mode
+    }
+
+    /// Get the graphics mode, which is always [`GraphicsMode::LowRes`] without the `schip`
+    /// feature.
+    #[cfg(not(feature = "schip"))]
+    pub fn graphics_mode(&self) -> GraphicsMode {
+        GraphicsMode::LowRes
+    }
+
+    /// Get the 128x64 display, which is only drawn to in [`GraphicsMode::HighRes`].
+    ///
+    /// [`Interpreter::step`] always returns a 64x32 display, so in high resolution mode, that's
+    /// this display shrunk down by half.
+    #[cfg(feature = "schip")]
+    pub fn hires_display(&self) -> &HiResDisplay {
+        &self.hires_display
+    }
+
+    /// Replace the built-in font with a custom one. The font has 5 bytes for each of the 16 hex
+    /// digits, just like [`memory::default_font`].
+    pub fn load_font(&mut self, font: &[u8; 80]) {
+        load_font(&mut self.memory, font);
+        self.custom_font = true;
+    }
+
+    /// Check that the interpreter's state is consistent, for diagnosing bugs in the interpreter
+    /// itself rather than in programs. We check that:
+    /// - the built-in font hasn't been overwritten, unless a custom font was loaded
+    /// - the program counter leaves room for a whole instruction in memory
+    /// - the stack pointer is within the stack
+    /// - the registers, the memory register, and the stack are all zero if no cycles have run since
+    ///   the interpreter was created or last [`reset`](Self::reset)
+    pub fn self_test(&self) -> SelfTestResult {
+        let mut problems = Vec::new();
+
+        let font = &self.memory[FONT_ADDRESS_START..][..80];
+        if !self.custom_font && font != memory::default_font() {
+            problems.push(format!(
+                "The font at 0x{FONT_ADDRESS_START:0>3X} doesn't match the built-in font"
+            ));
+        }
+
+        if self.program_counter as usize > MEMORY_SIZE - INSTRUCTION_SIZE {
+            problems.push(format!(
+                "The program counter 0x{:0>3X} is past the last instruction in memory",
+                self.program_counter
+            ));
+        }
+
+        if self.stack_pointer as usize > STACK_DEPTH {
+            problems.push(format!(
+                "The stack pointer {} is past the end of the stack, which holds {STACK_DEPTH} \
+                 addresses",
+                self.stack_pointer
+            ));
+        }
+
+        if self.cycles_since_reset() == 0 {
+            if let Some(reg) = self.v_registers.iter().position(|&v| v != 0) {
+                problems.push(format!(
+                    "V{reg:X} is 0x{:0>2X} before any cycles have run",
+                    self.v_registers[reg]
+                ));
+            }
+            if self.memory_register != 0 {
+                problems.push(format!(
+                    "I is 0x{:0>3X} before any cycles have run",
+                    self.memory_register
+                ));
+            }
+            if self.stack.iter().any(|&addr| addr != 0) {
+                problems.push("The stack isn't empty before any cycles have run".to_string());
+            }
+        }
+
+        if problems.is_empty() {
+            SelfTestResult::Pass
+        } else {
+            SelfTestResult::Fail(problems)
+        }
+    }
+
+    /// Get the layout of this interpreter's memory.
+    pub fn memory_layout(&self) -> MemoryLayout {
+        MemoryLayout {
+            program_start: self.entry_point,
+            ..chip8_memory_layout()
+        }
+    }
+
+    /// Get the address that the ROM was loaded at, and where execution started.
+    pub fn rom_entry_point(&self) -> u16 {
+        self.entry_point
+    }
+
+    /// Get the size of the ROM that was loaded, in bytes.
+    pub fn program_size(&self) -> usize {
+        self.rom_len as usize
+    }
+
+    /// Get the address just after the last byte of the ROM that was loaded.
+    pub fn program_end_address(&self) -> u16 {
+        self.entry_point + self.rom_len
+    }
+
+    /// Get the start and end addresses of the first region of memory of the given kind, with the
+    /// end exclusive. If there's no region of that kind, like the stack, which isn't stored in
+    /// memory, the region is empty.
+    pub fn memory_region_by_name(&self, kind: RegionKind) -> (u16, u16) {
+        self.memory_map()
+            .regions
+            .into_iter()
+            .find(|region| region.kind == kind)
+            .map_or((0, 0), |region| (region.start, region.end))
+    }
+
+    /// Get a map of what each part of memory is used for, for annotating memory views.
+    pub fn memory_map(&self) -> MemoryMap {
+        MemoryMap::new(&self.memory_layout(), self.rom_len)
+    }
+
+    /// Get the memory that the instruction would access if it was executed now, based on the
+    /// current value of the memory register. Fetching the instruction itself doesn't count, and
+    /// accesses of zero bytes, like drawing a sprite with no rows, are left out.
+    pub fn memory_regions_accessed_by(&self, instr: Instruction) -> Vec<MemoryAccess> {
+        let (size, mode) = match instr {
+            Instruction::Draw(_, _, n) => (n as usize, AccessMode::Read),
+            Instruction::StoreBcdInMemory(_) => (3, AccessMode::Write),
+            Instruction::StoreRegistersInMemory(x) => (x as usize + 1, AccessMode::Write),
+            Instruction::ReadRegistersFromMemory(x) => (x as usize + 1, AccessMode::Read),
+            _ => return Vec::new(),
+        };
+
+        if size == 0 {
+            return Vec::new();
+        }
+
+        vec![MemoryAccess {
+            addr: self.memory_register,
+            size,
+            mode,
+        }]
+    }
+
+    /// Get the Adler-32 checksum of the part of memory that the ROM was loaded into. This
+    /// changes if the program modifies itself.
+    pub fn memory_checksum(&self) -> u32 {
+        let start = (self.entry_point as usize).min(MEMORY_SIZE);
+        let end = (start + self.rom_len as usize).min(MEMORY_SIZE);
+        checksum::adler32(&self.memory[start..end])
+    }
+
+    /// Get the Fletcher-16 checksum of the display, with the pixels packed 8 to a byte.
+    pub fn display_checksum(&self) -> u16 {
+        checksum::fletcher16(&self.display.to_packed_bits())
+    }
+
+    /// Get a fast hash of the display, which only changes when the display does. This is
+    /// computed whenever the display changes, so it's cheap to call often.
+    pub fn screen_hash(&self) -> u64 {
+        self.screen_hash
+    }
+
+    /// Has the display changed since [`screen_hash`](Self::screen_hash) returned
+    /// `previous_hash`?
+    pub fn screen_changed_since(&self, previous_hash: u64) -> bool {
+        self.screen_hash != previous_hash
+    }
+
+    /// Get the XOR of all the V registers.
+    pub fn registers_checksum(&self) -> u8 {
+        self.v_registers.iter().fold(0, |acc, &v| acc ^ v)
+    }
+
+    /// Run `n` steps with the given keys held down, and return the last display that the
+    /// interpreter produced, if it produced any.
+    pub fn step_n(&mut self, n: u64, keys: &Keys) -> Option<Display> {
+        let mut display = None;
+        for _ in 0..n {
+            if self.halted {
+                break;
+            }
+            if let Some(new_display) = self.step(keys) {
+                display = Some(new_display);
+            }
+        }
+        display
+    }
+
+    /// Like [`Self::step_n`], but stop early if the interpreter is stuck in an infinite loop,
+    /// which we detect by the program counter being the same three times in a row. The
+    /// repeated instruction isn't executed a third time. This also catches loops that
+    /// [`Self::is_halted`] doesn't, like waiting for a key that's never pressed.
+    ///
+    /// Returns the number of steps that were actually run, along with the last display.
+    pub fn step_n_or_halt(&mut self, n: u64, keys: &Keys) -> (u64, Option<Display>) {
+        let mut display = None;
+        let mut last_pc = None;
+        let mut repeats = 0;
+
+        for count in 0..n {
+            if self.halted {
+                return (count, display);
+            }
+
+            if last_pc == Some(self.program_counter) {
+                repeats += 1;
+                if repeats >= 3 {
+                    return (count, display);
+                }
+            } else {
+                last_pc = Some(self.program_counter);
+                repeats = 1;
+            }
+
+            if let Some(new_display) = self.step(keys) {
+                display = Some(new_display);
+            }
+        }
+
+        (n, display)
+    }
+
+    /// If the interpreter is blocked waiting for a key to be pressed, get the register that the
+    /// key will be stored in.
+    pub fn pending_key_wait(&self) -> Option<u8> {
+        self.waiting_for_key_press
+    }
+
+    /// If the interpreter is blocked waiting for a key to be pressed, get how long it's been
+    /// waiting for.
+    pub fn key_wait_duration(&self) -> Option<Duration> {
+        self.key_wait_started.map(C::elapsed)
+    }
+
+    /// Get the live portion of the stack, with the oldest return address first.
+    pub fn inspect_stack(&self) -> &[u16] {
+        &self.stack[..self.stack_pointer as usize]
+    }
+
+    /// Get the number of return addresses on the stack.
+    pub fn stack_depth(&self) -> u8 {
+        self.stack_pointer
+    }
+
+    /// Get the value of the register Vx, or `None` if `reg` isn't in `0..=15`.
+    pub fn get_register(&self, reg: u8) -> Option<u8> {
+        self.v_registers.get(reg as usize).copied()
+    }
+
+    /// Set the register Vx to `value`. Returns false and does nothing if `reg` isn't in
+    /// `0..=15`.
+    pub fn set_register(&mut self, reg: u8, value: u8) -> bool {
+        match self.v_registers.get_mut(reg as usize) {
+            Some(register) => {
+                *register = value;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Get the value of the `I` register.
+    pub fn get_memory_register(&self) -> u16 {
+        self.memory_register
+    }
+
+    /// Set the value of the `I` register.
+    pub fn set_memory_register(&mut self, value: u16) {
+        self.memory_register = value;
+    }
+
+    /// Get the address of the next instruction to execute.
+    pub fn get_program_counter(&self) -> u16 {
+        self.program_counter
+    }
+
+    /// Set the address of the next instruction to execute. Returns false and does nothing if
+    /// `pc` is outside of memory.
+    pub fn set_program_counter(&mut self, pc: u16) -> bool {
+        if pc as usize >= MEMORY_SIZE {
+            return false;
+        }
+        self.program_counter = pc;
+        true
+    }
+
+    /// Move execution to `addr`, like a debugger would. Returns false and does nothing if `addr`
+    /// is outside of memory.
+    ///
+    /// Unlike [`set_program_counter`](Self::set_program_counter), this also un-halts the program
+    /// and stops waiting for a key press, so that it can carry on from the new address.
+    pub fn goto(&mut self, addr: u16) -> bool {
+        if !self.set_program_counter(addr) {
+            return false;
+        }
+        self.halted = false;
+        self.waiting_for_key_press = None;
+        self.key_wait_started = None;
+        true
+    }
+
+    /// Call the subroutine at `addr`, like a `Call` instruction at the current program counter
+    /// would. The current program counter is pushed onto the stack, so that
+    /// [`return_from`](Self::return_from) or a `Return` instruction will come back to it.
+    ///
+    /// Returns false and does nothing if `addr` is outside of memory or the stack is full.
+    pub fn call_at(&mut self, addr: u16) -> bool {
+        if addr as usize >= MEMORY_SIZE || self.stack_pointer as usize >= STACK_DEPTH {
+            return false;
+        }
+        self.stack[self.stack_pointer as usize] = self.program_counter;
+        self.stack_pointer += 1;
+        self.goto(addr)
+    }
+
+    /// Return from the current subroutine, like a `Return` instruction would, by popping the
+    /// address off the top of the stack and moving execution there.
+    ///
+    /// Returns false and does nothing if the stack is empty.
+    pub fn return_from(&mut self) -> bool {
+        let Some(sp) = self.stack_pointer.checked_sub(1) else {
+            return false;
+        };
+        self.stack_pointer = sp;
+        self.goto(self.stack[sp as usize])
+    }
+
+    /// Get the value of the delay timer.
+    pub fn get_delay_timer(&self) -> u8 {
+        self.delay_timer.get()
+    }
+
+    /// Set the value of the delay timer.
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.delay_timer.set(value);
+    }
+
+    /// Get the value of the sound timer.
+    pub fn get_sound_timer(&self) -> u8 {
+        self.sound_timer.get()
+    }
+
+    /// Set the value of the sound timer.
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.sound_timer.set(value);
+    }
+
+    /// Set the number of return addresses on the stack, without changing the addresses
+    /// themselves. Returns false and does nothing if `sp` is more than the stack can hold.
+    ///
+    /// This is a back door for setting up tests, and isn't meant to be used by real frontends,
+    /// since it can easily leave the stack in a state that no program could reach.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn set_stack_pointer(&mut self, sp: u8) -> bool {
+        if sp as usize > STACK_DEPTH {
+            return false;
+        }
+        self.stack_pointer = sp;
+        true
+    }
+
+    /// Take a complete snapshot of the state of the interpreter.
+    pub fn dump_state(&self) -> InterpreterState {
+        InterpreterState {
+            v: self.v_registers,
+            i: self.memory_register,
+            pc: self.program_counter,
+            sp: self.stack_pointer,
+            stack: self.stack,
+            dt: self.delay_timer.get(),
+            st: self.sound_timer.get(),
+            memory: Box::new(self.memory),
+            display: self
+                .display
+                .map(|row| row.map(|pixel| pixel == Pixel::White)),
+        }
+    }
+
+    /// Restore a snapshot that was taken with [`dump_state`](Self::dump_state), or return an
+    /// error without changing anything if the stack pointer or program counter are out of range.
+    ///
+    /// Anything that isn't part of the snapshot, like the clock speed and quirks, is left alone,
+    /// but the interpreter stops waiting for a key press and is no longer halted, like after
+    /// [`reset`](Self::reset).
+    pub fn restore_state(&mut self, state: InterpreterState) -> Result<(), RestoreStateError> {
+        if state.sp as usize > STACK_DEPTH {
+            return Err(RestoreStateError::StackPointerTooBig {
+                sp: state.sp,
+                max: STACK_DEPTH,
+            });
+        }
+        if state.pc as usize >= MEMORY_SIZE {
+            return Err(RestoreStateError::ProgramCounterOutOfBounds { pc: state.pc });
+        }
+
+        self.v_registers = state.v;
+        self.memory_register = state.i;
+        self.program_counter = state.pc;
+        self.stack_pointer = state.sp;
+        self.stack = state.stack;
+        self.delay_timer.set(state.dt);
+        self.sound_timer.set(state.st);
+        self.memory = *state.memory;
+        self.display = state.display.map(|row| row.map(pixel_from_bool));
+        self.screen_hash = self.display.screen_hash();
+        self.waiting_for_key_press = None;
+        self.key_wait_started = None;
+        self.halted = false;
+
+        // Snapshots only hold the 64x32 display
+        #[cfg(feature = "schip")]
+        {
+            self.graphics_mode = GraphicsMode::LowRes;
+            self.hires_display = [[Pixel::Black; _]; _];
+        }
+
+        Ok(())
+    }
+
+    /// Write the given bytes directly into memory, starting at `addr`. This is useful for trainers
+    /// and cheat codes.
+    ///
+    /// Nothing is written if the patch would go past the end of memory.
+    pub fn apply_patch(&mut self, addr: u16, bytes: &[u8]) -> Result<(), PatchError> {
+        let start = addr as usize;
+        let patch =
+            self.memory
+                .get_mut(start..start + bytes.len())
+                .ok_or(PatchError::OutOfBounds {
+                    addr,
+                    len: bytes.len(),
+                })?;
+        patch.copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Encode the instruction and write it into memory at `addr`.
+    pub fn apply_instruction_patch(
+        &mut self,
+        addr: u16,
+        instruction: Instruction,
+    ) -> Result<(), PatchError> {
+        self.apply_patch(addr, &encode(instruction)?)
+    }
+
+    /// Undo a patch by writing the original bytes back into memory at `addr`.
+    pub fn revert_patch(&mut self, original_bytes: &[u8], addr: u16) -> Result<(), PatchError> {
+        self.apply_patch(addr, original_bytes)
+    }
+
+    /// Get the current display along with the colours that it should be drawn with.
+    pub fn screen_output(&self) -> ScreenOutput {
+        ScreenOutput {
+            display: self.display,
+            color_scheme: self.color_scheme,
+        }
+    }
+
+    /// Set the colours that the display should be drawn with.
+    pub fn set_color_scheme(&mut self, color_scheme: ColorScheme) {
+        self.color_scheme = color_scheme;
+    }
+
+    /// Get the SHA-256 hash of the ROM that the interpreter was created with. This only covers
+    /// the program bytes, not the whole of memory, and doesn't change if memory is patched.
+    #[cfg(feature = "sha2")]
+    pub fn rom_hash(&self) -> [u8; 32] {
+        self.rom_hash
+    }
+
+    /// Get the keyboard layout used to translate key presses from `chip8_base`'s runner.
+    pub fn key_map(&self) -> &KeyMap {
+        &self.key_map
+    }
+
+    /// Set the keyboard layout used to translate key presses from `chip8_base`'s runner.
+    pub fn set_key_map(&mut self, key_map: KeyMap) {
+        self.key_map = key_map;
+    }
+
+    /// Record that the CHIP-8 key `key` has been pressed or released, for
+    /// [`step_with_injected_keys`](Self::step_with_injected_keys). This is for frontends that get
+    /// key events asynchronously, rather than reading the state of every key before each step.
+    ///
+    /// The key isn't translated with the [`key_map`](Self::key_map), and keys outside of `0..=15`
+    /// are ignored.
+    pub fn inject_key_event(&mut self, key: u8, pressed: bool) {
+        if let Some(state) = self.current_keys.get_mut(key as usize) {
+            *state = pressed;
+        }
+    }
+
+    /// Execute a single cycle like [`Interpreter::step`], but with the keys that have been
+    /// pressed by [`inject_key_event`](Self::inject_key_event).
+    pub fn step_with_injected_keys(&mut self) -> Option<Display> {
+        let keys = self.current_keys;
+        self.step_and_handle(&keys)
+    }
+
+    /// Get the last instruction that was executed, if any.
+    pub fn last_executed_instruction(&self) -> Option<Instruction> {
+        self.last_instruction
+    }
+
+    /// Get the address of the last instruction that was executed, if any. This is where the
+    /// program counter was before the instruction was fetched.
+    pub fn last_executed_pc(&self) -> Option<u16> {
+        self.last_executed_pc
+    }
+
+    /// Disassemble `context` instructions either side of the program counter, like a debugger.
+    /// See [`disassemble_around`](Self::disassemble_around) for the format.
+    pub fn disassemble_around_pc(&self, context: u8) -> String {
+        self.disassemble_around(self.program_counter, context)
+    }
+
+    /// Disassemble `context` instructions either side of `addr`, with one line for each
+    /// instruction, like this:
+    ///
+    /// ```text
+    ///   0x020C  6001  ld v0, #01
+    /// > 0x020E  D015  drw v0, v1, 5
+    ///   0x0210  00EE  ret
+    /// ```
+    ///
+    /// The line for `addr` is marked with `>`. Opcodes that don't decode to an instruction are
+    /// shown as `db` directives. The listing stops early at the start and end of memory.
+    pub fn disassemble_around(&self, addr: u16, context: u8) -> String {
+        let step = INSTRUCTION_SIZE as u16;
+        let first = addr.saturating_sub(context as u16 * step);
+        let last = addr
+            .saturating_add(context as u16 * step)
+            .min((MEMORY_SIZE - INSTRUCTION_SIZE) as u16);
+
+        (first..=last)
+            .step_by(INSTRUCTION_SIZE)
+            .map(|line_addr| {
+                let bytes = [
+                    self.memory[line_addr as usize],
+                    self.memory[line_addr as usize + 1],
+                ];
+                let marker = if line_addr == addr { '>' } else { ' ' };
+                let mnemonic = match decode(bytes) {
+                    Ok(instruction) => instruction.to_string(),
+                    Err(_) => format!("db #{:0>2X}, #{:0>2X}", bytes[0], bytes[1]),
+                };
+                format!(
+                    "{marker} 0x{line_addr:0>4X}  {:0>4X}  {mnemonic}",
+                    u16::from_be_bytes(bytes)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Decode the instruction at the given address without executing it. This is `None` if the
+    /// opcode there isn't a valid instruction, or if the address is too close to the end of
+    /// memory to hold a whole instruction.
+    pub fn instruction_at(&self, addr: u16) -> Option<Instruction> {
+        let addr = addr as usize;
+        if addr > MEMORY_SIZE - INSTRUCTION_SIZE {
+            return None;
+        }
+
+        decode([self.memory[addr], self.memory[addr + 1]]).ok()
+    }
+
+    /// Decode every instruction from `start` up to but not including `end`, stepping over one
+    /// instruction at a time, and skipping any opcodes that aren't valid instructions.
+    pub fn instructions_in_range(&self, start: u16, end: u16) -> Vec<(u16, Instruction)> {
+        (start..end)
+            .step_by(INSTRUCTION_SIZE)
+            .filter_map(|addr| Some((addr, self.instruction_at(addr)?)))
+            .collect()
+    }
+
+    /// Decode the instruction that the program counter points to, which will run next.
+    pub fn next_instruction(&self) -> Option<Instruction> {
+        self.instruction_at(self.program_counter)
+    }
+
+    /// Get statistics about how closely the interpreter is keeping to its clock speed.
+    pub fn timing_stats(&self) -> TimingStats {
+        self.timings.stats(self.speed)
+    }
+
+    /// Has the program halted? This happens when it jumps to the address of the jump
+    /// instruction itself, which is a common way for CHIP-8 programs to end.
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Get the number of cycles that have been executed since the interpreter was created. Steps
+    /// taken while the program is halted don't count.
+    pub fn cycle_count(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Get the number of cycles that have been executed since the interpreter was last
+    /// [`reset`](Self::reset), or since it was created if it's never been reset.
+    pub fn cycles_since_reset(&self) -> u64 {
+        self.cycles - self.cycles_at_reset
+    }
+
+    /// Get the number of times that the 60 Hz clock which drives the delay and sound timers has
+    /// ticked, whether or not the timers were running.
+    pub fn frame_count(&self) -> u64 {
+        self.frames.get()
+    }
+
+    /// Reset the interpreter like the reset button on a real machine. The registers, stack,
+    /// timers, display, and unpolled events are cleared, and execution starts again from the entry
+    /// point.
+    ///
+    /// Memory is left alone, so anything that the program wrote to memory is still there, and the
+    /// [`cycle_count`](Self::cycle_count) keeps counting from where it was.
+    pub fn reset(&mut self) {
+        self.v_registers = [0; _];
+        self.memory_register = 0;
+        self.delay_timer.set(0);
+        self.sound_timer.set(0);
+        self.program_counter = self.entry_point;
+        self.stack_pointer = 0;
+        self.stack = [0; _];
+        self.display = [[Pixel::Black; _]; _];
+        #[cfg(feature = "schip")]
+        {
+            self.graphics_mode = GraphicsMode::LowRes;
+            self.hires_display = [[Pixel::Black; _]; _];
+        }
+        self.screen_hash = BLANK_SCREEN_HASH;
+        self.display_dirty = false;
+        self.waiting_for_key_press = None;
+        self.key_wait_started = None;
+        self.halted = false;
+        self.last_instruction = None;
+        self.last_executed_pc = None;
+        self.last_breakpoint = None;
+        self.last_clear_at_cycle = None;
+        self.events = EventQueue::new();
+        self.cycles_at_reset = self.cycles;
+    }
+
+    /// Get the cycle in which the screen was last cleared, if it's been cleared at all.
+    pub fn last_clear_at_cycle(&self) -> Option<u64> {
+        self.last_clear_at_cycle
+    }
+
+    /// Load the value from the given register.
+    #[inline]
+    fn reg(&self, x: u8) -> u8 {
+        debug_assert!(x & 0xF0 == 0, "Register numbers should only be 4 bits");
+        self.v_registers[x as usize]
+    }
+
+    #[inline]
+    fn mut_reg(&mut self, x: u8) -> &mut u8 {
+        debug_assert!(x & 0xF0 == 0, "Register numbers should only be 4 bits");
+        &mut self.v_registers[x as usize]
+    }
+
+    /// Get the value of the operand.
+    #[inline]
+    fn get_operand(&self, op: Operand) -> u8 {
+        match op {
+            Operand::Register(x) => self.reg(x),
+            Operand::Literal(byte) => byte,
+        }
+    }
+
+    /// Reset VF to 0 after a bitwise operation, if that quirk is enabled.
+    #[inline]
+    fn reset_vf_if_quirk(&mut self) {
+        if self.quirks.or_and_xor_reset_vf {
+            self.v_registers[0xF] = 0;
+        }
+    }
+
+    /// Get the value that a shift instruction should shift into VX, which is VY if that quirk is
+    /// enabled, or VX itself otherwise.
+    #[inline]
+    fn shift_source(&self, x: u8, y: u8) -> u8 {
+        if self.quirks.shift_uses_vy {
+            self.reg(y)
+        } else {
+            self.reg(x)
+        }
+    }
+
+    /// Move the memory register past the registers that were just stored or read, if that quirk
+    /// is enabled.
+    #[inline]
+    fn increment_memory_register_if_quirk(&mut self, reg_num: u8) {
+        if self.quirks.memory_register_incremented {
+            self.memory_register = (self.memory_register + reg_num as u16 + 1) & 0xFFF;
+        }
+    }
+
+    /// Fetch the next instruction from memory.
+    fn fetch(&mut self) -> [u8; 2] {
+        debug_assert!(self.program_counter < 0x1000);
+        let instruction = [
+            self.memory[self.program_counter as usize],
+            self.memory[self.program_counter as usize + 1],
+        ];
+        self.advance_program_counter();
+        instruction
+    }
+
+    /// Move the program counter to the next instruction, wrapping around to 0x000 at the end of
+    /// memory.
+    fn advance_program_counter(&mut self) {
+        self.program_counter =
+            self.program_counter.wrapping_add(INSTRUCTION_SIZE as u16) % MEMORY_SIZE as u16;
+    }
+
+    /// Execute a single cycle with the given CHIP-8 keys, calling the error and breakpoint
+    /// handlers if needed.
+    fn step_and_handle(&mut self, keys: &Keys) -> Option<Display> {
+        let display = match self.try_step(keys) {
+            Ok(display) => display,
+            Err(error) => {
+                self.events.push(InterpreterEvent::Error(error));
+                (self.on_error_handler)(self, error);
+                self.halted = true;
+                return None;
+            }
+        };
+
+        if let Some(addr) = self.breakpoint_reached() {
+            (self.on_breakpoint_handler)(self, addr);
+        }
+
+        display
+    }
+
+    /// Return an error if any of the `len` bytes starting at `I` are outside of memory. The
+    /// `address` is where the instruction that's accessing them was fetched from.
+    fn check_memory_access(&self, len: usize, address: u16) -> Result<(), InterpreterError> {
+        if self.memory_register as usize + len > MEMORY_SIZE {
+            return Err(InterpreterError::MemoryOutOfBounds {
+                i: self.memory_register,
+                len,
+                address,
+            });
+        }
+        Ok(())
+    }
+
+    /// Check whether the key in register VX is pressed, or return an error if VX isn't a valid
+    /// key. The `address` is where the instruction was fetched from.
+    fn key_pressed(&self, x: u8, keys: &Keys, address: u16) -> Result<bool, InterpreterError> {
+        let key = self.reg(x);
+        keys.get(key as usize)
+            .copied()
+            .ok_or(InterpreterError::InvalidKey { key, address })
+    }
+
+    /// Execute the given instruction, which was fetched from the given address.
+    fn execute(
+        &mut self,
+        instruction: Instruction,
+        keys: &Keys,
+        address: u16,
+    ) -> Result<(), InterpreterError> {
+        use Instruction as I;
+
+        match instruction {
+            I::Nop => (),
+            I::ClearScreen => {
+                self.clear_display();
+                self.timings.record_frame();
+            }
+            #[cfg(feature = "schip")]
+            I::LowRes => {
+                self.graphics_mode = GraphicsMode::LowRes;
+                self.clear_display();
+            }
+            #[cfg(feature = "schip")]
+            I::HighRes => {
+                self.graphics_mode = GraphicsMode::HighRes;
+                self.clear_display();
+            }
+            // Without SUPER-CHIP, 00FE and 00FF decode as machine code routines, so these can only
+            // be executed directly. There's no high resolution display to switch to, so do nothing
+            // like the routines would.
+            #[cfg(not(feature = "schip"))]
+            I::LowRes | I::HighRes => (),
+            // The rest of SUPER-CHIP isn't supported yet, so these are treated like opcodes that
+            // we don't recognise
+            I::ScrollDown(_)
+            | I::ScrollRight
+            | I::ScrollLeft
+            | I::Exit
+            | I::DrawLargeSprite(_, _)
+            | I::LoadLargeDigitAddress(_)
+            | I::StoreRegistersInRpl(_)
+            | I::ReadRegistersFromRpl(_) => {
+                let opcode = encode(instruction).expect("Decoded instructions can be encoded");
+                self.handle_unimplemented(u16::from_be_bytes(opcode), address)?;
+            }
+            I::Return => {
+                self.stack_pointer = self
+                    .stack_pointer
+                    .checked_sub(1)
+                    .ok_or(InterpreterError::StackUnderflow { address })?;
+                self.program_counter = self.stack[self.stack_pointer as usize];
+            }
+            I::Jump(target) => {
+                // The program counter has already moved past this jump, and may have wrapped
+                let jump_address =
+                    self.program_counter.wrapping_sub(INSTRUCTION_SIZE as u16) % MEMORY_SIZE as u16;
+                if target == jump_address {
+                    self.halted = true;
+                    self.events.push(InterpreterEvent::HaltDetected);
+                }
+                self.program_counter = target;
+            }
+            I::Call(subroutine) => {
+                if self.stack_pointer as usize >= STACK_DEPTH {
+                    return Err(InterpreterError::StackOverflow { address });
+                }
+                self.stack[self.stack_pointer as usize] = self.program_counter;
+                self.stack_pointer += 1;
+                self.program_counter = subroutine;
+            }
+            I::SkipIfEqual(x, op) => {
+                if self.reg(x) == self.get_operand(op) {
+                    self.advance_program_counter();
+                }
+            }
+            I::SkipIfNotEqual(x, op) => {
+                if self.reg(x) != self.get_operand(op) {
+                    self.advance_program_counter();
+                }
+            }
+            I::LoadRegister(x, op) => *self.mut_reg(x) = self.get_operand(op),
+            I::AddNoCarry(x, byte) => *self.mut_reg(x) = self.reg(x).wrapping_add(byte),
+            I::Or(x, y) => {
+                *self.mut_reg(x) |= self.reg(y);
+                self.reset_vf_if_quirk();
+            }
+            I::And(x, y) => {
+                *self.mut_reg(x) &= self.reg(y);
+                self.reset_vf_if_quirk();
+            }
+            I::Xor(x, y) => {
+                *self.mut_reg(x) ^= self.reg(y);
+                self.reset_vf_if_quirk();
+            }
+            I::AddWithCarry(x, y) => {
+                let (value, carry) = self.reg(x).overflowing_add(self.reg(y));
+                *self.mut_reg(x) = value;
+                *self.mut_reg(0xF) = carry as u8;
+            }
+            I::Sub(x, y) => {
+                // VF is set when there's no borrow, and written last so that it wins if x is F
+                let no_borrow = self.reg(x) >= self.reg(y);
+                *self.mut_reg(x) = self.reg(x).wrapping_sub(self.reg(y));
+                *self.mut_reg(0xF) = no_borrow as u8;
+            }
+            I::ShiftRight(x, y) => {
+                let value = self.shift_source(x, y);
+                *self.mut_reg(x) = value >> 1;
+                *self.mut_reg(0xF) = value & 1;
+            }
+            I::SubN(x, y) => {
+                let no_borrow = self.reg(y) >= self.reg(x);
+                *self.mut_reg(x) = self.reg(y).wrapping_sub(self.reg(x));
+                *self.mut_reg(0xF) = no_borrow as u8;
+            }
+            I::ShiftLeft(x, y) => {
+                let value = self.shift_source(x, y);
+                *self.mut_reg(x) = value << 1;
+                *self.mut_reg(0xF) = value >> 7;
+            }
+            I::LoadMemoryRegister(address) => self.memory_register = address,
+            I::JumpPlusV0(base) => {
+                let register = if self.quirks.jump_plus_uses_vx {
+                    ((base >> 8) & 0xF) as u8
+                } else {
+                    0
+                };
+                self.program_counter = (base + self.reg(register) as u16) & 0xFFF;
+            }
+            I::LoadRandomWithMask(x, mask) => *self.mut_reg(x) = rand::random::<u8>() & mask,
+            I::Draw(x, y, n) => {
+                self.check_memory_access(n as usize, address)?;
+                let (x, y) = (self.reg(x), self.reg(y));
+                self.timings.record_frame();
+
+                let mut sprite = [0; 15];
+                for (offset, row) in sprite.iter_mut().take(n as usize).enumerate() {
+                    *row = self.read_memory(self.memory_register + offset as u16);
+                }
+                let sprite = &sprite[..n as usize];
+
+                let wrap = self.quirks.sprite_wrapping;
+                #[cfg(not(feature = "schip"))]
+                let erased = draw_sprite(&mut self.display, x, y, sprite, wrap);
+                #[cfg(feature = "schip")]
+                let erased = match self.graphics_mode {
+                    GraphicsMode::LowRes => draw_sprite(&mut self.display, x, y, sprite, wrap),
+                    GraphicsMode::HighRes => {
+                        let erased = draw_sprite(&mut self.hires_display, x, y, sprite, wrap);
+                        self.display = downsample(&self.hires_display);
+                        erased
+                    }
+                };
+                self.v_registers[0xF] = erased as u8;
+
+                self.screen_hash = self.display.screen_hash();
+                self.display_dirty = true;
+                self.events.push(InterpreterEvent::ScreenUpdated);
+                (self.on_display_update_handler)(self);
+            }
+            I::SkipIfKeyPressed(x) => {
+                if self.key_pressed(x, keys, address)? {
+                    self.advance_program_counter();
+                }
+            }
+            I::SkipIfKeyNotPressed(x) => {
+                if !self.key_pressed(x, keys, address)? {
+                    self.advance_program_counter();
+                }
+            }
+            I::LoadFromDelayTimer(x) => *self.mut_reg(x) = self.delay_timer.get(),
+            I::WaitForKeyPress(x) => {
+                self.waiting_for_key_press = Some(x);
+                self.key_wait_started = Some(C::now());
+                self.events.push(InterpreterEvent::WaitingForKey(x));
+            }
+            I::LoadIntoDelayTimer(x) => self.delay_timer.set(self.reg(x)),
+            I::LoadIntoSoundTimer(x) => self.sound_timer.set(self.reg(x)),
+            I::AddToMemoryRegister(x) => {
+                self.memory_register = (self.memory_register + self.reg(x) as u16) & 0xFFF
+            }
+            I::LoadDigitAddress(x) => self.memory_register = font_address(self.reg(x) & 0xF),
+            I::StoreBcdInMemory(x) => {
+                self.check_memory_access(3, address)?;
+                let num = self.reg(x);
+                let hundreds = (num - (num % 100)) / 100;
+                let tens = (num - (num % 10) - hundreds * 100) / 10;
+                let units = num - hundreds * 100 - tens * 10;
+                self.write_memory(self.memory_register, hundreds);
+                self.write_memory(self.memory_register + 1, tens);
+                self.write_memory(self.memory_register + 2, units);
+            }
+            I::StoreRegistersInMemory(reg_num) => {
+                self.check_memory_access(reg_num as usize + 1, address)?;
+                for x in 0..=reg_num {
+                    self.write_memory(self.memory_register + x as u16, self.reg(x));
+                }
+                self.increment_memory_register_if_quirk(reg_num);
+            }
+            I::ReadRegistersFromMemory(reg_num) => {
+                self.check_memory_access(reg_num as usize + 1, address)?;
+                for x in 0..=reg_num {
+                    *self.mut_reg(x) = self.read_memory(self.memory_register + x as u16);
+                }
+                self.increment_memory_register_if_quirk(reg_num);
+            }
+        };
+        Ok(())
+    }
+
+    /// Read a byte of memory for an instruction, and tell the memory read handler about it.
+    fn read_memory(&self, address: u16) -> u8 {
+        let value = self.memory[address as usize];
+        (self.on_memory_read_handler)(self, address, value);
+        value
+    }
+
+    /// Write a byte of memory for an instruction, and tell the memory write handler about it.
+    fn write_memory(&mut self, address: u16, value: u8) {
+        self.memory[address as usize] = value;
+        (self.on_memory_write_handler)(self, address, value);
+    }
+
+    /// Clear the display, and the high resolution one if there is one.
+    fn clear_display(&mut self) {
+        self.display = [[Pixel::Black; _]; _];
+        #[cfg(feature = "schip")]
+        {
+            self.hires_display = [[Pixel::Black; _]; _];
+        }
+        self.screen_hash = BLANK_SCREEN_HASH;
+        self.on_clear_screen();
+    }
+
+    /// Called whenever the screen gets cleared.
+    fn on_clear_screen(&mut self) {
+        self.last_clear_at_cycle = Some(self.cycles);
+        self.display_dirty = false;
+        self.events.push(InterpreterEvent::ScreenCleared);
+    }
+
+    /// Queue an event if the buzzer started or stopped since `was_buzzing` was checked.
+    fn push_buzzer_event(&mut self, was_buzzing: bool) {
+        match (was_buzzing, self.sound_timer.get() > 0) {
+            (false, true) => self.events.push(InterpreterEvent::BuzzerStarted),
+            (true, false) => self.events.push(InterpreterEvent::BuzzerStopped),
+            _ => (),
+        }
+    }
+
+    /// Tell the timers how much time has passed since they were last ticked, so that they can
+    /// count down at 60 Hz.
+    fn decrement_timers(&mut self) {
+        let now = C::now();
+        let elapsed = now - self.last_timer_tick;
+        self.last_timer_tick = now;
+
+        self.delay_timer.tick(elapsed);
+        self.sound_timer.tick(elapsed);
+        self.frames.tick(elapsed);
+
+        #[cfg(feature = "display-effects")]
+        self.fade_ghost_display(elapsed);
+    }
+}
+
+/// The default error handler for [`Interpreter::step`], which panics with the error, and the
+/// hash of the ROM if we have it.
+#[cfg_attr(not(feature = "sha2"), allow(unused_variables))]
+fn panic_on_error<C: Clock>(interpreter: &Chip8Interpreter<C>, error: InterpreterError) {
+    #[cfg(feature = "sha2")]
+    panic!(
+        "{error} (ROM SHA-256: {})",
+        crate::rom_hash::hash_to_hex(&interpreter.rom_hash)
+    );
+
+    #[cfg(not(feature = "sha2"))]
+    panic!("{error}");
+}
+
+/// XOR the sprite onto the display with its top left corner at `(x, y)`, wrapping the
+/// coordinates, and return true if any pixels were erased. The sprite itself is clipped at the
+/// edges of the display, unless `wrap` is true, when it wraps around to the other side.
+fn draw_sprite<const W: usize, const H: usize>(
+    display: &mut [[Pixel; W]; H],
+    x: u8,
+    y: u8,
+    sprite: &[u8],
+    wrap: bool,
+) -> bool {
+    let first_x = x as usize % W;
+    let first_y = y as usize % H;
+    let mut erased = false;
+
+    for (dy, row) in sprite.iter().enumerate() {
+        let y = first_y + dy;
+        if y >= H && !wrap {
+            break;
+        }
+
+        for dx in 0..8 {
+            let x = first_x + dx;
+            if x >= W && !wrap {
+                break;
+            }
+
+            let (x, y) = (x % W, y % H);
+            let pixel = pixel_from_u8(row & (0x80 >> dx));
+            let old_pixel = display[y][x];
+            display[y][x] = old_pixel ^ pixel;
+            erased |= bool::from(old_pixel & pixel);
+        }
+    }
+
+    erased
+}
+
+/// Panic if the display persistence isn't in `0.0..=1.0`, or return it otherwise.
+#[cfg(feature = "display-effects")]
+fn check_display_persistence(value: f32) -> f32 {
+    assert!(
+        (0. ..=1.).contains(&value),
+        "The display persistence must be between 0.0 and 1.0, not {value}"
+    );
+    value
+}
+
+impl<C: Clock> Interpreter for Chip8Interpreter<C> {
+    /// Execute a single cycle with keys from `chip8_base`'s runner, which are translated with the
+    /// [`key_map`](Chip8Interpreter::key_map) first. If this reaches a breakpoint, we call the
+    /// handler from [`set_on_breakpoint`](Chip8Interpreter::set_on_breakpoint) afterwards.
+    ///
+    /// These keys are only used for this step, and don't affect the keys from
+    /// [`inject_key_event`](Chip8Interpreter::inject_key_event).
+    fn step(&mut self, keys: &Keys) -> Option<Display> {
+        let keys = self.key_map.translate(keys);
+        self.step_and_handle(&keys)
+    }
+
+    fn speed(&self) -> Duration {
+        self.speed
+    }
+
+    fn buzzer_active(&self) -> bool {
+        self.audio_enabled && self.sound_timer.get() > 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_clock::MockClock;
+
+    #[test]
+    fn or_and_xor_reset_vf_quirk_test() {
+        let keys = [false; 16];
+
+        for instruction in [
+            Instruction::Or(0, 1),
+            Instruction::And(0, 1),
+            Instruction::Xor(0, 1),
+        ] {
+            let mut interpreter = Chip8Interpreter::new(&[], 700., Quirks::default());
+            interpreter.v_registers[0xF] = 1;
+            interpreter.execute_instruction(instruction, &keys).unwrap();
+            assert_eq!(interpreter.v_registers[0xF], 0);
+
+            let mut interpreter = Chip8Interpreter::new(&[], 700., Quirks::chip48());
+            interpreter.v_registers[0xF] = 1;
+            interpreter.execute_instruction(instruction, &keys).unwrap();
+            assert_eq!(interpreter.v_registers[0xF], 1);
+        }
+    }
+
+    #[test]
+    fn shift_uses_vy_quirk_test() {
+        let keys = [false; 16];
+
+        for (quirks, source) in [
+            (Quirks::chip8(), 0b1000_0011),
+            (Quirks::chip48(), 0b0110_0000),
+        ] {
+            let mut interpreter = Chip8Interpreter::new(&[], 700., quirks);
+            interpreter.set_register(0, 0b0110_0000);
+            interpreter.set_register(1, 0b1000_0011);
+            interpreter
+                .execute_instruction(Instruction::ShiftRight(0, 1), &keys)
+                .unwrap();
+            assert_eq!(interpreter.reg(0), source >> 1);
+            assert_eq!(interpreter.reg(0xF), source & 1);
+
+            interpreter.set_register(0, 0b0110_0000);
+            interpreter
+                .execute_instruction(Instruction::ShiftLeft(0, 1), &keys)
+                .unwrap();
+            assert_eq!(interpreter.reg(0), source << 1);
+            assert_eq!(interpreter.reg(0xF), source >> 7);
+        }
+    }
+
+    #[test]
+    fn memory_register_incremented_quirk_test() {
+        let keys = [false; 16];
+
+        for (quirks, expected) in [(Quirks::chip8(), 0x303), (Quirks::chip48(), 0x300)] {
+            for instruction in [
+                Instruction::StoreRegistersInMemory(2),
+                Instruction::ReadRegistersFromMemory(2),
+            ] {
+                let mut interpreter = Chip8Interpreter::new(&[], 700., quirks);
+                interpreter.set_memory_register(0x300);
+                interpreter.execute_instruction(instruction, &keys).unwrap();
+                assert_eq!(interpreter.get_memory_register(), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn jump_plus_uses_vx_quirk_test() {
+        let keys = [false; 16];
+
+        for (quirks, expected) in [(Quirks::chip8(), 0x311), (Quirks::chip48(), 0x332)] {
+            let mut interpreter = Chip8Interpreter::new(&[], 700., quirks);
+            interpreter.set_register(0, 0x11);
+            interpreter.set_register(3, 0x32);
+            interpreter
+                .execute_instruction(Instruction::JumpPlusV0(0x300), &keys)
+                .unwrap();
+            assert_eq!(interpreter.get_program_counter(), expected);
+        }
+    }
+
+    #[test]
+    fn sprite_wrapping_quirk_test() {
+        let keys = [false; 16];
+
+        for sprite_wrapping in [false, true] {
+            let mut interpreter = Chip8Interpreter::new(
+                &[],
+                700.,
+                Quirks {
+                    sprite_wrapping,
+                    ..Quirks::default()
+                },
+            );
+
+            // Draw a 2x2 square in the bottom right corner
+            interpreter.memory[0x300..0x302].copy_from_slice(&[0b1100_0000; 2]);
+            interpreter.set_memory_register(0x300);
+            interpreter.set_register(0, 63);
+            interpreter.set_register(1, 31);
+            interpreter
+                .execute_instruction(Instruction::Draw(0, 1, 2), &keys)
+                .unwrap();
+
+            let corners = [(31, 63), (31, 0), (0, 63), (0, 0)];
+            let lit = corners.map(|(y, x)| interpreter.display[y][x] == Pixel::White);
+            if sprite_wrapping {
+                assert_eq!(lit, [true; 4]);
+            } else {
+                assert_eq!(lit, [true, false, false, false]);
+            }
+        }
+    }
+
+    #[test]
+    fn memory_layout_test() {
+        let interpreter = Chip8Interpreter::new(&[0x12, 0x00], 700., Quirks::default());
+        let layout = interpreter.memory_layout();
+
+        assert_eq!(layout.program_start, 0x200);
+        assert_eq!(layout.total_ram, 4096);
+        assert_eq!(layout.stack_depth, 16);
+        assert_eq!(layout.stack_start, None);
+        assert_eq!(layout.font_start, 0x50);
+
+        let font = layout.font_start as usize..(layout.font_start + layout.font_size) as usize;
+        assert_eq!(
+            &interpreter.memory[font][..5],
+            [0xF0, 0x90, 0x90, 0x90, 0xF0]
+        );
+        assert_eq!(
+            interpreter.memory[layout.program_start as usize..][..2],
+            [0x12, 0x00]
+        );
+    }
+
+    #[test]
+    fn memory_region_test() {
+        let interpreter = Chip8Interpreter::new(&[0x12, 0x00, 0xAB], 700., Quirks::default());
+        assert_eq!(interpreter.program_size(), 3);
+        assert_eq!(interpreter.program_end_address(), 0x203);
+
+        assert_eq!(
+            interpreter.memory_region_by_name(RegionKind::Font),
+            (0x50, 0x140)
+        );
+        assert_eq!(
+            interpreter.memory_region_by_name(RegionKind::Program),
+            (0x200, 0x203)
+        );
+        assert_eq!(interpreter.memory_region_by_name(RegionKind::Stack), (0, 0));
+        assert_eq!(
+            interpreter.memory_region_by_name(RegionKind::Reserved),
+            (0x000, 0x050)
+        );
+        assert_eq!(
+            interpreter.memory_map().region_at(0x202).kind,
+            RegionKind::Program
+        );
+        assert_eq!(
+            interpreter.memory_region_by_name(RegionKind::Free),
+            (0x203, 0x1000)
+        );
+
+        let interpreter = Chip8Interpreter::from_config(Chip8Config {
+            entry_point: 0x600,
+            ..Chip8Config::new(vec![0; 0x10])
+        });
+        assert_eq!(interpreter.program_size(), 0x10);
+        assert_eq!(
+            interpreter.memory_region_by_name(RegionKind::Program),
+            (0x600, 0x610)
+        );
+    }
+
+    #[test]
+    fn step_n_test() {
+        let keys = [false; 16];
+
+        // ld i, #050; draw v0, v0, 5; jmp #204
+        let rom = [0xA0, 0x50, 0xD0, 0x05, 0x12, 0x04];
+
+        let mut interpreter = Chip8Interpreter::new(&rom, 700., Quirks::default());
+        assert_eq!(interpreter.step_n(0, &keys), None);
+
+        let display = interpreter.step_n(2, &keys).unwrap();
+        assert_eq!(interpreter.cycles, 2);
+        assert_eq!(
+            display[0][..5],
+            [
+                Pixel::White,
+                Pixel::White,
+                Pixel::White,
+                Pixel::White,
+                Pixel::Black
+            ]
+        );
+        assert_eq!(
+            display[1][..5],
+            [
+                Pixel::White,
+                Pixel::Black,
+                Pixel::Black,
+                Pixel::White,
+                Pixel::Black
+            ]
+        );
+
+        // The jump to itself halts the program, so we stop early
+        assert_eq!(interpreter.step_n(10, &keys), Some(display));
+        assert_eq!(interpreter.cycles, 3);
+
+        let mut interpreter = Chip8Interpreter::new(&rom, 700., Quirks::default());
+        assert_eq!(interpreter.step_n_or_halt(100, &keys), (3, Some(display)));
+
+        let mut interpreter = Chip8Interpreter::new(&rom, 700., Quirks::default());
+        assert_eq!(interpreter.step_n_or_halt(2, &keys), (2, Some(display)));
+
+        // ld v0, k
+        let mut interpreter = Chip8Interpreter::new(&[0xF0, 0x0A], 700., Quirks::default());
+        let (count, _) = interpreter.step_n_or_halt(100, &keys);
+        assert_eq!(count, 3);
+        assert!(!interpreter.is_halted());
+    }
+
+    #[test]
+    fn pending_key_wait_test() {
+        let mut keys = [false; 16];
+
+        // ld v3, k; jmp #202
+        let mut interpreter =
+            Chip8Interpreter::new(&[0xF3, 0x0A, 0x12, 0x02], 700., Quirks::default());
+        assert_eq!(interpreter.pending_key_wait(), None);
+        assert_eq!(interpreter.key_wait_duration(), None);
+
+        interpreter.step_n(5, &keys);
+        assert_eq!(interpreter.pending_key_wait(), Some(3));
+        assert!(interpreter.key_wait_duration().is_some());
+
+        keys[0xB] = true;
+        interpreter.step(&keys);
+        assert_eq!(interpreter.pending_key_wait(), None);
+        assert_eq!(interpreter.key_wait_duration(), None);
+        assert_eq!(interpreter.reg(3), 0xB);
+    }
+
+    #[test]
+    fn on_unimplemented_test() {
+        let keys = [false; 16];
+
+        // An unrecognised opcode, then ld v0, 1
+        let rom = [0xFF, 0xFF, 0x60, 0x01];
+
+        let mut interpreter = Chip8Interpreter::new(&rom, 700., Quirks::default());
+        assert_eq!(
+            interpreter.try_step(&keys),
+            Err(InterpreterError::UnrecognisedInstruction {
+                opcode: 0xFFFF,
+                address: 0x200
+            })
+        );
+
+        let mut interpreter = Chip8Interpreter::new(&rom, 700., Quirks::default());
+        interpreter.set_on_unimplemented(|_| OnUnimplementedAction::Skip);
+        interpreter.step_n(2, &keys);
+        assert_eq!(interpreter.reg(0), 1);
+        assert!(!interpreter.is_halted());
+
+        let mut interpreter = Chip8Interpreter::new(&rom, 700., Quirks::default());
+        interpreter.set_on_unimplemented(|opcode| {
+            assert_eq!(opcode, 0xFFFF);
+            OnUnimplementedAction::Halt
+        });
+        interpreter.step_n(2, &keys);
+        assert_eq!(interpreter.reg(0), 0);
+        assert!(interpreter.is_halted());
+
+        // The SUPER-CHIP instructions that we can decode but not execute are handled the same way
+        #[cfg(feature = "schip")]
+        {
+            let mut interpreter = Chip8Interpreter::new(&[0x00, 0xFB], 700., Quirks::default());
+            assert_eq!(
+                interpreter.try_step(&keys),
+                Err(InterpreterError::UnrecognisedInstruction {
+                    opcode: 0x00FB,
+                    address: 0x200
+                })
+            );
+
+            let mut interpreter = Chip8Interpreter::new(&[0xD0, 0x10], 700., Quirks::default());
+            interpreter.set_on_unimplemented(|_| OnUnimplementedAction::Skip);
+            interpreter.step(&keys);
+            assert_eq!(interpreter.get_program_counter(), 0x202);
+        }
+
+        // Without SUPER-CHIP, 00FE and 00FF run as a Nop like they always used to
+        #[cfg(not(feature = "schip"))]
+        {
+            // high; low; ld v0, 1
+            let rom = [0x00, 0xFF, 0x00, 0xFE, 0x60, 0x01];
+            let mut interpreter = Chip8Interpreter::new(&rom, 700., Quirks::default());
+            for _ in 0..3 {
+                assert_eq!(interpreter.try_step(&keys).map(|_| ()), Ok(()));
+            }
+            assert_eq!(interpreter.reg(0), 1);
+            assert_eq!(interpreter.graphics_mode(), GraphicsMode::LowRes);
+
+            assert_eq!(
+                interpreter.execute_instruction(Instruction::HighRes, &keys),
+                Ok(())
+            );
+            assert_eq!(interpreter.graphics_mode(), GraphicsMode::LowRes);
+        }
+    }
+
+    #[test]
+    fn inspect_stack_test() {
+        let keys = [false; 16];
+
+        // call #206; jmp #204; nop; call #20A; ret; ret
+        let rom = [
+            0x22, 0x06, 0x12, 0x04, 0x00, 0x00, 0x22, 0x0A, 0x00, 0xEE, 0x00, 0xEE,
+        ];
+        let mut interpreter = Chip8Interpreter::new(&rom, 700., Quirks::default());
+        assert_eq!(interpreter.inspect_stack(), []);
+        assert_eq!(interpreter.stack_depth(), 0);
+
+        interpreter.step_n(2, &keys);
+        assert_eq!(interpreter.inspect_stack(), [0x202, 0x208]);
+        assert_eq!(interpreter.stack_depth(), 2);
+
+        interpreter.step_n(2, &keys);
+        assert_eq!(interpreter.inspect_stack(), []);
+        assert_eq!(interpreter.stack_depth(), 0);
+        assert_eq!(interpreter.program_counter, 0x202);
+    }
+
+    #[test]
+    fn memory_regions_accessed_by_test() {
+        use Instruction as I;
+
+        let mut interpreter = Chip8Interpreter::new(&[], 700., Quirks::default());
+        interpreter.memory_register = 0x300;
+        let access = |size, mode| {
+            vec![MemoryAccess {
+                addr: 0x300,
+                size,
+                mode,
+            }]
+        };
+
+        assert_eq!(
+            interpreter.memory_regions_accessed_by(I::Draw(1, 2, 5)),
+            access(5, AccessMode::Read)
+        );
+        assert_eq!(
+            interpreter.memory_regions_accessed_by(I::StoreBcdInMemory(0xA)),
+            access(3, AccessMode::Write)
+        );
+        assert_eq!(
+            interpreter.memory_regions_accessed_by(I::StoreRegistersInMemory(3)),
+            access(4, AccessMode::Write)
+        );
+        assert_eq!(
+            interpreter.memory_regions_accessed_by(I::ReadRegistersFromMemory(0xF)),
+            access(16, AccessMode::Read)
+        );
+
+        assert!(interpreter
+            .memory_regions_accessed_by(I::Draw(1, 2, 0))
+            .is_empty());
+        assert!(interpreter
+            .memory_regions_accessed_by(I::LoadMemoryRegister(0x400))
+            .is_empty());
+        assert!(interpreter
+            .memory_regions_accessed_by(I::Jump(0x200))
+            .is_empty());
+    }
+
+    #[test]
+    fn self_test_test() {
+        let new = || Chip8Interpreter::new(&[0x60, 0x01, 0x12, 0x02], 700., Quirks::default());
+        let problems = |interpreter: Chip8Interpreter| match interpreter.self_test() {
+            SelfTestResult::Pass => 0,
+            SelfTestResult::Fail(problems) => problems.len(),
+        };
+
+        let mut interpreter = new();
+        assert_eq!(interpreter.self_test(), SelfTestResult::Pass);
+        interpreter.step(&[false; 16]);
+        assert_eq!(interpreter.self_test(), SelfTestResult::Pass);
+
+        let mut interpreter = new();
+        interpreter.memory[FONT_ADDRESS_START + 7] ^= 0xFF;
+        assert_eq!(problems(interpreter), 1);
+        interpreter.load_font(&[0xAA; 80]);
+        assert_eq!(interpreter.self_test(), SelfTestResult::Pass);
+
+        let mut interpreter = new();
+        interpreter.program_counter = 0xFFF;
+        assert_eq!(problems(interpreter), 1);
+
+        let mut interpreter = new();
+        interpreter.stack_pointer = STACK_DEPTH as u8 + 1;
+        assert_eq!(problems(interpreter), 1);
+
+        let mut interpreter = new();
+        interpreter.v_registers[3] = 5;
+        interpreter.memory_register = 0x300;
+        interpreter.stack[15] = 0x200;
+        assert_eq!(problems(interpreter), 3);
+        interpreter.cycles = 1;
+        assert_eq!(interpreter.self_test(), SelfTestResult::Pass);
+
+        // Resetting starts the count of cycles again, so the registers are checked again
+        let mut interpreter = new();
+        interpreter.step(&[false; 16]);
+        interpreter.reset();
+        assert_eq!(interpreter.self_test(), SelfTestResult::Pass);
+        interpreter.v_registers[3] = 5;
+        assert_eq!(problems(interpreter), 1);
+    }
+
+    #[test]
+    fn load_font_test() {
+        let keys = [false; 16];
+        let font: [u8; 80] = std::array::from_fn(|idx| idx as u8);
+
+        let mut interpreter = Chip8Interpreter::new(&[], 700., Quirks::default());
+        assert_eq!(
+            interpreter.memory[FONT_ADDRESS_START..][..80],
+            *memory::default_font()
+        );
+        interpreter.load_font(&font);
+
+        for digit in [0, 3, 0xF] {
+            interpreter.v_registers[0] = digit;
+            interpreter
+                .execute_instruction(Instruction::LoadDigitAddress(0), &keys)
+                .unwrap();
+
+            let address = interpreter.memory_register as usize;
+            assert_eq!(address, FONT_ADDRESS_START + 5 * digit as usize);
+            assert_eq!(
+                interpreter.memory[address..][..5],
+                font[5 * digit as usize..][..5]
+            );
+        }
+    }
+
+    #[test]
+    fn is_halted_test() {
+        let keys = [false; 16];
+
+        // jmp #200
+        let mut interpreter = Chip8Interpreter::new(&[0x12, 0x00], 700., Quirks::default());
+        assert!(!interpreter.is_halted());
+
+        assert!(interpreter.step(&keys).is_some());
+        assert!(interpreter.is_halted());
+        assert_eq!(interpreter.step(&keys), None);
+        assert_eq!(interpreter.cycles, 1);
+
+        // jmp #202; jmp #200
+        let mut interpreter =
+            Chip8Interpreter::new(&[0x12, 0x02, 0x12, 0x00], 700., Quirks::default());
+        interpreter.step_n(10, &keys);
+        assert!(!interpreter.is_halted());
+    }
+
+    #[test]
+    fn last_clear_at_cycle_test() {
+        let keys = [false; 16];
+
+        // ld v0, 1; cls; jmp #204
+        let mut interpreter = Chip8Interpreter::new(
+            &[0x60, 0x01, 0x00, 0xE0, 0x12, 0x04],
+            700.,
+            Quirks::default(),
+        );
+        assert_eq!(interpreter.last_clear_at_cycle(), None);
+
+        interpreter.step(&keys);
+        assert_eq!(interpreter.last_clear_at_cycle(), None);
+
+        for _ in 0..10 {
+            interpreter.step(&keys);
+            assert_eq!(interpreter.last_clear_at_cycle(), Some(1));
+        }
+    }
+
+    #[test]
+    fn set_stack_pointer_test() {
+        let keys = [false; 16];
+
+        // call #204; nop; ret
+        let rom = [0x22, 0x04, 0x00, 0x00, 0x00, 0xEE];
+        let mut interpreter = Chip8Interpreter::new(&rom, 700., Quirks::default());
+        interpreter.step(&keys);
+        assert_eq!(interpreter.dump_state().sp, 1);
+
+        assert!(interpreter.set_stack_pointer(0));
+        assert_eq!(
+            interpreter.try_step(&keys),
+            Err(InterpreterError::StackUnderflow { address: 0x204 })
+        );
+
+        assert!(interpreter.set_program_counter(0x204));
+        assert!(interpreter.set_stack_pointer(1));
+        interpreter.step(&keys);
+        assert_eq!(interpreter.get_program_counter(), 0x202);
+
+        assert!(interpreter.set_stack_pointer(STACK_DEPTH as u8));
+        assert!(!interpreter.set_stack_pointer(STACK_DEPTH as u8 + 1));
+        assert_eq!(interpreter.dump_state().sp, STACK_DEPTH as u8);
+    }
+
+    #[test]
+    fn error_test() {
+        let keys = [false; 16];
+
+        assert_eq!(
+            Chip8Interpreter::try_from_config(Chip8Config::new(vec![0; 0xE01])).map(|_| ()),
+            Err(InterpreterError::RomTooBig {
+                size: 0xE01,
+                max: 0xE00
+            })
+        );
+        assert_eq!(
+            Chip8Interpreter::try_from_config(Chip8Config {
+                entry_point: 0xF00,
+                ..Chip8Config::new(vec![0; 0x101])
+            })
+            .map(|_| ()),
+            Err(InterpreterError::RomTooBig {
+                size: 0x101,
+                max: 0x100
+            })
+        );
+
+        // An empty ROM fits anywhere, but the entry point still has to be in memory
+        for entry_point in [0x1000, 0xFFFF] {
+            assert_eq!(
+                Chip8Interpreter::try_from_config(Chip8Config {
+                    entry_point,
+                    ..Chip8Config::new(vec![])
+                })
+                .map(|_| ()),
+                Err(InterpreterError::EntryPointOutOfBounds { entry_point })
+            );
+        }
+
+        // ret
+        let mut interpreter = Chip8Interpreter::new(&[0x00, 0xEE], 700., Quirks::default());
+        assert_eq!(
+            interpreter.try_step(&keys),
+            Err(InterpreterError::StackUnderflow { address: 0x200 })
+        );
+
+        // call #200
+        let mut interpreter = Chip8Interpreter::new(&[0x22, 0x00], 700., Quirks::default());
+        for _ in 0..STACK_DEPTH {
+            interpreter.try_step(&keys).unwrap();
+        }
+        assert_eq!(
+            interpreter.try_step(&keys),
+            Err(InterpreterError::StackOverflow { address: 0x200 })
+        );
+
+        // ld i, #ffe; drw v0, v0, 5
+        let rom = [0xAF, 0xFE, 0xD0, 0x05];
+        let mut interpreter = Chip8Interpreter::new(&rom, 700., Quirks::default());
+        interpreter.try_step(&keys).unwrap();
+        assert_eq!(
+            interpreter.try_step(&keys),
+            Err(InterpreterError::MemoryOutOfBounds {
+                i: 0xFFE,
+                len: 5,
+                address: 0x202
+            })
+        );
+
+        // ld v0, 16; skp v0
+        let rom = [0x60, 0x10, 0xE0, 0x9E];
+        let mut interpreter = Chip8Interpreter::new(&rom, 700., Quirks::default());
+        interpreter.try_step(&keys).unwrap();
+        assert_eq!(
+            interpreter.try_step(&keys),
+            Err(InterpreterError::InvalidKey {
+                key: 16,
+                address: 0x202
+            })
+        );
+    }
+
+    #[test]
+    fn on_error_test() {
+        use std::sync::atomic::{AtomicU16, Ordering};
+
+        static ERROR_ADDRESS: AtomicU16 = AtomicU16::new(0);
+        let keys = [false; 16];
+
+        // ld v0, 1; ret
+        let mut interpreter =
+            Chip8Interpreter::new(&[0x60, 0x01, 0x00, 0xEE], 700., Quirks::default());
+        interpreter.set_on_error(|_, error| {
+            if let InterpreterError::StackUnderflow { address } = error {
+                ERROR_ADDRESS.store(address, Ordering::Relaxed);
+            }
+        });
+
+        assert!(interpreter.step(&keys).is_some());
+        assert_eq!(interpreter.step(&keys), None);
+        assert_eq!(ERROR_ADDRESS.load(Ordering::Relaxed), 0x202);
+        assert!(interpreter.is_halted());
+    }
+
+    #[test]
+    #[should_panic(expected = "Stack underflow")]
+    fn on_error_default_panics_test() {
+        Chip8Interpreter::new(&[0x00, 0xEE], 700., Quirks::default()).step(&[false; 16]);
+    }
+
+    #[test]
+    fn program_counter_out_of_bounds_test() {
+        let keys = [false; 16];
+
+        // jmp #FFE, with ld v0, 1 in the last two bytes of memory, after which we wrap to 0x000
+        let mut interpreter = Chip8Interpreter::new(&[0x1F, 0xFE], 700., Quirks::default());
+        interpreter.memory[0xFFE..].copy_from_slice(&[0x60, 0x01]);
+
+        assert!(interpreter.try_step(&keys).is_ok());
+        assert!(interpreter.try_step(&keys).is_ok());
+        assert_eq!(interpreter.v_registers[0], 1);
+        assert_eq!(interpreter.program_counter, 0x000);
+
+        // jmp #FFF, where the second byte would be outside of memory
+        let mut interpreter = Chip8Interpreter::new(&[0x1F, 0xFF], 700., Quirks::default());
+        assert!(interpreter.try_step(&keys).is_ok());
+        assert_eq!(
+            interpreter.try_step(&keys),
+            Err(InterpreterError::ProgramCounterOutOfBounds { pc: 0xFFF })
+        );
+    }
+
+    #[test]
+    fn program_counter_wrapping_test() {
+        let keys = [false; 16];
+
+        // se v0, 0 in the last two bytes of memory, which skips past the end
+        let mut interpreter = Chip8Interpreter::new(&[], 700., Quirks::default());
+        interpreter.memory[0xFFE..].copy_from_slice(&[0x30, 0x00]);
+        interpreter.program_counter = 0xFFE;
+        assert!(interpreter.try_step(&keys).is_ok());
+        assert_eq!(interpreter.program_counter, 0x002);
+
+        // jmp #FFE in the last two bytes of memory still halts after the program counter wraps
+        let mut interpreter = Chip8Interpreter::new(&[], 700., Quirks::default());
+        interpreter.memory[0xFFE..].copy_from_slice(&[0x1F, 0xFE]);
+        interpreter.program_counter = 0xFFE;
+        assert!(interpreter.try_step(&keys).is_ok());
+        assert_eq!(interpreter.program_counter, 0xFFE);
+        assert!(interpreter.is_halted());
+    }
+
+    #[test]
+    fn audio_sample_test() {
+        let mut interpreter = Chip8Interpreter::new(&[], 700., Quirks::default());
+        interpreter.set_audio_sample_rate(880.);
+        assert_eq!(interpreter.audio_sample(), None);
+
+        interpreter.sound_timer.set(10);
+        let samples: Vec<_> = (0..4).map(|_| interpreter.audio_sample()).collect();
+        assert_eq!(samples, [Some(1.), Some(-1.), Some(1.), Some(-1.)]);
+    }
+
+    #[test]
+    fn clock_test() {
+        // ld v3, k
+        let mut interpreter =
+            Chip8Interpreter::<MockClock>::with_clock(Chip8Config::new(vec![0xF3, 0x0A]));
+        interpreter.step(&[false; 16]);
+        assert_eq!(interpreter.key_wait_duration(), Some(Duration::ZERO));
+
+        MockClock::advance(Duration::from_millis(250));
+        assert_eq!(
+            interpreter.key_wait_duration(),
+            Some(Duration::from_millis(250))
+        );
+    }
+
+    #[test]
+    fn poll_event_test() {
+        use InterpreterEvent as E;
+
+        let no_keys = [false; 16];
+
+        // cls; ld v0, 5; sound v0; drw v0, v1, 5; ld v1, k; jmp #20A
+        let mut interpreter = Chip8Interpreter::<MockClock>::with_clock(Chip8Config::new(vec![
+            0x00, 0xE0, 0x60, 0x05, 0xF0, 0x18, 0xD0, 0x15, 0xF1, 0x0A, 0x12, 0x0A,
+        ]));
+        assert_eq!(interpreter.poll_event(), None);
+
+        interpreter.step_n(5, &no_keys);
+
+        let mut keys = no_keys;
+        keys[3] = true;
+        interpreter.step(&keys);
+
+        // The sound timer runs out while we jump to ourselves
+        MockClock::advance(Duration::from_millis(100));
+        interpreter.step(&no_keys);
+
+        let events: Vec<_> = std::iter::from_fn(|| interpreter.poll_event()).collect();
+        assert_eq!(
+            events,
+            [
+                E::ScreenCleared,
+                E::BuzzerStarted,
+                E::ScreenUpdated,
+                E::WaitingForKey(1),
+                E::KeyReceived(1, 3),
+                E::HaltDetected,
+                E::BuzzerStopped,
+            ]
+        );
+
+        // ret
+        let mut interpreter =
+            Chip8Interpreter::<MockClock>::with_clock(Chip8Config::new(vec![0x00, 0xEE]));
+        interpreter.set_on_error(|_, _| ());
+        interpreter.step(&no_keys);
+        assert_eq!(
+            interpreter.poll_event(),
+            Some(E::Error(InterpreterError::StackUnderflow {
+                address: 0x200
+            }))
+        );
+        assert_eq!(interpreter.poll_event(), None);
+
+        // Events from before a reset are forgotten
+        interpreter.step(&no_keys);
+        interpreter.reset();
+        assert_eq!(interpreter.poll_event(), None);
+    }
+
+    #[test]
+    fn debugger_navigation_test() {
+        let keys = [false; 16];
+
+        // jmp #200; ld v0, 1; ret
+        let rom = [0x12, 0x00, 0x60, 0x01, 0x00, 0xEE];
+        let mut interpreter = Chip8Interpreter::new(&rom, 700., Quirks::default());
+        interpreter.step(&keys);
+        assert!(interpreter.is_halted());
+
+        assert!(!interpreter.goto(0x1000));
+        assert!(interpreter.goto(0x202));
+        assert!(!interpreter.is_halted());
+
+        // Calling the subroutine and letting it return leaves the stack how it was
+        assert!(interpreter.call_at(0x202));
+        assert_eq!(interpreter.inspect_stack(), [0x202]);
+        interpreter.step_n(2, &keys);
+        assert_eq!(interpreter.get_register(0), Some(1));
+        assert_eq!(interpreter.get_program_counter(), 0x202);
+        assert!(interpreter.inspect_stack().is_empty());
+
+        // Returning manually from a nested call
+        assert!(interpreter.call_at(0x300));
+        assert!(interpreter.call_at(0x400));
+        assert_eq!(interpreter.inspect_stack(), [0x202, 0x300]);
+        assert!(interpreter.return_from());
+        assert_eq!(interpreter.get_program_counter(), 0x300);
+        assert!(interpreter.return_from());
+        assert_eq!(interpreter.get_program_counter(), 0x202);
+        assert!(!interpreter.return_from());
+        assert_eq!(interpreter.get_program_counter(), 0x202);
+
+        assert!(!interpreter.call_at(0x1000));
+        for _ in 0..STACK_DEPTH {
+            assert!(interpreter.call_at(0x202));
+        }
+        assert!(!interpreter.call_at(0x202));
+        assert_eq!(interpreter.stack_depth() as usize, STACK_DEPTH);
+        // ld v0, k; ld v1, 1
+        let mut interpreter =
+            Chip8Interpreter::new(&[0xF0, 0x0A, 0x61, 0x01], 700., Quirks::default());
+        interpreter.step(&keys);
+        assert_eq!(interpreter.pending_key_wait(), Some(0));
+
+        // Jumping away stops waiting for the key, so the next step runs normally
+        assert!(interpreter.goto(0x202));
+        assert_eq!(interpreter.pending_key_wait(), None);
+        assert_eq!(interpreter.key_wait_duration(), None);
+        interpreter.step(&keys);
+        assert_eq!(interpreter.get_register(1), Some(1));
+    }
+
+    #[test]
+    fn disassemble_around_pc_test() {
+        // ld v0, 1; ld i, #050; drw v0, v1, 5; ret; and an invalid opcode
+        let rom = [0x60, 0x01, 0xA0, 0x50, 0xD0, 0x15, 0x00, 0xEE, 0xFF, 0xFF];
+        let mut interpreter = Chip8Interpreter::new(&rom, 700., Quirks::default());
+        interpreter.step_n(2, &[false; 16]);
+
+        assert_eq!(
+            interpreter.disassemble_around_pc(1),
+            "  0x0202  A050  ld i, #050\n> 0x0204  D015  drw v0, v1, 5\n  0x0206  00EE  ret"
+        );
+        assert_eq!(
+            interpreter.disassemble_around_pc(0),
+            "> 0x0204  D015  drw v0, v1, 5"
+        );
+
+        let listing = interpreter.disassemble_around(0x208, 8);
+        assert_eq!(listing.lines().count(), 17);
+        assert!(listing.starts_with("  0x01F8"));
+        assert!(listing.contains("> 0x0208  FFFF  db #FF, #FF\n  0x020A  0000  nop"));
+
+        // The listing is cut short at the start and end of memory
+        assert_eq!(interpreter.disassemble_around(0x002, 3).lines().count(), 5);
+        assert_eq!(interpreter.disassemble_around(0xFFE, 3).lines().count(), 4);
+    }
+
+    #[test]
+    fn cycle_count_test() {
+        let keys = [false; 16];
+
+        // ld v0, 1; add v0, 1; jmp #202
+        let mut interpreter = Chip8Interpreter::<MockClock>::with_clock(Chip8Config::new(vec![
+            0x60, 0x01, 0x70, 0x01, 0x12, 0x02,
+        ]));
+        assert_eq!(interpreter.cycle_count(), 0);
+        assert_eq!(interpreter.frame_count(), 0);
+
+        for n in 1..=10 {
+            interpreter.step(&keys);
+            assert_eq!(interpreter.cycle_count(), n);
+            assert_eq!(interpreter.cycles_since_reset(), n);
+        }
+
+        // Each frame is 1/60 of a second, so this is just over 3 frames
+        MockClock::advance(Duration::from_millis(51));
+        interpreter.step(&keys);
+        assert_eq!(interpreter.frame_count(), 3);
+
+        interpreter.reset();
+        assert_eq!(interpreter.get_program_counter(), 0x200);
+        assert_eq!(interpreter.get_register(0), Some(0));
+        assert_eq!(interpreter.cycle_count(), 11);
+        assert_eq!(interpreter.cycles_since_reset(), 0);
+
+        interpreter.step(&keys);
+        assert_eq!(interpreter.cycle_count(), 12);
+        assert_eq!(interpreter.cycles_since_reset(), 1);
+        assert_eq!(interpreter.get_register(0), Some(1));
+    }
+
+    #[cfg(feature = "schip")]
+    #[test]
+    fn graphics_mode_test() {
+        let keys = [false; 16];
+
+        // high; ld v0, 100; ld v1, 60; ld i, #050; drw v0, v1, 5; drw v0, v1, 5; drw v0, v1, 5;
+        // low; drw v0, v1, 5
+        let mut interpreter = Chip8Interpreter::<MockClock>::with_clock(Chip8Config::new(vec![
+            0x00, 0xFF, 0x60, 0x64, 0x61, 0x3C, 0xA0, 0x50, 0xD0, 0x15, 0xD0, 0x15, 0xD0, 0x15,
+            0x00, 0xFE, 0xD0, 0x15,
+        ]));
+        assert_eq!(interpreter.graphics_mode(), GraphicsMode::LowRes);
+        assert_eq!(
+            (interpreter.display_width(), interpreter.display_height()),
+            (64, 32)
+        );
+
+        interpreter.step(&keys);
+        assert_eq!(interpreter.graphics_mode(), GraphicsMode::HighRes);
+        assert_eq!(
+            (interpreter.display_width(), interpreter.display_height()),
+            (128, 64)
+        );
+
+        // The sprite is clipped at the bottom of the 128x64 display, and shrunk into the display
+        // that step returns
+        let display = interpreter.step_n(4, &keys).unwrap();
+        let hires = interpreter.hires_display();
+        assert_eq!(hires[60][100..104], [Pixel::White; 4]);
+        assert_eq!(
+            hires[61][100..104],
+            [Pixel::White, Pixel::Black, Pixel::Black, Pixel::White]
+        );
+        assert_eq!(
+            hires
+                .iter()
+                .flatten()
+                .filter(|&&p| p == Pixel::White)
+                .count(),
+            10
+        );
+        assert_eq!(display, downsample(hires));
+        assert_eq!(display[30][50], Pixel::White);
+        assert_eq!(interpreter.get_register(0xF), Some(0));
+
+        // Drawing the same sprite again erases it
+        interpreter.step(&keys);
+        assert_eq!(interpreter.get_register(0xF), Some(1));
+        assert_eq!(interpreter.hires_display(), &[[Pixel::Black; 128]; 64]);
+        interpreter.step(&keys);
+
+        // Switching mode clears the screen, and the coordinates wrap at 64x32 again
+        interpreter.step(&keys);
+        assert_eq!(interpreter.graphics_mode(), GraphicsMode::LowRes);
+        assert_eq!(interpreter.hires_display(), &[[Pixel::Black; 128]; 64]);
+        assert_eq!(interpreter.last_clear_at_cycle(), Some(7));
+
+        let display = interpreter.step(&keys).unwrap();
+        assert_eq!(display[28][36..40], [Pixel::White; 4]);
+        assert_eq!(interpreter.hires_display(), &[[Pixel::Black; 128]; 64]);
+
+        interpreter.reset();
+        assert_eq!(interpreter.graphics_mode(), GraphicsMode::LowRes);
+    }
+
+    #[test]
+    fn instruction_at_test() {
+        use chip8_instructions::Operand::Literal as Lit;
+        use Instruction as I;
+
+        // cls; ld v0, #12; add v0, v1; invalid; drw v0, v1, 5; jmp #200
+        let rom = [
+            0x00, 0xE0, 0x60, 0x12, 0x80, 0x14, 0xFF, 0xFF, 0xD0, 0x15, 0x12, 0x00,
+        ];
+        let mut interpreter = Chip8Interpreter::new(&rom, 700., Quirks::default());
+
+        assert_eq!(interpreter.next_instruction(), Some(I::ClearScreen));
+        assert_eq!(
+            interpreter.instruction_at(0x202),
+            Some(I::LoadRegister(0, Lit(0x12)))
+        );
+        assert_eq!(interpreter.instruction_at(0x206), None);
+        assert_eq!(interpreter.instruction_at(0xFFE), Some(I::Nop));
+        assert_eq!(interpreter.instruction_at(0xFFF), None);
+        assert_eq!(interpreter.instruction_at(0xFFFF), None);
+
+        assert_eq!(
+            interpreter.instructions_in_range(0x200, 0x20C),
+            [
+                (0x200, I::ClearScreen),
+                (0x202, I::LoadRegister(0, Lit(0x12))),
+                (0x204, I::AddWithCarry(0, 1)),
+                (0x208, I::Draw(0, 1, 5)),
+                (0x20A, I::Jump(0x200)),
+            ]
+        );
+        assert_eq!(
+            interpreter.instructions_in_range(0x202, 0x206),
+            [
+                (0x202, I::LoadRegister(0, Lit(0x12))),
+                (0x204, I::AddWithCarry(0, 1))
+            ]
+        );
+        assert!(interpreter.instructions_in_range(0x20C, 0x20C).is_empty());
+        assert_eq!(interpreter.instructions_in_range(0xFFC, 0xFFFF).len(), 2);
+
+        interpreter.step(&[false; 16]);
+        assert_eq!(
+            interpreter.next_instruction(),
+            Some(I::LoadRegister(0, Lit(0x12)))
+        );
+    }
+
+    #[test]
+    fn audio_enabled_test() {
+        // jmp #200
+        let mut interpreter =
+            Chip8Interpreter::<MockClock>::with_clock(Chip8Config::new(vec![0x12, 0x00]));
+        assert!(interpreter.audio_enabled());
+
+        interpreter.sound_timer.set(10);
+        assert!(interpreter.buzzer_active());
+
+        interpreter.toggle_audio();
+        assert!(!interpreter.audio_enabled());
+        assert!(!interpreter.buzzer_active());
+        assert_eq!(interpreter.audio_sample(), None);
+
+        // The sound timer still counts down at 60 Hz while muted, so this is just over 6 ticks
+        MockClock::advance(Duration::from_millis(101));
+        interpreter.step(&[false; 16]);
+        assert_eq!(interpreter.sound_timer.get(), 4);
+        assert!(!interpreter.buzzer_active());
+
+        interpreter.set_audio_enabled(true);
+        assert!(interpreter.buzzer_active());
+    }
+
+    #[test]
+    fn checksums_test() {
+        let keys = [false; 16];
+
+        // ld v0, #7B; ld i, #200; bcd v0; nop
+        let rom = [0x60, 0x7B, 0xA2, 0x00, 0xF0, 0x33, 0x00, 0x00];
+        let mut interpreter = Chip8Interpreter::new(&rom, 700., Quirks::default());
+        assert_eq!(interpreter.memory_checksum(), checksum::adler32(&rom));
+        assert_eq!(interpreter.display_checksum(), 0);
+        assert_eq!(interpreter.registers_checksum(), 0);
+
+        interpreter.step_n(3, &keys);
+        assert_eq!(
+            interpreter.memory_checksum(),
+            checksum::adler32(&[0x01, 0x02, 0x03, 0x00, 0xF0, 0x33, 0x00, 0x00])
+        );
+        assert_eq!(interpreter.registers_checksum(), 0x7B);
+
+        let memory_checksum = interpreter.memory_checksum();
+        let registers_checksum = interpreter.registers_checksum();
+        interpreter.step(&keys);
+        assert_eq!(interpreter.memory_checksum(), memory_checksum);
+        assert_eq!(interpreter.registers_checksum(), registers_checksum);
+        assert_eq!(interpreter.display_checksum(), 0);
+
+        interpreter.v_registers[1] = 0x7B;
+        assert_eq!(interpreter.registers_checksum(), 0);
+
+        interpreter.display[0][0] = Pixel::White;
+        assert_eq!(interpreter.display_checksum(), 0x8080);
+    }
+
+    #[test]
+    fn register_access_test() {
+        let mut interpreter = Chip8Interpreter::new(&[], 700., Quirks::default());
+
+        assert!(interpreter.set_register(0xA, 0x42));
+        assert_eq!(interpreter.get_register(0xA), Some(0x42));
+        assert!(!interpreter.set_register(16, 0x42));
+        assert_eq!(interpreter.get_register(16), None);
+
+        interpreter.set_memory_register(0x300);
+        assert_eq!(interpreter.get_memory_register(), 0x300);
+
+        assert!(interpreter.set_program_counter(0xFFF));
+        assert_eq!(interpreter.get_program_counter(), 0xFFF);
+        assert!(!interpreter.set_program_counter(0x1000));
+        assert_eq!(interpreter.get_program_counter(), 0xFFF);
+
+        interpreter.set_delay_timer(30);
+        interpreter.set_sound_timer(20);
+        assert_eq!(interpreter.get_delay_timer(), 30);
+        assert_eq!(interpreter.get_sound_timer(), 20);
+        assert_eq!(interpreter.dump_state().dt, 30);
+        assert_eq!(interpreter.dump_state().st, 20);
+    }
+
+    #[test]
+    fn inject_key_event_test() {
+        // ld v0, k
+        let mut interpreter = Chip8Interpreter::new(&[0xF0, 0x0A], 700., Quirks::default());
+        interpreter.inject_key_event(16, true);
+        interpreter.step_with_injected_keys();
+        assert_eq!(interpreter.pending_key_wait(), Some(0));
+
+        interpreter.inject_key_event(5, true);
+        interpreter.inject_key_event(3, true);
+        interpreter.inject_key_event(3, false);
+
+        // The runner's keys override the injected ones for a single step
+        interpreter.step(&[false; 16]);
+        assert_eq!(interpreter.pending_key_wait(), Some(0));
+
+        interpreter.step_with_injected_keys();
+        assert_eq!(interpreter.pending_key_wait(), None);
+        assert_eq!(interpreter.get_register(0), Some(5));
+    }
+
+    #[test]
+    fn display_dirty_test() {
+        use std::sync::atomic::{AtomicU16, Ordering};
+
+        static UPDATES: AtomicU16 = AtomicU16::new(0);
+        let keys = [false; 16];
+
+        // ld v0, 1; draw v0, v0, 5; draw v0, v0, 5; cls
+        let rom = [0x60, 0x01, 0xD0, 0x05, 0xD0, 0x05, 0x00, 0xE0];
+        let mut interpreter = Chip8Interpreter::new(&rom, 700., Quirks::default());
+        interpreter.set_on_display_update(|interpreter| {
+            assert!(interpreter.display_dirty());
+            UPDATES.fetch_add(1, Ordering::Relaxed);
+        });
+
+        interpreter.step(&keys);
+        assert!(!interpreter.display_dirty());
+
+        interpreter.step(&keys);
+        assert!(interpreter.display_dirty());
+        assert_eq!(UPDATES.load(Ordering::Relaxed), 1);
+
+        interpreter.clear_display_dirty();
+        assert!(!interpreter.display_dirty());
+
+        interpreter.step(&keys);
+        assert!(interpreter.display_dirty());
+        assert_eq!(UPDATES.load(Ordering::Relaxed), 2);
+
+        interpreter.step(&keys);
+        assert!(!interpreter.display_dirty());
+        assert_eq!(UPDATES.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn on_memory_access_test() {
+        use std::sync::atomic::{AtomicU16, Ordering};
+
+        static WRITES: AtomicU16 = AtomicU16::new(0);
+        static READS: AtomicU16 = AtomicU16::new(0);
+        let keys = [false; 16];
+
+        // ld i, #300; ld v0, 123; bcd v0; stor v1; rstr v1; drw v0, v0, 4
+        let rom = [
+            0xA3, 0x00, 0x60, 0x7B, 0xF0, 0x33, 0xF1, 0x55, 0xF1, 0x65, 0xD0, 0x04,
+        ];
+        let mut interpreter = Chip8Interpreter::new(&rom, 700., Quirks::default());
+        interpreter.set_on_memory_write(|interpreter, address, value| {
+            assert_eq!(interpreter.memory[address as usize], value);
+            assert!((0x300..0x303).contains(&address));
+            WRITES.fetch_add(1, Ordering::Relaxed);
+        });
+        interpreter.set_on_memory_read(|interpreter, address, value| {
+            assert_eq!(interpreter.memory[address as usize], value);
+            READS.fetch_add(1, Ordering::Relaxed);
+        });
+
+        interpreter.step_n(3, &keys);
+        assert_eq!(WRITES.load(Ordering::Relaxed), 3);
+        assert_eq!(READS.load(Ordering::Relaxed), 0);
+
+        interpreter.step(&keys);
+        assert_eq!(WRITES.load(Ordering::Relaxed), 5);
+
+        interpreter.step(&keys);
+        assert_eq!(READS.load(Ordering::Relaxed), 2);
+
+        interpreter.step(&keys);
+        assert_eq!(READS.load(Ordering::Relaxed), 6);
+        assert_eq!(WRITES.load(Ordering::Relaxed), 5);
+    }
+
+    #[test]
+    fn set_speed_test() {
+        let mut interpreter = Chip8Interpreter::new(&[], 700., Quirks::default());
+
+        interpreter.set_speed(Duration::from_millis(5));
+        assert_eq!(interpreter.speed(), Duration::from_millis(5));
+        assert_eq!(
+            interpreter.timing_stats().target_step_duration,
+            Duration::from_millis(5)
+        );
+
+        interpreter.set_frequency(500.);
+        assert_eq!(interpreter.speed(), Duration::from_millis(2));
+    }
+
+    #[test]
+    #[should_panic(expected = "can't be zero")]
+    fn set_speed_zero_test() {
+        Chip8Interpreter::new(&[], 700., Quirks::default()).set_speed(Duration::ZERO);
+    }
+
+    #[test]
+    #[should_panic(expected = "must be positive")]
+    fn set_frequency_negative_test() {
+        Chip8Interpreter::new(&[], 700., Quirks::default()).set_frequency(-60.);
+    }
+
+    #[test]
+    fn invalid_frequency_test() {
+        for clock_frequency in [0., -60., f32::NAN, f32::INFINITY, 1e20, 1e-30] {
+            assert_eq!(
+                Chip8Interpreter::try_from_config(Chip8Config {
+                    clock_frequency,
+                    ..Chip8Config::new(vec![])
+                })
+                .map(|_| ()),
+                Err(InterpreterError::InvalidFrequency),
+                "{clock_frequency}"
+            );
+        }
+    }
+
+    #[test]
+    fn max_rom_size_test() {
+        // A ROM that exactly fits is fine
+        Chip8Interpreter::from_config(Chip8Config {
+            max_rom_size: 1536,
+            ..Chip8Config::new(vec![0xFF; 1536])
+        });
+
+        // The default allows a ROM that fills the rest of memory
+        Chip8Interpreter::new(&[0xFF; 0xE00], 700., Quirks::default());
+    }
+
+    #[test]
+    #[should_panic(expected = "can be at most 1536 bytes")]
+    fn max_rom_size_too_big_test() {
+        Chip8Interpreter::from_config(Chip8Config {
+            max_rom_size: 1536,
+            ..Chip8Config::new(vec![0xFF; 1537])
+        });
+    }
+
+    #[test]
+    fn execute_instruction_test() {
+        use Instruction as I;
+        use Operand::{Literal as Lit, Register as Reg};
+
+        let no_keys = [false; 16];
+        let new = || Chip8Interpreter::new(&[], 700., Quirks::default());
+
+        // Nop
+        let mut interpreter = new();
+        interpreter.execute_instruction(I::Nop, &no_keys).unwrap();
+        assert_eq!(interpreter.get_program_counter(), 0x200);
+        assert_eq!(interpreter.dump_state(), new().dump_state());
+
+        // ClearScreen
+        let mut interpreter = new();
+        interpreter.display[5][5] = Pixel::White;
+        interpreter
+            .execute_instruction(I::ClearScreen, &no_keys)
+            .unwrap();
+        assert_eq!(interpreter.display, [[Pixel::Black; _]; _]);
+        assert_eq!(interpreter.last_clear_at_cycle(), Some(0));
+
+        // Call and Return
+        let mut interpreter = new();
+        interpreter
+            .execute_instruction(I::Call(0x300), &no_keys)
+            .unwrap();
+        assert_eq!(interpreter.get_program_counter(), 0x300);
+        assert_eq!(interpreter.inspect_stack(), &[0x200]);
+        interpreter
+            .execute_instruction(I::Return, &no_keys)
+            .unwrap();
+        assert_eq!(interpreter.get_program_counter(), 0x200);
+        assert_eq!(interpreter.stack_depth(), 0);
+
+        // Jump and JumpPlusV0
+        let mut interpreter = new();
+        interpreter
+            .execute_instruction(I::Jump(0x400), &no_keys)
+            .unwrap();
+        assert_eq!(interpreter.get_program_counter(), 0x400);
+        interpreter.set_register(0, 0x10);
+        interpreter
+            .execute_instruction(I::JumpPlusV0(0x300), &no_keys)
+            .unwrap();
+        assert_eq!(interpreter.get_program_counter(), 0x310);
+
+        // SkipIfEqual and SkipIfNotEqual, with literals and registers
+        let mut interpreter = new();
+        interpreter.set_register(1, 0x42);
+        interpreter.set_register(2, 0x42);
+        for (instruction, skips) in [
+            (I::SkipIfEqual(1, Lit(0x42)), true),
+            (I::SkipIfEqual(1, Lit(0x43)), false),
+            (I::SkipIfEqual(1, Reg(2)), true),
+            (I::SkipIfEqual(1, Reg(3)), false),
+            (I::SkipIfNotEqual(1, Lit(0x42)), false),
+            (I::SkipIfNotEqual(1, Lit(0x43)), true),
+            (I::SkipIfNotEqual(1, Reg(2)), false),
+            (I::SkipIfNotEqual(1, Reg(3)), true),
+        ] {
+            let pc = interpreter.get_program_counter();
+            interpreter
+                .execute_instruction(instruction, &no_keys)
+                .unwrap();
+            let expected = if skips { pc + 2 } else { pc };
+            assert_eq!(
+                interpreter.get_program_counter(),
+                expected,
+                "{instruction:?}"
+            );
+        }
+
+        // LoadRegister and AddNoCarry
+        let mut interpreter = new();
+        interpreter
+            .execute_instruction(I::LoadRegister(0, Lit(0x3F)), &no_keys)
+            .unwrap();
+        interpreter
+            .execute_instruction(I::LoadRegister(1, Reg(0)), &no_keys)
+            .unwrap();
+        assert_eq!(interpreter.get_register(1), Some(0x3F));
+        interpreter
+            .execute_instruction(I::AddNoCarry(0, 1), &no_keys)
+            .unwrap();
+        assert_eq!(interpreter.get_register(0), Some(0x40));
+        interpreter
+            .execute_instruction(I::AddNoCarry(0, 0xC0), &no_keys)
+            .unwrap();
+        assert_eq!(interpreter.get_register(0), Some(0x00));
+        assert_eq!(interpreter.get_register(0xF), Some(0));
+
+        // Or, And, and Xor
+        let mut interpreter = new();
+        for (instruction, expected) in [
+            (I::Or(0, 1), 0b1110),
+            (I::And(0, 1), 0b1000),
+            (I::Xor(0, 1), 0b0110),
+        ] {
+            interpreter.set_register(0, 0b1100);
+            interpreter.set_register(1, 0b1010);
+            interpreter
+                .execute_instruction(instruction, &no_keys)
+                .unwrap();
+            assert_eq!(
+                interpreter.get_register(0),
+                Some(expected),
+                "{instruction:?}"
+            );
+        }
+
+        // AddWithCarry
+        let mut interpreter = new();
+        interpreter.set_register(0, 0xF0);
+        interpreter.set_register(1, 0x20);
+        interpreter
+            .execute_instruction(I::AddWithCarry(0, 1), &no_keys)
+            .unwrap();
+        assert_eq!(interpreter.get_register(0), Some(0x10));
+        assert_eq!(interpreter.get_register(0xF), Some(1));
+        interpreter
+            .execute_instruction(I::AddWithCarry(0, 1), &no_keys)
+            .unwrap();
+        assert_eq!(interpreter.get_register(0), Some(0x30));
+        assert_eq!(interpreter.get_register(0xF), Some(0));
+
+        // Sub and SubN
+        let mut interpreter = new();
+        interpreter.set_register(0, 0x30);
+        interpreter.set_register(1, 0x10);
+        interpreter
+            .execute_instruction(I::Sub(0, 1), &no_keys)
+            .unwrap();
+        assert_eq!(interpreter.get_register(0), Some(0x20));
+        assert_eq!(interpreter.get_register(0xF), Some(1));
+        interpreter
+            .execute_instruction(I::SubN(0, 1), &no_keys)
+            .unwrap();
+        assert_eq!(interpreter.get_register(0), Some(0xF0));
+        assert_eq!(interpreter.get_register(0xF), Some(0));
+
+        // VF is 1 when there's no borrow, including when the registers are equal
+        for (instruction, vx, vy, result, flag) in [
+            (I::Sub(0, 1), 0x10, 0x30, 0xE0, 0),
+            (I::Sub(0, 1), 0x30, 0x30, 0x00, 1),
+            (I::SubN(0, 1), 0x10, 0x30, 0x20, 1),
+            (I::SubN(0, 1), 0x30, 0x30, 0x00, 1),
+        ] {
+            interpreter.set_register(0, vx);
+            interpreter.set_register(1, vy);
+            interpreter
+                .execute_instruction(instruction, &no_keys)
+                .unwrap();
+            assert_eq!(interpreter.get_register(0), Some(result), "{instruction:?}");
+            assert_eq!(interpreter.get_register(0xF), Some(flag), "{instruction:?}");
+        }
+
+        // When the result goes in VF, the flag overwrites it
+        interpreter.set_register(0xF, 0x30);
+        interpreter.set_register(1, 0x10);
+        interpreter
+            .execute_instruction(I::Sub(0xF, 1), &no_keys)
+            .unwrap();
+        assert_eq!(interpreter.get_register(0xF), Some(1));
+        interpreter.set_register(0xF, 0x30);
+        interpreter
+            .execute_instruction(I::SubN(0xF, 1), &no_keys)
+            .unwrap();
+        assert_eq!(interpreter.get_register(0xF), Some(0));
+
+        // ShiftRight and ShiftLeft
+        let mut interpreter = new();
+        interpreter.set_register(0, 0b1000_0011);
+        interpreter
+            .execute_instruction(I::ShiftRight(0, 0), &no_keys)
+            .unwrap();
+        assert_eq!(interpreter.get_register(0), Some(0b0100_0001));
+        assert_eq!(interpreter.get_register(0xF), Some(1));
+        interpreter
+            .execute_instruction(I::ShiftLeft(0, 0), &no_keys)
+            .unwrap();
+        assert_eq!(interpreter.get_register(0), Some(0b1000_0010));
+        assert_eq!(interpreter.get_register(0xF), Some(0));
+        interpreter
+            .execute_instruction(I::ShiftLeft(0, 0), &no_keys)
+            .unwrap();
+        assert_eq!(interpreter.get_register(0), Some(0b0000_0100));
+        assert_eq!(interpreter.get_register(0xF), Some(1));
+
+        // LoadMemoryRegister and AddToMemoryRegister
+        let mut interpreter = new();
+        interpreter
+            .execute_instruction(I::LoadMemoryRegister(0x300), &no_keys)
+            .unwrap();
+        assert_eq!(interpreter.get_memory_register(), 0x300);
+        interpreter.set_register(4, 0x20);
+        interpreter
+            .execute_instruction(I::AddToMemoryRegister(4), &no_keys)
+            .unwrap();
+        assert_eq!(interpreter.get_memory_register(), 0x320);
+
+        // LoadRandomWithMask
+        let mut interpreter = new();
+        for _ in 0..20 {
+            interpreter
+                .execute_instruction(I::LoadRandomWithMask(0, 0x0F), &no_keys)
+                .unwrap();
+            assert!(interpreter.get_register(0).unwrap() <= 0x0F);
+        }
+        interpreter
+            .execute_instruction(I::LoadRandomWithMask(0, 0), &no_keys)
+            .unwrap();
+        assert_eq!(interpreter.get_register(0), Some(0));
+
+        // Draw
+        let mut interpreter = new();
+        interpreter.set_memory_register(FONT_ADDRESS_START as u16);
+        interpreter
+            .execute_instruction(I::Draw(0, 0, 5), &no_keys)
+            .unwrap();
+        assert_eq!(interpreter.display[0][..4], [Pixel::White; 4]);
+        assert_eq!(interpreter.get_register(0xF), Some(0));
+        interpreter
+            .execute_instruction(I::Draw(0, 0, 5), &no_keys)
+            .unwrap();
+        assert_eq!(interpreter.display, [[Pixel::Black; _]; _]);
+        assert_eq!(interpreter.get_register(0xF), Some(1));
+
+        // SkipIfKeyPressed and SkipIfKeyNotPressed
+        let mut interpreter = new();
+        let mut keys = [false; 16];
+        keys[7] = true;
+        interpreter.set_register(0, 7);
+        interpreter
+            .execute_instruction(I::SkipIfKeyPressed(0), &keys)
+            .unwrap();
+        assert_eq!(interpreter.get_program_counter(), 0x202);
+        interpreter
+            .execute_instruction(I::SkipIfKeyNotPressed(0), &keys)
+            .unwrap();
+        assert_eq!(interpreter.get_program_counter(), 0x202);
+        interpreter
+            .execute_instruction(I::SkipIfKeyPressed(0), &no_keys)
+            .unwrap();
+        assert_eq!(interpreter.get_program_counter(), 0x202);
+        interpreter
+            .execute_instruction(I::SkipIfKeyNotPressed(0), &no_keys)
+            .unwrap();
+        assert_eq!(interpreter.get_program_counter(), 0x204);
+
+        // WaitForKeyPress
+        let mut interpreter = new();
+        interpreter
+            .execute_instruction(I::WaitForKeyPress(3), &no_keys)
+            .unwrap();
+        assert_eq!(interpreter.pending_key_wait(), Some(3));
+
+        // LoadIntoDelayTimer, LoadIntoSoundTimer, and LoadFromDelayTimer
+        let mut interpreter = new();
+        interpreter.set_register(0, 30);
+        interpreter.set_register(1, 20);
+        interpreter
+            .execute_instruction(I::LoadIntoDelayTimer(0), &no_keys)
+            .unwrap();
+        interpreter
+            .execute_instruction(I::LoadIntoSoundTimer(1), &no_keys)
+            .unwrap();
+        assert_eq!(interpreter.get_delay_timer(), 30);
+        assert_eq!(interpreter.get_sound_timer(), 20);
+        interpreter
+            .execute_instruction(I::LoadFromDelayTimer(2), &no_keys)
+            .unwrap();
+        assert_eq!(interpreter.get_register(2), Some(30));
+
+        // LoadDigitAddress
+        let mut interpreter = new();
+        interpreter.set_register(0, 0xA);
+        interpreter
+            .execute_instruction(I::LoadDigitAddress(0), &no_keys)
+            .unwrap();
+        assert_eq!(
+            interpreter.get_memory_register(),
+            FONT_ADDRESS_START as u16 + 50
+        );
+
+        // StoreBcdInMemory
+        let mut interpreter = new();
+        interpreter.set_register(0, 254);
+        interpreter.set_memory_register(0x300);
+        interpreter
+            .execute_instruction(I::StoreBcdInMemory(0), &no_keys)
+            .unwrap();
+        assert_eq!(interpreter.memory[0x300..0x303], [2, 5, 4]);
+
+        // StoreRegistersInMemory and ReadRegistersFromMemory
+        let mut interpreter = new();
+        for reg in 0..=3 {
+            interpreter.set_register(reg, reg + 10);
+        }
+        interpreter.set_memory_register(0x300);
+        interpreter
+            .execute_instruction(I::StoreRegistersInMemory(2), &no_keys)
+            .unwrap();
+        assert_eq!(interpreter.memory[0x300..0x304], [10, 11, 12, 0]);
+        assert_eq!(interpreter.get_memory_register(), 0x303);
+        interpreter.memory[0x300..0x304].copy_from_slice(&[1, 2, 3, 4]);
+        interpreter.set_memory_register(0x300);
+        interpreter
+            .execute_instruction(I::ReadRegistersFromMemory(2), &no_keys)
+            .unwrap();
+        assert_eq!(interpreter.v_registers[..4], [1, 2, 3, 13]);
+        assert_eq!(interpreter.get_program_counter(), 0x200);
+    }
+
+    #[cfg(feature = "display-effects")]
+    #[test]
+    fn display_persistence_test() {
+        let keys = [false; 16];
+
+        // ld i, #50; draw v0, v0, 1; draw v0, v0, 1; ld v1, 0; jmp #206
+        let rom = vec![0xA0, 0x50, 0xD0, 0x01, 0xD0, 0x01, 0x61, 0x00, 0x12, 0x06];
+        let mut interpreter = Chip8Interpreter::<MockClock>::with_clock(Chip8Config {
+            display_persistence: 0.9,
+            ..Chip8Config::new(rom)
+        });
+
+        interpreter.step(&keys);
+        let display = interpreter.step(&keys).unwrap();
+        assert_eq!(display[0][..4], [Pixel::White; 4]);
+        assert_eq!(display[0][4], Pixel::Black);
+        assert_eq!(interpreter.ghost_display()[0][0], 1.);
+
+        // The sprite has been erased, but hasn't had any time to fade yet
+        let display = interpreter.step(&keys).unwrap();
+        assert_eq!(interpreter.display[0][0], Pixel::Black);
+        assert_eq!(display[0][0], Pixel::White);
+
+        // 6 frames leaves 0.9^6 = 0.53 of the brightness
+        MockClock::advance(Duration::from_millis(100));
+        let display = interpreter.step(&keys).unwrap();
+        assert!((interpreter.ghost_display()[0][0] - 0.9f32.powi(6)).abs() < 1e-3);
+        assert_eq!(display[0][0], Pixel::White);
+
+        MockClock::advance(Duration::from_millis(100));
+        let display = interpreter.step(&keys).unwrap();
+        assert_eq!(display[0][0], Pixel::Black);
+
+        // With no persistence, pixels turn off straight away
+        interpreter.set_display_persistence(0.);
+        interpreter.ghost_display[0][0] = 1.;
+        let display = interpreter.step(&keys).unwrap();
+        assert_eq!(display, interpreter.display);
+    }
+
+    #[cfg(feature = "display-effects")]
+    #[test]
+    #[should_panic(expected = "between 0.0 and 1.0")]
+    fn display_persistence_range_test() {
+        Chip8Interpreter::new(&[], 700., Quirks::default()).set_display_persistence(1.5);
+    }
+
+    #[test]
+    fn screen_hash_test() {
+        let keys = [false; 16];
+
+        // ld i, #50; draw v0, v0, 5; ld v1, 1; cls
+        let rom = [0xA0, 0x50, 0xD0, 0x05, 0x61, 0x01, 0x00, 0xE0];
+        let mut interpreter = Chip8Interpreter::new(&rom, 700., Quirks::default());
+        assert_eq!(interpreter.screen_hash(), BLANK_SCREEN_HASH);
+
+        interpreter.step_n(2, &keys);
+        let drawn_state = interpreter.dump_state();
+        let hash_after_draw = interpreter.screen_hash();
+        assert_ne!(hash_after_draw, BLANK_SCREEN_HASH);
+        assert_eq!(hash_after_draw, interpreter.display.screen_hash());
+        assert!(interpreter.screen_changed_since(BLANK_SCREEN_HASH));
+
+        interpreter.step(&keys);
+        assert!(!interpreter.screen_changed_since(hash_after_draw));
+
+        interpreter.step(&keys);
+        assert_eq!(interpreter.screen_hash(), BLANK_SCREEN_HASH);
+
+        interpreter.restore_state(drawn_state).unwrap();
+        assert_eq!(interpreter.screen_hash(), hash_after_draw);
+    }
+
+    #[test]
+    fn dump_and_restore_state_test() {
+        let keys = [false; 16];
+
+        // ld v3, #42; ld i, #300; call #208; nop; draw v0, v0, 5
+        let rom = [0x63, 0x42, 0xA3, 0x00, 0x22, 0x08, 0x00, 0x00, 0xD0, 0x05];
+        let mut interpreter = Chip8Interpreter::new(&rom, 700., Quirks::default());
+        interpreter.memory[0x300..0x305].fill(0xFF);
+        interpreter.step_n(4, &keys);
+
+        let state = interpreter.dump_state();
+        assert_eq!(state.v[3], 0x42);
+        assert_eq!(state.i, 0x300);
+        assert_eq!(state.pc, 0x20A);
+        assert_eq!(state.sp, 1);
+        assert_eq!(state.stack[0], 0x206);
+        assert!(state.display[4][7]);
+        assert!(!state.display[5][0]);
+
+        let mut restored = Chip8Interpreter::new(&[], 700., Quirks::default());
+        restored.restore_state(state.clone()).unwrap();
+        assert_eq!(restored.dump_state(), state);
+        assert_eq!(restored.display, interpreter.display);
+
+        // Restoring stops waiting for a key press and un-halts the interpreter
+        // ld v0, k; jmp #202
+        let mut waiting = Chip8Interpreter::new(&[0xF0, 0x0A, 0x12, 0x02], 700., Quirks::default());
+        waiting.step(&keys);
+        assert_eq!(waiting.pending_key_wait(), Some(0));
+        waiting.restore_state(state.clone()).unwrap();
+        assert_eq!(waiting.pending_key_wait(), None);
+        assert_eq!(waiting.key_wait_duration(), None);
+        assert_eq!(waiting.dump_state(), state);
+
+        let mut halted = Chip8Interpreter::new(&[0x12, 0x00], 700., Quirks::default());
+        halted.step(&keys);
+        assert!(halted.is_halted());
+        halted.restore_state(state.clone()).unwrap();
+        assert!(!halted.is_halted());
+
+        // Snapshots that don't fit in the interpreter are rejected without changing anything
+        let mut bad_state = state.clone();
+        bad_state.sp = STACK_DEPTH as u8 + 1;
+        assert_eq!(
+            restored.restore_state(bad_state),
+            Err(RestoreStateError::StackPointerTooBig {
+                sp: STACK_DEPTH as u8 + 1,
+                max: STACK_DEPTH
+            })
+        );
+        let mut bad_state = state.clone();
+        bad_state.pc = 0x1000;
+        bad_state.v[3] = 0;
+        assert_eq!(
+            restored.restore_state(bad_state),
+            Err(RestoreStateError::ProgramCounterOutOfBounds { pc: 0x1000 })
+        );
+        assert_eq!(restored.dump_state(), state);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_state_test() {
+        let mut interpreter = Chip8Interpreter::new(&[0x60, 0x01], 700., Quirks::default());
+        interpreter.step(&[false; 16]);
+        interpreter.display[31][63] = Pixel::White;
+
+        let state = interpreter.dump_state();
+        let bytes = bincode::serialize(&state).unwrap();
+        assert_eq!(
+            bincode::deserialize::<InterpreterState>(&bytes).unwrap(),
+            state
+        );
+    }
+
+    #[test]
+    fn entry_point_test() {
+        let interpreter = Chip8Interpreter::new(&[0x12, 0x00], 700., Quirks::default());
+        assert_eq!(interpreter.rom_entry_point(), 0x200);
+
+        // ld v0, 5; jmp #302
+        let mut interpreter = Chip8Interpreter::from_config(Chip8Config {
+            entry_point: 0x300,
+            ..Chip8Config::new(vec![0x60, 0x05, 0x13, 0x02])
+        });
+        assert_eq!(interpreter.rom_entry_point(), 0x300);
+        assert_eq!(interpreter.memory_layout().program_start, 0x300);
+        assert_eq!(interpreter.memory[0x200], 0);
+
+        interpreter.step_n(2, &[false; 16]);
+        assert_eq!(interpreter.v_registers[0], 5);
+        assert!(interpreter.is_halted());
+    }
+
+    #[test]
+    fn timing_stats_test() {
+        let keys = [false; 16];
+
+        // cls; draw v0, v0, 5; ld v0, 1; jmp #206
+        let rom = [0x00, 0xE0, 0xD0, 0x05, 0x60, 0x01, 0x12, 0x06];
+        let mut interpreter = Chip8Interpreter::new(&rom, 700., Quirks::default());
+        assert_eq!(interpreter.timing_stats().steps_completed, 0);
+        assert_eq!(
+            interpreter.timing_stats().target_step_duration,
+            interpreter.speed()
+        );
+
+        for steps in 1..=3 {
+            interpreter.step(&keys);
+            assert_eq!(interpreter.timing_stats().steps_completed, steps);
+        }
+        assert_eq!(interpreter.timing_stats().frames_completed, 2);
+
+        // Steps after halting don't count
+        interpreter.step_n(10, &keys);
+        assert!(interpreter.is_halted());
+        assert_eq!(interpreter.timing_stats().steps_completed, 4);
+    }
+
+    #[test]
+    fn last_executed_instruction_test() {
+        let keys = [false; 16];
+
+        // ld v3, 1; ld v5, 2; draw v3, v5, 4; #FFFF
+        let rom = [0x63, 0x01, 0x65, 0x02, 0xD3, 0x54, 0xFF, 0xFF];
+        let mut interpreter = Chip8Interpreter::new(&rom, 700., Quirks::default());
+        interpreter.set_on_unimplemented(|_| OnUnimplementedAction::Skip);
+        assert_eq!(interpreter.last_executed_instruction(), None);
+        assert_eq!(interpreter.last_executed_pc(), None);
+
+        interpreter.step_n(3, &keys);
+        assert_eq!(
+            interpreter.last_executed_instruction(),
+            Some(Instruction::Draw(3, 5, 4))
+        );
+        assert_eq!(interpreter.last_executed_pc(), Some(0x204));
+
+        // Unrecognised opcodes aren't executed
+        interpreter.step(&keys);
+        assert_eq!(interpreter.last_executed_pc(), Some(0x204));
+    }
+
+    #[test]
+    fn apply_patch_test() {
+        let keys = [false; 16];
+
+        // jmp #204; ld v0, 1; jmp #204
+        let rom = [0x12, 0x04, 0x60, 0x01, 0x12, 0x04];
+        let mut interpreter = Chip8Interpreter::new(&rom, 700., Quirks::default());
+
+        // Redirect the first jump to the ld instruction
+        interpreter
+            .apply_instruction_patch(0x200, Instruction::Jump(0x202))
+            .unwrap();
+        interpreter.step_n_or_halt(10, &keys);
+        assert_eq!(interpreter.v_registers[0], 1);
+
+        interpreter.revert_patch(&[0x12, 0x04], 0x200).unwrap();
+        assert_eq!(interpreter.memory[0x200..0x202], [0x12, 0x04]);
+
+        assert_eq!(interpreter.apply_patch(0xFFE, &[0xAB, 0xCD]), Ok(()));
+        assert_eq!(
+            interpreter.apply_patch(0xFFF, &[0xAB, 0xCD]),
+            Err(PatchError::OutOfBounds {
+                addr: 0xFFF,
+                len: 2
+            })
+        );
+        assert_eq!(interpreter.memory[0xFFE..], [0xAB, 0xCD]);
+        assert!(matches!(
+            interpreter.apply_instruction_patch(0x200, Instruction::Jump(0x1000)),
+            Err(PatchError::Encoding(_))
+        ));
+    }
+
+    #[test]
+    fn screen_output_test() {
+        // ld v0, 0; font v0; draw v0, v0, 5
+        let rom = [0x60, 0x00, 0xF0, 0x29, 0xD0, 0x05];
+        let mut interpreter = Chip8Interpreter::new(&rom, 700., Quirks::default());
+        let display = interpreter.step_n(3, &[false; 16]);
+
+        let output = interpreter.screen_output();
+        assert_eq!(Some(output.display), display);
+        assert_eq!(output.color_scheme, ColorScheme::classic());
+
+        let green = ColorScheme {
+            foreground: [0x33, 0xFF, 0x33],
+            background: [0x00, 0x00, 0x00],
+        };
+        interpreter.set_color_scheme(green);
+        assert_eq!(interpreter.screen_output().color_scheme, green);
+    }
+
+    #[test]
+    fn key_map_test() {
+        // ld v3, k; jmp #202
+        let mut interpreter = Chip8Interpreter::from_config(Chip8Config {
+            key_map: KeyMap::azerty(),
+            ..Chip8Config::new(vec![0xF3, 0x0A, 0x12, 0x02])
+        });
+        assert_eq!(interpreter.key_map(), &KeyMap::azerty());
+
+        // The runner reports the key labelled A as key 7, which is key 4 on AZERTY
+        let mut keys = [false; 16];
+        interpreter.step(&keys);
+        keys[0x7] = true;
+        interpreter.step(&keys);
+        assert_eq!(interpreter.reg(3), 0x4);
+
+        // try_step takes CHIP-8 keys directly
+        interpreter.set_key_map(KeyMap::empty());
+        interpreter.program_counter = 0x200;
+        interpreter.try_step(&[false; 16]).unwrap();
+        interpreter.try_step(&keys).unwrap();
+        assert_eq!(interpreter.reg(3), 0x7);
+    }
+
+    #[test]
+    #[cfg(feature = "sha2")]
+    fn rom_hash_test() {
+        let rom = [0x60, 0x01, 0x12, 0x02];
+        let mut interpreter = Chip8Interpreter::new(&rom, 700., Quirks::default());
+        assert_eq!(interpreter.rom_hash(), crate::rom_hash::hash_rom(&rom));
+
+        interpreter.apply_patch(0x200, &[0x60, 0x02]).unwrap();
+        assert_eq!(interpreter.rom_hash(), crate::rom_hash::hash_rom(&rom));
+    }
+
+    #[test]
+    fn breakpoint_test() {
+        use std::sync::atomic::{AtomicU16, Ordering};
+
+        // ld v0, 1; ld v1, 2; jmp #200
+        let mut interpreter = Chip8Interpreter::new(
+            &[0x60, 0x01, 0x61, 0x02, 0x12, 0x00],
+            700.,
+            Quirks::default(),
+        );
+        let keys = [false; 16];
+
+        interpreter.add_breakpoint(0x202);
+        interpreter.add_breakpoint(0x1000);
+        assert!(interpreter.has_breakpoint(0x202));
+        assert!(!interpreter.has_breakpoint(0x200));
+        assert!(!interpreter.has_breakpoint(0x1000));
+
+        interpreter.step(&keys);
+        assert_eq!(interpreter.breakpoint_reached(), None);
+        interpreter.step(&keys);
+        assert_eq!(interpreter.breakpoint_reached(), Some(0x202));
+        assert_eq!(interpreter.reg(1), 2);
+        interpreter.step(&keys);
+        assert_eq!(interpreter.breakpoint_reached(), None);
+
+        // The handler can't capture anything, so it records the address in a static
+        static REACHED: AtomicU16 = AtomicU16::new(0);
+        interpreter.set_on_breakpoint(|_, addr| REACHED.store(addr, Ordering::Relaxed));
+        interpreter.step_n(2, &keys);
+        assert_eq!(REACHED.load(Ordering::Relaxed), 0x202);
+
+        assert!(interpreter.remove_breakpoint(0x202));
+        assert!(!interpreter.remove_breakpoint(0x202));
+        interpreter.step_n(3, &keys);
+        assert_eq!(interpreter.breakpoint_reached(), None);
     }
 }
@@ -0,0 +1,101 @@
+//! This module contains the [`InterpreterState`] type, which is a complete snapshot of an
+//! interpreter that can be saved and restored.
+
+use super::memory::{MEMORY_SIZE, STACK_DEPTH};
+use crate::display::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+/// A complete snapshot of the state of an interpreter, for bug reports and test oracles.
+///
+/// The display uses `bool` for each pixel, where `true` means white. With the `serde` feature,
+/// this type can be serialized and deserialized.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct InterpreterState {
+    /// The general purpose registers V0 to VF.
+    pub v: [u8; 16],
+
+    /// The `I` register.
+    pub i: u16,
+
+    /// The program counter.
+    pub pc: u16,
+
+    /// The stack pointer.
+    pub sp: u8,
+
+    /// The whole stack, including any slots above the stack pointer.
+    pub stack: [u16; STACK_DEPTH],
+
+    /// The delay timer.
+    pub dt: u8,
+
+    /// The sound timer.
+    pub st: u8,
+
+    /// All the memory of the interpreter.
+    #[cfg_attr(feature = "serde", serde(with = "serde_memory"))]
+    pub memory: Box<[u8; MEMORY_SIZE]>,
+
+    /// The display, where `true` means a white pixel.
+    #[cfg_attr(feature = "serde", serde(with = "serde_display"))]
+    pub display: [[bool; DISPLAY_WIDTH]; DISPLAY_HEIGHT],
+}
+
+/// Serde only supports arrays of up to 32 elements, so we serialize the memory as a sequence.
+#[cfg(feature = "serde")]
+mod serde_memory {
+    use super::MEMORY_SIZE;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        memory: &[u8; MEMORY_SIZE],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(memory)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Box<[u8; MEMORY_SIZE]>, D::Error> {
+        let memory = Vec::<u8>::deserialize(deserializer)?;
+        let len = memory.len();
+        memory
+            .into_boxed_slice()
+            .try_into()
+            .map_err(|_| D::Error::invalid_length(len, &"4096 bytes of memory"))
+    }
+}
+
+/// Serde only supports arrays of up to 32 elements, so we serialize the display as a sequence of
+/// rows.
+#[cfg(feature = "serde")]
+mod serde_display {
+    use super::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    type BoolDisplay = [[bool; DISPLAY_WIDTH]; DISPLAY_HEIGHT];
+
+    pub fn serialize<S: Serializer>(
+        display: &BoolDisplay,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(display.iter().map(|row| &row[..]))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<BoolDisplay, D::Error> {
+        let rows = Vec::<Vec<bool>>::deserialize(deserializer)?;
+        if rows.len() != DISPLAY_HEIGHT {
+            return Err(D::Error::invalid_length(rows.len(), &"32 rows"));
+        }
+
+        let mut display = [[false; DISPLAY_WIDTH]; DISPLAY_HEIGHT];
+        for (display_row, row) in display.iter_mut().zip(rows) {
+            *display_row = row
+                .try_into()
+                .map_err(|row: Vec<bool>| D::Error::invalid_length(row.len(), &"64 pixels"))?;
+        }
+        Ok(display)
+    }
+}
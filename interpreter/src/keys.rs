@@ -0,0 +1,315 @@
+//! This module provides constructors for [`Keys`], which make it easier to build key states in
+//! tests and other programmatic input sources, and the [`KeyMap`] type for keyboard layouts.
+
+use chip8_base::Keys;
+use std::fmt;
+use thiserror::Error;
+
+/// An error returned when a string contains something other than hex digits for keys.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Error)]
+#[error("{0:?} is not a CHIP-8 key, which must be a hex digit")]
+pub struct InvalidKeyError(pub char);
+
+/// Extra constructors and conversions for [`Keys`], which is a foreign type alias and can't have
+/// inherent methods.
+pub trait KeysExt: Sized {
+    /// Build the keys from a bitmask, where bit 0 is key 0 and bit 15 is key F.
+    fn from_bitmask(bitmask: u16) -> Self;
+
+    /// Convert the keys to a bitmask, where bit 0 is key 0 and bit 15 is key F.
+    fn to_bitmask(&self) -> u16;
+
+    /// Build the keys with exactly the given keys pressed. Any key numbers above 0xF are ignored.
+    fn from_pressed_keys(keys: impl IntoIterator<Item = u8>) -> Self;
+
+    /// Build the keys from a string of hex digits, where each digit is a pressed key. For
+    /// example, `"A"` means that only key A is pressed, and `"05"` means keys 0 and 5 are pressed.
+    fn try_from_hex_str(string: &str) -> Result<Self, InvalidKeyError>;
+
+    /// Iterate over the numbers of the keys that are pressed, in ascending order.
+    fn pressed_keys(&self) -> impl Iterator<Item = u8>;
+}
+
+impl KeysExt for Keys {
+    fn from_bitmask(bitmask: u16) -> Self {
+        let mut keys = [false; 16];
+        for (idx, key) in keys.iter_mut().enumerate() {
+            *key = bitmask & (1 << idx) != 0;
+        }
+        keys
+    }
+
+    fn to_bitmask(&self) -> u16 {
+        self.iter()
+            .enumerate()
+            .filter(|(_, &pressed)| pressed)
+            .fold(0, |bitmask, (idx, _)| bitmask | (1 << idx))
+    }
+
+    fn from_pressed_keys(keys: impl IntoIterator<Item = u8>) -> Self {
+        let mut pressed = [false; 16];
+        for key in keys {
+            if let Some(slot) = pressed.get_mut(key as usize) {
+                *slot = true;
+            }
+        }
+        pressed
+    }
+
+    fn try_from_hex_str(string: &str) -> Result<Self, InvalidKeyError> {
+        let keys = string
+            .chars()
+            .map(|c| {
+                c.to_digit(16)
+                    .map(|key| key as u8)
+                    .ok_or(InvalidKeyError(c))
+            })
+            .collect::<Result<Vec<u8>, _>>()?;
+        Ok(Self::from_pressed_keys(keys))
+    }
+
+    fn pressed_keys(&self) -> impl Iterator<Item = u8> {
+        self.iter()
+            .enumerate()
+            .filter(|(_, &pressed)| pressed)
+            .map(|(idx, _)| idx as u8)
+    }
+}
+
+/// A wrapper around [`Keys`] that formats and serializes them as a `u16` bitmask, where bit 0 is
+/// key 0 and bit 15 is key F.
+///
+/// [`Keys`] is a foreign type alias, so we can't implement these traits for it directly.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct KeysBitmask(pub Keys);
+
+impl From<Keys> for KeysBitmask {
+    fn from(keys: Keys) -> Self {
+        Self(keys)
+    }
+}
+
+impl From<KeysBitmask> for Keys {
+    fn from(bitmask: KeysBitmask) -> Self {
+        bitmask.0
+    }
+}
+
+impl fmt::Binary for KeysBitmask {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Binary::fmt(&self.0.to_bitmask(), f)
+    }
+}
+
+impl fmt::UpperHex for KeysBitmask {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::UpperHex::fmt(&self.0.to_bitmask(), f)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for KeysBitmask {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u16(self.0.to_bitmask())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for KeysBitmask {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        u16::deserialize(deserializer).map(|bitmask| Self(Keys::from_bitmask(bitmask)))
+    }
+}
+
+/// The physical keys that `chip8_base`'s runner reads for each CHIP-8 key, as lowercase ASCII.
+/// These are the keys of [`KeyMap::qwerty`].
+const RUNNER_LAYOUT: [u8; 16] = *b"x123qweasdzc4rfv";
+
+/// A mapping from physical keys to CHIP-8 keys.
+///
+/// Physical keys are identified by the lowercase ASCII code of the character on them, so the key
+/// labelled Q is `b'q'`. The layouts here all put the CHIP-8 keypad on the left of the keyboard:
+///
+/// ```text
+/// 1 2 3 C      1 2 3 4
+/// 4 5 6 D  ->  Q W E R
+/// 7 8 9 E      A S D F
+/// A 0 B F      Z X C V
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyMap {
+    /// The CHIP-8 key for each physical key, or `None` if the physical key isn't mapped.
+    pub mapping: [Option<u8>; 256],
+}
+
+impl Default for KeyMap {
+    /// The standard layout for a QWERTY keyboard.
+    fn default() -> Self {
+        Self::qwerty()
+    }
+}
+
+impl KeyMap {
+    /// Create a key map with no keys mapped.
+    pub fn empty() -> Self {
+        Self {
+            mapping: [None; 256],
+        }
+    }
+
+    /// Create a key map from the physical keys for CHIP-8 keys 0 to F in order.
+    fn from_layout(layout: &[u8; 16]) -> Self {
+        let mut map = Self::empty();
+        for (key, &code) in layout.iter().enumerate() {
+            map.set(code, Some(key as u8));
+        }
+        map
+    }
+
+    /// The standard layout for a QWERTY keyboard, which is the one that `chip8_base` uses.
+    pub fn qwerty() -> Self {
+        Self::from_layout(&RUNNER_LAYOUT)
+    }
+
+    /// The standard layout moved to the same physical keys on an AZERTY keyboard.
+    pub fn azerty() -> Self {
+        Self::from_layout(b"x123azeqsdwc4rfv")
+    }
+
+    /// Get the CHIP-8 key for the given physical key, if it's mapped.
+    pub fn get(&self, code: u8) -> Option<u8> {
+        self.mapping[code as usize]
+    }
+
+    /// Map the physical key to the given CHIP-8 key, or unmap it with `None`.
+    pub fn set(&mut self, code: u8, key: Option<u8>) {
+        self.mapping[code as usize] = key;
+    }
+
+    /// Build the state of the CHIP-8 keys from the physical keys that are pressed.
+    pub fn keys_from_codes(&self, codes: impl IntoIterator<Item = u8>) -> Keys {
+        Keys::from_pressed_keys(codes.into_iter().filter_map(|code| self.get(code)))
+    }
+
+    /// Translate keys that were read by `chip8_base`'s runner, which always uses the QWERTY
+    /// layout, into keys for this layout.
+    pub fn translate(&self, keys: &Keys) -> Keys {
+        self.keys_from_codes(
+            RUNNER_LAYOUT
+                .iter()
+                .zip(keys)
+                .filter(|(_, &pressed)| pressed)
+                .map(|(&code, _)| code),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bitmask_roundtrip_test() {
+        let keys = Keys::from_bitmask(0b0000_0000_0000_0001);
+        assert!(keys[0]);
+        assert_eq!(keys.iter().filter(|&&pressed| pressed).count(), 1);
+
+        let keys = Keys::from_bitmask(0b1000_0100_0000_0010);
+        assert!(keys[0x1] && keys[0xA] && keys[0xF]);
+        assert_eq!(keys.iter().filter(|&&pressed| pressed).count(), 3);
+
+        for bitmask in [0, 1, 0x8000, 0xFFFF, 0b1010_0101_1100_0011] {
+            assert_eq!(Keys::from_bitmask(bitmask).to_bitmask(), bitmask);
+        }
+    }
+
+    #[test]
+    fn from_pressed_keys_test() {
+        assert_eq!(Keys::from_pressed_keys([]).to_bitmask(), 0);
+        assert_eq!(Keys::from_pressed_keys([0, 0xF]).to_bitmask(), 0x8001);
+        assert_eq!(Keys::from_pressed_keys([3, 3, 0x10]).to_bitmask(), 0b1000);
+    }
+
+    #[test]
+    fn try_from_hex_str_test() {
+        assert_eq!(
+            Keys::try_from_hex_str("A").map(|keys| keys.to_bitmask()),
+            Ok(1 << 0xA)
+        );
+        assert_eq!(
+            Keys::try_from_hex_str("05f").map(|keys| keys.to_bitmask()),
+            Ok(0x8021)
+        );
+        assert_eq!(
+            Keys::try_from_hex_str("").map(|keys| keys.to_bitmask()),
+            Ok(0)
+        );
+        assert_eq!(Keys::try_from_hex_str("1G"), Err(InvalidKeyError('G')));
+    }
+
+    #[test]
+    fn pressed_keys_test() {
+        assert_eq!(Keys::from_bitmask(0).pressed_keys().count(), 0);
+        assert_eq!(
+            Keys::from_bitmask(0x8421)
+                .pressed_keys()
+                .collect::<Vec<_>>(),
+            [0x0, 0x5, 0xA, 0xF]
+        );
+        assert_eq!(Keys::from_bitmask(0xFFFF).pressed_keys().count(), 16);
+    }
+
+    #[test]
+    fn keys_bitmask_fmt_test() {
+        let keys = KeysBitmask(Keys::from_pressed_keys([0x1, 0xA, 0xF]));
+        assert_eq!(format!("{keys:b}"), "1000010000000010");
+        assert_eq!(format!("{keys:#06X}"), "0x8402");
+        assert_eq!(format!("{:X}", KeysBitmask::default()), "0");
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn keys_bitmask_serde_test() {
+        for bitmask in [0, 1, 0x8000, 0xFFFF, 0b1010_0101_1100_0011] {
+            let keys = KeysBitmask(Keys::from_bitmask(bitmask));
+            let bytes = bincode::serialize(&keys).unwrap();
+            assert_eq!(bytes, bitmask.to_le_bytes());
+            assert_eq!(bincode::deserialize::<KeysBitmask>(&bytes).unwrap(), keys);
+        }
+    }
+
+    #[test]
+    fn key_map_test() {
+        let qwerty = KeyMap::default();
+        assert_eq!(qwerty, KeyMap::qwerty());
+        assert_eq!(qwerty.get(b'1'), Some(0x1));
+        assert_eq!(qwerty.get(b'x'), Some(0x0));
+        assert_eq!(qwerty.get(b'v'), Some(0xF));
+        assert_eq!(qwerty.get(b'p'), None);
+        assert_eq!(qwerty.mapping.iter().flatten().count(), 16);
+
+        for bitmask in [0, 1, 0x8000, 0xFFFF, 0b1010_0101_1100_0011] {
+            let keys = Keys::from_bitmask(bitmask);
+            assert_eq!(qwerty.translate(&keys), keys);
+        }
+
+        let azerty = KeyMap::azerty();
+        assert_eq!(azerty.get(b'a'), Some(0x4));
+        assert_eq!(azerty.get(b'q'), Some(0x7));
+        assert_eq!(azerty.get(b'w'), Some(0xA));
+        assert_eq!(azerty.keys_from_codes(*b"azp").to_bitmask(), 0b11_0000);
+
+        // The runner reports A as key 7, which is key 4 on AZERTY
+        assert_eq!(
+            azerty
+                .translate(&Keys::from_pressed_keys([0x7]))
+                .to_bitmask(),
+            1 << 0x4
+        );
+
+        let mut custom = KeyMap::empty();
+        custom.set(b' ', Some(0x5));
+        assert_eq!(custom.keys_from_codes(*b" x").to_bitmask(), 1 << 0x5);
+        assert_eq!(custom.translate(&Keys::from_bitmask(0xFFFF)), [false; 16]);
+    }
+}
@@ -0,0 +1,38 @@
+//! This is a simple CHIP-8 interpreter based on this UWCS project:
+//! <https://rs118.uwcs.co.uk/chip8.html>
+
+pub mod audio;
+pub mod capabilities;
+pub mod checksum;
+pub mod display;
+mod interpreter;
+pub mod keys;
+#[cfg(test)]
+mod mock_clock;
+pub mod null_interpreter;
+pub mod pipeline;
+pub mod platform;
+pub mod recording;
+pub mod rewind;
+#[cfg(feature = "sha2")]
+pub mod rom_hash;
+pub mod rom_loader;
+pub mod speed_controller;
+pub mod stats;
+#[cfg(feature = "precise-timing")]
+pub mod throttled_interpreter;
+pub mod timer;
+pub mod warm_up;
+#[cfg(feature = "wasm_bindgen")]
+pub mod web;
+
+pub use self::interpreter::{
+    memory::{
+        default_font, font_address, large_font_address, AccessMode, MemoryAccess, MemoryLayout,
+        MemoryMap, MemoryRegion, RegionKind, FONT_ADDRESS_START, LARGE_FONT_ADDRESS_START,
+        MEMORY_SIZE, PROGRAM_START, STACK_DEPTH,
+    },
+    Chip8Config, Chip8Interpreter, InterpreterError, InterpreterEvent, InterpreterState,
+    OnUnimplementedAction, PatchError, Quirks, RestoreStateError, SelfTestResult,
+    EVENT_QUEUE_CAPACITY,
+};
@@ -1,35 +1,431 @@
 //! This is a simple CHIP-8 interpreter based on this UWCS project:
 //! <https://rs118.uwcs.co.uk/chip8.html>
 
-#![feature(generic_arg_infer)]
+use chip8::{
+    keys::KeyMap,
+    recording::{InputRecorder, InputReplayer, Recording},
+    rom_loader::{load_rom_file, RomFile},
+    speed_controller::SpeedController,
+    warm_up::WarmUp,
+    Chip8Config, Chip8Interpreter, Quirks, SelfTestResult, PROGRAM_START,
+};
+use chip8_base::{Display, Interpreter, Keys};
+use chip8_v2::{Chip8Config as Chip8ConfigV2, Chip8InterpreterV2};
+use clap::{Parser, ValueEnum};
+use std::{
+    num::ParseIntError,
+    path::PathBuf,
+    process,
+    time::{Duration, Instant},
+};
 
-mod interpreter;
+/// How often to print the timing stats with `--stats`.
+const STATS_INTERVAL: Duration = Duration::from_secs(5);
 
-use clap::Parser;
-use std::fs;
+/// How often to save the recording with `--record`.
+const RECORDING_SAVE_INTERVAL: Duration = Duration::from_secs(5);
 
 /// Execute a ROM with a simple CHIP-8 interpreter.
 #[derive(Parser)]
 #[command(author, version, about)]
 struct Args {
-    /// The filename of the ROM to execute.
-    rom: String,
+    /// The filename of the ROM to execute. Raw binary (`.ch8`, `.c8`, `.rom`, `.bin`),
+    /// hex-encoded text (`.hex`), and Intel HEX (`.ihex`) files are supported, and any other
+    /// file is loaded as raw binary. An Intel HEX file can also have a custom font at the font
+    /// address.
+    rom: PathBuf,
 
     /// The frequency of the interpreter's clock, measured in Hz.
     #[arg(long, short, default_value_t = 700.0)]
     frequency: f32,
+
+    /// Measure how fast the interpreter really runs, and adjust the time between steps to keep it
+    /// at the frequency, since the runner always sleeps a bit too long.
+    #[arg(long)]
+    speed_control: bool,
+
+    /// Spin for the last moment before each step instead of sleeping, so that steps happen at
+    /// precise times. This keeps a CPU core busy.
+    #[cfg(feature = "precise-timing")]
+    #[arg(long, conflicts_with = "speed_control")]
+    precise_timing: bool,
+
+    /// Use the second version of the interpreter, which fixes known bugs and emulates the
+    /// original CHIP-8 quirks.
+    #[arg(long)]
+    v2: bool,
+
+    /// The address to load the ROM at and start executing from, like `0x200`. This isn't
+    /// supported by the second version of the interpreter.
+    #[arg(long, value_parser = parse_address, default_value_t = PROGRAM_START as u16)]
+    entry_point: u16,
+
+    /// Print timing stats to stderr every few seconds, and when the program halts. This isn't
+    /// supported by the second version of the interpreter.
+    #[arg(long)]
+    stats: bool,
+
+    /// The number of cycles to run with no keys pressed before opening the window.
+    #[arg(long, default_value_t = 0)]
+    warm_up_cycles: u64,
+
+    /// The keyboard layout to read the CHIP-8 keypad from. This isn't supported by the second
+    /// version of the interpreter.
+    #[arg(long, value_enum, default_value_t = KeyLayout::Default)]
+    key_map: KeyLayout,
+
+    /// An address to print the state of the interpreter at when it's reached, like `0x208`. This
+    /// can be given more than once, and isn't supported by the second version of the
+    /// interpreter.
+    #[arg(long = "breakpoint", value_parser = parse_address)]
+    breakpoints: Vec<u16>,
+
+    /// Record the keys that are pressed in every step to this file, so that they can be replayed
+    /// later with `--replay`. The file is saved every few seconds.
+    #[arg(long)]
+    record: Option<PathBuf>,
+
+    /// Replay the keys from a file that was made with `--record`, ignoring the keyboard. Keys
+    /// aren't pressed at all once the recording ends.
+    #[arg(long)]
+    replay: Option<PathBuf>,
+
+    /// Start with the audio muted. This isn't supported by the second version of the interpreter.
+    #[arg(long)]
+    mute: bool,
+
+    /// Check that the interpreter's state is consistent before running anything, and refuse to
+    /// run if it isn't. This isn't supported by the second version of the interpreter.
+    #[arg(long, conflicts_with = "v2")]
+    self_test: bool,
+
+    /// Print the disassembly around an address, with the given number of instructions either
+    /// side, like `--debug-disassemble 0x200 5`, and exit without running anything. This isn't
+    /// supported by the second version of the interpreter.
+    #[arg(
+        long,
+        num_args = 2,
+        value_names = ["ADDR", "CONTEXT"],
+        value_parser = parse_address,
+        conflicts_with = "v2"
+    )]
+    debug_disassemble: Option<Vec<u16>>,
+
+    /// Whether `OR`, `AND`, and `XOR` should reset VF to 0. The default for each quirk is the
+    /// behaviour of the original COSMAC VIP. None of the quirks are supported by the second
+    /// version of the interpreter.
+    #[arg(long, value_name = "BOOL")]
+    quirk_vf_reset: Option<bool>,
+
+    /// Whether `SHR` and `SHL` should shift VY into VX, instead of shifting VX in place.
+    #[arg(long, value_name = "BOOL")]
+    quirk_shift: Option<bool>,
+
+    /// Whether storing and reading registers in memory should add X + 1 to I.
+    #[arg(long, value_name = "BOOL")]
+    quirk_memory: Option<bool>,
+
+    /// Whether `BNNN` should jump to `NNN` plus VX instead of V0, where X is the top nibble of
+    /// `NNN`.
+    #[arg(long, value_name = "BOOL")]
+    quirk_jump: Option<bool>,
+
+    /// Whether sprites should wrap around the edges of the display, instead of being clipped.
+    #[arg(long, value_name = "BOOL")]
+    quirk_wrap: Option<bool>,
+
+    /// The expected SHA-256 hash of the ROM, as 64 hex digits. We refuse to run the ROM if its
+    /// hash doesn't match.
+    #[cfg(feature = "sha2")]
+    #[arg(long, value_parser = chip8::rom_hash::parse_hash)]
+    check_hash: Option<[u8; 32]>,
+}
+
+impl Args {
+    /// Get the quirks for the interpreter, starting from the defaults and changing any that were
+    /// given with the `--quirk-*` flags.
+    fn quirks(&self) -> Quirks {
+        let default = Quirks::default();
+        Quirks {
+            or_and_xor_reset_vf: self.quirk_vf_reset.unwrap_or(default.or_and_xor_reset_vf),
+            shift_uses_vy: self.quirk_shift.unwrap_or(default.shift_uses_vy),
+            memory_register_incremented: self
+                .quirk_memory
+                .unwrap_or(default.memory_register_incremented),
+            jump_plus_uses_vx: self.quirk_jump.unwrap_or(default.jump_plus_uses_vx),
+            sprite_wrapping: self.quirk_wrap.unwrap_or(default.sprite_wrapping),
+        }
+    }
+}
+
+/// The keyboard layouts that can be chosen with `--key-map`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+enum KeyLayout {
+    /// The standard layout, which is the same as QWERTY.
+    Default,
+
+    /// The standard layout on a QWERTY keyboard.
+    Qwerty,
+
+    /// The standard layout on an AZERTY keyboard.
+    Azerty,
+}
+
+impl KeyLayout {
+    /// Get the key map for this layout.
+    fn key_map(self) -> KeyMap {
+        match self {
+            Self::Default => KeyMap::default(),
+            Self::Qwerty => KeyMap::qwerty(),
+            Self::Azerty => KeyMap::azerty(),
+        }
+    }
+}
+
+/// Wraps an interpreter to print its timing stats.
+///
+/// The window can close at any time without returning control to us, so we print the stats
+/// regularly rather than only on exit.
+struct StatsPrinter {
+    /// The interpreter to run.
+    interpreter: Chip8Interpreter,
+
+    /// When we last printed the stats.
+    last_print: Instant,
+
+    /// Have we printed the stats for the program halting?
+    printed_halt: bool,
+}
+
+impl Interpreter for StatsPrinter {
+    fn step(&mut self, keys: &Keys) -> Option<Display> {
+        let display = self.interpreter.step(keys);
+
+        let halted = self.interpreter.is_halted() && !self.printed_halt;
+        if halted || self.last_print.elapsed() >= STATS_INTERVAL {
+            eprintln!("{:#?}", self.interpreter.timing_stats());
+            self.last_print = Instant::now();
+            self.printed_halt |= halted;
+        }
+
+        display
+    }
+
+    fn speed(&self) -> Duration {
+        self.interpreter.speed()
+    }
+
+    fn buzzer_active(&self) -> bool {
+        self.interpreter.buzzer_active()
+    }
+}
+
+/// Wraps an [`InputRecorder`] to save its recording regularly.
+///
+/// Like [`StatsPrinter`], we can't wait until the window closes to save the recording.
+struct RecordingSaver<I: Interpreter> {
+    /// The interpreter to run, with its recording.
+    recorder: InputRecorder<I>,
+
+    /// The file to save the recording to.
+    path: PathBuf,
+
+    /// When we last saved the recording.
+    last_save: Instant,
+}
+
+impl<I: Interpreter> Interpreter for RecordingSaver<I> {
+    fn step(&mut self, keys: &Keys) -> Option<Display> {
+        let display = self.recorder.step(keys);
+
+        if self.last_save.elapsed() >= RECORDING_SAVE_INTERVAL {
+            if let Err(e) = self.recorder.save_recording(&self.path) {
+                eprintln!("Failed to save recording: {e}");
+            }
+            self.last_save = Instant::now();
+        }
+
+        display
+    }
+
+    fn speed(&self) -> Duration {
+        self.recorder.speed()
+    }
+
+    fn buzzer_active(&self) -> bool {
+        self.recorder.buzzer_active()
+    }
+}
+
+/// Run the interpreter, controlling its speed and replaying and recording keys if we were asked
+/// to.
+fn run<I: Interpreter + Send + 'static>(interpreter: I, args: &Args) -> ! {
+    #[cfg(feature = "precise-timing")]
+    if args.precise_timing {
+        use chip8::throttled_interpreter::{ThrottledInterpreter, DEFAULT_SPIN_THRESHOLD};
+
+        run_with_replayer(
+            ThrottledInterpreter::new(interpreter, DEFAULT_SPIN_THRESHOLD),
+            args,
+        )
+    }
+
+    if args.speed_control {
+        let target = args.frequency.into();
+        run_with_replayer(SpeedController::with_target_ips(interpreter, target), args)
+    } else {
+        run_with_replayer(interpreter, args)
+    }
+}
+
+/// Run the interpreter, replaying and recording keys if we were asked to.
+fn run_with_replayer<I: Interpreter + Send + 'static>(interpreter: I, args: &Args) -> ! {
+    match &args.replay {
+        Some(path) => {
+            let recording = match Recording::load(path) {
+                Ok(recording) => recording,
+                Err(e) => panic!("Failed to load recording: {e}"),
+            };
+            run_with_recorder(InputReplayer::new(interpreter, recording), args)
+        }
+        None => run_with_recorder(interpreter, args),
+    }
+}
+
+/// Run the interpreter, recording keys if we were asked to.
+fn run_with_recorder<I: Interpreter + Send + 'static>(interpreter: I, args: &Args) -> ! {
+    match &args.record {
+        Some(path) => chip8_base::run(RecordingSaver {
+            recorder: InputRecorder::new(interpreter),
+            path: path.clone(),
+            last_save: Instant::now(),
+        }),
+        None => chip8_base::run(interpreter),
+    }
+}
+
+/// Parse an address in hex with a `0x` prefix, or in decimal.
+fn parse_address(address: &str) -> Result<u16, ParseIntError> {
+    match address.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => address.parse(),
+    }
 }
 
 fn main() {
     let args = Args::parse();
 
-    let rom = match fs::read(args.rom) {
-        Ok(data) => data,
-        Err(e) => panic!("Failed to read file: {e:?}"),
+    let RomFile { rom, font } = match load_rom_file(&args.rom) {
+        Ok(rom_file) => rom_file,
+        Err(e) => panic!("Failed to load ROM: {e}"),
     };
 
-    chip8_base::run(self::interpreter::Chip8Interpreter::new(
-        &rom,
-        args.frequency,
-    ));
+    #[cfg(feature = "sha2")]
+    if let Some(expected) = args.check_hash {
+        use chip8::rom_hash::{hash_rom, hash_to_hex};
+
+        let actual = hash_rom(&rom);
+        if actual != expected {
+            panic!(
+                "ROM hash mismatch: expected {}, but found {}",
+                hash_to_hex(&expected),
+                hash_to_hex(&actual)
+            );
+        }
+    }
+
+    if args.v2 {
+        if font.is_some() {
+            eprintln!("The second version of the interpreter doesn't support custom fonts");
+            process::exit(1);
+        }
+        run(new_v2_interpreter(rom, &args), &args)
+    } else if args.stats {
+        run(
+            StatsPrinter {
+                interpreter: new_interpreter(rom, font, &args),
+                last_print: Instant::now(),
+                printed_halt: false,
+            },
+            &args,
+        )
+    } else {
+        run(new_interpreter(rom, font, &args), &args)
+    }
+}
+
+/// Create the second version of the interpreter, or exit if we can't.
+fn new_v2_interpreter(rom: Vec<u8>, args: &Args) -> Chip8InterpreterV2 {
+    let mut interpreter = match Chip8InterpreterV2::new(Chip8ConfigV2 {
+        clock_frequency: args.frequency,
+        ..Chip8ConfigV2::new(rom)
+    }) {
+        Ok(interpreter) => interpreter,
+        Err(e) => {
+            eprintln!("Failed to create interpreter: {e}");
+            process::exit(1);
+        }
+    };
+    interpreter.warm_up(args.warm_up_cycles);
+    interpreter
+}
+
+/// Create the interpreter with the ROM and its custom font, if it has one, and set it up with the
+/// arguments, or exit if we can't. This also exits after running the self test or printing the
+/// disassembly, if we were asked to.
+fn new_interpreter(rom: Vec<u8>, font: Option<[u8; 80]>, args: &Args) -> Chip8Interpreter {
+    let mut interpreter = match Chip8Interpreter::try_from_config(Chip8Config {
+        clock_frequency: args.frequency,
+        entry_point: args.entry_point,
+        key_map: args.key_map.key_map(),
+        quirks: args.quirks(),
+        ..Chip8Config::new(rom)
+    }) {
+        Ok(interpreter) => interpreter,
+        Err(e) => {
+            eprintln!("Failed to create interpreter: {e}");
+            process::exit(1);
+        }
+    };
+    if let Some(font) = &font {
+        interpreter.load_font(font);
+    }
+    if args.self_test {
+        if let SelfTestResult::Fail(problems) = interpreter.self_test() {
+            eprintln!("The interpreter failed its self test:");
+            for problem in problems {
+                eprintln!("  {problem}");
+            }
+            process::exit(1);
+        }
+    }
+    if let Some(&[addr, context]) = args.debug_disassemble.as_deref() {
+        let Ok(context) = u8::try_from(context) else {
+            eprintln!("The disassembly context can be at most 255 instructions, not {context}");
+            process::exit(1);
+        };
+        println!("{}", interpreter.disassemble_around(addr, context));
+        process::exit(0);
+    }
+    interpreter.set_on_error(|_, error| {
+        eprintln!("The program crashed: {error}");
+        process::exit(1);
+    });
+    interpreter.set_audio_enabled(!args.mute);
+    for &addr in &args.breakpoints {
+        interpreter.add_breakpoint(addr);
+    }
+    interpreter.set_on_breakpoint(|interpreter, addr| {
+        let state = interpreter.dump_state();
+        eprintln!(
+            "Breakpoint reached at {addr:#05X}: {:?}",
+            interpreter.last_executed_instruction()
+        );
+        eprintln!(
+            "V = {:02X?}, I = {:#05X}, SP = {}",
+            state.v, state.i, state.sp
+        );
+    });
+    interpreter.warm_up(args.warm_up_cycles);
+    interpreter
 }
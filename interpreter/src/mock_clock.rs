@@ -0,0 +1,27 @@
+//! This module contains [`MockClock`], which lets tests control the time that the interpreter
+//! sees.
+
+use crate::platform::{Clock, Timestamp};
+use std::{cell::Cell, time::Duration};
+
+thread_local! {
+    /// The current time of [`MockClock`], which is separate for each test.
+    static MOCK_TIME: Cell<Duration> = const { Cell::new(Duration::ZERO) };
+}
+
+/// A clock that only moves forward when we call [`MockClock::advance`].
+#[derive(Clone, Copy, Debug)]
+pub struct MockClock;
+
+impl MockClock {
+    /// Move the clock forward by the given duration.
+    pub fn advance(duration: Duration) {
+        MOCK_TIME.with(|time| time.set(time.get() + duration));
+    }
+}
+
+impl Clock for MockClock {
+    fn now() -> Timestamp {
+        Timestamp(MOCK_TIME.with(Cell::get))
+    }
+}
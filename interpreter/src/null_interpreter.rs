@@ -0,0 +1,78 @@
+//! This module contains the [`NullInterpreter`] type.
+
+use chip8_base::{Display, Interpreter, Keys, Pixel};
+use std::time::Duration;
+
+/// An interpreter that doesn't run any CHIP-8 code.
+///
+/// Stepping it does nothing, so it can be used to measure the overhead of whatever is driving an
+/// interpreter, separately from the CHIP-8 logic itself. It can also be used as a display-only
+/// renderer, by manually feeding it displays with [`show`](Self::show).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NullInterpreter {
+    /// The display that will be returned by the next step, if any.
+    pending_display: Option<Display>,
+}
+
+impl NullInterpreter {
+    /// Create a new interpreter with no pending display.
+    pub fn new() -> Self {
+        Self {
+            pending_display: None,
+        }
+    }
+
+    /// Queue a display to be returned by the next call to [`step`](Interpreter::step). Every other
+    /// step returns `None`.
+    pub fn show(&mut self, display: Display) {
+        self.pending_display = Some(display);
+    }
+
+    /// Queue a completely black display to be returned by the next step.
+    pub fn clear(&mut self) {
+        self.show([[Pixel::Black; 64]; 32]);
+    }
+}
+
+impl Default for NullInterpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter for NullInterpreter {
+    fn step(&mut self, _keys: &Keys) -> Option<Display> {
+        self.pending_display.take()
+    }
+
+    fn speed(&self) -> Duration {
+        Duration::ZERO
+    }
+
+    fn buzzer_active(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn null_interpreter_test() {
+        let keys = [false; 16];
+        let mut interpreter = NullInterpreter::new();
+        assert_eq!(interpreter.step(&keys), None);
+        assert_eq!(interpreter.speed(), Duration::ZERO);
+        assert!(!interpreter.buzzer_active());
+
+        let mut display = [[Pixel::Black; 64]; 32];
+        display[3][5] = Pixel::White;
+        interpreter.show(display);
+        assert_eq!(interpreter.step(&keys), Some(display));
+        assert_eq!(interpreter.step(&keys), None);
+
+        interpreter.clear();
+        assert_eq!(interpreter.step(&keys), Some([[Pixel::Black; 64]; 32]));
+    }
+}
@@ -0,0 +1,164 @@
+//! This module contains [`InterpreterPipeline`], which runs hooks before and after every step of
+//! an interpreter.
+
+use chip8_base::{Display, Interpreter, Keys};
+use std::{fmt, time::Duration};
+
+/// A hook that's called before each step, with the interpreter and the keys for the step.
+type PreStepHook<I> = Box<dyn Fn(&I, &Keys) + Send>;
+
+/// A hook that's called after each step, with the interpreter, the keys for the step, and the
+/// display that the step returned.
+type PostStepHook<I> = Box<dyn Fn(&I, &Keys, &Option<Display>) + Send>;
+
+/// Wraps an interpreter to call hooks before and after every step, so that things like tracing
+/// and performance monitoring can be layered on top of any interpreter without changing it.
+///
+/// ```
+/// # use chip8::{pipeline::InterpreterPipeline, Chip8Interpreter, Quirks};
+/// let interpreter = Chip8Interpreter::new(&[0x00, 0xE0], 700., Quirks::default());
+/// let pipeline = InterpreterPipeline::new(interpreter)
+///     .with_pre_step(|interpreter, _| println!("PC: {:#X}", interpreter.get_program_counter()))
+///     .with_post_step(|_, _, display| {
+///         if display.is_some() {
+///             println!("The display changed");
+///         }
+///     });
+/// ```
+pub struct InterpreterPipeline<I: Interpreter> {
+    /// The interpreter to run.
+    interpreter: I,
+
+    /// The hooks to call before each step, in the order that they were added.
+    pre_step_hooks: Vec<PreStepHook<I>>,
+
+    /// The hooks to call after each step, in the order that they were added.
+    post_step_hooks: Vec<PostStepHook<I>>,
+}
+
+impl<I: Interpreter> InterpreterPipeline<I> {
+    /// Wrap the interpreter with no hooks.
+    pub fn new(interpreter: I) -> Self {
+        Self {
+            interpreter,
+            pre_step_hooks: Vec::new(),
+            post_step_hooks: Vec::new(),
+        }
+    }
+
+    /// Add a hook to call before each step, after any pre-step hooks that were already added.
+    pub fn with_pre_step(mut self, f: impl Fn(&I, &Keys) + Send + 'static) -> Self {
+        self.pre_step_hooks.push(Box::new(f));
+        self
+    }
+
+    /// Add a hook to call after each step, after any post-step hooks that were already added.
+    pub fn with_post_step(
+        mut self,
+        f: impl Fn(&I, &Keys, &Option<Display>) + Send + 'static,
+    ) -> Self {
+        self.post_step_hooks.push(Box::new(f));
+        self
+    }
+
+    /// Get the interpreter inside the pipeline.
+    pub fn inner(&self) -> &I {
+        &self.interpreter
+    }
+
+    /// Unwrap the interpreter, dropping the hooks.
+    pub fn into_inner(self) -> I {
+        self.interpreter
+    }
+}
+
+impl<I: Interpreter + fmt::Debug> fmt::Debug for InterpreterPipeline<I> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InterpreterPipeline")
+            .field("interpreter", &self.interpreter)
+            .field("pre_step_hooks", &self.pre_step_hooks.len())
+            .field("post_step_hooks", &self.post_step_hooks.len())
+            .finish()
+    }
+}
+
+impl<I: Interpreter> Interpreter for InterpreterPipeline<I> {
+    fn step(&mut self, keys: &Keys) -> Option<Display> {
+        for hook in &self.pre_step_hooks {
+            hook(&self.interpreter, keys);
+        }
+
+        let display = self.interpreter.step(keys);
+
+        for hook in &self.post_step_hooks {
+            hook(&self.interpreter, keys, &display);
+        }
+
+        display
+    }
+
+    fn speed(&self) -> Duration {
+        self.interpreter.speed()
+    }
+
+    fn buzzer_active(&self) -> bool {
+        self.interpreter.buzzer_active()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Chip8Interpreter, Quirks};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn pipeline_test() {
+        let keys = [false; 16];
+        let events = Arc::new(Mutex::new(Vec::new()));
+
+        // cls; jmp #200
+        let interpreter = Chip8Interpreter::new(&[0x00, 0xE0, 0x12, 0x00], 700., Quirks::default());
+        let mut pipeline = InterpreterPipeline::new(interpreter)
+            .with_pre_step({
+                let events = Arc::clone(&events);
+                move |interpreter: &Chip8Interpreter, _: &Keys| {
+                    events
+                        .lock()
+                        .unwrap()
+                        .push(format!("pre 1 {:X}", interpreter.get_program_counter()))
+                }
+            })
+            .with_post_step({
+                let events = Arc::clone(&events);
+                move |interpreter: &Chip8Interpreter, _: &Keys, display: &Option<Display>| {
+                    events.lock().unwrap().push(format!(
+                        "post {:X} {}",
+                        interpreter.get_program_counter(),
+                        display.is_some()
+                    ))
+                }
+            })
+            .with_pre_step({
+                let events = Arc::clone(&events);
+                move |_: &Chip8Interpreter, _: &Keys| events.lock().unwrap().push("pre 2".into())
+            });
+
+        pipeline.step(&keys);
+        pipeline.step(&keys);
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                "pre 1 200",
+                "pre 2",
+                "post 202 true",
+                "pre 1 202",
+                "pre 2",
+                "post 200 true"
+            ]
+        );
+
+        assert_eq!(pipeline.speed(), pipeline.inner().speed());
+        assert_eq!(pipeline.into_inner().get_program_counter(), 0x200);
+    }
+}
@@ -0,0 +1,70 @@
+//! This module contains the [`Clock`] trait, which lets the interpreter read the time on platforms
+//! where [`std::time::Instant`] isn't available, like `wasm32-unknown-unknown`.
+
+use std::{fmt, ops::Sub, sync::OnceLock, time::Duration};
+
+/// A point in time, measured from an arbitrary epoch that depends on the [`Clock`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp(pub Duration);
+
+impl Sub for Timestamp {
+    type Output = Duration;
+
+    /// Get the time between two timestamps, or zero if `rhs` is later than `self`.
+    fn sub(self, rhs: Self) -> Duration {
+        self.0.saturating_sub(rhs.0)
+    }
+}
+
+/// A source of the current time.
+pub trait Clock: Copy + fmt::Debug {
+    /// Get the current time.
+    fn now() -> Timestamp;
+
+    /// Get the time that has passed since `start`.
+    fn elapsed(start: Timestamp) -> Duration {
+        Self::now() - start
+    }
+}
+
+/// A clock that uses [`std::time::Instant`], with the epoch at the first time it's read.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StdClock;
+
+impl Clock for StdClock {
+    fn now() -> Timestamp {
+        static EPOCH: OnceLock<std::time::Instant> = OnceLock::new();
+        Timestamp(EPOCH.get_or_init(std::time::Instant::now).elapsed())
+    }
+}
+
+/// A clock that uses `performance.now()` in the browser, with the epoch when the page loaded.
+#[cfg(feature = "web")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WebClock;
+
+#[cfg(feature = "web")]
+impl Clock for WebClock {
+    fn now() -> Timestamp {
+        let millis = web_sys::window()
+            .and_then(|window| window.performance())
+            .expect("WebClock should only be used in a browser window")
+            .now();
+        Timestamp(Duration::from_secs_f64(millis / 1000.))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn std_clock_test() {
+        let start = StdClock::now();
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(StdClock::elapsed(start) >= Duration::from_millis(5));
+        assert!(StdClock::now() > start);
+
+        assert_eq!(start - StdClock::now(), Duration::ZERO);
+    }
+}
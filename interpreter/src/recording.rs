@@ -0,0 +1,259 @@
+//! This module contains [`InputRecorder`] and [`InputReplayer`], which record the keys that are
+//! pressed while an interpreter runs, and play them back later.
+
+use crate::keys::KeysExt;
+use chip8_base::{Display, Interpreter, Keys};
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+    time::Duration,
+};
+
+/// The bytes at the start of every recording file.
+const MAGIC: &[u8; 6] = b"CH8REC";
+
+/// The keys that were pressed in each step of a run of an interpreter.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Recording {
+    /// The cycle number of each step, starting from 0, with the keys that were pressed.
+    pub steps: Vec<(u64, Keys)>,
+}
+
+impl Recording {
+    /// Write the recording in its binary format, which is [`MAGIC`], then the number of steps,
+    /// then the cycle number and key bitmask of each step, all little-endian.
+    pub fn write_to(&self, mut writer: impl Write) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&(self.steps.len() as u64).to_le_bytes())?;
+        for (cycle, keys) in &self.steps {
+            writer.write_all(&cycle.to_le_bytes())?;
+            writer.write_all(&keys.to_bitmask().to_le_bytes())?;
+        }
+        writer.flush()
+    }
+
+    /// Read a recording that was written with [`write_to`](Self::write_to).
+    pub fn read_from(mut reader: impl Read) -> io::Result<Self> {
+        let mut magic = [0; MAGIC.len()];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "This is not a CHIP-8 input recording",
+            ));
+        }
+
+        let mut u64_buf = [0; 8];
+        let mut u16_buf = [0; 2];
+
+        reader.read_exact(&mut u64_buf)?;
+        let len = u64::from_le_bytes(u64_buf);
+
+        let mut steps = Vec::new();
+        for _ in 0..len {
+            reader.read_exact(&mut u64_buf)?;
+            reader.read_exact(&mut u16_buf)?;
+            steps.push((
+                u64::from_le_bytes(u64_buf),
+                Keys::from_bitmask(u16::from_le_bytes(u16_buf)),
+            ));
+        }
+
+        Ok(Self { steps })
+    }
+
+    /// Save the recording to a file.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        self.write_to(BufWriter::new(File::create(path)?))
+    }
+
+    /// Load a recording from a file.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        Self::read_from(BufReader::new(File::open(path)?))
+    }
+}
+
+/// Wraps an interpreter to record the keys that are passed to every step.
+#[derive(Clone, Debug)]
+pub struct InputRecorder<I: Interpreter> {
+    /// The interpreter to run.
+    interpreter: I,
+
+    /// The steps that have been recorded so far.
+    recording: Recording,
+
+    /// The number of steps so far.
+    cycle: u64,
+}
+
+impl<I: Interpreter> InputRecorder<I> {
+    /// Wrap the interpreter with an empty recording.
+    pub fn new(interpreter: I) -> Self {
+        Self {
+            interpreter,
+            recording: Recording::default(),
+            cycle: 0,
+        }
+    }
+
+    /// Get the steps that have been recorded so far.
+    pub fn recording(&self) -> &Recording {
+        &self.recording
+    }
+
+    /// Save the steps that have been recorded so far to a file.
+    pub fn save_recording(&self, path: &Path) -> io::Result<()> {
+        self.recording.save(path)
+    }
+
+    /// Unwrap the interpreter and the recording.
+    pub fn into_inner(self) -> (I, Recording) {
+        (self.interpreter, self.recording)
+    }
+}
+
+impl<I: Interpreter> Interpreter for InputRecorder<I> {
+    fn step(&mut self, keys: &Keys) -> Option<Display> {
+        self.recording.steps.push((self.cycle, *keys));
+        self.cycle += 1;
+        self.interpreter.step(keys)
+    }
+
+    fn speed(&self) -> Duration {
+        self.interpreter.speed()
+    }
+
+    fn buzzer_active(&self) -> bool {
+        self.interpreter.buzzer_active()
+    }
+}
+
+/// Wraps an interpreter to ignore the keys that are passed to each step, and use the keys from a
+/// [`Recording`] instead. Once the recording runs out, no keys are pressed.
+#[derive(Clone, Debug)]
+pub struct InputReplayer<I: Interpreter> {
+    /// The interpreter to run.
+    interpreter: I,
+
+    /// The recording to replay.
+    recording: Recording,
+
+    /// The index of the next step in the recording.
+    next: usize,
+
+    /// The number of steps so far.
+    cycle: u64,
+}
+
+impl<I: Interpreter> InputReplayer<I> {
+    /// Wrap the interpreter to replay the recording from the start.
+    pub fn new(interpreter: I, recording: Recording) -> Self {
+        Self {
+            interpreter,
+            recording,
+            next: 0,
+            cycle: 0,
+        }
+    }
+
+    /// Has every step in the recording been replayed?
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.recording.steps.len()
+    }
+
+    /// Unwrap the interpreter.
+    pub fn into_inner(self) -> I {
+        self.interpreter
+    }
+}
+
+impl<I: Interpreter> Interpreter for InputReplayer<I> {
+    fn step(&mut self, _keys: &Keys) -> Option<Display> {
+        let keys = match self.recording.steps.get(self.next) {
+            Some(&(cycle, keys)) if cycle == self.cycle => {
+                self.next += 1;
+                keys
+            }
+            _ => [false; 16],
+        };
+        self.cycle += 1;
+        self.interpreter.step(&keys)
+    }
+
+    fn speed(&self) -> Duration {
+        self.interpreter.speed()
+    }
+
+    fn buzzer_active(&self) -> bool {
+        self.interpreter.buzzer_active()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Chip8Interpreter, Quirks};
+
+    /// A ROM that draws the digit of each key that's pressed at the position of a counter.
+    ///
+    /// ```text
+    /// start:
+    ///     ld v0, k
+    ///     ld f, v0
+    ///     drw v1, v2, 5
+    ///     add v1, 5
+    ///     jp start
+    /// ```
+    const ROM: [u8; 10] = [0xF0, 0x0A, 0xF0, 0x29, 0xD1, 0x25, 0x71, 0x05, 0x12, 0x00];
+
+    #[test]
+    fn record_and_replay_test() {
+        let mut recorder = InputRecorder::new(Chip8Interpreter::new(&ROM, 700., Quirks::default()));
+        let mut displays = Vec::new();
+        for step in 0..100u64 {
+            let keys = Keys::from_pressed_keys([(step % 16) as u8]);
+            displays.push(recorder.step(&keys));
+        }
+
+        let (_, recording) = recorder.into_inner();
+        assert_eq!(recording.steps.len(), 100);
+
+        let mut bytes = Vec::new();
+        recording.write_to(&mut bytes).unwrap();
+        assert_eq!(bytes.len(), MAGIC.len() + 8 + 100 * 10);
+        let recording = Recording::read_from(bytes.as_slice()).unwrap();
+
+        let mut replayer = InputReplayer::new(
+            Chip8Interpreter::new(&ROM, 700., Quirks::default()),
+            recording,
+        );
+        for display in displays {
+            // The keys passed to the replayer are ignored
+            assert_eq!(replayer.step(&[true; 16]), display);
+        }
+        assert!(replayer.is_finished());
+
+        // No keys are pressed after the recording ends, so the ROM gets stuck waiting for one
+        for _ in 0..10 {
+            replayer.step(&[true; 16]);
+        }
+        assert_eq!(replayer.into_inner().pending_key_wait(), Some(0));
+    }
+
+    #[test]
+    fn invalid_recording_test() {
+        let error = Recording::read_from(&b"NOTREC\0\0\0\0\0\0\0\0"[..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let mut bytes = Vec::new();
+        Recording {
+            steps: vec![(0, [true; 16])],
+        }
+        .write_to(&mut bytes)
+        .unwrap();
+        bytes.pop();
+        let error = Recording::read_from(bytes.as_slice()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,150 @@
+//! This module contains the [`Rewind`] type, which lets an interpreter step backwards.
+
+use chip8_base::{Display, Interpreter, Keys};
+use std::{collections::VecDeque, time::Duration};
+
+/// The number of steps that [`Rewind`] remembers by default.
+pub const DEFAULT_REWIND_BUFFER_SIZE: usize = 60;
+
+/// Wraps an interpreter to remember its state before each of the most recent steps, so that they
+/// can be undone with [`step_back`](Self::step_back).
+///
+/// Each step stores a full copy of the interpreter, so a large buffer can use a lot of memory.
+#[derive(Clone, Debug)]
+pub struct Rewind<I: Interpreter + Clone> {
+    /// The interpreter to run.
+    interpreter: I,
+
+    /// The state of the interpreter before each recent step, with the most recent at the back.
+    history: VecDeque<I>,
+
+    /// The maximum number of states to keep in `history`.
+    buffer_size: usize,
+}
+
+impl<I: Interpreter + Clone> Rewind<I> {
+    /// Wrap the interpreter, remembering the last [`DEFAULT_REWIND_BUFFER_SIZE`] steps.
+    pub fn new(interpreter: I) -> Self {
+        Self {
+            interpreter,
+            history: VecDeque::with_capacity(DEFAULT_REWIND_BUFFER_SIZE),
+            buffer_size: DEFAULT_REWIND_BUFFER_SIZE,
+        }
+    }
+
+    /// Set the number of steps to remember. If there are more than that already, the oldest ones
+    /// are forgotten.
+    pub fn set_rewind_buffer_size(&mut self, size: usize) {
+        self.buffer_size = size;
+        while self.history.len() > size {
+            self.history.pop_front();
+        }
+    }
+
+    /// Undo the last step, restoring the interpreter to how it was before. Returns false and
+    /// does nothing if there are no steps left to undo.
+    pub fn step_back(&mut self) -> bool {
+        match self.history.pop_back() {
+            Some(previous) => {
+                self.interpreter = previous;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Get the number of steps that can currently be undone.
+    pub fn rewindable_steps(&self) -> usize {
+        self.history.len()
+    }
+
+    /// Get a reference to the wrapped interpreter.
+    pub fn inner(&self) -> &I {
+        &self.interpreter
+    }
+
+    /// Unwrap the interpreter, forgetting its history.
+    pub fn into_inner(self) -> I {
+        self.interpreter
+    }
+}
+
+impl<I: Interpreter + Clone> Interpreter for Rewind<I> {
+    fn step(&mut self, keys: &Keys) -> Option<Display> {
+        if self.buffer_size > 0 {
+            if self.history.len() >= self.buffer_size {
+                self.history.pop_front();
+            }
+            self.history.push_back(self.interpreter.clone());
+        }
+
+        self.interpreter.step(keys)
+    }
+
+    fn speed(&self) -> Duration {
+        self.interpreter.speed()
+    }
+
+    fn buzzer_active(&self) -> bool {
+        self.interpreter.buzzer_active()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Chip8Interpreter, Quirks};
+
+    #[test]
+    fn rewind_test() {
+        let keys = [false; 16];
+
+        // ld v0, 1; add v0, 2; ld i, #300; call #20A; nop; cls; ret
+        let rom = [
+            0x60, 0x01, 0x70, 0x02, 0xA3, 0x00, 0x22, 0x0A, 0x00, 0x00, 0x00, 0xE0, 0x00, 0xEE,
+        ];
+        let mut rewind = Rewind::new(Chip8Interpreter::new(&rom, 700., Quirks::default()));
+        let initial_state = rewind.inner().dump_state();
+        assert!(!rewind.step_back());
+
+        for _ in 0..5 {
+            rewind.step(&keys);
+        }
+        assert_eq!(rewind.rewindable_steps(), 5);
+        assert_eq!(rewind.inner().dump_state().pc, 0x20C);
+        assert_eq!(rewind.inner().stack_depth(), 1);
+
+        for _ in 0..5 {
+            assert!(rewind.step_back());
+        }
+        assert!(!rewind.step_back());
+        assert_eq!(rewind.inner().dump_state(), initial_state);
+    }
+
+    #[test]
+    fn rewind_buffer_size_test() {
+        let keys = [false; 16];
+
+        // add v0, 1; jmp #200
+        let rom = [0x70, 0x01, 0x12, 0x00];
+        let mut rewind = Rewind::new(Chip8Interpreter::new(&rom, 700., Quirks::default()));
+        rewind.set_rewind_buffer_size(3);
+
+        for _ in 0..10 {
+            rewind.step(&keys);
+        }
+        assert_eq!(rewind.rewindable_steps(), 3);
+        assert_eq!(rewind.inner().get_register(0), Some(5));
+
+        // Undoing the jmp and then the add
+        assert!(rewind.step_back());
+        assert!(rewind.step_back());
+        assert_eq!(rewind.inner().get_register(0), Some(4));
+
+        rewind.set_rewind_buffer_size(0);
+        assert!(!rewind.step_back());
+        rewind.step(&keys);
+        assert_eq!(rewind.rewindable_steps(), 0);
+        assert_eq!(rewind.into_inner().get_register(0), Some(5));
+    }
+}
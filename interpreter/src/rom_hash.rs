@@ -0,0 +1,55 @@
+//! This module computes SHA-256 hashes of ROMs, which can be used to identify them.
+
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+/// An error returned when a string isn't a valid hex-encoded SHA-256 hash.
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+#[error("{0:?} is not a SHA-256 hash, which must be 64 hex digits")]
+pub struct InvalidHashError(pub String);
+
+/// Compute the SHA-256 hash of the program bytes of a ROM.
+pub fn hash_rom(rom: &[u8]) -> [u8; 32] {
+    Sha256::digest(rom).into()
+}
+
+/// Format a hash as lowercase hex.
+pub fn hash_to_hex(hash: &[u8; 32]) -> String {
+    hash.iter().map(|byte| format!("{byte:0>2x}")).collect()
+}
+
+/// Parse a hash from 64 hex digits, in either case.
+pub fn parse_hash(hex: &str) -> Result<[u8; 32], InvalidHashError> {
+    let error = || InvalidHashError(hex.to_string());
+
+    if hex.len() != 64 || !hex.is_ascii() {
+        return Err(error());
+    }
+
+    let mut hash = [0; 32];
+    for (idx, byte) in hash.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[idx * 2..idx * 2 + 2], 16).map_err(|_| error())?;
+    }
+    Ok(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_rom_test() {
+        let empty = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        assert_eq!(hash_to_hex(&hash_rom(&[])), empty);
+        assert_eq!(parse_hash(empty), Ok(hash_rom(&[])));
+        assert_eq!(parse_hash(&empty.to_uppercase()), Ok(hash_rom(&[])));
+
+        let hash = hash_rom(&[0x12, 0x00]);
+        assert_eq!(parse_hash(&hash_to_hex(&hash)), Ok(hash));
+        assert_ne!(hash, hash_rom(&[0x12, 0x02]));
+
+        assert!(parse_hash("").is_err());
+        assert!(parse_hash(&empty[1..]).is_err());
+        assert!(parse_hash(&empty.replace('e', "g")).is_err());
+    }
+}
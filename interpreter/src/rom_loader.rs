@@ -0,0 +1,277 @@
+//! This module handles loading ROMs from files in several formats.
+
+use crate::FONT_ADDRESS_START;
+use std::{fs, io, path::Path};
+use thiserror::Error;
+
+/// An error that occured while loading a ROM file.
+#[derive(Debug, Error)]
+pub enum RomLoadError {
+    /// We couldn't read the file.
+    #[error("Failed to read ROM file: {0}")]
+    Io(#[from] io::Error),
+
+    /// The file was meant to be hex-encoded, but it wasn't valid.
+    #[error("Invalid hex in ROM file: {0}")]
+    InvalidHex(String),
+}
+
+/// A ROM that was loaded from a file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RomFile {
+    /// The program, which should be loaded at the entry point.
+    pub rom: Vec<u8>,
+
+    /// A custom font to load instead of the built-in one, if the file had one.
+    pub font: Option<[u8; 80]>,
+}
+
+/// Load a ROM from the given file, detecting the format from the file extension.
+///
+/// - `.ch8`, `.c8`, `.rom`, and `.bin` files are raw binary, and so are files with any other
+///   extension or none at all, since ROMs are often shared without one.
+/// - `.hex` files are hex-encoded text, with two hex digits per byte. Whitespace is ignored.
+/// - `.ihex` files are in the Intel HEX format. Data at the font address, like the font that
+///   `ch8a --font-file` embeds, is the font, and the ROM starts at the lowest address of the
+///   rest of the file.
+pub fn load_rom_file(path: &Path) -> Result<RomFile, RomLoadError> {
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+        .unwrap_or_default();
+
+    let rom = match extension.as_str() {
+        "hex" => parse_hex_text(&fs::read_to_string(path)?)?,
+        "ihex" => return parse_intel_hex(&fs::read_to_string(path)?),
+        _ => fs::read(path)?,
+    };
+    Ok(RomFile { rom, font: None })
+}
+
+/// Parse a string of hex digits into bytes, ignoring whitespace.
+fn parse_hex_text(text: &str) -> Result<Vec<u8>, RomLoadError> {
+    let digits: Vec<char> = text.chars().filter(|c| !c.is_whitespace()).collect();
+
+    if !digits.len().is_multiple_of(2) {
+        return Err(RomLoadError::InvalidHex(
+            "Expected an even number of hex digits".to_string(),
+        ));
+    }
+
+    digits
+        .chunks(2)
+        .map(|pair| {
+            let pair: String = pair.iter().collect();
+            u8::from_str_radix(&pair, 16)
+                .map_err(|_| RomLoadError::InvalidHex(format!("{pair:?} is not a hex byte")))
+        })
+        .collect()
+}
+
+/// Parse an Intel HEX file into bytes. Data records at the font address make up the font, which
+/// has to be complete. The returned ROM starts at the lowest address of any other data record,
+/// and any gaps between records are filled with zeroes.
+fn parse_intel_hex(text: &str) -> Result<RomFile, RomLoadError> {
+    let mut records: Vec<(u16, Vec<u8>)> = Vec::new();
+
+    for (idx, line) in text.lines().enumerate() {
+        let line_number = idx + 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let Some(record) = line.strip_prefix(':') else {
+            return Err(RomLoadError::InvalidHex(format!(
+                "Line {line_number} doesn't start with ':'"
+            )));
+        };
+        let bytes = parse_hex_text(record)?;
+
+        if bytes.len() < 5 || bytes.len() != bytes[0] as usize + 5 {
+            return Err(RomLoadError::InvalidHex(format!(
+                "Line {line_number} has the wrong length"
+            )));
+        }
+        if bytes.iter().fold(0u8, |acc, &byte| acc.wrapping_add(byte)) != 0 {
+            return Err(RomLoadError::InvalidHex(format!(
+                "Line {line_number} has an invalid checksum"
+            )));
+        }
+
+        let address = u16::from_be_bytes([bytes[1], bytes[2]]);
+        let data = &bytes[4..bytes.len() - 1];
+
+        match bytes[3] {
+            // Data
+            0x00 => records.push((address, data.to_vec())),
+            // End of file
+            0x01 => break,
+            // Start address, which we don't need
+            0x03 | 0x05 => {}
+            record_type => {
+                return Err(RomLoadError::InvalidHex(format!(
+                    "Line {line_number} has unsupported record type 0x{record_type:0>2X}"
+                )))
+            }
+        }
+    }
+
+    let font_addresses = FONT_ADDRESS_START..FONT_ADDRESS_START + 80;
+    let (font_records, records): (Vec<_>, Vec<_>) =
+        records.into_iter().partition(|(address, data)| {
+            !data.is_empty()
+                && font_addresses.contains(&(*address as usize))
+                && font_addresses.contains(&(*address as usize + data.len() - 1))
+        });
+
+    let font = if font_records.is_empty() {
+        None
+    } else {
+        let mut font = [0; 80];
+        let mut font_len = 0;
+        for (address, data) in font_records {
+            let offset = address as usize - FONT_ADDRESS_START;
+            font[offset..offset + data.len()].copy_from_slice(&data);
+            font_len += data.len();
+        }
+        if font_len != font.len() {
+            return Err(RomLoadError::InvalidHex(format!(
+                "The font at 0x{FONT_ADDRESS_START:0>3X} should be 80 bytes, but it's \
+                 {font_len} bytes"
+            )));
+        }
+        Some(font)
+    };
+
+    let Some(start) = records.iter().map(|(address, _)| *address as usize).min() else {
+        return Ok(RomFile {
+            rom: Vec::new(),
+            font,
+        });
+    };
+    let end = records
+        .iter()
+        .map(|(address, data)| *address as usize + data.len())
+        .max()
+        .unwrap_or(start);
+
+    let mut rom = vec![0; end - start];
+    for (address, data) in records {
+        let offset = address as usize - start;
+        rom[offset..offset + data.len()].copy_from_slice(&data);
+    }
+
+    Ok(RomFile { rom, font })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, path::PathBuf};
+
+    /// Write the contents to a temporary file with the given name and return its path.
+    fn temp_file(name: &str, contents: &[u8]) -> PathBuf {
+        let path = env::temp_dir().join(format!("chip8-rom-loader-{}-{name}", std::process::id()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn load_raw_binary_test() {
+        let rom = [0x60, 0x01, 0x12, 0x00];
+
+        for name in ["test.ch8", "test.c8", "test.rom", "test.BIN"] {
+            let path = temp_file(name, &rom);
+            assert_eq!(load_rom_file(&path).unwrap().rom, rom);
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn load_hex_text_test() {
+        let path = temp_file("test.hex", b"6001 7001\n12 02\n");
+        assert_eq!(
+            load_rom_file(&path).unwrap(),
+            RomFile {
+                rom: vec![0x60, 0x01, 0x70, 0x01, 0x12, 0x02],
+                font: None,
+            }
+        );
+        fs::remove_file(path).unwrap();
+
+        assert!(matches!(
+            parse_hex_text("600"),
+            Err(RomLoadError::InvalidHex(_))
+        ));
+        assert!(matches!(
+            parse_hex_text("60zz"),
+            Err(RomLoadError::InvalidHex(_))
+        ));
+    }
+
+    #[test]
+    fn load_intel_hex_test() {
+        let path = temp_file(
+            "test.ihex",
+            b":040200006001700128\n:020204001202E4\n:00000001FF\n",
+        );
+        assert_eq!(
+            load_rom_file(&path).unwrap(),
+            RomFile {
+                rom: vec![0x60, 0x01, 0x70, 0x01, 0x12, 0x02],
+                font: None,
+            }
+        );
+        fs::remove_file(path).unwrap();
+
+        assert!(matches!(
+            parse_intel_hex(":040200006001700129\n"),
+            Err(RomLoadError::InvalidHex(_))
+        ));
+        assert!(matches!(
+            parse_intel_hex("040200006001700128\n"),
+            Err(RomLoadError::InvalidHex(_))
+        ));
+    }
+
+    #[test]
+    fn load_intel_hex_font_test() {
+        // This is what `ch8a --font-file --output-format ihex` writes, with the font at 0x050
+        let path = temp_file(
+            "font.ihex",
+            b":10005000000102030405060708090A0B0C0D0E0F28\n\
+              :10006000101112131415161718191A1B1C1D1E1F18\n\
+              :10007000202122232425262728292A2B2C2D2E2F08\n\
+              :10008000303132333435363738393A3B3C3D3E3FF8\n\
+              :10009000404142434445464748494A4B4C4D4E4FE8\n\
+              :040200006001120285\n\
+              :00000001FF\n",
+        );
+        assert_eq!(
+            load_rom_file(&path).unwrap(),
+            RomFile {
+                rom: vec![0x60, 0x01, 0x12, 0x02],
+                font: Some(std::array::from_fn(|idx| idx as u8)),
+            }
+        );
+        fs::remove_file(path).unwrap();
+
+        // Part of a font is no use
+        assert!(matches!(
+            parse_intel_hex(":10005000000102030405060708090A0B0C0D0E0F28\n:040200006001120285\n"),
+            Err(RomLoadError::InvalidHex(_))
+        ));
+    }
+
+    #[test]
+    fn unknown_extension_test() {
+        // Anything that isn't hex is loaded as raw binary
+        let rom = [0x60, 0x01, 0x12, 0x00];
+        for name in ["test.txt", "test"] {
+            let path = temp_file(name, &rom);
+            assert_eq!(load_rom_file(&path).unwrap().rom, rom);
+            fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,185 @@
+//! This module contains [`SpeedController`], which adjusts the speed of an interpreter so that it
+//! really runs at the frequency that it's meant to.
+
+use crate::platform::{Clock, StdClock, Timestamp};
+use chip8_base::{Display, Interpreter, Keys};
+use std::{marker::PhantomData, time::Duration};
+
+/// How much each new measurement affects the averages, from 0.0 for not at all to 1.0 for
+/// replacing them completely.
+const SMOOTHING: f64 = 0.1;
+
+/// Wraps an interpreter to keep it running at a target number of instructions per second.
+///
+/// `chip8_base`'s runner sleeps for [`speed`](Interpreter::speed) minus the time that the step
+/// took, but sleeping always takes a bit longer than asked, so the interpreter runs slower than
+/// it should. We measure the time between steps with the clock `C`, and shorten the speed by the
+/// average overshoot to make up for it.
+#[derive(Clone, Debug)]
+pub struct SpeedController<I: Interpreter, C: Clock = StdClock> {
+    /// The interpreter to run.
+    interpreter: I,
+
+    /// The time that each step should take to hit the target.
+    target_period: Duration,
+
+    /// The speed that we're asking the runner to run at.
+    speed: Duration,
+
+    /// The average time between the start of each step, if we've measured it yet.
+    average_period: Option<Duration>,
+
+    /// The average time that each step takes on top of the speed that we asked for, if we've
+    /// measured it yet.
+    average_overshoot: Option<Duration>,
+
+    /// The time when the last step started.
+    last_step: Option<Timestamp>,
+
+    /// The clock that we read the time from.
+    clock: PhantomData<C>,
+}
+
+impl<I: Interpreter> SpeedController<I> {
+    /// Wrap the interpreter to run at the given number of instructions per second.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `target` isn't a positive finite number.
+    pub fn with_target_ips(interpreter: I, target: f64) -> Self {
+        Self::with_clock(interpreter, target)
+    }
+}
+
+impl<I: Interpreter, C: Clock> SpeedController<I, C> {
+    /// Wrap the interpreter to run at the given number of instructions per second, reading the
+    /// time from the clock `C`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `target` isn't a positive finite number.
+    pub fn with_clock(interpreter: I, target: f64) -> Self {
+        let target_period = period_from_ips(target);
+        Self {
+            interpreter,
+            target_period,
+            speed: target_period,
+            average_period: None,
+            average_overshoot: None,
+            last_step: None,
+            clock: PhantomData,
+        }
+    }
+
+    /// Get the number of instructions per second that we're aiming for.
+    pub fn target_ips(&self) -> f64 {
+        self.target_period.as_secs_f64().recip()
+    }
+
+    /// Change the number of instructions per second that we're aiming for.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `ips` isn't a positive finite number.
+    pub fn set_target_ips(&mut self, ips: f64) {
+        self.target_period = period_from_ips(ips);
+        self.speed = self
+            .target_period
+            .saturating_sub(self.average_overshoot.unwrap_or_default());
+    }
+
+    /// Get the average number of instructions per second that we've measured, or 0.0 if we
+    /// haven't run enough steps to measure it yet.
+    pub fn current_ips(&self) -> f64 {
+        self.average_period
+            .map_or(0., |period| period.as_secs_f64().recip())
+    }
+
+    /// Unwrap the interpreter.
+    pub fn into_inner(self) -> I {
+        self.interpreter
+    }
+}
+
+impl<I: Interpreter, C: Clock> Interpreter for SpeedController<I, C> {
+    fn step(&mut self, keys: &Keys) -> Option<Display> {
+        let now = C::now();
+        if let Some(last_step) = self.last_step {
+            let period = now - last_step;
+            let overshoot = period.saturating_sub(self.speed);
+            self.average_period = Some(smooth(self.average_period, period));
+            self.average_overshoot = Some(smooth(self.average_overshoot, overshoot));
+            self.speed = self
+                .target_period
+                .saturating_sub(self.average_overshoot.unwrap_or_default());
+        }
+        self.last_step = Some(now);
+
+        self.interpreter.step(keys)
+    }
+
+    fn speed(&self) -> Duration {
+        self.speed
+    }
+
+    fn buzzer_active(&self) -> bool {
+        self.interpreter.buzzer_active()
+    }
+}
+
+/// Get the time that each instruction should take to run `ips` instructions per second.
+fn period_from_ips(ips: f64) -> Duration {
+    assert!(
+        ips.is_finite() && ips > 0.,
+        "The target instructions per second must be positive, not {ips}"
+    );
+    Duration::from_secs_f64(ips.recip())
+}
+
+/// Add a new measurement to an average, or start the average if there isn't one yet.
+fn smooth(average: Option<Duration>, measurement: Duration) -> Duration {
+    match average {
+        Some(average) => average.mul_f64(1. - SMOOTHING) + measurement.mul_f64(SMOOTHING),
+        None => measurement,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mock_clock::MockClock, null_interpreter::NullInterpreter};
+
+    #[test]
+    fn speed_controller_test() {
+        let keys = [false; 16];
+        let overshoot = Duration::from_micros(500);
+        let mut controller =
+            SpeedController::<_, MockClock>::with_clock(NullInterpreter::new(), 1000.);
+        assert_eq!(controller.speed(), Duration::from_millis(1));
+        assert_eq!(controller.current_ips(), 0.);
+
+        // Pretend to be a runner that always sleeps for 0.5ms too long
+        controller.step(&keys);
+        MockClock::advance(controller.speed() + overshoot);
+        controller.step(&keys);
+        assert_eq!(controller.speed(), Duration::from_micros(500));
+        assert!((controller.current_ips() - 1000. / 1.5).abs() < 1e-6);
+
+        for _ in 0..200 {
+            MockClock::advance(controller.speed() + overshoot);
+            controller.step(&keys);
+        }
+        assert_eq!(controller.speed(), Duration::from_micros(500));
+        assert!((controller.current_ips() - 1000.).abs() < 1.);
+
+        controller.set_target_ips(500.);
+        assert_eq!(controller.target_ips(), 500.);
+        assert_eq!(controller.speed(), Duration::from_micros(1500));
+    }
+
+    #[test]
+    #[should_panic(expected = "must be positive")]
+    fn zero_target_ips_test() {
+        SpeedController::<_, MockClock>::with_clock(NullInterpreter::new(), 0.);
+    }
+}
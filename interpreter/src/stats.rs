@@ -0,0 +1,156 @@
+//! This module contains the [`TimingStats`] type, which helps to debug timing issues.
+
+use crate::platform::Timestamp;
+use std::time::Duration;
+
+/// The number of recent steps that we use to compute the step duration statistics.
+const STEP_WINDOW: usize = 128;
+
+/// Statistics about how closely an interpreter is keeping to its target clock speed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TimingStats {
+    /// The duration that each step should take, according to the clock frequency.
+    pub target_step_duration: Duration,
+
+    /// The mean time between recent steps.
+    pub actual_step_duration_avg: Duration,
+
+    /// The 99th percentile of the time between recent steps.
+    pub actual_step_duration_p99: Duration,
+
+    /// How far the interpreter has drifted from where its clock should be, in either direction,
+    /// since the first step.
+    pub timer_drift_accumulated: Duration,
+
+    /// The number of steps that have been executed.
+    pub steps_completed: u64,
+
+    /// The number of steps that changed the display.
+    pub frames_completed: u64,
+}
+
+/// Keeps track of when steps and frames happen, so that we can compute [`TimingStats`].
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct StepTimings {
+    /// A ring buffer of the most recent times between steps.
+    durations: [Duration; STEP_WINDOW],
+
+    /// The number of valid entries in `durations`.
+    len: usize,
+
+    /// The index in `durations` to write the next entry to.
+    next: usize,
+
+    /// When the first step happened.
+    first_step: Option<Timestamp>,
+
+    /// When the most recent step happened.
+    last_step: Option<Timestamp>,
+
+    /// The number of steps so far.
+    steps: u64,
+
+    /// The number of frames so far.
+    frames: u64,
+}
+
+impl StepTimings {
+    /// Create a new set of timings with no steps.
+    pub fn new() -> Self {
+        Self {
+            durations: [Duration::ZERO; STEP_WINDOW],
+            len: 0,
+            next: 0,
+            first_step: None,
+            last_step: None,
+            steps: 0,
+            frames: 0,
+        }
+    }
+
+    /// Record that a step happened at the given time.
+    pub fn record_step(&mut self, now: Timestamp) {
+        if let Some(last_step) = self.last_step {
+            self.durations[self.next] = now - last_step;
+            self.next = (self.next + 1) % STEP_WINDOW;
+            self.len = (self.len + 1).min(STEP_WINDOW);
+        }
+
+        self.first_step.get_or_insert(now);
+        self.last_step = Some(now);
+        self.steps += 1;
+    }
+
+    /// Record that the last step changed the display.
+    pub fn record_frame(&mut self) {
+        self.frames += 1;
+    }
+
+    /// Compute the statistics, given the duration that each step should take.
+    pub fn stats(&self, target_step_duration: Duration) -> TimingStats {
+        let mut recent = self.durations[..self.len].to_vec();
+        recent.sort_unstable();
+
+        let actual_step_duration_avg = match self.len {
+            0 => Duration::ZERO,
+            len => recent.iter().sum::<Duration>() / len as u32,
+        };
+        let actual_step_duration_p99 = match self.len {
+            0 => Duration::ZERO,
+            len => recent[(len * 99).div_ceil(100) - 1],
+        };
+
+        let timer_drift_accumulated = match (self.first_step, self.last_step) {
+            (Some(first_step), Some(last_step)) => {
+                let actual = last_step - first_step;
+                let ideal = target_step_duration * (self.steps - 1) as u32;
+                actual.abs_diff(ideal)
+            }
+            _ => Duration::ZERO,
+        };
+
+        TimingStats {
+            target_step_duration,
+            actual_step_duration_avg,
+            actual_step_duration_p99,
+            timer_drift_accumulated,
+            steps_completed: self.steps,
+            frames_completed: self.frames,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn step_timings_test() {
+        let target = Duration::from_millis(10);
+        let mut timings = StepTimings::new();
+        assert_eq!(
+            timings.stats(target),
+            TimingStats {
+                target_step_duration: target,
+                ..TimingStats::default()
+            }
+        );
+
+        // 100 steps 10ms apart, and then one more step 50ms later
+        for step in 0..100 {
+            timings.record_step(Timestamp(target * step));
+        }
+        timings.record_step(Timestamp(target * 99 + Duration::from_millis(50)));
+        timings.record_frame();
+
+        let stats = timings.stats(target);
+        assert_eq!(stats.steps_completed, 101);
+        assert_eq!(stats.frames_completed, 1);
+        assert_eq!(
+            stats.actual_step_duration_avg,
+            Duration::from_micros(10_400)
+        );
+        assert_eq!(stats.actual_step_duration_p99, Duration::from_millis(10));
+        assert_eq!(stats.timer_drift_accumulated, Duration::from_millis(40));
+    }
+}
@@ -0,0 +1,161 @@
+//! This module contains [`ThrottledInterpreter`], which waits between steps itself so that they
+//! happen at precise times.
+
+use chip8_base::{Display, Interpreter, Keys};
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+/// The default time before each step that we spin instead of sleeping.
+pub const DEFAULT_SPIN_THRESHOLD: Duration = Duration::from_millis(1);
+
+/// How much each new measurement affects the average oversleep, from 0.0 for not at all to 1.0
+/// for replacing it completely.
+const SMOOTHING: f64 = 0.1;
+
+/// Wraps an interpreter to run its steps at precise times.
+///
+/// `chip8_base`'s runner sleeps between steps, but most OS schedulers only wake a sleeping thread
+/// to within a millisecond or so. Instead, we wait before each step ourselves. We sleep until
+/// `spin_threshold` before the deadline, then spin until the deadline itself. Each deadline is
+/// exactly [`speed`](Interpreter::speed) after the last one, so lateness doesn't build up over
+/// time, and we learn how long sleeps overshoot by to sleep less in future.
+///
+/// Spinning keeps a CPU core busy, so this is only available with the `precise-timing` feature.
+#[derive(Clone, Debug)]
+pub struct ThrottledInterpreter<I: Interpreter> {
+    /// The interpreter to run.
+    interpreter: I,
+
+    /// How long before each deadline we stop sleeping and start spinning.
+    spin_threshold: Duration,
+
+    /// When the next step should start, if we've taken a step yet.
+    next_deadline: Option<Instant>,
+
+    /// The average time that sleeping takes on top of what we asked for.
+    average_oversleep: Duration,
+
+    /// How late the last step started after its deadline.
+    drift: Duration,
+}
+
+impl<I: Interpreter> ThrottledInterpreter<I> {
+    /// Wrap the interpreter, spinning for the last `spin_threshold` before each step instead of
+    /// sleeping. [`DEFAULT_SPIN_THRESHOLD`] is a good choice.
+    pub fn new(interpreter: I, spin_threshold: Duration) -> Self {
+        Self {
+            interpreter,
+            spin_threshold,
+            next_deadline: None,
+            average_oversleep: Duration::ZERO,
+            drift: Duration::ZERO,
+        }
+    }
+
+    /// Get how long before each step we stop sleeping and start spinning.
+    pub fn spin_threshold(&self) -> Duration {
+        self.spin_threshold
+    }
+
+    /// Change how long before each step we stop sleeping and start spinning.
+    pub fn set_spin_threshold(&mut self, spin_threshold: Duration) {
+        self.spin_threshold = spin_threshold;
+    }
+
+    /// Get how late the last step started after the time that it should have.
+    pub fn drift(&self) -> Duration {
+        self.drift
+    }
+
+    /// Unwrap the interpreter.
+    pub fn into_inner(self) -> I {
+        self.interpreter
+    }
+
+    /// Sleep and then spin until the deadline.
+    fn wait_until(&mut self, deadline: Instant) {
+        let Some(remaining) = deadline.checked_duration_since(Instant::now()) else {
+            return;
+        };
+
+        if remaining > self.spin_threshold {
+            let sleep_time = remaining
+                .saturating_sub(self.spin_threshold)
+                .saturating_sub(self.average_oversleep);
+            let sleep_start = Instant::now();
+            thread::sleep(sleep_time);
+            let oversleep = sleep_start.elapsed().saturating_sub(sleep_time);
+            self.average_oversleep =
+                self.average_oversleep.mul_f64(1. - SMOOTHING) + oversleep.mul_f64(SMOOTHING);
+        }
+
+        while Instant::now() < deadline {
+            std::hint::spin_loop();
+        }
+    }
+}
+
+impl<I: Interpreter> Interpreter for ThrottledInterpreter<I> {
+    fn step(&mut self, keys: &Keys) -> Option<Display> {
+        if let Some(deadline) = self.next_deadline {
+            self.wait_until(deadline);
+            self.drift = Instant::now().saturating_duration_since(deadline);
+        }
+
+        let start = Instant::now();
+        let display = self.interpreter.step(keys);
+
+        // If we've fallen more than a whole step behind, start again from now rather than
+        // rushing through steps to catch up
+        let period = self.interpreter.speed();
+        let base = match self.next_deadline {
+            Some(deadline) if start.saturating_duration_since(deadline) < period => deadline,
+            _ => start,
+        };
+        self.next_deadline = Some(base + period);
+
+        display
+    }
+
+    /// We do all the waiting in [`step`](Interpreter::step), so the runner shouldn't wait at all.
+    fn speed(&self) -> Duration {
+        Duration::ZERO
+    }
+
+    fn buzzer_active(&self) -> bool {
+        self.interpreter.buzzer_active()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Chip8Interpreter, Quirks};
+
+    #[test]
+    fn throttled_interpreter_test() {
+        let keys = [false; 16];
+
+        // ld v0, 1; jmp #200
+        let interpreter =
+            Chip8Interpreter::new(&[0x60, 0x01, 0x12, 0x00], 1000., Quirks::default());
+        let mut throttled = ThrottledInterpreter::new(interpreter, DEFAULT_SPIN_THRESHOLD);
+        assert_eq!(throttled.speed(), Duration::ZERO);
+
+        let start = Instant::now();
+        for _ in 0..=20 {
+            throttled.step(&keys);
+        }
+        let elapsed = start.elapsed();
+
+        // The first step doesn't wait, and then every step waits for 1ms
+        assert!(elapsed >= Duration::from_millis(20), "{elapsed:?}");
+        assert!(elapsed < Duration::from_millis(200), "{elapsed:?}");
+
+        throttled.set_spin_threshold(Duration::ZERO);
+        assert_eq!(throttled.spin_threshold(), Duration::ZERO);
+        assert_eq!(throttled.into_inner().dump_state().v[0], 1);
+    }
+}
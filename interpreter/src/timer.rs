@@ -0,0 +1,208 @@
+//! This module contains the [`SoftwareTimer`] and [`FrameCounter`] types.
+
+use std::time::Duration;
+use thiserror::Error;
+
+/// The frequency that CHIP-8 timers count down at, in Hz.
+pub const TIMER_FREQUENCY: f64 = 60.;
+
+/// The frequency given to a timer isn't a positive, finite number of Hz, or it's so high or low
+/// that the time between ticks doesn't fit in a [`Duration`].
+#[derive(Clone, Copy, Debug, PartialEq, Error)]
+#[error("The timer frequency must be positive and finite, not {0} Hz")]
+pub struct InvalidFrequencyError(pub f64);
+
+/// Get the time between each tick at the given frequency.
+fn period(freq_hz: f64) -> Result<Duration, InvalidFrequencyError> {
+    match Duration::try_from_secs_f64(freq_hz.recip()) {
+        Ok(period) if freq_hz > 0. && !period.is_zero() => Ok(period),
+        _ => Err(InvalidFrequencyError(freq_hz)),
+    }
+}
+
+/// A timer that counts down to 0 at a fixed frequency, like the CHIP-8 delay and sound timers.
+///
+/// This timer doesn't read the clock itself, so the caller is responsible for telling it how much
+/// time has passed. That makes it easy to test with synthetic durations.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SoftwareTimer {
+    /// The current value of the timer.
+    value: u8,
+
+    /// The time that has passed since the timer last counted down.
+    accumulator: Duration,
+
+    /// The time between each decrement of the timer.
+    period: Duration,
+}
+
+impl SoftwareTimer {
+    /// Create a new timer with the given initial value, counting down at the given frequency.
+    pub fn new(initial: u8, freq_hz: f64) -> Result<Self, InvalidFrequencyError> {
+        Ok(Self {
+            value: initial,
+            accumulator: Duration::ZERO,
+            period: period(freq_hz)?,
+        })
+    }
+
+    /// Tell the timer that the given amount of time has passed, and return its new value.
+    pub fn tick(&mut self, elapsed: Duration) -> u8 {
+        self.accumulator = self.accumulator.saturating_add(elapsed);
+
+        // Like with FrameCounter, there can be more decrements than fit in a u32 after a long
+        // pause, so we keep the remainder instead of subtracting them from the accumulator
+        let nanos = self.accumulator.as_nanos();
+        let period = self.period.as_nanos();
+        self.accumulator = Duration::from_nanos((nanos % period) as u64);
+        self.value = self
+            .value
+            .saturating_sub((nanos / period).try_into().unwrap_or(u8::MAX));
+
+        self.value
+    }
+
+    /// Set the value of the timer.
+    pub fn set(&mut self, value: u8) {
+        self.value = value;
+    }
+
+    /// Get the value of the timer.
+    pub fn get(&self) -> u8 {
+        self.value
+    }
+}
+
+impl Default for SoftwareTimer {
+    fn default() -> Self {
+        Self::new(0, TIMER_FREQUENCY).expect("60 Hz is a valid frequency")
+    }
+}
+
+/// Counts how many times a fixed frequency clock has ticked, like the 60 Hz clock that drives the
+/// CHIP-8 timers.
+///
+/// Unlike [`SoftwareTimer`], this keeps counting up forever instead of stopping at 0.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameCounter {
+    /// The number of ticks so far.
+    count: u64,
+
+    /// The time that has passed since the last tick.
+    accumulator: Duration,
+
+    /// The time between each tick.
+    period: Duration,
+}
+
+impl FrameCounter {
+    /// Create a new counter at 0, ticking at the given frequency.
+    pub fn new(freq_hz: f64) -> Result<Self, InvalidFrequencyError> {
+        Ok(Self {
+            count: 0,
+            accumulator: Duration::ZERO,
+            period: period(freq_hz)?,
+        })
+    }
+
+    /// Tell the counter that the given amount of time has passed, and return the new count.
+    pub fn tick(&mut self, elapsed: Duration) -> u64 {
+        self.accumulator = self.accumulator.saturating_add(elapsed);
+
+        // There can be more ticks than fit in a u32 after a long pause, so we keep the remainder
+        // instead of subtracting the ticks from the accumulator
+        let nanos = self.accumulator.as_nanos();
+        let period = self.period.as_nanos();
+        self.accumulator = Duration::from_nanos((nanos % period) as u64);
+        self.count = self
+            .count
+            .saturating_add((nanos / period).try_into().unwrap_or(u64::MAX));
+
+        self.count
+    }
+
+    /// Get the number of ticks so far.
+    pub fn get(&self) -> u64 {
+        self.count
+    }
+}
+
+impl Default for FrameCounter {
+    fn default() -> Self {
+        Self::new(TIMER_FREQUENCY).expect("60 Hz is a valid frequency")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tick_test() {
+        let mut timer = SoftwareTimer::new(10, 50.).unwrap();
+        let period = Duration::from_millis(20);
+
+        assert_eq!(timer.tick(Duration::ZERO), 10);
+        assert_eq!(timer.tick(period / 2), 10);
+        assert_eq!(timer.tick(period / 2), 9);
+        assert_eq!(timer.tick(period * 3), 6);
+        assert_eq!(timer.tick(Duration::from_secs(1)), 0);
+        assert_eq!(timer.tick(period), 0);
+
+        timer.set(200);
+        assert_eq!(timer.get(), 200);
+        assert_eq!(timer.tick(Duration::from_secs(10)), 0);
+
+        // Long pauses don't overflow
+        timer.set(200);
+        assert_eq!(timer.tick(Duration::MAX), 0);
+        timer.set(200);
+        assert_eq!(timer.tick(Duration::ZERO), 200);
+
+        // Far more decrements than fit in a u32 still leave the right remainder
+        let mut timer = SoftwareTimer::new(10, 1e9).unwrap();
+        assert_eq!(timer.tick(Duration::new(5, 999_999_999)), 0);
+        timer.set(10);
+        assert_eq!(timer.tick(Duration::from_nanos(1)), 9);
+    }
+
+    #[test]
+    fn frequency_test() {
+        let mut timer = SoftwareTimer::new(100, 10.).unwrap();
+
+        assert_eq!(timer.tick(Duration::from_millis(99)), 100);
+        assert_eq!(timer.tick(Duration::from_millis(1)), 99);
+        assert_eq!(timer.tick(Duration::from_millis(250)), 97);
+        assert_eq!(timer.tick(Duration::from_millis(50)), 96);
+    }
+
+    #[test]
+    fn frame_counter_test() {
+        let mut counter = FrameCounter::new(50.).unwrap();
+        let period = Duration::from_millis(20);
+
+        assert_eq!(counter.tick(Duration::ZERO), 0);
+        assert_eq!(counter.tick(period / 2), 0);
+        assert_eq!(counter.tick(period / 2), 1);
+        assert_eq!(counter.tick(period * 3), 4);
+        assert_eq!(counter.tick(Duration::from_secs(10)), 504);
+        assert_eq!(counter.get(), 504);
+
+        // Far more ticks than fit in a u32 are all counted, and none are left over
+        let mut counter = FrameCounter::new(1e9).unwrap();
+        assert_eq!(counter.tick(Duration::from_secs(5)), 5_000_000_000);
+        assert_eq!(counter.tick(Duration::ZERO), 5_000_000_000);
+
+        assert_eq!(counter.tick(Duration::MAX), u64::MAX);
+        assert_eq!(counter.tick(Duration::from_secs(1)), u64::MAX);
+    }
+
+    #[test]
+    fn invalid_frequency_test() {
+        for freq_hz in [0., -60., f64::NAN, f64::INFINITY, 1e10, 1e-30] {
+            // NaN isn't equal to itself, so we can't compare the errors
+            assert!(SoftwareTimer::new(0, freq_hz).is_err(), "{freq_hz}");
+            assert!(FrameCounter::new(freq_hz).is_err(), "{freq_hz}");
+        }
+    }
+}
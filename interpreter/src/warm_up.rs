@@ -0,0 +1,48 @@
+//! This module contains the [`WarmUp`] trait, which lets ROMs run their initialisation code before
+//! anyone starts looking at them.
+
+use chip8_base::Interpreter;
+
+/// Run an interpreter for a while before using it properly. This is implemented for every
+/// [`Interpreter`], which is a foreign trait and can't have new default methods.
+pub trait WarmUp {
+    /// Run the given number of cycles, ignoring any display updates.
+    ///
+    /// This is useful for ROMs that spend their first few cycles clearing the screen and setting
+    /// up data, and for test fixtures that only care about what happens after that.
+    ///
+    /// No keys are pressed during the warm-up, and the timers carry on counting down in real time
+    /// as usual. Since the warm-up usually runs much faster than the clock speed, initialisation
+    /// code that waits on the delay timer or a key press may behave differently to a normal run.
+    fn warm_up(&mut self, cycles: u64);
+}
+
+impl<I: Interpreter> WarmUp for I {
+    fn warm_up(&mut self, cycles: u64) {
+        let keys = [false; 16];
+        for _ in 0..cycles {
+            self.step(&keys);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Chip8Interpreter, Quirks};
+
+    #[test]
+    fn warm_up_test() {
+        // ld v0, 0; add v0, 1; jmp #202
+        let rom = [0x60, 0x00, 0x70, 0x01, 0x12, 0x02];
+        let mut interpreter = Chip8Interpreter::new(&rom, 700., Quirks::default());
+
+        interpreter.warm_up(0);
+        assert_eq!(interpreter.dump_state().pc, 0x200);
+
+        interpreter.warm_up(7);
+        let state = interpreter.dump_state();
+        assert_eq!(state.v[0], 3);
+        assert_eq!(state.pc, 0x202);
+    }
+}
@@ -0,0 +1,73 @@
+//! This module exports the interpreter to JavaScript with `wasm-bindgen`, so that it can be
+//! embedded in a web page.
+
+use crate::{keys::KeysExt, platform::WebClock, Chip8Config, Chip8Interpreter};
+use chip8_base::{Interpreter, Keys};
+use wasm_bindgen::prelude::*;
+
+/// A CHIP-8 interpreter that reads the time from `performance.now()`, for use from JavaScript.
+#[wasm_bindgen]
+pub struct WebInterpreter {
+    /// The interpreter itself.
+    interpreter: Chip8Interpreter<WebClock>,
+}
+
+#[wasm_bindgen]
+impl WebInterpreter {
+    /// Create a new interpreter with the ROM loaded at 0x200, running at the given clock
+    /// frequency in Hz. If the ROM is too big, we throw an error.
+    #[wasm_bindgen(constructor)]
+    pub fn new(rom: &[u8], clock_frequency: f32) -> Result<WebInterpreter, JsError> {
+        Ok(Self {
+            interpreter: Chip8Interpreter::try_with_clock(Chip8Config {
+                clock_frequency,
+                ..Chip8Config::new(rom.to_vec())
+            })?,
+        })
+    }
+
+    /// Execute a single cycle, with the keys given as a bitmask where bit N means that key N is
+    /// pressed.
+    ///
+    /// If the display should be updated, we return it as 64 * 32 bytes, row by row, with 1 for
+    /// white pixels and 0 for black pixels. If the program can't continue, we throw an error.
+    pub fn step(&mut self, keys: u16) -> Result<Option<Vec<u8>>, JsError> {
+        let display = self.interpreter.try_step(&Keys::from_bitmask(keys))?;
+        Ok(display.map(|display| {
+            display
+                .iter()
+                .flatten()
+                .map(|&pixel| pixel.into())
+                .collect()
+        }))
+    }
+
+    /// Get the time that each step should take, in milliseconds.
+    #[wasm_bindgen(js_name = speedMs)]
+    pub fn speed_ms(&self) -> f64 {
+        self.interpreter.speed().as_secs_f64() * 1000.
+    }
+
+    /// Change the clock frequency in Hz while the interpreter is running. This throws an error
+    /// if the frequency isn't positive.
+    #[wasm_bindgen(js_name = setFrequency)]
+    pub fn set_frequency(&mut self, clock_frequency: f32) -> Result<(), JsError> {
+        if !(clock_frequency.is_finite() && clock_frequency > 0.) {
+            return Err(JsError::new("The clock frequency must be positive"));
+        }
+        self.interpreter.set_frequency(clock_frequency);
+        Ok(())
+    }
+
+    /// Should the buzzer be making a sound?
+    #[wasm_bindgen(js_name = buzzerActive)]
+    pub fn buzzer_active(&self) -> bool {
+        self.interpreter.buzzer_active()
+    }
+
+    /// Mute or unmute the buzzer.
+    #[wasm_bindgen(js_name = setAudioEnabled)]
+    pub fn set_audio_enabled(&mut self, enabled: bool) {
+        self.interpreter.set_audio_enabled(enabled);
+    }
+}
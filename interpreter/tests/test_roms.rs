@@ -0,0 +1,64 @@
+//! Run well-known test ROMs and compare the final display against known-good snapshots.
+//!
+//! Snapshots are stored as ASCII art in `tests/snapshots/`, with `#` for white pixels and `.`
+//! for black pixels. Run the tests with `UPDATE_SNAPSHOTS=1` to overwrite them with the current
+//! output.
+
+use chip8::{Chip8Interpreter, Quirks};
+use chip8_base::Display;
+use std::{env, fs, path::PathBuf};
+
+/// Run the given ROM from the `roms/` directory for a fixed number of cycles with no keys
+/// pressed, and return the final display.
+fn run_rom(name: &str, cycles: u64) -> Display {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("../roms")
+        .join(name);
+    let rom = fs::read(path).unwrap();
+
+    Chip8Interpreter::new(&rom, 700., Quirks::default())
+        .step_n(cycles, &[false; 16])
+        .unwrap()
+}
+
+/// Render the display as ASCII art.
+fn render(display: &Display) -> String {
+    display
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|&pixel| if pixel.into() { '#' } else { '.' })
+                .chain(['\n'])
+                .collect::<String>()
+        })
+        .collect()
+}
+
+/// Assert that the display matches the named snapshot.
+fn assert_snapshot(display: &Display, snapshot: &str) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/snapshots")
+        .join(snapshot);
+    let actual = render(display);
+
+    if env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        fs::write(path, actual).unwrap();
+        return;
+    }
+
+    let expected = fs::read_to_string(path).unwrap();
+    assert!(
+        actual == expected,
+        "Display doesn't match snapshot {snapshot:?}\n\nExpected:\n{expected}\nActual:\n{actual}"
+    );
+}
+
+#[test]
+fn ibm_logo_test() {
+    assert_snapshot(&run_rom("IBM Logo.ch8", 100), "ibm_logo.txt");
+}
+
+#[test]
+fn test_opcode_test() {
+    assert_snapshot(&run_rom("test_opcode.ch8", 1000), "test_opcode.txt");
+}